@@ -0,0 +1,101 @@
+//! Exports build information read by `GET /version`:
+//! `GIT_SHA` (unless already set in the environment) and `BUILD_TIMESTAMP`
+//! (Unix seconds, `SOURCE_DATE_EPOCH` when set for reproducible builds).
+//! The script reruns whenever the sources, manifest or checked-out commit
+//! change, so the timestamp follows the last build that changed the binary.
+//!
+//! With the `embed-assets` feature it also lists the web client files under
+//! `static/` in `$OUT_DIR/web_assets.rs`, so they are compiled into the binary.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    // Without these, declaring the inputs above would stop Cargo from rerunning
+    // the script on source edits and leave `BUILD_TIMESTAMP` stale
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    if std::env::var_os("GIT_SHA").is_none()
+        && let Some(sha) = git_sha()
+    {
+        println!("cargo:rustc-env=GIT_SHA={}", sha);
+    }
+
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        });
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", timestamp);
+
+    if std::env::var_os("CARGO_FEATURE_EMBED_ASSETS").is_some() {
+        embed_web_assets();
+    }
+}
+
+/// Write `WEB_ASSETS`, a `(path relative to static/, contents)` table of every
+/// web client file
+fn embed_web_assets() {
+    println!("cargo:rerun-if-changed=static");
+
+    let root = Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap()).join("static");
+    let mut files = Vec::new();
+    collect_files(&root, &mut files);
+    files.sort();
+
+    let mut code = String::from("pub(crate) static WEB_ASSETS: &[(&str, &[u8])] = &[\n");
+    for file in &files {
+        let name = file
+            .strip_prefix(&root)
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/");
+        code.push_str(&format!(
+            "    ({:?}, include_bytes!({:?})),\n",
+            name,
+            file.display().to_string()
+        ));
+    }
+    code.push_str("];\n");
+
+    let out = Path::new(&std::env::var("OUT_DIR").unwrap()).join("web_assets.rs");
+    std::fs::write(out, code).expect("Failed to write web_assets.rs");
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+/// Commit the source tree was built from; `None` outside a git checkout
+fn git_sha() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let sha = String::from_utf8(output.stdout).ok()?;
+    Some(sha.trim().to_string()).filter(|sha| !sha.is_empty())
+}
@@ -8,6 +8,7 @@
 use music_station::lyrics::LyricFormat;
 use music_station::lyrics::fetcher::*;
 use music_station::lyrics::providers::*;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -82,8 +83,8 @@ async fn main() -> anyhow::Result<()> {
         );
 
         let aggregator = LyricsAggregator::new()
-            .add_provider(Box::new(provider1))
-            .add_provider(Box::new(provider2));
+            .add_provider(Arc::new(provider1))
+            .add_provider(Arc::new(provider2));
 
         println!("Registered providers: {:?}", aggregator.provider_names());
 
@@ -126,8 +127,8 @@ async fn main() -> anyhow::Result<()> {
         );
 
         let aggregator = LyricsAggregator::new()
-            .add_provider(Box::new(provider1))
-            .add_provider(Box::new(provider2));
+            .add_provider(Arc::new(provider1))
+            .add_provider(Arc::new(provider2));
 
         let query = LyricsQuery::new("Yesterday").with_artist("The Beatles");
         println!(
@@ -175,8 +176,8 @@ async fn main() -> anyhow::Result<()> {
         let provider2 = MockLyricsProvider::new("empty-provider");
 
         let aggregator = LyricsAggregator::new()
-            .add_provider(Box::new(provider1))
-            .add_provider(Box::new(provider2));
+            .add_provider(Arc::new(provider1))
+            .add_provider(Arc::new(provider2));
 
         println!("Checking provider health...");
         let health = aggregator.health_check_all().await;
@@ -1,8 +1,10 @@
+use music_search_rs::lrc::merge_bilingual;
 use music_search_rs::{MusicApi, NetEaseMusicApi, QQMusicApi, SearchSource, SearchType};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::fs;
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use tracing_subscriber::{fmt, EnvFilter};
 
 /// Music Search & Lyrics Downloader
@@ -23,10 +25,72 @@ struct Args {
     /// Search query (song name or artist)
     #[arg(short, long, value_name = "QUERY")]
     query: Option<String>,
+
+    /// Directory lyrics are saved to (created if missing)
+    #[arg(short, long, value_name = "DIR", default_value = "lyrics")]
+    out_dir: PathBuf,
+
+    /// File name without extension; supports {title}, {artist}, {album} and {type}.
+    /// A '/' in the template creates subdirectories.
+    #[arg(short, long, value_name = "TEMPLATE", default_value = DEFAULT_FILENAME_TEMPLATE)]
+    filename_template: String,
+
+    /// Save original and translation as one bilingual LRC instead of two files
+    #[arg(long)]
+    combine: bool,
+
+    /// Select the Nth search result (1-based) instead of prompting
+    #[arg(short = 'n', long, value_name = "N")]
+    song_index: Option<usize>,
+
+    /// Lyrics to save instead of prompting
+    #[arg(short, long, value_enum, value_name = "TYPE")]
+    lyric_type: Option<LyricType>,
+
+    /// Print results as JSON on stdout; messages go to stderr and nothing is
+    /// read from stdin once the service and query are known. Failures print
+    /// `{"error": "..."}` and exit with status 1.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LyricType {
+    Original,
+    Translation,
+    Transliteration,
+    All,
 }
 
+impl LyricType {
+    /// Names of the entries this type selects; a bilingual file (`--combine`)
+    /// holds both the original and the translation
+    fn names(self) -> &'static [&'static str] {
+        match self {
+            LyricType::Original => &["Original", "Bilingual"],
+            LyricType::Translation => &["Translation", "Bilingual"],
+            LyricType::Transliteration => &["Transliteration"],
+            LyricType::All => &["Original", "Translation", "Transliteration", "Bilingual"],
+        }
+    }
+}
+
+/// Print human-readable output; in `--json` mode it goes to stderr so stdout
+/// only carries the JSON document
+macro_rules! say {
+    ($json:expr, $($arg:tt)*) => {
+        if $json {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+const DEFAULT_FILENAME_TEMPLATE: &str = "{title} - {artist}_{type}";
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
     // Initialize tracing subscriber for logging
     // Use RUST_LOG environment variable to control log level
     // Example: RUST_LOG=debug ./music_search
@@ -40,8 +104,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .init();
 
     let args = Args::parse();
+    let json = args.json;
+
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if json {
+                // Nothing else has been printed to stdout when a step fails
+                let _ = print_json(&serde_json::json!({ "error": e.to_string() }));
+            } else {
+                eprintln!("Error: {}", e);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    println!("=== Music Search & Lyrics Downloader ===\n");
+/// Search, select and save lyrics as the arguments ask. Anything that stops
+/// the flow short of the user choosing to exit is returned as an error.
+async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let json = args.json;
+
+    say!(json, "=== Music Search & Lyrics Downloader ===\n");
 
     // Determine API to use
     let api_choice = if let Some(api_name) = args.api {
@@ -49,79 +133,85 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "netease" | "ne" | "163" | "1" => "1".to_string(),
             "qq" | "qqmusic" | "tencent" | "2" => "2".to_string(),
             _ => {
-                eprintln!("Invalid API choice: '{}'. Use 'netease' or 'qq'.", api_name);
-                return Ok(());
+                return Err(format!("Invalid API choice: '{}'. Use 'netease' or 'qq'.", api_name).into());
             }
         }
     } else {
         // Interactive selection
-        println!("Select music service:");
-        println!("1. NetEase Cloud Music");
-        println!("2. QQ Music");
-        print!("Enter choice (1 or 2): ");
-        io::stdout().flush()?;
-
-        let mut choice = String::new();
-        io::stdin().read_line(&mut choice)?;
-        choice.trim().to_string()
+        say!(json, "Select music service:");
+        say!(json, "1. NetEase Cloud Music");
+        say!(json, "2. QQ Music");
+        prompt(json, "Enter choice (1 or 2): ")?
     };
 
     let api: Box<dyn MusicApi> = match api_choice.as_str() {
         "1" => Box::new(NetEaseMusicApi::new(args.cookie.clone())?),
         "2" => Box::new(QQMusicApi::new(args.cookie.clone())?),
-        _ => {
-            eprintln!("Invalid choice. Exiting.");
-            return Ok(());
-        }
+        _ => return Err("Invalid service choice".into()),
     };
 
     let source_name = match api.source() {
         SearchSource::NetEaseMusic => "NetEase Cloud Music",
         SearchSource::QQMusic => "QQ Music",
     };
-    println!("\nUsing {} service", source_name);
+    say!(json, "\nUsing {} service", source_name);
     
     if args.cookie.is_some() {
-        println!("Using provided cookie for authentication");
+        say!(json, "Using provided cookie for authentication");
     }
-    println!();
+    say!(json, "");
 
     // Get search query
     let query = if let Some(q) = args.query {
         q
     } else {
-        print!("Enter song name or artist to search: ");
-        io::stdout().flush()?;
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        input.trim().to_string()
+        prompt(json, "Enter song name or artist to search: ")?
     };
 
     if query.is_empty() {
-        eprintln!("Search query cannot be empty. Exiting.");
-        return Ok(());
+        return Err("Search query cannot be empty".into());
     }
 
     // Search for songs
-    println!("\nSearching for '{}'...\n", query);
+    say!(json, "\nSearching for '{}'...\n", query);
     let search_result = api.search(&query, SearchType::SongId).await?;
 
     if !search_result.is_success() {
-        println!("Search failed: {}", search_result.error_msg.unwrap_or_else(|| "Unknown error".to_string()));
-        return Ok(());
+        return Err(format!(
+            "Search failed: {}",
+            search_result.error_msg.unwrap_or_else(|| "Unknown error".to_string())
+        )
+        .into());
     }
 
     let search_data = search_result.data.as_ref().unwrap();
     
     if search_data.song_vos.is_empty() {
-        println!("No results found.");
+        if let Some(index) = args.song_index {
+            return Err(format!("--song-index {} is out of range: the search returned no results", index).into());
+        }
+        if json {
+            print_json(&serde_json::json!({ "query": query, "source": api.source(), "songs": [] }))?;
+        } else {
+            say!(json, "No results found.");
+        }
+        return Ok(());
+    }
+
+    // Without a song to select, JSON mode only lists the results
+    if json && args.song_index.is_none() {
+        print_json(&serde_json::json!({
+            "query": query,
+            "source": api.source(),
+            "songs": search_data.song_vos,
+        }))?;
         return Ok(());
     }
 
     // Display search results
-    println!("Search Results:");
-    println!("{:<4} {:<40} {:<30} {:<20}", "No.", "Song", "Artist", "Album");
-    println!("{}", "-".repeat(100));
+    say!(json, "Search Results:");
+    say!(json, "{:<4} {:<40} {:<30} {:<20}", "No.", "Song", "Artist", "Album");
+    say!(json, "{}", "-".repeat(100));
 
     for (idx, song) in search_data.song_vos.iter().enumerate() {
         let artist_names = song.author_name.join(", ");
@@ -148,121 +238,181 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             song.album_name.clone()
         };
 
-        println!("{:<4} {:<40} {:<30} {:<20}", 
+        say!(json, "{:<4} {:<40} {:<30} {:<20}", 
             idx + 1, song_name, artist_display, album_display);
     }
 
     // Select song
-    print!("\nEnter song number to download lyrics (or 0 to exit): ");
-    io::stdout().flush()?;
-    let mut selection = String::new();
-    io::stdin().read_line(&mut selection)?;
-    
-    let selection: usize = match selection.trim().parse() {
-        Ok(n) if n > 0 && n <= search_data.song_vos.len() => n,
-        Ok(0) => {
-            println!("Exiting.");
-            return Ok(());
+    let song_count = search_data.song_vos.len();
+    let selection: usize = if let Some(index) = args.song_index {
+        if index == 0 || index > song_count {
+            return Err(format!(
+                "--song-index {} is out of range: the search returned {} result(s)",
+                index, song_count
+            )
+            .into());
         }
-        _ => {
-            eprintln!("Invalid selection. Exiting.");
-            return Ok(());
+        index
+    } else {
+        match prompt(json, "\nEnter song number to download lyrics (or 0 to exit): ")?.parse() {
+            Ok(n) if n > 0 && n <= song_count => n,
+            Ok(0) => {
+                say!(json, "Exiting.");
+                return Ok(());
+            }
+            _ => return Err("Invalid song selection".into()),
         }
     };
 
     let selected_song = &search_data.song_vos[selection - 1];
-    println!("\nSelected: {} - {}", selected_song.title, 
+    say!(json, "\nSelected: {} - {}", selected_song.title, 
         selected_song.author_name.join(", "));
 
     // Get lyrics
-    println!("\nFetching lyrics...");
+    say!(json, "\nFetching lyrics...");
     let lyric_result = api.get_lyric(&selected_song.display_id, &selected_song.display_id, false).await?;
 
     if !lyric_result.is_success() {
-        println!("Failed to get lyrics: {}", lyric_result.error_msg.unwrap_or_else(|| "Unknown error".to_string()));
-        return Ok(());
+        return Err(format!(
+            "Failed to get lyrics: {}",
+            lyric_result.error_msg.unwrap_or_else(|| "Unknown error".to_string())
+        )
+        .into());
     }
 
     let lyric_data = lyric_result.data.as_ref().unwrap();
 
     // Check available lyrics
-    let mut available_lyrics = Vec::new();
-    if let Some(lyric) = &lyric_data.lyric {
-        if !lyric.is_empty() {
-            available_lyrics.push(("Original", lyric));
-        }
-    }
-    if let Some(translate) = &lyric_data.translate_lyric {
-        if !translate.is_empty() {
-            available_lyrics.push(("Translation", translate));
+    let original = lyric_data.lyric.as_ref().filter(|l| !l.is_empty());
+    let translation = lyric_data.translate_lyric.as_ref().filter(|l| !l.is_empty());
+    let transliteration = lyric_data.transliteration_lyric.as_ref().filter(|l| !l.is_empty());
+
+    let mut available_lyrics: Vec<(&str, String)> = Vec::new();
+    match (original, translation) {
+        (Some(original), Some(translation)) if args.combine => {
+            available_lyrics.push(("Bilingual", merge_bilingual(original, translation)));
         }
-    }
-    if let Some(transliteration) = &lyric_data.transliteration_lyric {
-        if !transliteration.is_empty() {
-            available_lyrics.push(("Transliteration", transliteration));
+        _ => {
+            if args.combine {
+                say!(json, "No translation available; saving lyrics separately.");
+            }
+            available_lyrics.extend(original.map(|l| ("Original", l.clone())));
+            available_lyrics.extend(translation.map(|l| ("Translation", l.clone())));
         }
     }
+    available_lyrics.extend(transliteration.map(|l| ("Transliteration", l.clone())));
 
     if available_lyrics.is_empty() {
-        println!("No lyrics found for this song.");
-        return Ok(());
+        return Err("No lyrics found for this song".into());
     }
 
-    // Display lyrics types available
-    println!("\nAvailable lyrics:");
-    for (idx, (name, _)) in available_lyrics.iter().enumerate() {
-        println!("{}. {}", idx + 1, name);
-    }
+    // Select lyrics type: from --lyric-type, all of them in JSON mode, or interactively
+    let selected: Vec<&(&str, String)> = match args.lyric_type {
+        Some(lyric_type) => {
+            let names = lyric_type.names();
+            let selected: Vec<_> = available_lyrics.iter().filter(|(name, _)| names.contains(name)).collect();
+            if selected.is_empty() {
+                let available: Vec<_> = available_lyrics.iter().map(|(name, _)| name.to_lowercase()).collect();
+                return Err(format!(
+                    "No {} lyrics for this song (available: {})",
+                    names[0].to_lowercase(),
+                    available.join(", ")
+                )
+                .into());
+            }
+            selected
+        }
+        None if json => available_lyrics.iter().collect(),
+        None => {
+            // Display lyrics types available
+            say!(json, "\nAvailable lyrics:");
+            for (idx, (name, _)) in available_lyrics.iter().enumerate() {
+                say!(json, "{}. {}", idx + 1, name);
+            }
 
-    // Select lyrics type
-    print!("\nEnter lyrics type number to download (or 0 to download all): ");
-    io::stdout().flush()?;
-    let mut lyric_choice = String::new();
-    io::stdin().read_line(&mut lyric_choice)?;
-    
-    let lyric_choice: usize = match lyric_choice.trim().parse() {
-        Ok(n) if n <= available_lyrics.len() => n,
-        _ => {
-            eprintln!("Invalid selection. Exiting.");
-            return Ok(());
+            let lyric_choice = prompt(json, "\nEnter lyrics type number to download (or 0 to download all): ")?;
+            match lyric_choice.parse::<usize>() {
+                Ok(0) => available_lyrics.iter().collect(),
+                Ok(n) if n <= available_lyrics.len() => vec![&available_lyrics[n - 1]],
+                _ => return Err("Invalid lyrics type selection".into()),
+            }
         }
     };
 
-    // Create output directory
-    let output_dir = "lyrics";
-    fs::create_dir_all(output_dir)?;
-
-    // Sanitize filename
-    let safe_filename = sanitize_filename(&format!(
-        "{} - {}",
-        selected_song.title,
-        selected_song.author_name.join(", ")
-    ));
-
-    // Download and save lyrics
-    if lyric_choice == 0 {
-        // Download all lyrics types
-        for (name, content) in &available_lyrics {
-            let filename = format!("{}_{}.lrc", safe_filename, name.to_lowercase());
-            let filepath = Path::new(output_dir).join(&filename);
-            
-            fs::write(&filepath, content)?;
-            println!("✓ Saved {} lyrics to: {}", name, filepath.display());
+    let artist = selected_song.author_name.join(", ");
+    let mut saved_files = Vec::new();
+    for (name, content) in selected {
+        let filename = render_filename(
+            &args.filename_template,
+            &selected_song.title,
+            &artist,
+            &selected_song.album_name,
+            &name.to_lowercase(),
+        );
+        let filepath = args.out_dir.join(format!("{}.lrc", filename));
+        if let Some(parent) = filepath.parent() {
+            fs::create_dir_all(parent)?;
         }
-    } else {
-        // Download selected lyrics type
-        let (name, content) = &available_lyrics[lyric_choice - 1];
-        let filename = format!("{}_{}.lrc", safe_filename, name.to_lowercase());
-        let filepath = Path::new(output_dir).join(&filename);
-        
+
         fs::write(&filepath, content)?;
-        println!("✓ Saved {} lyrics to: {}", name, filepath.display());
+        say!(json, "✓ Saved {} lyrics to: {}", name, filepath.display());
+        saved_files.push(serde_json::json!({ "type": name.to_lowercase(), "path": filepath }));
+    }
+
+    if json {
+        print_json(&serde_json::json!({ "song": selected_song, "files": saved_files }))?;
+    } else {
+        say!(json, "\n✓ Download complete!");
+    }
+    Ok(())
+}
+
+/// Print a prompt and read one trimmed line from stdin. In `--json` mode the
+/// prompt goes to stderr so stdout only carries the JSON document.
+fn prompt(json: bool, message: &str) -> io::Result<String> {
+    if json {
+        eprint!("{}", message);
+        io::stderr().flush()?;
+    } else {
+        print!("{}", message);
+        io::stdout().flush()?;
     }
 
-    println!("\n✓ Download complete!");
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
+
+/// Write the `--json` result document to stdout
+fn print_json(value: &serde_json::Value) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
     Ok(())
 }
 
+/// Fill the `{title}`, `{artist}`, `{album}` and `{type}` placeholders of a
+/// filename template. Each value is sanitized on its own so a '/' in a title
+/// cannot create directories, while '/' in the template itself still can.
+fn render_filename(template: &str, title: &str, artist: &str, album: &str, lyric_type: &str) -> String {
+    let rendered = template
+        .replace("{title}", &sanitize_filename(title))
+        .replace("{artist}", &sanitize_filename(artist))
+        .replace("{album}", &sanitize_filename(album))
+        .replace("{type}", lyric_type);
+
+    // Drop empty and relative components so output stays inside the output dir
+    let components: Vec<&str> = rendered
+        .split('/')
+        .map(str::trim)
+        .filter(|c| !c.is_empty() && *c != "." && *c != "..")
+        .collect();
+
+    if components.is_empty() {
+        lyric_type.to_string()
+    } else {
+        components.join("/")
+    }
+}
+
 /// Sanitize filename by removing or replacing invalid characters
 fn sanitize_filename(filename: &str) -> String {
     filename
@@ -0,0 +1,94 @@
+use crate::error::{MusicSearchError, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
+
+/// User-Agent sent when `HttpOptions::user_agent` is not set
+pub const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36";
+
+/// Extra HTTP settings applied to every request an API client sends
+#[derive(Debug, Clone, Default)]
+pub struct HttpOptions {
+    /// Replaces the default browser User-Agent
+    pub user_agent: Option<String>,
+    /// Headers added to each request. A header with the same name as a
+    /// built-in one (e.g. `Referer`) replaces it.
+    pub headers: Vec<(String, String)>,
+}
+
+impl HttpOptions {
+    /// Add a header sent with every request
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Override the User-Agent
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Build the HTTP client and the validated extra headers
+    pub(crate) fn build(&self) -> Result<(Client, HeaderMap)> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| MusicSearchError::Other(format!("Invalid header name '{}': {}", name, e)))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| MusicSearchError::Other(format!("Invalid value for header '{}': {}", name, e)))?;
+            headers.insert(name, value);
+        }
+
+        let client = Client::builder()
+            .user_agent(self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT))
+            .build()?;
+
+        Ok((client, headers))
+    }
+}
+
+/// Accept one HTTP request on a local port, answer it with `{}` and return
+/// the raw request text, for asserting what a client sends
+#[cfg(test)]
+pub(crate) async fn capture_request<F, Fut>(send: F) -> String
+where
+    F: FnOnce(String) -> Fut,
+    Fut: std::future::Future<Output = ()>,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+
+    let server = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let n = socket.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        socket
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}")
+            .await
+            .unwrap();
+        String::from_utf8_lossy(&request).to_lowercase()
+    });
+
+    send(url).await;
+    server.await.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_header_is_rejected() {
+        let options = HttpOptions::default().with_header("Bad Header", "x");
+        assert!(options.build().is_err());
+    }
+}
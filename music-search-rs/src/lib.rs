@@ -1,15 +1,23 @@
 pub mod error;
+pub mod http;
+pub mod lrc;
 pub mod models;
 pub mod netease;
+pub mod normalize;
 pub mod qqmusic;
 
 use async_trait::async_trait;
 pub use error::{MusicSearchError, Result};
+pub use http::HttpOptions;
 pub use models::*;
 pub use netease::NetEaseMusicApi;
+pub use normalize::{normalize_title, TitleNormalizer};
 pub use qqmusic::QQMusicApi;
 use std::collections::HashMap;
 
+/// Version of the music-search-rs crate
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 /// Unified Music API trait for search services
 #[async_trait]
 pub trait MusicApi: Send + Sync {
@@ -116,15 +124,7 @@ impl MusicApi for NetEaseMusicApi {
     }
 
     async fn get_song_link(&self, song_id: &str) -> Result<ResultVo<String>> {
-        let datum_map = self.get_song_url(&[song_id.to_string()]).await?;
-        
-        if let Some(datum) = datum_map.get(song_id) {
-            if let Some(url) = &datum.url {
-                return Ok(ResultVo::success(url.clone()));
-            }
-        }
-        
-        Ok(ResultVo::failure(error_msg::SONG_URL_GET_FAILED.to_string()))
+        self.get_song_link_at(song_id, None).await
     }
 
     async fn get_lyric(&self, _id: &str, display_id: &str, _is_verbatim: bool) -> Result<ResultVo<LyricVo>> {
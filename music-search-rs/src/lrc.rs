@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+/// Merge an original LRC and its translation into one bilingual LRC.
+///
+/// Each timed original line is followed by the translation line with the
+/// same timestamp, so players that show duplicate timestamps together display
+/// both. Tag lines (`[ar:...]`) and untimed lines are kept from the original;
+/// translation lines without a matching original timestamp are dropped.
+pub fn merge_bilingual(original: &str, translation: &str) -> String {
+    let mut translations: HashMap<u64, &str> = HashMap::new();
+    for line in translation.lines() {
+        let (times, text) = split_timestamps(line);
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        for time in times {
+            translations.entry(time).or_insert(text);
+        }
+    }
+
+    let mut merged = String::new();
+    for line in original.lines() {
+        merged.push_str(line);
+        merged.push('\n');
+
+        let (times, text) = split_timestamps(line);
+        if text.trim().is_empty() {
+            continue;
+        }
+        if let Some(translated) = times.first().and_then(|time| translations.get(time)) {
+            let tags = &line[..line.len() - text.len()];
+            merged.push_str(tags);
+            merged.push_str(translated);
+            merged.push('\n');
+        }
+    }
+
+    merged
+}
+
+/// Split the leading `[mm:ss.xx]` tags off a line, returning their times in
+/// milliseconds and the remaining text. Non-time tags end the prefix.
+fn split_timestamps(line: &str) -> (Vec<u64>, &str) {
+    let mut times = Vec::new();
+    let mut rest = line.trim_start();
+
+    while let Some(tag) = rest.strip_prefix('[') {
+        let Some(end) = tag.find(']') else {
+            break;
+        };
+        let Some(time) = parse_time(&tag[..end]) else {
+            break;
+        };
+        times.push(time);
+        rest = &tag[end + 1..];
+    }
+
+    if times.is_empty() {
+        (times, line)
+    } else {
+        (times, rest)
+    }
+}
+
+/// Parse `mm:ss`, `mm:ss.xx` or `mm:ss.xxx` into milliseconds
+fn parse_time(value: &str) -> Option<u64> {
+    let (minutes, seconds) = value.split_once(':')?;
+    let minutes: u64 = minutes.parse().ok()?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let seconds: u64 = seconds.parse().ok()?;
+
+    let millis = match fraction.len() {
+        0 => 0,
+        1..=3 => fraction.parse::<u64>().ok()? * 10u64.pow(3 - fraction.len() as u32),
+        _ => return None,
+    };
+
+    Some((minutes * 60 + seconds) * 1000 + millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_bilingual() {
+        let original = "[ar:Artist]\n[00:01.00]Hello\n[00:05.50]World\n[00:09.00]\n";
+        // Different fraction precision still matches
+        let translation = "[by:someone]\n[00:01.000]你好\n[00:05.5]世界\n[00:07.00]多余\n";
+
+        assert_eq!(
+            merge_bilingual(original, translation),
+            "[ar:Artist]\n[00:01.00]Hello\n[00:01.00]你好\n[00:05.50]World\n[00:05.50]世界\n[00:09.00]\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("01:02.34"), Some(62_340));
+        assert_eq!(parse_time("00:00.5"), Some(500));
+        assert_eq!(parse_time("00:03"), Some(3_000));
+        assert_eq!(parse_time("ar:Artist"), None);
+    }
+}
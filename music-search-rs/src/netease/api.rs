@@ -1,4 +1,5 @@
 use crate::error::{MusicSearchError, Result};
+use crate::http::HttpOptions;
 use crate::models::*;
 use crate::netease::models::*;
 use aes::Aes128;
@@ -6,7 +7,8 @@ use base64::{engine::general_purpose, Engine as _};
 use cbc::Encryptor;
 use cbc::cipher::{BlockEncryptMut, KeyIvInit};
 use rand::Rng;
-use reqwest::Client;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, RequestBuilder};
 use rsa::BigUint;
 use serde_json::json;
 use std::collections::HashMap;
@@ -18,15 +20,31 @@ const MODULUS: &str = "00e0b509f6259df8642dbc35662901477df22677ec152b5ff68ace615
 const NONCE: &str = "0CoJUm6Qyw8W8jud";
 const VI: &[u8] = b"0102030405060708";
 
+/// Where NetEase Music's web API is served; see `NetEaseMusicApi::with_base_url`
+pub const NETEASE_BASE_URL: &str = "https://music.163.com";
+
+/// Bitrates (bits per second) tried in order for song URLs: lossless, then
+/// 320k and 128k for songs without a higher quality
+pub const SONG_URL_BITRATES: [u32; 3] = [999000, 320000, 128000];
+
 pub struct NetEaseMusicApi {
     client: Client,
     secret_key: String,
     enc_sec_key: String,
     cookie: Option<String>,
+    /// Extra headers from `HttpOptions`, applied after the built-in ones
+    headers: HeaderMap,
+    /// Scheme and host requests are sent to, without a trailing slash
+    base_url: String,
 }
 
 impl NetEaseMusicApi {
     pub fn new(cookie: Option<String>) -> Result<Self> {
+        Self::with_options(cookie, HttpOptions::default())
+    }
+
+    /// Create a client that sends the extra headers and User-Agent in `options`
+    pub fn with_options(cookie: Option<String>, options: HttpOptions) -> Result<Self> {
         info!("Initializing NetEase Music API client");
         let secret_key = create_secret_key(16);
         let enc_sec_key = rsa_encode(&secret_key)?;
@@ -37,21 +55,38 @@ impl NetEaseMusicApi {
             debug!("No cookie provided, using anonymous access");
         }
 
+        if !options.headers.is_empty() {
+            debug!("Using {} custom headers", options.headers.len());
+        }
+        let (client, headers) = options.build()?;
+
         Ok(Self {
-            client: Client::builder()
-                .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-                .build()?,
+            client,
             secret_key,
             enc_sec_key,
             cookie,
+            headers,
+            base_url: NETEASE_BASE_URL.to_string(),
         })
     }
 
+    /// Send requests to `base_url` instead of `NETEASE_BASE_URL`, e.g. a
+    /// proxy or a mock server in tests
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Full URL of an API path
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
     /// Search for songs, albums, or playlists
     #[instrument(skip(self), fields(service = "netease"))]
     pub async fn search(&self, keyword: &str, search_type: SearchType) -> Result<ResultVo<SearchResultVo>> {
         info!("Searching for '{}' with type {:?}", keyword, search_type);
-        let url = "https://music.163.com/weapi/cloudsearch/get/web";
+        let url = self.url("/weapi/cloudsearch/get/web");
 
         // 1: song, 10: album, 1000: playlist
         let type_code = match search_type {
@@ -70,7 +105,7 @@ impl NetEaseMusicApi {
         });
 
         let prepared = self.prepare(&data.to_string())?;
-        let response = self.send_post(url, &prepared).await?;
+        let response = self.send_post(&url, &prepared).await?;
 
         let json_val: serde_json::Value = serde_json::from_str(&response)
             .map_err(|e| {
@@ -114,7 +149,7 @@ impl NetEaseMusicApi {
             return Ok(HashMap::new());
         }
 
-        let url = "https://music.163.com/weapi/v3/song/detail?csrf_token=";
+        let url = self.url("/weapi/v3/song/detail?csrf_token=");
         
         let songs: Vec<serde_json::Value> = song_ids
             .iter()
@@ -127,7 +162,7 @@ impl NetEaseMusicApi {
         });
 
         let prepared = self.prepare(&data.to_string())?;
-        let response = self.send_post(url, &prepared).await?;
+        let response = self.send_post(&url, &prepared).await?;
 
         let detail_result: DetailResult = serde_json::from_str(&response)
             .map_err(|e| {
@@ -152,7 +187,7 @@ impl NetEaseMusicApi {
     #[instrument(skip(self), fields(service = "netease"))]
     pub async fn get_playlist(&self, playlist_id: &str) -> Result<PlaylistResult> {
         info!("Fetching playlist: {}", playlist_id);
-        let url = "https://music.163.com/weapi/v6/playlist/detail?csrf_token=";
+        let url = self.url("/weapi/v6/playlist/detail?csrf_token=");
 
         let data = json!({
             "csrf_token": "",
@@ -164,7 +199,7 @@ impl NetEaseMusicApi {
         });
 
         let prepared = self.prepare(&data.to_string())?;
-        let response = self.send_post(url, &prepared).await?;
+        let response = self.send_post(&url, &prepared).await?;
 
         let result: PlaylistResult = serde_json::from_str(&response)
             .map_err(|e| {
@@ -185,7 +220,7 @@ impl NetEaseMusicApi {
     #[instrument(skip(self), fields(service = "netease"))]
     pub async fn get_album(&self, album_id: &str) -> Result<AlbumResult> {
         info!("Fetching album: {}", album_id);
-        let url = format!("https://music.163.com/weapi/v1/album/{}?csrf_token=", album_id);
+        let url = self.url(&format!("/weapi/v1/album/{}?csrf_token=", album_id));
 
         let data = json!({
             "csrf_token": ""
@@ -213,7 +248,7 @@ impl NetEaseMusicApi {
     #[instrument(skip(self), fields(service = "netease"))]
     pub async fn get_lyric(&self, song_id: &str) -> Result<LyricResult> {
         info!("Fetching lyrics for song: {}", song_id);
-        let url = "https://music.163.com/weapi/song/lyric?csrf_token=";
+        let url = self.url("/weapi/song/lyric?csrf_token=");
 
         let data = json!({
             "id": song_id,
@@ -229,7 +264,7 @@ impl NetEaseMusicApi {
         });
 
         let prepared = self.prepare(&data.to_string())?;
-        let response = self.send_post(url, &prepared).await?;
+        let response = self.send_post(&url, &prepared).await?;
 
         let result: LyricResult = serde_json::from_str(&response)
             .map_err(|e| {
@@ -243,21 +278,52 @@ impl NetEaseMusicApi {
         Ok(result)
     }
 
-    /// Get song URL
+    /// Get song URLs. With `bitrate`, only that quality is requested;
+    /// otherwise each of `SONG_URL_BITRATES` is tried in turn for the songs
+    /// that have no URL yet.
     #[instrument(skip(self), fields(service = "netease"))]
-    pub async fn get_song_url(&self, song_ids: &[String]) -> Result<HashMap<String, Datum>> {
+    pub async fn get_song_url(&self, song_ids: &[String], bitrate: Option<u32>) -> Result<HashMap<String, Datum>> {
         info!("Fetching song URLs for {} tracks", song_ids.len());
-        let url = "https://music.163.com/weapi/song/enhance/player/url?csrf_token=";
+        let bitrates = match bitrate {
+            Some(bitrate) => vec![bitrate],
+            None => SONG_URL_BITRATES.to_vec(),
+        };
+
+        fetch_with_bitrate_fallback(song_ids, &bitrates, |ids, bitrate| async move {
+            self.fetch_song_urls(&ids, bitrate).await
+        })
+        .await
+    }
+
+    /// Get a playable link for one song, falling back to lower bitrates
+    /// unless `bitrate` asks for a specific quality. [`MusicApi::get_song_link`]
+    /// is the same with the fallback.
+    ///
+    /// [`MusicApi::get_song_link`]: crate::MusicApi::get_song_link
+    pub async fn get_song_link_at(&self, song_id: &str, bitrate: Option<u32>) -> Result<ResultVo<String>> {
+        let datum_map = self.get_song_url(&[song_id.to_string()], bitrate).await?;
+
+        if let Some(url) = datum_map.get(song_id).and_then(|datum| datum.url.clone()) {
+            return Ok(ResultVo::success(url));
+        }
+
+        Ok(ResultVo::failure(error_msg::SONG_URL_GET_FAILED.to_string()))
+    }
+
+    /// Request song URLs at a single bitrate (in bits per second)
+    async fn fetch_song_urls(&self, song_ids: &[String], bitrate: u32) -> Result<HashMap<String, Datum>> {
+        debug!("Requesting {} song URLs at {} bps", song_ids.len(), bitrate);
+        let url = self.url("/weapi/song/enhance/player/url?csrf_token=");
 
         let ids_str = format!("[{}]", song_ids.join(","));
         let data = json!({
             "ids": ids_str,
-            "br": "999000",
+            "br": bitrate.to_string(),
             "csrf_token": ""
         });
 
         let prepared = self.prepare(&data.to_string())?;
-        let response = self.send_post(url, &prepared).await?;
+        let response = self.send_post(&url, &prepared).await?;
 
         let song_urls: SongUrls = serde_json::from_str(&response)
             .map_err(|e| {
@@ -293,21 +359,60 @@ impl NetEaseMusicApi {
         debug!("POST request to: {}", url);
         debug!("Parameters count: {}", data.len());
         
+        let response = self.post_request(url).form(data).send().await?;
+        let text = response.text().await?;
+        debug!("Response received, length: {} bytes", text.len());
+        Ok(text)
+    }
+
+    /// POST request with the Referer, cookie and custom headers set
+    fn post_request(&self, url: &str) -> RequestBuilder {
         let mut req = self.client
             .post(url)
-            .header("Referer", "https://music.163.com/")
-            .form(data);
+            .header("Referer", "https://music.163.com/");
 
         if let Some(cookie) = &self.cookie {
             req = req.header("Cookie", cookie);
             debug!("Using cookie for authentication");
         }
 
-        let response = req.send().await?;
-        let text = response.text().await?;
-        debug!("Response received, length: {} bytes", text.len());
-        Ok(text)
+        req.headers(self.headers.clone())
+    }
+}
+
+/// Fetch URLs at each bitrate in turn, re-requesting only the songs whose URL
+/// is still missing. A URL found at a higher bitrate is never replaced.
+async fn fetch_with_bitrate_fallback<F, Fut>(
+    song_ids: &[String],
+    bitrates: &[u32],
+    mut fetch: F,
+) -> Result<HashMap<String, Datum>>
+where
+    F: FnMut(Vec<String>, u32) -> Fut,
+    Fut: std::future::Future<Output = Result<HashMap<String, Datum>>>,
+{
+    let mut result: HashMap<String, Datum> = HashMap::new();
+    let mut pending = song_ids.to_vec();
+
+    for &bitrate in bitrates {
+        if pending.is_empty() {
+            break;
+        }
+
+        let fetched = fetch(pending.clone(), bitrate).await?;
+        for (id, datum) in fetched {
+            if datum.url.is_some() || !result.contains_key(&id) {
+                result.insert(id, datum);
+            }
+        }
+        pending.retain(|id| result.get(id).is_none_or(|datum| datum.url.is_none()));
+
+        if !pending.is_empty() {
+            debug!("{} songs have no URL at {} bps", pending.len(), bitrate);
+        }
     }
+
+    Ok(result)
 }
 
 fn aes_encode(secret_data: &str, secret: &str) -> Result<String> {
@@ -376,6 +481,64 @@ mod tests {
     }
 
     #[tokio::test]
+    async fn test_custom_headers_are_sent() {
+        let options = HttpOptions::default()
+            .with_user_agent("custom-agent/1.0")
+            .with_header("X-Real-IP", "1.2.3.4")
+            .with_header("Referer", "https://example.com/");
+        let api = NetEaseMusicApi::with_options(Some("MUSIC_U=abc".to_string()), options).unwrap();
+
+        let request = crate::http::capture_request(|url| async move {
+            api.send_post(&url, &HashMap::new()).await.unwrap();
+        })
+        .await;
+
+        assert!(request.contains("user-agent: custom-agent/1.0"));
+        assert!(request.contains("x-real-ip: 1.2.3.4"));
+        assert!(request.contains("referer: https://example.com/"));
+        assert!(!request.contains("referer: https://music.163.com/"));
+        assert!(request.contains("cookie: music_u=abc"));
+    }
+
+    #[tokio::test]
+    async fn test_bitrate_fallback() {
+        let ids = vec!["1".to_string(), "2".to_string(), "3".to_string()];
+        let mut requests = Vec::new();
+
+        // Song 1 exists in lossless, song 2 only at 128k, song 3 never
+        let result = fetch_with_bitrate_fallback(&ids, &SONG_URL_BITRATES, |ids, bitrate| {
+            requests.push((ids.clone(), bitrate));
+            async move {
+                Ok(ids
+                    .into_iter()
+                    .map(|id| {
+                        let url = match (id.as_str(), bitrate) {
+                            ("1", 999000) | ("2", 128000) => Some(format!("https://cdn/{}/{}", id, bitrate)),
+                            _ => None,
+                        };
+                        (id.clone(), Datum { id, url })
+                    })
+                    .collect())
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(result["1"].url.as_deref(), Some("https://cdn/1/999000"));
+        assert_eq!(result["2"].url.as_deref(), Some("https://cdn/2/128000"));
+        assert_eq!(result["3"].url, None);
+        assert_eq!(
+            requests,
+            [
+                (ids.clone(), 999000),
+                (vec!["2".to_string(), "3".to_string()], 320000),
+                (vec!["2".to_string(), "3".to_string()], 128000),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "hits the live NetEase API; tests/mock_server.rs covers search offline"]
     async fn test_search() {
         let api = NetEaseMusicApi::new(None).unwrap();
         let result = api.search("告白气球", SearchType::SongId).await;
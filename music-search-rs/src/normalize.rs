@@ -0,0 +1,181 @@
+/// Words that mark a featured-artist credit ("feat. Y", "ft. Y", "featuring Y").
+/// Undotted "feat" is left alone so names like "Little Feat" survive.
+const FEATURING_MARKERS: &[&str] = &["feat.", "ft.", "featuring"];
+
+/// Words that mark a bracketed or dash-separated release annotation
+/// ("[2021 Remaster]", "(Radio Edit)", "- Mono Version")
+const VERSION_WORDS: &[&str] = &[
+    "remaster",
+    "remastered",
+    "version",
+    "ver.",
+    "edit",
+    "mono",
+    "stereo",
+    "explicit",
+    "deluxe",
+    "anniversary",
+    "bonus",
+];
+
+/// Cleans titles and artist names before searching and matching, so tags like
+/// "Song (feat. Y) [2021 Remaster]" match a provider's plain "Song".
+///
+/// Whitespace is always collapsed; each kind of annotation can be kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TitleNormalizer {
+    /// Drop featured-artist credits, bracketed or trailing
+    pub strip_featuring: bool,
+    /// Drop bracketed or " - " suffixed remaster/version/edit annotations
+    pub strip_version_tags: bool,
+}
+
+impl Default for TitleNormalizer {
+    fn default() -> Self {
+        Self {
+            strip_featuring: true,
+            strip_version_tags: true,
+        }
+    }
+}
+
+impl TitleNormalizer {
+    /// Normalize a title or artist name. Falls back to the whitespace-collapsed
+    /// input when stripping would leave nothing (e.g. a title that is only
+    /// "(Remastered)").
+    pub fn normalize(&self, value: &str) -> String {
+        let collapsed = collapse_whitespace(value);
+        let mut result = String::new();
+        let mut rest = collapsed.as_str();
+
+        while let Some(start) = rest.find(|c| closing_bracket(c).is_some()) {
+            let open = rest[start..].chars().next().unwrap();
+            let close = closing_bracket(open).unwrap();
+            let inner_start = start + open.len_utf8();
+            let Some(inner_len) = rest[inner_start..].find(close) else {
+                break;
+            };
+            let inner = &rest[inner_start..inner_start + inner_len];
+
+            result.push_str(&rest[..start]);
+            if !self.is_annotation(inner) {
+                result.push_str(&rest[start..inner_start + inner_len + close.len_utf8()]);
+            }
+            rest = &rest[inner_start + inner_len + close.len_utf8()..];
+        }
+        result.push_str(rest);
+
+        let mut result = collapse_whitespace(&result);
+        if self.strip_featuring {
+            if let Some(index) = featuring_start(&result) {
+                result.truncate(index);
+            }
+        }
+        if self.strip_version_tags {
+            if let Some((head, tail)) = result.rsplit_once(" - ") {
+                if has_version_word(tail) {
+                    result = head.to_string();
+                }
+            }
+        }
+
+        let result = collapse_whitespace(&result);
+        if result.is_empty() {
+            collapsed
+        } else {
+            result
+        }
+    }
+
+    /// Whether the contents of a bracketed segment should be dropped
+    fn is_annotation(&self, inner: &str) -> bool {
+        (self.strip_featuring && featuring_start(inner) == Some(0))
+            || (self.strip_version_tags && has_version_word(inner))
+    }
+}
+
+/// Normalize a title or artist name with the default [`TitleNormalizer`]
+pub fn normalize_title(value: &str) -> String {
+    TitleNormalizer::default().normalize(value)
+}
+
+/// Closing counterpart of an opening bracket, including full-width ones
+fn closing_bracket(open: char) -> Option<char> {
+    match open {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '（' => Some('）'),
+        '【' => Some('】'),
+        _ => None,
+    }
+}
+
+fn collapse_whitespace(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Byte offset of the word that starts a featured-artist credit, including
+/// an unclosed bracket in front of it
+fn featuring_start(value: &str) -> Option<usize> {
+    let mut offset = 0;
+    for word in value.split(' ') {
+        let marker = word.trim_start_matches(|c| closing_bracket(c).is_some());
+        if FEATURING_MARKERS.contains(&marker.to_lowercase().as_str()) {
+            return Some(offset);
+        }
+        offset += word.len() + 1;
+    }
+    None
+}
+
+fn has_version_word(value: &str) -> bool {
+    value
+        .split_whitespace()
+        .any(|word| VERSION_WORDS.contains(&word.to_lowercase().as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_title() {
+        assert_eq!(normalize_title("Song (feat. Y) [2021 Remaster]"), "Song");
+        assert_eq!(normalize_title("  Song   ft. Y & Z "), "Song");
+        assert_eq!(normalize_title("Song - 2011 Remastered Version"), "Song");
+        assert_eq!(normalize_title("Song (Radio Edit)"), "Song");
+        assert_eq!(normalize_title("歌名（feat. 某人）"), "歌名");
+        assert_eq!(normalize_title("Artist Featuring Guest"), "Artist");
+    }
+
+    #[test]
+    fn test_normalize_title_keeps_other_text() {
+        assert_eq!(normalize_title("Song (Part 2)"), "Song (Part 2)");
+        assert_eq!(normalize_title("Left - Right"), "Left - Right");
+        assert_eq!(normalize_title("Draft Punk"), "Draft Punk");
+        assert_eq!(normalize_title("Little Feat"), "Little Feat");
+        assert_eq!(normalize_title("(Remastered)"), "(Remastered)");
+        assert_eq!(normalize_title("Unclosed (feat. Y"), "Unclosed");
+    }
+
+    #[test]
+    fn test_normalizer_options() {
+        let keep_featuring = TitleNormalizer {
+            strip_featuring: false,
+            ..TitleNormalizer::default()
+        };
+        assert_eq!(
+            keep_featuring.normalize("Song (feat. Y) [2021 Remaster]"),
+            "Song (feat. Y)"
+        );
+
+        let keep_versions = TitleNormalizer {
+            strip_version_tags: false,
+            ..TitleNormalizer::default()
+        };
+        assert_eq!(
+            keep_versions.normalize("Song (feat. Y) [2021 Remaster]"),
+            "Song [2021 Remaster]"
+        );
+    }
+}
@@ -1,32 +1,72 @@
 use crate::error::{MusicSearchError, Result};
+use crate::http::HttpOptions;
 use crate::models::*;
 use crate::qqmusic::decrypt::decrypt_lyrics;
 use crate::qqmusic::models::*;
 use quick_xml::events::Event;
 use quick_xml::Reader;
 use rand::Rng;
-use reqwest::Client;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, RequestBuilder};
 use serde_json::json;
 use std::collections::HashMap;
 use tracing::{debug, error, info, instrument, warn};
 
+/// Host of QQ Music's `musicu.fcg` API (search, song links); see `QQMusicApi::with_base_url`
+pub const QQ_MUSICU_BASE_URL: &str = "https://u.y.qq.com";
+/// Host of QQ Music's older `c.y.qq.com` APIs (songs, albums, playlists, lyrics)
+pub const QQ_C_BASE_URL: &str = "https://c.y.qq.com";
+
 pub struct QQMusicApi {
     client: Client,
     cookie: Option<String>,
+    /// Extra headers from `HttpOptions`, applied after the built-in ones
+    headers: HeaderMap,
+    /// Scheme and host of the `musicu.fcg` API, without a trailing slash
+    musicu_base_url: String,
+    /// Scheme and host of the `c.y.qq.com` APIs, without a trailing slash
+    c_base_url: String,
 }
 
 impl QQMusicApi {
     pub fn new(cookie: Option<String>) -> Result<Self> {
+        Self::with_options(cookie, HttpOptions::default())
+    }
+
+    /// Create a client that sends the extra headers and User-Agent in `options`
+    pub fn with_options(cookie: Option<String>, options: HttpOptions) -> Result<Self> {
         info!("Initializing QQ Music API client");
         debug!("Cookie provided: {}", cookie.is_some());
+        debug!("Custom headers: {}", options.headers.len());
+        let (client, headers) = options.build()?;
         Ok(Self {
-            client: Client::builder()
-                .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
-                .build()?,
+            client,
             cookie,
+            headers,
+            musicu_base_url: QQ_MUSICU_BASE_URL.to_string(),
+            c_base_url: QQ_C_BASE_URL.to_string(),
         })
     }
 
+    /// Send the requests for both QQ Music hosts to `base_url`, e.g. a proxy
+    /// or a mock server in tests
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        self.musicu_base_url = base_url.clone();
+        self.c_base_url = base_url;
+        self
+    }
+
+    /// Full URL of a `musicu.fcg`-host path
+    fn musicu_url(&self, path: &str) -> String {
+        format!("{}{}", self.musicu_base_url, path)
+    }
+
+    /// Full URL of a `c.y.qq.com` path
+    fn c_url(&self, path: &str) -> String {
+        format!("{}{}", self.c_base_url, path)
+    }
+
     /// Search for songs, albums, or playlists
     #[instrument(skip(self), fields(service = "qqmusic"))]
     pub async fn search(&self, keyword: &str, search_type: SearchType) -> Result<ResultVo<SearchResultVo>> {
@@ -54,7 +94,7 @@ impl QQMusicApi {
             }
         });
 
-        let response = self.send_json_post("https://u.y.qq.com/cgi-bin/musicu.fcg", &data).await?;
+        let response = self.send_json_post(&self.musicu_url("/cgi-bin/musicu.fcg"), &data).await?;
         debug!("Received response, length: {} bytes", response.len());
         
         let result: MusicFcgApiResult = serde_json::from_str(&response)
@@ -102,7 +142,7 @@ impl QQMusicApi {
         params.insert("platform", "yqq");
         params.insert("needNewCode", "0");
 
-        let response = self.send_post("https://c.y.qq.com/v8/fcg-bin/fcg_play_single_song.fcg", &params).await?;
+        let response = self.send_post(&self.c_url("/v8/fcg-bin/fcg_play_single_song.fcg"), &params).await?;
         let json_str = resolve_resp_json(callback, &response);
         debug!("Parsed JSON response length: {} bytes", json_str.len());
         
@@ -130,7 +170,7 @@ impl QQMusicApi {
         params.insert("onlysong", "0");
         params.insert("new_format", "1");
 
-        let response = self.send_post("https://c.y.qq.com/qzone/fcg-bin/fcg_ucc_getcdinfo_byids_cp.fcg", &params).await?;
+        let response = self.send_post(&self.c_url("/qzone/fcg-bin/fcg_ucc_getcdinfo_byids_cp.fcg"), &params).await?;
         let result: PlaylistResult = serde_json::from_str(&response)
             .map_err(|e| {
                 error!("Failed to parse playlist response: {}", e);
@@ -157,7 +197,7 @@ impl QQMusicApi {
             params.insert("albummid", album_id);
         }
 
-        let response = self.send_post("https://c.y.qq.com/v8/fcg-bin/fcg_v8_album_info_cp.fcg", &params).await?;
+        let response = self.send_post(&self.c_url("/v8/fcg-bin/fcg_v8_album_info_cp.fcg"), &params).await?;
         let result: AlbumResult = serde_json::from_str(&response)
             .map_err(|e| {
                 error!("Failed to parse album response: {}", e);
@@ -178,7 +218,7 @@ impl QQMusicApi {
         params.insert("lrctype", "4");
         params.insert("musicid", song_id);
 
-        let mut response = self.send_post("https://c.y.qq.com/qqmusic/fcgi-bin/lyric_download.fcg", &params).await?;
+        let mut response = self.send_post(&self.c_url("/qqmusic/fcgi-bin/lyric_download.fcg"), &params).await?;
         debug!("Received lyrics response, length: {} bytes", response.len());
         debug!("Response preview (first 500 chars): {}", 
             response.chars().take(500).collect::<String>());
@@ -306,7 +346,7 @@ impl QQMusicApi {
             }
         });
 
-        let response = self.send_json_post("https://u.y.qq.com/cgi-bin/musicu.fcg", &data).await?;
+        let response = self.send_json_post(&self.musicu_url("/cgi-bin/musicu.fcg"), &data).await?;
         debug!("Received song link response, length: {} bytes", response.len());
         
         let json_val: serde_json::Value = serde_json::from_str(&response)
@@ -347,17 +387,7 @@ impl QQMusicApi {
         debug!("POST request to: {}", url);
         debug!("Parameters count: {}", params.len());
         
-        let mut req = self.client
-            .post(url)
-            .header("Referer", "https://c.y.qq.com/")
-            .form(params);
-
-        if let Some(cookie) = &self.cookie {
-            req = req.header("Cookie", cookie);
-            debug!("Using cookie for authentication");
-        }
-
-        let response = req.send().await?;
+        let response = self.post_request(url).form(params).send().await?;
         let text = response.text().await?;
         debug!("Response received, length: {} bytes", text.len());
         Ok(text)
@@ -366,20 +396,24 @@ impl QQMusicApi {
     async fn send_json_post(&self, url: &str, data: &serde_json::Value) -> Result<String> {
         debug!("JSON POST request to: {}", url);
         
+        let response = self.post_request(url).json(data).send().await?;
+        let text = response.text().await?;
+        debug!("JSON response received, length: {} bytes", text.len());
+        Ok(text)
+    }
+
+    /// POST request with the Referer, cookie and custom headers set
+    fn post_request(&self, url: &str) -> RequestBuilder {
         let mut req = self.client
             .post(url)
-            .header("Referer", "https://c.y.qq.com/")
-            .json(data);
+            .header("Referer", "https://c.y.qq.com/");
 
         if let Some(cookie) = &self.cookie {
             req = req.header("Cookie", cookie);
             debug!("Using cookie for authentication");
         }
 
-        let response = req.send().await?;
-        let text = response.text().await?;
-        debug!("JSON response received, length: {} bytes", text.len());
-        Ok(text)
+        req.headers(self.headers.clone())
     }
 
     fn get_guid(&self) -> String {
@@ -509,12 +543,29 @@ mod tests {
     use super::*;
 
     #[tokio::test]
+    #[ignore = "hits the live QQ Music API; tests/mock_server.rs covers search offline"]
     async fn test_search() {
         let api = QQMusicApi::new(None).unwrap();
         let result = api.search("告白气球", SearchType::SongId).await;
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_custom_headers_are_sent() {
+        let options = HttpOptions::default().with_header("X-Forwarded-For", "1.2.3.4");
+        let api = QQMusicApi::with_options(None, options).unwrap();
+
+        let request = crate::http::capture_request(|url| async move {
+            api.send_json_post(&url, &json!({})).await.unwrap();
+        })
+        .await;
+
+        assert!(request.contains("x-forwarded-for: 1.2.3.4"));
+        // Defaults are kept when not overridden
+        assert!(request.contains("referer: https://c.y.qq.com/"));
+        assert!(request.contains("user-agent: mozilla/5.0"));
+    }
+
     #[test]
     fn test_resolve_resp_json() {
         let input = "callback({\"data\": \"test\"})";
@@ -0,0 +1,202 @@
+//! Offline tests for the NetEase and QQ Music clients, run against a local
+//! mock server serving recorded responses from `tests/fixtures`
+
+use music_search_rs::{MusicApi, NetEaseMusicApi, QQMusicApi, SearchSource, SearchType};
+use wiremock::matchers::{body_string_contains, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const NETEASE_SEARCH: &str = include_str!("fixtures/netease_search.json");
+const NETEASE_SONG_DETAIL: &str = include_str!("fixtures/netease_song_detail.json");
+const NETEASE_LYRIC: &str = include_str!("fixtures/netease_lyric.json");
+const QQMUSIC_SEARCH: &str = include_str!("fixtures/qqmusic_search.json");
+const QQMUSIC_SONG: &str = include_str!("fixtures/qqmusic_song.jsonp");
+const QQMUSIC_LYRIC: &str = include_str!("fixtures/qqmusic_lyric.xml");
+
+/// Serve `body` once for POST requests to `route` on `server`
+async fn mount_post(server: &MockServer, route: &str, body: &str) {
+    Mock::given(method("POST"))
+        .and(path(route))
+        .respond_with(ResponseTemplate::new(200).set_body_string(body))
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+fn netease_api(server: &MockServer) -> NetEaseMusicApi {
+    NetEaseMusicApi::new(None).unwrap().with_base_url(server.uri())
+}
+
+fn qqmusic_api(server: &MockServer) -> QQMusicApi {
+    QQMusicApi::new(None).unwrap().with_base_url(server.uri())
+}
+
+#[tokio::test]
+async fn test_netease_search() {
+    let server = MockServer::start().await;
+    // Request bodies are encrypted, so only the form fields can be checked
+    Mock::given(method("POST"))
+        .and(path("/weapi/cloudsearch/get/web"))
+        .and(header("Referer", "https://music.163.com/"))
+        .and(body_string_contains("encSecKey="))
+        .respond_with(ResponseTemplate::new(200).set_body_string(NETEASE_SEARCH))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let api = netease_api(&server);
+    let result = api.search("告白气球", SearchType::SongId).await.unwrap();
+
+    assert!(result.success);
+    let data = result.data.unwrap();
+    assert_eq!(data.search_source, SearchSource::NetEaseMusic);
+    assert_eq!(data.song_vos.len(), 2);
+    let song = &data.song_vos[0];
+    assert_eq!(song.display_id, "418603077");
+    assert_eq!(song.title, "告白气球");
+    assert_eq!(song.author_name, ["周杰伦"]);
+    assert_eq!(song.album_name, "周杰伦的床边故事");
+    assert_eq!(song.duration, 215146);
+}
+
+#[tokio::test]
+async fn test_netease_get_songs() {
+    let server = MockServer::start().await;
+    mount_post(&server, "/weapi/v3/song/detail", NETEASE_SONG_DETAIL).await;
+
+    let api = netease_api(&server);
+    let ids = vec!["418603077".to_string(), "1".to_string()];
+    let songs = MusicApi::get_songs(&api, &ids).await.unwrap();
+
+    let song = songs["418603077"].data.as_ref().unwrap();
+    assert_eq!(song.name, "告白气球");
+    assert_eq!(song.singer, ["周杰伦"]);
+    assert_eq!(song.album, "周杰伦的床边故事");
+    assert!(song.pics.ends_with("3265549553028224.jpg"));
+    assert_eq!(song.duration, 215146);
+    // Songs missing from the response are reported per ID
+    assert!(!songs["1"].success);
+}
+
+#[tokio::test]
+async fn test_netease_get_lyric() {
+    let server = MockServer::start().await;
+    mount_post(&server, "/weapi/song/lyric", NETEASE_LYRIC).await;
+
+    let api = netease_api(&server);
+    let result = MusicApi::get_lyric(&api, "418603077", "418603077", false)
+        .await
+        .unwrap();
+
+    let lyric = result.data.unwrap();
+    assert_eq!(lyric.search_source, SearchSource::NetEaseMusic);
+    assert!(lyric.lyric.unwrap().contains("[00:25.950]塞纳河畔 左岸的咖啡"));
+    assert_eq!(lyric.translate_lyric.as_deref(), Some(""));
+}
+
+#[tokio::test]
+async fn test_netease_get_song_link() {
+    let server = MockServer::start().await;
+    mount_post(
+        &server,
+        "/weapi/song/enhance/player/url",
+        r#"{"code": 200, "data": [{"id": "418603077", "url": "https://m701.music.126.net/song.flac"}]}"#,
+    )
+    .await;
+
+    // The trait method is reachable by method syntax, with the bitrate fallback
+    let api = netease_api(&server);
+    let link = api.get_song_link("418603077").await.unwrap();
+    assert_eq!(link.data.as_deref(), Some("https://m701.music.126.net/song.flac"));
+}
+
+#[tokio::test]
+async fn test_netease_unexpected_body() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("<html>busy</html>"))
+        .mount(&server)
+        .await;
+
+    let api = netease_api(&server);
+    assert!(api.get_lyric("418603077").await.is_err());
+}
+
+#[tokio::test]
+async fn test_qqmusic_search() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/cgi-bin/musicu.fcg"))
+        .and(header("Referer", "https://c.y.qq.com/"))
+        .and(body_string_contains("DoSearchForQQMusicDesktop"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(QQMUSIC_SEARCH))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let api = qqmusic_api(&server);
+    let result = api.search("告白气球", SearchType::SongId).await.unwrap();
+
+    assert!(result.success);
+    let data = result.data.unwrap();
+    assert_eq!(data.search_source, SearchSource::QQMusic);
+    assert_eq!(data.song_vos.len(), 1);
+    let song = &data.song_vos[0];
+    assert_eq!(song.display_id, "107192078");
+    assert_eq!(song.title, "告白气球");
+    assert_eq!(song.author_name, ["周杰伦"]);
+    assert_eq!(song.album_name, "周杰伦的床边故事");
+    assert_eq!(song.duration, 215000);
+}
+
+#[tokio::test]
+async fn test_qqmusic_get_songs() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v8/fcg-bin/fcg_play_single_song.fcg"))
+        .and(body_string_contains("songmid=003OUlho2HcRHC"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(QQMUSIC_SONG))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let api = qqmusic_api(&server);
+    let ids = vec!["003OUlho2HcRHC".to_string()];
+    let songs = MusicApi::get_songs(&api, &ids).await.unwrap();
+
+    let song = songs["003OUlho2HcRHC"].data.as_ref().unwrap();
+    assert_eq!(song.id, "107192078");
+    assert_eq!(song.display_id, "003OUlho2HcRHC");
+    assert_eq!(song.name, "告白气球");
+    assert_eq!(song.singer, ["周杰伦"]);
+    assert_eq!(
+        song.pics,
+        "https://y.qq.com/music/photo_new/T002R800x800M000003RMaRI1iFoYd_1.jpg"
+    );
+    assert_eq!(song.duration, 215000);
+}
+
+#[tokio::test]
+async fn test_qqmusic_get_lyric() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/qqmusic/fcgi-bin/lyric_download.fcg"))
+        .and(body_string_contains("musicid=200255722"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(QQMUSIC_LYRIC))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let api = qqmusic_api(&server);
+    let result = MusicApi::get_lyric(&api, "200255722", "0025S6Ym4NcmlQ", false)
+        .await
+        .unwrap();
+
+    let lyric = result.data.unwrap();
+    assert_eq!(lyric.search_source, SearchSource::QQMusic);
+    // The fixture's `<content>` is encrypted and compressed QRC
+    let text = lyric.lyric.unwrap();
+    assert!(text.starts_with("[ti:唯一]"));
+    assert!(text.contains("[ar:G.E.M. 邓紫棋]"));
+    assert!(lyric.translate_lyric.is_none());
+    assert!(lyric.transliteration_lyric.is_none());
+}
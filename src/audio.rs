@@ -1,3 +1,4 @@
+use crate::lyrics::LyricFormat;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -12,13 +13,51 @@ pub struct MetadataUpdate {
     pub album_artist: Option<String>,
     pub genre: Option<String>,
     pub year: Option<String>,
+    /// Track number, or `N/M` to set the total as well
     pub track_number: Option<String>,
+    /// Number of tracks on the disc; takes precedence over a total in `track_number`
+    pub track_total: Option<String>,
+    /// Disc number, or `N/M` to set the total as well
     pub disc_number: Option<String>,
+    /// Number of discs in the release; takes precedence over a total in `disc_number`
+    pub disc_total: Option<String>,
     pub composer: Option<String>,
     pub comment: Option<String>,
     pub custom_fields: Option<HashMap<String, String>>,
 }
 
+impl MetadataUpdate {
+    /// Track number and total to write, from `track_number` and `track_total`
+    fn track(&self) -> (Option<String>, Option<String>) {
+        number_and_total(self.track_number.as_deref(), self.track_total.as_deref())
+    }
+
+    /// Disc number and total to write, from `disc_number` and `disc_total`
+    fn disc(&self) -> (Option<String>, Option<String>) {
+        number_and_total(self.disc_number.as_deref(), self.disc_total.as_deref())
+    }
+}
+
+/// Split a combined `N/M` number and total, as ID3 `TRCK`/`TPOS` and many
+/// Vorbis comments store them. Either side may be missing: `"3"` has no
+/// total and the malformed `"/5"` has no number.
+pub fn split_number_total(value: &str) -> (Option<String>, Option<String>) {
+    let non_empty = |part: &str| Some(part.trim().to_string()).filter(|part| !part.is_empty());
+    match value.split_once('/') {
+        Some((number, total)) => (non_empty(number), non_empty(total)),
+        None => (non_empty(value), None),
+    }
+}
+
+/// Number and total of an update; an explicit `total` wins over one in `number`
+fn number_and_total(number: Option<&str>, total: Option<&str>) -> (Option<String>, Option<String>) {
+    let (number, combined_total) = number.map(split_number_total).unwrap_or_default();
+    let total = total
+        .map(|total| total.trim().to_string())
+        .or(combined_total);
+    (number, total)
+}
+
 /// Metadata extracted from an audio file
 #[derive(Debug, Clone, Serialize)]
 pub struct AudioMetadata {
@@ -29,14 +68,40 @@ pub struct AudioMetadata {
     pub genre: Option<String>,
     pub year: Option<String>,
     pub track_number: Option<String>,
+    /// Number of tracks on the disc, from `N/M` track numbers or a separate total tag
+    pub track_total: Option<String>,
     pub disc_number: Option<String>,
+    /// Number of discs in the release, from `N/M` disc numbers or a separate total tag
+    pub disc_total: Option<String>,
     pub composer: Option<String>,
     pub comment: Option<String>,
     pub duration_secs: Option<u64>,
+    /// Set by a `COMPILATION` / `TCMP` / `cpil` tag
+    pub compilation: bool,
+    /// Audio is stored with a lossless codec (FLAC, ALAC, WavPack or APE)
+    pub lossless: bool,
+    /// Sample rate in Hz
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u8>,
+    /// Bit depth of lossless and PCM streams; lossy codecs have none
+    pub bits_per_sample: Option<u8>,
+    /// Average bitrate over the audio data of the file
+    pub bitrate_kbps: Option<u32>,
     pub custom_fields: HashMap<String, String>,
 }
 
 impl AudioMetadata {
+    /// ReplayGain adjustment in dB from a `REPLAYGAIN_<scope>_GAIN` tag (Vorbis
+    /// comment, ID3 `TXXX`, APE item or MP4 freeform atom), where `scope` is
+    /// `TRACK` or `ALBUM`
+    pub fn replay_gain(&self, scope: &str) -> Option<f64> {
+        let key = format!("REPLAYGAIN_{}_GAIN", scope);
+        self.custom_fields
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(&key))
+            .and_then(|(_, value)| parse_replay_gain(value))
+    }
+
     pub fn new() -> Self {
         AudioMetadata {
             title: None,
@@ -46,13 +111,64 @@ impl AudioMetadata {
             genre: None,
             year: None,
             track_number: None,
+            track_total: None,
             disc_number: None,
+            disc_total: None,
             composer: None,
             comment: None,
             duration_secs: None,
+            compilation: false,
+            lossless: false,
+            sample_rate: None,
+            channels: None,
+            bits_per_sample: None,
+            bitrate_kbps: None,
             custom_fields: HashMap::new(),
         }
     }
+
+    /// Fill in the sample rate, channels and bit depth from a track's codec
+    /// parameters, and the bitrate from the file's audio bytes over the
+    /// duration (Symphonia doesn't report bitrates). Call once
+    /// `duration_secs` is known.
+    fn set_stream_properties(
+        &mut self,
+        track: Option<&symphonia::core::formats::Track>,
+        path: &Path,
+    ) {
+        if let Some(params) = track.map(|track| &track.codec_params) {
+            self.sample_rate = params.sample_rate;
+            self.channels = params.channels.map(|channels| channels.count() as u8);
+            self.bits_per_sample = params.bits_per_sample.map(|bits| bits as u8);
+        }
+
+        let seconds = track
+            .and_then(track_duration_exact_secs)
+            .or(self.duration_secs.map(|secs| secs as f64));
+        self.bitrate_kbps = seconds.and_then(|seconds| {
+            let audio_bytes = audio_data_len(path).ok()?;
+            average_bitrate_kbps(audio_bytes, seconds)
+        });
+    }
+    /// Set the track number from a tag that may hold `N/M`; a total is only
+    /// replaced when the tag has one, so a separate total tag read earlier stays
+    pub fn set_track_number(&mut self, value: &str) {
+        let (number, total) = split_number_total(value);
+        self.track_number = number;
+        if total.is_some() {
+            self.track_total = total;
+        }
+    }
+
+    /// Set the disc number from a tag that may hold `N/M`, like [`Self::set_track_number`]
+    pub fn set_disc_number(&mut self, value: &str) {
+        let (number, total) = split_number_total(value);
+        self.disc_number = number;
+        if total.is_some() {
+            self.disc_total = total;
+        }
+    }
+
     pub fn update_from_std_key(
         &mut self,
         std_key: symphonia::core::meta::StandardTagKey,
@@ -63,16 +179,383 @@ impl AudioMetadata {
             symphonia::core::meta::StandardTagKey::Artist => self.artist = Some(value),
             symphonia::core::meta::StandardTagKey::Album => self.album = Some(value),
             symphonia::core::meta::StandardTagKey::AlbumArtist => self.album_artist = Some(value),
+            symphonia::core::meta::StandardTagKey::Compilation => {
+                self.compilation = is_tag_flag_set(&value)
+            }
             symphonia::core::meta::StandardTagKey::Genre => self.genre = Some(value),
             symphonia::core::meta::StandardTagKey::Date => self.year = Some(value),
-            symphonia::core::meta::StandardTagKey::TrackNumber => self.track_number = Some(value),
-            symphonia::core::meta::StandardTagKey::DiscNumber => self.disc_number = Some(value),
+            symphonia::core::meta::StandardTagKey::TrackNumber => self.set_track_number(&value),
+            symphonia::core::meta::StandardTagKey::TrackTotal => self.track_total = Some(value),
+            symphonia::core::meta::StandardTagKey::DiscNumber => self.set_disc_number(&value),
+            symphonia::core::meta::StandardTagKey::DiscTotal => self.disc_total = Some(value),
             symphonia::core::meta::StandardTagKey::Composer => self.composer = Some(value),
             symphonia::core::meta::StandardTagKey::Comment => self.comment = Some(value),
             _ => {}
         }
     }
 }
+
+/// `mean` namespace used for M4A freeform (`----`) atoms holding custom fields
+const M4A_FREEFORM_MEAN: &str = "com.apple.iTunes";
+
+/// Upper bound for a plausible track duration (24 hours).
+/// Corrupt headers can report absurd frame counts; anything above this is discarded.
+pub const MAX_DURATION_SECS: u64 = 24 * 60 * 60;
+
+/// Compute a track's duration from its codec parameters, rejecting implausible values
+fn track_duration_secs(track: &symphonia::core::formats::Track) -> Option<u64> {
+    let time_base = track.codec_params.time_base?;
+    let n_frames = track.codec_params.n_frames?;
+    plausible_duration(time_base.calc_time(n_frames).seconds)
+}
+
+/// A track's duration in fractional seconds, for averaging its bitrate
+fn track_duration_exact_secs(track: &symphonia::core::formats::Track) -> Option<f64> {
+    let time_base = track.codec_params.time_base?;
+    let n_frames = track.codec_params.n_frames?;
+    let time = time_base.calc_time(n_frames);
+    plausible_duration(time.seconds)?;
+    Some(time.seconds as f64 + time.frac)
+}
+
+/// Bytes of a file's audio data: the `mdat` atoms of an MP4, the pages of an
+/// Ogg file after its header packets (comments and pictures included), or
+/// for other formats everything after the leading tags and metadata blocks
+fn audio_data_len(path: &Path) -> Result<u64> {
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(path).context("Failed to open audio file")?;
+    let file_len = file
+        .metadata()
+        .context("Failed to read file metadata")?
+        .len();
+    let mut magic = [0u8; 8];
+    if file.read_exact(&mut magic).is_ok() {
+        if &magic[..4] == b"OggS" {
+            return Ok(file_len.saturating_sub(ogg_audio_start(&mut file)?));
+        }
+        if &magic[4..] == b"ftyp" {
+            return mp4_mdat_len(&mut file, file_len);
+        }
+    }
+    Ok(file_len.saturating_sub(stream_layout(path)?.audio_start))
+}
+
+/// Offset of the first Ogg page holding audio, i.e. the first whose granule
+/// position is set: header pages have 0, and pages where no packet ends -1
+fn ogg_audio_start<R: std::io::Read + std::io::Seek>(reader: &mut R) -> Result<u64> {
+    use std::io::SeekFrom;
+
+    let mut offset = 0;
+    loop {
+        let mut header = [0u8; 27];
+        reader.seek(SeekFrom::Start(offset))?;
+        if reader.read_exact(&mut header).is_err() {
+            // No audio pages at all
+            return Ok(offset);
+        }
+        if &header[..4] != b"OggS" {
+            anyhow::bail!("Invalid Ogg page at byte {}", offset);
+        }
+        let granule = u64::from_le_bytes(header[6..14].try_into().unwrap());
+        if granule != 0 && granule != u64::MAX {
+            return Ok(offset);
+        }
+
+        let mut segments = vec![0u8; usize::from(header[26])];
+        reader
+            .read_exact(&mut segments)
+            .context("Truncated Ogg segment table")?;
+        let body_len: u64 = segments.iter().map(|&len| u64::from(len)).sum();
+        offset += 27 + segments.len() as u64 + body_len;
+    }
+}
+
+/// Total payload size of the top-level `mdat` atoms of an MP4 file
+fn mp4_mdat_len<R: std::io::Read + std::io::Seek>(reader: &mut R, file_len: u64) -> Result<u64> {
+    use std::io::SeekFrom;
+
+    let mut offset = 0;
+    let mut total = 0;
+    while offset + 8 <= file_len {
+        let mut header = [0u8; 8];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader
+            .read_exact(&mut header)
+            .context("Truncated MP4 atom header")?;
+        let mut header_len = 8;
+        let size = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+            // 64-bit size following the type
+            1 => {
+                let mut large = [0u8; 8];
+                reader
+                    .read_exact(&mut large)
+                    .context("Truncated MP4 atom header")?;
+                header_len = 16;
+                u64::from_be_bytes(large)
+            }
+            // Extends to the end of the file
+            0 => file_len - offset,
+            size => u64::from(size),
+        };
+        if size < header_len {
+            anyhow::bail!("Invalid MP4 atom size at byte {}", offset);
+        }
+        if &header[4..] == b"mdat" {
+            total += size - header_len;
+        }
+        offset += size;
+    }
+    Ok(total)
+}
+
+/// Average bitrate in kbit/s of `audio_bytes` played over `seconds`
+fn average_bitrate_kbps(audio_bytes: u64, seconds: f64) -> Option<u32> {
+    if seconds <= 0.0 || audio_bytes == 0 {
+        return None;
+    }
+    Some((audio_bytes as f64 * 8.0 / seconds / 1000.0).round() as u32)
+}
+
+/// Whether a track's codec is lossless. Containers such as Ogg and MP4 can hold
+/// either kind, so this looks at the codec rather than the file extension.
+fn is_lossless_codec(track: &symphonia::core::formats::Track) -> bool {
+    use symphonia::core::codecs::{CODEC_TYPE_ALAC, CODEC_TYPE_FLAC, CODEC_TYPE_WAVPACK};
+
+    matches!(
+        track.codec_params.codec,
+        CODEC_TYPE_FLAC | CODEC_TYPE_ALAC | CODEC_TYPE_WAVPACK
+    )
+}
+
+/// Discard durations above `MAX_DURATION_SECS`
+fn plausible_duration(seconds: u64) -> Option<u64> {
+    if seconds > MAX_DURATION_SECS {
+        tracing::warn!("Ignoring implausible duration of {} seconds", seconds);
+        return None;
+    }
+    Some(seconds)
+}
+
+/// Bitrates in kbps indexed by the 4-bit header field, for
+/// [MPEG-1 Layer I, II, III] and [MPEG-2/2.5 Layer I, II/III]
+const MP3_BITRATES: [[u32; 15]; 5] = [
+    [
+        0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// Bitrate in kbps of an MPEG audio frame header, or `None` if it is not a valid header
+fn mp3_header_bitrate(header: [u8; 4]) -> Option<u32> {
+    if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+
+    let version = (header[1] >> 3) & 0x03;
+    let layer = (header[1] >> 1) & 0x03;
+    let bitrate_index = (header[2] >> 4) as usize;
+    let sample_rate_index = (header[2] >> 2) & 0x03;
+
+    if version == 1 || layer == 0 || bitrate_index == 0 || bitrate_index == 15 {
+        return None;
+    }
+    if sample_rate_index == 3 {
+        return None;
+    }
+
+    // layer bits: 3 = Layer I, 2 = Layer II, 1 = Layer III
+    let table = match (version, layer) {
+        (3, 3) => 0,
+        (3, 2) => 1,
+        (3, _) => 2,
+        (_, 3) => 3,
+        _ => 4,
+    };
+    Some(MP3_BITRATES[table][bitrate_index])
+}
+
+/// Length of a leading ID3v2 tag, or 0 if the stream doesn't start with one
+fn id3v2_tag_len<R: std::io::Read + std::io::Seek>(reader: &mut R) -> Result<u64> {
+    use std::io::SeekFrom;
+
+    let mut id3_header = [0u8; 10];
+    reader.seek(SeekFrom::Start(0))?;
+    if reader.read_exact(&mut id3_header).is_err() || &id3_header[..3] != b"ID3" {
+        return Ok(0);
+    }
+
+    // Size is a 28-bit syncsafe integer, excluding the header and optional footer
+    let size = id3_header[6..10]
+        .iter()
+        .fold(0u64, |acc, b| (acc << 7) | u64::from(b & 0x7F));
+    let footer = if id3_header[5] & 0x10 != 0 { 10 } else { 0 };
+    Ok(10 + size + footer)
+}
+
+/// Where the audio frames of a file start, for cutting time slices out of it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamLayout {
+    /// Leading bytes a decoder needs before any slice of the audio
+    /// (FLAC's stream marker and metadata blocks)
+    pub header_len: u64,
+    /// Offset of the first audio frame
+    pub audio_start: u64,
+}
+
+/// Find the header and audio data of a file. Formats whose frames are
+/// self-synchronizing (MP3, WavPack) only skip leading tags.
+pub fn stream_layout(path: &Path) -> Result<StreamLayout> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = std::fs::File::open(path).context("Failed to open audio file")?;
+    let mut offset = id3v2_tag_len(&mut file)?;
+
+    let mut marker = [0u8; 4];
+    file.seek(SeekFrom::Start(offset))?;
+    if file.read_exact(&mut marker).is_err() || &marker != b"fLaC" {
+        return Ok(StreamLayout {
+            header_len: 0,
+            audio_start: offset,
+        });
+    }
+
+    // Walk the FLAC metadata blocks: 1 bit "last block", 7 bits type, 24 bits length
+    offset += 4;
+    loop {
+        let mut block_header = [0u8; 4];
+        file.read_exact(&mut block_header)
+            .context("Truncated FLAC metadata")?;
+        let len = u64::from(u32::from_be_bytes(block_header) & 0x00FF_FFFF);
+        offset += 4 + len;
+        if block_header[0] & 0x80 != 0 {
+            break;
+        }
+        file.seek(SeekFrom::Start(offset))?;
+    }
+
+    Ok(StreamLayout {
+        header_len: offset,
+        audio_start: offset,
+    })
+}
+
+/// Estimate an MP3's duration from the first frame's bitrate, assuming constant bitrate.
+/// Used when the stream carries no Xing/Info frame count.
+fn mp3_header_duration_secs<R: std::io::Read + std::io::Seek>(
+    reader: &mut R,
+) -> Result<Option<u64>> {
+    use std::io::SeekFrom;
+
+    let file_len = reader.seek(SeekFrom::End(0))?;
+    let audio_start = id3v2_tag_len(reader)?;
+
+    // Exclude a trailing ID3v1 tag
+    let mut audio_end = file_len;
+    if file_len >= 128 {
+        let mut marker = [0u8; 3];
+        reader.seek(SeekFrom::Start(file_len - 128))?;
+        reader.read_exact(&mut marker)?;
+        if &marker == b"TAG" {
+            audio_end = file_len - 128;
+        }
+    }
+
+    // Look for the first valid frame header near the start of the audio data
+    let mut buf = vec![0u8; 64 * 1024];
+    reader.seek(SeekFrom::Start(audio_start))?;
+    let read = reader.read(&mut buf)?;
+    let Some((offset, bitrate)) = buf[..read].windows(4).enumerate().find_map(|(i, w)| {
+        mp3_header_bitrate([w[0], w[1], w[2], w[3]]).map(|bitrate| (i as u64, bitrate))
+    }) else {
+        return Ok(None);
+    };
+
+    let audio_bytes = audio_end.saturating_sub(audio_start + offset);
+    Ok(plausible_duration(
+        audio_bytes * 8 / (u64::from(bitrate) * 1000),
+    ))
+}
+
+/// Lyrics stored in an audio file's tags
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmbeddedLyrics {
+    pub content: String,
+    pub format: LyricFormat,
+    /// ISO 639-2 code of an ID3 lyrics frame, unless it is unset (`XXX`/`und`)
+    pub language: Option<String>,
+}
+
+impl EmbeddedLyrics {
+    /// Lyrics text whose format is detected from its timestamps, since
+    /// unsynchronized tags often hold LRC anyway
+    fn detect(content: &str, language: Option<&str>) -> Option<Self> {
+        let content = content.trim();
+        if content.is_empty() {
+            return None;
+        }
+        Some(Self {
+            content: content.to_string(),
+            format: LyricFormat::detect_from_content(content),
+            language: id3_language(language),
+        })
+    }
+}
+
+/// Language of an ID3 `USLT`/`SYLT` frame, dropping the placeholder codes
+fn id3_language(lang: Option<&str>) -> Option<String> {
+    let lang = lang?.trim_matches(char::from(0)).trim();
+    if lang.is_empty() || lang.eq_ignore_ascii_case("xxx") || lang.eq_ignore_ascii_case("und") {
+        return None;
+    }
+    Some(lang.to_ascii_lowercase())
+}
+
+/// Vorbis comment keys holding lyrics, in order of preference
+const VORBIS_LYRICS_KEYS: [&str; 3] = ["LYRICS", "UNSYNCEDLYRICS", "UNSYNCED LYRICS"];
+
+/// Lyrics from raw Vorbis comments (`LYRICS`, or `UNSYNCEDLYRICS` as written by foobar2000)
+fn vorbis_comment_lyrics(tags: &[(String, String)]) -> Option<EmbeddedLyrics> {
+    VORBIS_LYRICS_KEYS.iter().find_map(|wanted| {
+        tags.iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(wanted))
+            .find_map(|(_, value)| EmbeddedLyrics::detect(value, None))
+    })
+}
+
+/// Format the millisecond timestamps of a `SYLT` frame as LRC lines (`[mm:ss.xx]text`)
+fn sylt_to_lrc(content: &[(u32, String)]) -> String {
+    let mut lines: Vec<_> = content
+        .iter()
+        .map(|(ms, text)| (*ms, text.trim_matches(['\r', '\n'])))
+        .filter(|(_, text)| !text.is_empty())
+        .collect();
+    lines.sort_by_key(|(ms, _)| *ms);
+
+    lines
+        .iter()
+        .map(|(ms, text)| {
+            format!(
+                "[{:02}:{:02}.{:02}]{}",
+                ms / 60_000,
+                ms / 1000 % 60,
+                ms % 1000 / 10,
+                text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 /// Trait representing operations on audio files
 pub trait AudioFile: Send + Sync {
     /// Get the file format name (e.g., "flac", "mp3")
@@ -87,14 +570,107 @@ pub trait AudioFile: Send + Sync {
     /// Check if the file has embedded cover art
     fn has_cover_art(&self, path: &Path) -> Result<bool>;
 
-    /// Get cover art data from the file
-    fn get_cover_art(&self, path: &Path) -> Result<Option<Vec<u8>>>;
+    /// Get cover art data from the file with the MIME type stored alongside
+    /// it, which is empty for formats that don't record one
+    fn get_cover_art(&self, path: &Path) -> Result<Option<(Vec<u8>, String)>>;
 
     /// Set cover art for the file
     fn set_cover_art(&self, path: &Path, data: Vec<u8>, mime_type: &str) -> Result<()>;
 
     /// Remove cover art from the file
     fn remove_cover_art(&self, path: &Path) -> Result<()>;
+
+    /// Whether `write_lyrics` can embed lyrics in this format
+    fn supports_embedded_lyrics(&self) -> bool {
+        false
+    }
+
+    /// Embed lyrics (plain text or LRC) in the file, replacing any existing lyrics
+    fn write_lyrics(&self, path: &Path, _lyrics: &str) -> Result<()> {
+        anyhow::bail!(
+            "Embedding lyrics is not supported for {} files. File: {}",
+            self.format_name(),
+            path.display()
+        )
+    }
+
+    /// Read lyrics embedded in the file; synchronized lyrics are returned as LRC
+    fn read_lyrics(&self, _path: &Path) -> Result<Option<EmbeddedLyrics>> {
+        Ok(None)
+    }
+
+    /// Read every tag in the file as raw key/value pairs, including duplicates.
+    /// Binary values are reported as `<binary N bytes>`.
+    fn read_all_tags(&self, path: &Path) -> Result<Vec<(String, String)>>;
+
+    /// Compute the duration by reading every packet of the default track.
+    /// Slow, so only used when the container does not report a frame count.
+    fn packet_duration_secs(&self, path: &Path) -> Result<Option<u64>> {
+        use symphonia::core::errors::Error;
+        use symphonia::core::io::MediaSourceStream;
+        use symphonia::core::meta::MetadataOptions;
+
+        let file = std::fs::File::open(path).context("Failed to open audio file")?;
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let hint = probe_hint(self.format_name());
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &Default::default(), &MetadataOptions::default())
+            .context("Failed to probe audio file")?;
+
+        let mut format = probed.format;
+        let Some((track_id, time_base)) = format
+            .default_track()
+            .and_then(|t| t.codec_params.time_base.map(|tb| (t.id, tb)))
+        else {
+            return Ok(None);
+        };
+
+        let mut total_frames = 0u64;
+        loop {
+            match format.next_packet() {
+                Ok(packet) if packet.track_id() == track_id => total_frames += packet.dur,
+                Ok(_) => {}
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e).context("Failed to read packet"),
+            }
+        }
+
+        Ok(plausible_duration(
+            time_base.calc_time(total_frames).seconds,
+        ))
+    }
+}
+
+/// MIME type of an audio file extension, also sent as the `/stream` Content-Type
+pub fn audio_mime_type(extension: &str) -> Option<&'static str> {
+    match extension.to_lowercase().as_str() {
+        "flac" => Some("audio/flac"),
+        "mp3" => Some("audio/mpeg"),
+        "ogg" => Some("audio/ogg"),
+        "opus" => Some("audio/opus"),
+        "m4a" => Some("audio/mp4"),
+        "wv" => Some("audio/x-wavpack"),
+        "ape" => Some("audio/x-ape"),
+        _ => None,
+    }
+}
+
+/// Symphonia probe hint for a file handled as `format`, with both the
+/// extension and its MIME type so ambiguous files are probed as that format first
+fn probe_hint(format: &str) -> symphonia::core::probe::Hint {
+    let mut hint = symphonia::core::probe::Hint::new();
+    hint.with_extension(format);
+    if let Some(mime_type) = audio_mime_type(format) {
+        hint.mime_type(mime_type);
+    }
+    hint
+}
+
+/// Placeholder value for tags whose content is not text
+fn binary_placeholder(len: usize) -> String {
+    format!("<binary {} bytes>", len)
 }
 
 /// FLAC audio file implementation
@@ -108,13 +684,11 @@ impl AudioFile for FlacFile {
     fn parse_metadata(&self, path: &Path) -> Result<AudioMetadata> {
         use symphonia::core::io::MediaSourceStream;
         use symphonia::core::meta::MetadataOptions;
-        use symphonia::core::probe::Hint;
 
         let file = std::fs::File::open(path).context("Failed to open FLAC file")?;
         let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
-        let mut hint = Hint::new();
-        hint.with_extension("flac");
+        let hint = probe_hint("flac");
 
         let probed = symphonia::default::get_probe()
             .format(&hint, mss, &Default::default(), &MetadataOptions::default())
@@ -135,7 +709,11 @@ impl AudioFile for FlacFile {
             "DATE",
             "YEAR",
             "TRACKNUMBER",
+            "TRACKTOTAL",
+            "TOTALTRACKS",
             "DISCNUMBER",
+            "DISCTOTAL",
+            "TOTALDISCS",
             "COMPOSER",
             "COMMENT",
             "DESCRIPTION",
@@ -160,10 +738,13 @@ impl AudioFile for FlacFile {
                     "ALBUMARTIST" => audio_metadata.album_artist = Some(value),
                     "GENRE" => audio_metadata.genre = Some(value),
                     "DATE" | "YEAR" => audio_metadata.year = Some(value),
-                    "TRACKNUMBER" => audio_metadata.track_number = Some(value),
-                    "DISCNUMBER" => audio_metadata.disc_number = Some(value),
+                    "TRACKNUMBER" => audio_metadata.set_track_number(&value),
+                    "TRACKTOTAL" | "TOTALTRACKS" => audio_metadata.track_total = Some(value),
+                    "DISCNUMBER" => audio_metadata.set_disc_number(&value),
+                    "DISCTOTAL" | "TOTALDISCS" => audio_metadata.disc_total = Some(value),
                     "COMPOSER" => audio_metadata.composer = Some(value),
                     "COMMENT" | "DESCRIPTION" => audio_metadata.comment = Some(value),
+                    "COMPILATION" => audio_metadata.compilation = is_tag_flag_set(&value),
                     _ => {
                         if !standard_tags.contains(&key.as_str()) {
                             audio_metadata.custom_fields.insert(key, value);
@@ -173,14 +754,9 @@ impl AudioFile for FlacFile {
             }
         }
 
-        // Get duration from the default track
-        if let Some(track) = format.default_track() {
-            if let Some(time_base) = track.codec_params.time_base {
-                if let Some(n_frames) = track.codec_params.n_frames {
-                    audio_metadata.duration_secs = Some(time_base.calc_time(n_frames).seconds);
-                }
-            }
-        }
+        audio_metadata.duration_secs = format.default_track().and_then(track_duration_secs);
+        audio_metadata.lossless = format.default_track().is_some_and(is_lossless_codec);
+        audio_metadata.set_stream_properties(format.default_track(), path);
 
         Ok(audio_metadata)
     }
@@ -206,11 +782,19 @@ impl AudioFile for FlacFile {
         if let Some(year) = &update.year {
             tag.set_vorbis("DATE", vec![year.clone()]);
         }
-        if let Some(track_number) = &update.track_number {
-            tag.set_vorbis("TRACKNUMBER", vec![track_number.clone()]);
-        }
-        if let Some(disc_number) = &update.disc_number {
-            tag.set_vorbis("DISCNUMBER", vec![disc_number.clone()]);
+        // Vorbis comments keep totals in fields of their own
+        let (track_number, track_total) = update.track();
+        let (disc_number, disc_total) = update.disc();
+        let numbers = [
+            ("TRACKNUMBER", track_number),
+            ("TRACKTOTAL", track_total),
+            ("DISCNUMBER", disc_number),
+            ("DISCTOTAL", disc_total),
+        ];
+        for (key, value) in numbers {
+            if let Some(value) = value {
+                tag.set_vorbis(key, vec![value]);
+            }
         }
         if let Some(composer) = &update.composer {
             tag.set_vorbis("COMPOSER", vec![composer.clone()]);
@@ -234,10 +818,10 @@ impl AudioFile for FlacFile {
         Ok(tag.pictures().count() > 0)
     }
 
-    fn get_cover_art(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+    fn get_cover_art(&self, path: &Path) -> Result<Option<(Vec<u8>, String)>> {
         let tag = metaflac::Tag::read_from_path(path).context("Failed to read FLAC tags")?;
         if let Some(picture) = tag.pictures().next() {
-            Ok(Some(picture.data.clone()))
+            Ok(Some((picture.data.clone(), picture.mime_type.clone())))
         } else {
             Ok(None)
         }
@@ -271,6 +855,45 @@ impl AudioFile for FlacFile {
         tag.save().context("Failed to save FLAC tags")?;
         Ok(())
     }
+
+    fn supports_embedded_lyrics(&self) -> bool {
+        true
+    }
+
+    fn write_lyrics(&self, path: &Path, lyrics: &str) -> Result<()> {
+        let mut tag = metaflac::Tag::read_from_path(path).context("Failed to read FLAC tags")?;
+        tag.set_vorbis("LYRICS", vec![lyrics.to_string()]);
+        tag.save().context("Failed to save FLAC tags")?;
+        Ok(())
+    }
+
+    fn read_lyrics(&self, path: &Path) -> Result<Option<EmbeddedLyrics>> {
+        Ok(vorbis_comment_lyrics(&self.read_all_tags(path)?))
+    }
+
+    fn read_all_tags(&self, path: &Path) -> Result<Vec<(String, String)>> {
+        let tag = metaflac::Tag::read_from_path(path).context("Failed to read FLAC tags")?;
+        let mut tags = Vec::new();
+
+        if let Some(vorbis) = tag.vorbis_comments() {
+            let mut keys: Vec<&String> = vorbis.comments.keys().collect();
+            keys.sort();
+            for key in keys {
+                for value in &vorbis.comments[key] {
+                    tags.push((key.clone(), value.clone()));
+                }
+            }
+        }
+
+        for picture in tag.pictures() {
+            tags.push((
+                "METADATA_BLOCK_PICTURE".to_string(),
+                binary_placeholder(picture.data.len()),
+            ));
+        }
+
+        Ok(tags)
+    }
 }
 
 /// MP3 audio file implementation
@@ -282,15 +905,14 @@ impl AudioFile for Mp3File {
     }
 
     fn parse_metadata(&self, path: &Path) -> Result<AudioMetadata> {
+        use id3::TagLike;
         use symphonia::core::io::MediaSourceStream;
         use symphonia::core::meta::MetadataOptions;
-        use symphonia::core::probe::Hint;
 
         let file = std::fs::File::open(path).context("Failed to open MP3 file")?;
         let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
-        let mut hint = Hint::new();
-        hint.with_extension("mp3");
+        let hint = probe_hint("mp3");
 
         let probed = symphonia::default::get_probe()
             .format(&hint, mss, &Default::default(), &MetadataOptions::default())
@@ -325,12 +947,18 @@ impl AudioFile for Mp3File {
                     "TPE2" => audio_metadata.album_artist = Some(value),
                     "TCON" => audio_metadata.genre = Some(value),
                     "TDRC" => audio_metadata.year = Some(value),
-                    "TRCK" => audio_metadata.track_number = Some(value),
-                    "TPOS" => audio_metadata.disc_number = Some(value),
+                    "TRCK" => audio_metadata.set_track_number(&value),
+                    "TPOS" => audio_metadata.set_disc_number(&value),
                     "TCOM" => audio_metadata.composer = Some(value),
                     "COMM" => audio_metadata.comment = Some(value),
                     _ => {
-                        if !standard_tags.contains(&key.as_str()) {
+                        // User-defined TXXX frames are keyed by their description,
+                        // matching how they are written back
+                        if let Some(description) = tag.key.strip_prefix("TXXX:") {
+                            audio_metadata
+                                .custom_fields
+                                .insert(description.to_string(), value);
+                        } else if !standard_tags.contains(&key.as_str()) {
                             audio_metadata.custom_fields.insert(key, value);
                         }
                     }
@@ -338,14 +966,29 @@ impl AudioFile for Mp3File {
             }
         }
 
-        // Get duration from the default track
-        if let Some(track) = format.default_track() {
-            if let Some(time_base) = track.codec_params.time_base {
-                if let Some(n_frames) = track.codec_params.n_frames {
-                    audio_metadata.duration_secs = Some(time_base.calc_time(n_frames).seconds);
-                }
-            }
+        // Symphonia skips the iTunes TCMP frame, so read it with the id3 crate
+        audio_metadata.compilation = id3::Tag::read_from_path(path)
+            .ok()
+            .and_then(|tag| {
+                tag.get("TCMP")
+                    .and_then(|frame| frame.content().text())
+                    .map(is_tag_flag_set)
+            })
+            .unwrap_or(false);
+
+        audio_metadata.duration_secs = format.default_track().and_then(track_duration_secs);
+        audio_metadata.lossless = format.default_track().is_some_and(is_lossless_codec);
+
+        // No Xing/Info frame count: fall back to a constant-bitrate estimate
+        if audio_metadata.duration_secs.is_none() {
+            let mut file = std::fs::File::open(path).context("Failed to open MP3 file")?;
+            audio_metadata.duration_secs =
+                mp3_header_duration_secs(&mut file).unwrap_or_else(|e| {
+                    tracing::debug!("MP3 header duration estimate failed: {}", e);
+                    None
+                });
         }
+        audio_metadata.set_stream_properties(format.default_track(), path);
 
         Ok(audio_metadata)
     }
@@ -382,20 +1025,47 @@ impl AudioFile for Mp3File {
                 tracing::warn!("Invalid year format: {}", year);
             }
         }
-        if let Some(track_number) = &update.track_number {
+        // TRCK and TPOS are written as "N/M" when a total is known
+        let (track_number, track_total) = update.track();
+        if let Some(track_number) = track_number {
             if let Ok(track_num) = track_number.parse::<u32>() {
                 tag.set_track(track_num);
             } else {
                 tracing::warn!("Invalid track number format: {}", track_number);
             }
         }
-        if let Some(disc_number) = &update.disc_number {
+        if let Some(track_total) = track_total {
+            if let Ok(total) = track_total.parse::<u32>() {
+                tag.set_total_tracks(total);
+            } else {
+                tracing::warn!("Invalid track total format: {}", track_total);
+            }
+        }
+        let (disc_number, disc_total) = update.disc();
+        if let Some(disc_number) = disc_number {
             if let Ok(disc_num) = disc_number.parse::<u32>() {
                 tag.set_disc(disc_num);
             } else {
                 tracing::warn!("Invalid disc number format: {}", disc_number);
             }
         }
+        if let Some(disc_total) = disc_total {
+            if let Ok(total) = disc_total.parse::<u32>() {
+                tag.set_total_discs(total);
+            } else {
+                tracing::warn!("Invalid disc total format: {}", disc_total);
+            }
+        }
+
+        // Custom fields are stored as TXXX frames (description = key)
+        if let Some(custom_fields) = &update.custom_fields {
+            for (key, value) in custom_fields {
+                tag.add_frame(id3::frame::ExtendedText {
+                    description: key.clone(),
+                    value: value.clone(),
+                });
+            }
+        }
 
         tracing::debug!("Writing MP3 tags to file: {}", path.display());
 
@@ -415,10 +1085,18 @@ impl AudioFile for Mp3File {
         Ok(tag.pictures().count() > 0)
     }
 
-    fn get_cover_art(&self, path: &Path) -> Result<Option<Vec<u8>>> {
-        let tag = id3::Tag::read_from_path(path).context("Failed to read MP3 tags")?;
+    fn get_cover_art(&self, path: &Path) -> Result<Option<(Vec<u8>, String)>> {
+        let tag = match id3::Tag::read_from_path(path) {
+            Ok(tag) => tag,
+            // A file without an ID3 tag simply has no cover art
+            Err(id3::Error {
+                kind: id3::ErrorKind::NoTag,
+                ..
+            }) => return Ok(None),
+            Err(e) => return Err(e).context("Failed to read MP3 tags"),
+        };
         if let Some(picture) = tag.pictures().next() {
-            Ok(Some(picture.data.to_vec()))
+            Ok(Some((picture.data.to_vec(), picture.mime_type.clone())))
         } else {
             Ok(None)
         }
@@ -454,104 +1132,599 @@ impl AudioFile for Mp3File {
             .context("Failed to save MP3 tags")?;
         Ok(())
     }
-}
 
-/// OGG Vorbis audio file implementation
-pub struct OggFile;
+    fn supports_embedded_lyrics(&self) -> bool {
+        true
+    }
 
-impl AudioFile for OggFile {
-    fn format_name(&self) -> &'static str {
-        "ogg"
+    fn write_lyrics(&self, path: &Path, lyrics: &str) -> Result<()> {
+        use id3::TagLike;
+
+        let mut tag = id3::Tag::read_from_path(path).unwrap_or_default();
+        tag.remove_all_lyrics();
+        // `read_lyrics` prefers synced lyrics, so stale ones must not outlive the new text
+        tag.remove_all_synchronised_lyrics();
+        tag.add_frame(id3::frame::Lyrics {
+            lang: "eng".to_string(),
+            description: String::new(),
+            text: lyrics.to_string(),
+        });
+        tag.write_to_path(path, id3::Version::Id3v24)
+            .context("Failed to save MP3 tags")?;
+        Ok(())
     }
 
-    fn parse_metadata(&self, path: &Path) -> Result<AudioMetadata> {
-        use symphonia::core::io::MediaSourceStream;
-        use symphonia::core::meta::MetadataOptions;
-        use symphonia::core::probe::Hint;
+    fn read_lyrics(&self, path: &Path) -> Result<Option<EmbeddedLyrics>> {
+        let tag = match id3::Tag::read_from_path(path) {
+            Ok(tag) => tag,
+            Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => return Ok(None),
+            Err(e) => return Err(e).context("Failed to read MP3 tags"),
+        };
 
-        let file = std::fs::File::open(path).context("Failed to open OGG file")?;
-        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+        // SYLT over USLT, so synced lyrics aren't downgraded to plain text.
+        // Timestamps in MPEG frames can't be converted without the frame rate.
+        let synced = tag
+            .synchronised_lyrics()
+            .filter(|sylt| sylt.timestamp_format == id3::frame::TimestampFormat::Ms)
+            .map(|sylt| (sylt_to_lrc(&sylt.content), &sylt.lang))
+            .find(|(lrc, _)| !lrc.is_empty());
+        if let Some((content, lang)) = synced {
+            return Ok(Some(EmbeddedLyrics {
+                content,
+                format: LyricFormat::Lrc,
+                language: id3_language(Some(lang)),
+            }));
+        }
 
-        let mut hint = Hint::new();
-        hint.with_extension("ogg");
+        Ok(tag
+            .lyrics()
+            .find_map(|uslt| EmbeddedLyrics::detect(&uslt.text, Some(&uslt.lang))))
+    }
 
-        let probed = symphonia::default::get_probe()
-            .format(&hint, mss, &Default::default(), &MetadataOptions::default())
-            .context("Failed to probe OGG file")?;
+    fn read_all_tags(&self, path: &Path) -> Result<Vec<(String, String)>> {
+        use id3::Content;
 
-        let mut format = probed.format;
-        let mut metadata = probed.metadata;
+        let tag = id3::Tag::read_from_path(path).context("Failed to read MP3 tags")?;
 
-        let mut audio_metadata = AudioMetadata::new();
+        Ok(tag
+            .frames()
+            .map(|frame| {
+                let value = match frame.content() {
+                    Content::Picture(picture) => binary_placeholder(picture.data.len()),
+                    Content::EncapsulatedObject(object) => binary_placeholder(object.data.len()),
+                    Content::Private(private) => binary_placeholder(private.private_data.len()),
+                    Content::Unknown(unknown) => binary_placeholder(unknown.data.len()),
+                    content => content.to_string(),
+                };
+                (frame.id().to_string(), value)
+            })
+            .collect())
+    }
+}
 
-        // Standard tags for OGG Vorbis (Vorbis comments - same as FLAC)
-        let standard_tags = [
-            "TITLE",
-            "ARTIST",
-            "ALBUM",
-            "ALBUMARTIST",
-            "GENRE",
-            "DATE",
-            "YEAR",
-            "TRACKNUMBER",
-            "DISCNUMBER",
-            "COMPOSER",
-            "COMMENT",
-            "DESCRIPTION",
-        ];
+/// Ogg page header flag: the page starts with the rest of a packet
+const OGG_CONTINUED: u8 = 0x01;
+/// Ogg page header flag: first page of a logical bitstream
+const OGG_BOS: u8 = 0x02;
+
+/// CRC-32 lookup table of the Ogg page checksum (polynomial 0x04C11DB7, not reflected)
+const OGG_CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
 
-        // Extract metadata from Vorbis comments
-        let format_metadata = format.metadata();
-        if let Some(metadata_rev) = format_metadata.current().map_or_else(
-            || metadata.get().and_then(|m| m.current().cloned()),
-            |x| Some(x).cloned(),
-        ) {
-            for tag in metadata_rev.tags() {
-                let key = tag.key.to_uppercase();
-                let value = tag.value.to_string();
+fn ogg_crc(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, &byte| {
+        (crc << 8) ^ OGG_CRC_TABLE[usize::from((crc >> 24) as u8 ^ byte)]
+    })
+}
 
-                tracing::debug!("OGG metadata tag: {} = {}", key, value);
+/// A page of an Ogg file, borrowing from the file's bytes
+struct OggPage<'a> {
+    header_type: u8,
+    granule: u64,
+    serial: u32,
+    sequence: u32,
+    /// Lacing values; a value below 255 ends a packet
+    segments: &'a [u8],
+    body: &'a [u8],
+    /// The whole page as stored, header included
+    raw: &'a [u8],
+}
 
-                match key.as_str() {
-                    "TITLE" => audio_metadata.title = Some(value),
-                    "ARTIST" => audio_metadata.artist = Some(value),
-                    "ALBUM" => audio_metadata.album = Some(value),
-                    "ALBUMARTIST" => audio_metadata.album_artist = Some(value),
-                    "GENRE" => audio_metadata.genre = Some(value),
-                    "DATE" | "YEAR" => audio_metadata.year = Some(value),
-                    "TRACKNUMBER" => audio_metadata.track_number = Some(value),
-                    "DISCNUMBER" => audio_metadata.disc_number = Some(value),
-                    "COMPOSER" => audio_metadata.composer = Some(value),
-                    "COMMENT" | "DESCRIPTION" => audio_metadata.comment = Some(value),
-                    _ => {
-                        if !standard_tags.contains(&key.as_str()) {
-                            audio_metadata.custom_fields.insert(key, value);
-                        }
-                    }
-                }
-            }
+/// Split an Ogg file into its pages
+fn read_ogg_pages(data: &[u8]) -> Result<Vec<OggPage<'_>>> {
+    const HEADER_LEN: usize = 27;
+
+    let mut pages = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let header = data
+            .get(offset..offset + HEADER_LEN)
+            .context("Truncated Ogg page header")?;
+        if &header[..4] != b"OggS" {
+            anyhow::bail!("Invalid Ogg page at byte {}", offset);
         }
-
-        // Get duration from the default track
-        if let Some(track) = format.default_track() {
-            if let Some(time_base) = track.codec_params.time_base {
-                if let Some(n_frames) = track.codec_params.n_frames {
-                    audio_metadata.duration_secs = Some(time_base.calc_time(n_frames).seconds);
+        let le_u32 = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+
+        let segments_start = offset + HEADER_LEN;
+        let segments = data
+            .get(segments_start..segments_start + usize::from(header[26]))
+            .context("Truncated Ogg segment table")?;
+        let body_start = segments_start + segments.len();
+        let body_len: usize = segments.iter().map(|&len| usize::from(len)).sum();
+        let body = data
+            .get(body_start..body_start + body_len)
+            .context("Truncated Ogg page")?;
+
+        pages.push(OggPage {
+            header_type: header[5],
+            granule: u64::from_le_bytes(header[6..14].try_into().unwrap()),
+            serial: le_u32(14),
+            sequence: le_u32(18),
+            segments,
+            body,
+            raw: &data[offset..body_start + body_len],
+        });
+        offset = body_start + body_len;
+    }
+    Ok(pages)
+}
+
+/// Append one Ogg page to `out`, filling in its checksum
+fn write_ogg_page(out: &mut Vec<u8>, page: &OggPage<'_>) {
+    let start = out.len();
+    out.extend_from_slice(b"OggS");
+    out.push(0);
+    out.push(page.header_type);
+    out.extend_from_slice(&page.granule.to_le_bytes());
+    out.extend_from_slice(&page.serial.to_le_bytes());
+    out.extend_from_slice(&page.sequence.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.push(page.segments.len() as u8);
+    out.extend_from_slice(page.segments);
+    out.extend_from_slice(page.body);
+
+    let crc = ogg_crc(&out[start..]);
+    out[start + 22..start + 26].copy_from_slice(&crc.to_le_bytes());
+}
+
+/// Lay `packets` out in pages of up to 255 segments, numbered from `sequence`.
+/// Returns the sequence number of the next page.
+fn write_ogg_packets(
+    out: &mut Vec<u8>,
+    serial: u32,
+    mut sequence: u32,
+    header_type: u8,
+    packets: &[&[u8]],
+) -> u32 {
+    // Lacing value of each segment and whether it ends its packet
+    let mut lacing = Vec::new();
+    for packet in packets {
+        lacing.extend(std::iter::repeat_n((255u8, false), packet.len() / 255));
+        lacing.push(((packet.len() % 255) as u8, true));
+    }
+    let data = packets.concat();
+
+    let mut offset = 0;
+    let mut continued = false;
+    for (index, chunk) in lacing.chunks(255).enumerate() {
+        let segments: Vec<u8> = chunk.iter().map(|(len, _)| *len).collect();
+        let body_len: usize = segments.iter().map(|&len| usize::from(len)).sum();
+        let mut flags = if index == 0 { header_type } else { 0 };
+        if continued {
+            flags |= OGG_CONTINUED;
+        }
+        write_ogg_page(
+            out,
+            &OggPage {
+                header_type: flags,
+                // Header pages have granule position 0; -1 marks a page where no packet ends
+                granule: if chunk.iter().any(|(_, ends)| *ends) {
+                    0
+                } else {
+                    u64::MAX
+                },
+                serial,
+                sequence,
+                segments: &segments,
+                body: &data[offset..offset + body_len],
+                raw: &[],
+            },
+        );
+        offset += body_len;
+        sequence += 1;
+        continued = !chunk.last().is_some_and(|(_, ends)| *ends);
+    }
+    sequence
+}
+
+/// Common prefix of the Vorbis header packets after the packet type
+const VORBIS_SIGNATURE: &[u8] = b"vorbis";
+/// Magic of the Opus identification header packet
+const OPUS_HEAD_SIGNATURE: &[u8] = b"OpusHead";
+/// Magic of the Opus comment header packet
+const OPUS_TAGS_SIGNATURE: &[u8] = b"OpusTags";
+
+/// Codecs whose Ogg comment header can be rewritten
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OggCodec {
+    Vorbis,
+    Opus,
+}
+
+impl OggCodec {
+    /// Tell the codec apart by the identification header on the first page
+    fn detect(first_page: &OggPage<'_>) -> Self {
+        if first_page.body.starts_with(OPUS_HEAD_SIGNATURE) {
+            OggCodec::Opus
+        } else {
+            OggCodec::Vorbis
+        }
+    }
+}
+
+/// Header packets of the first Vorbis stream and the number of pages they fill.
+///
+/// The headers are the identification, comment and setup packets; a missing
+/// comment header yields only two. Audio data starts on a fresh page.
+fn vorbis_header_packets(pages: &[OggPage<'_>]) -> Result<(Vec<Vec<u8>>, usize)> {
+    let serial = pages.first().context("Empty OGG file")?.serial;
+    let mut packets: Vec<Vec<u8>> = Vec::new();
+    let mut packet = Vec::new();
+
+    for (page_index, page) in pages.iter().enumerate() {
+        if page.serial != serial {
+            anyhow::bail!("Multiplexed Ogg streams are not supported");
+        }
+        let mut body = page.body;
+        for (segment_index, &len) in page.segments.iter().enumerate() {
+            let (segment, rest) = body.split_at(usize::from(len));
+            packet.extend_from_slice(segment);
+            body = rest;
+            if len == 255 {
+                continue;
+            }
+
+            let done = std::mem::take(&mut packet);
+            if done.get(1..7) != Some(VORBIS_SIGNATURE) {
+                anyhow::bail!("Not an Ogg Vorbis stream");
+            }
+            let is_setup = done[0] == 5;
+            packets.push(done);
+            if is_setup {
+                if segment_index + 1 != page.segments.len() {
+                    anyhow::bail!("Audio data shares a page with the Vorbis headers");
+                }
+                return match packets.iter().map(|p| p[0]).collect::<Vec<_>>()[..] {
+                    [1, 3, 5] | [1, 5] => Ok((packets, page_index + 1)),
+                    _ => anyhow::bail!("Unexpected Vorbis header packets"),
+                };
+            }
+        }
+    }
+    anyhow::bail!("Missing Vorbis setup header")
+}
+
+/// Identification and comment header packets of the first Opus stream and the
+/// number of pages they fill. Both are required, and audio data starts on a
+/// fresh page after the comment header.
+fn opus_header_packets(pages: &[OggPage<'_>]) -> Result<(Vec<Vec<u8>>, usize)> {
+    let serial = pages.first().context("Empty OGG file")?.serial;
+    let mut packets: Vec<Vec<u8>> = Vec::new();
+    let mut packet = Vec::new();
+
+    for (page_index, page) in pages.iter().enumerate() {
+        if page.serial != serial {
+            anyhow::bail!("Multiplexed Ogg streams are not supported");
+        }
+        let mut body = page.body;
+        for (segment_index, &len) in page.segments.iter().enumerate() {
+            let (segment, rest) = body.split_at(usize::from(len));
+            packet.extend_from_slice(segment);
+            body = rest;
+            if len == 255 {
+                continue;
+            }
+
+            packets.push(std::mem::take(&mut packet));
+            if packets.len() == 2 {
+                if !packets[0].starts_with(OPUS_HEAD_SIGNATURE)
+                    || !packets[1].starts_with(OPUS_TAGS_SIGNATURE)
+                {
+                    anyhow::bail!("Unexpected Opus header packets");
+                }
+                if segment_index + 1 != page.segments.len() {
+                    anyhow::bail!("Audio data shares a page with the Opus headers");
+                }
+                return Ok((packets, page_index + 1));
+            }
+        }
+    }
+    anyhow::bail!("Missing Opus comment header")
+}
+
+/// The contents of a Vorbis comment header, which Opus files share; entries
+/// are kept as raw `KEY=value` bytes so the ones that aren't edited are
+/// written back as is
+#[derive(Debug, Default)]
+struct VorbisComments {
+    vendor: Vec<u8>,
+    entries: Vec<Vec<u8>>,
+}
+
+impl VorbisComments {
+    /// Parse a comment header packet (Vorbis type 3, or `OpusTags`)
+    fn parse(packet: &[u8]) -> Result<Self> {
+        fn take<'a>(rest: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+            if rest.len() < len {
+                anyhow::bail!("Truncated Vorbis comment header");
+            }
+            let (head, tail) = rest.split_at(len);
+            *rest = tail;
+            Ok(head)
+        }
+        fn take_len(rest: &mut &[u8]) -> Result<usize> {
+            Ok(u32::from_le_bytes(take(rest, 4)?.try_into().unwrap()) as usize)
+        }
+
+        let mut rest = match packet.strip_prefix(OPUS_TAGS_SIGNATURE) {
+            Some(rest) => rest,
+            None => packet
+                .get(1 + VORBIS_SIGNATURE.len()..)
+                .context("Truncated Vorbis comment header")?,
+        };
+        let vendor_len = take_len(&mut rest)?;
+        let vendor = take(&mut rest, vendor_len)?.to_vec();
+        let count = take_len(&mut rest)?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let len = take_len(&mut rest)?;
+            entries.push(take(&mut rest, len)?.to_vec());
+        }
+        Ok(Self { vendor, entries })
+    }
+
+    /// Replace every entry of `key` (compared case-insensitively) with one holding `value`
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        // Field names are printable ASCII without '='
+        if key.is_empty() || !key.bytes().all(|b| (0x20..=0x7D).contains(&b) && b != b'=') {
+            anyhow::bail!("Invalid Vorbis comment field name: {:?}", key);
+        }
+        self.entries.retain(|entry| {
+            let name = entry.split(|&b| b == b'=').next().unwrap_or_default();
+            !name.eq_ignore_ascii_case(key.as_bytes())
+        });
+        self.entries
+            .push(format!("{}={}", key.to_uppercase(), value).into_bytes());
+        Ok(())
+    }
+
+    /// Serialize as a comment header packet of `codec`; only Vorbis has a framing bit
+    fn to_packet(&self, codec: OggCodec) -> Vec<u8> {
+        let mut packet = match codec {
+            OggCodec::Vorbis => [&[3], VORBIS_SIGNATURE].concat(),
+            OggCodec::Opus => OPUS_TAGS_SIGNATURE.to_vec(),
+        };
+        packet.extend_from_slice(&(self.vendor.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&self.vendor);
+        packet.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for entry in &self.entries {
+            packet.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+            packet.extend_from_slice(entry);
+        }
+        if codec == OggCodec::Vorbis {
+            packet.push(1);
+        }
+        packet
+    }
+}
+
+/// Rewrite the comment header of an Ogg Vorbis or Opus file with `edit`.
+///
+/// Only the header pages are rebuilt; audio pages are copied unchanged, apart
+/// from their sequence numbers (and checksums) when the headers take a
+/// different number of pages than before. The file is replaced atomically.
+fn rewrite_vorbis_comments(
+    path: &Path,
+    edit: impl FnOnce(&mut VorbisComments) -> Result<()>,
+) -> Result<()> {
+    let data = std::fs::read(path).context("Failed to open OGG file")?;
+    let pages = read_ogg_pages(&data)?;
+    let codec = OggCodec::detect(pages.first().context("Empty OGG file")?);
+    let (packets, header_pages) = match codec {
+        OggCodec::Vorbis => vorbis_header_packets(&pages)?,
+        OggCodec::Opus => opus_header_packets(&pages)?,
+    };
+
+    let mut comments = match (codec, packets.len()) {
+        (OggCodec::Vorbis, 2) => VorbisComments::default(),
+        _ => VorbisComments::parse(&packets[1])?,
+    };
+    edit(&mut comments)?;
+    let comment_packet = comments.to_packet(codec);
+    // The Vorbis setup header follows the comments on the same pages
+    let mut tail_packets = vec![comment_packet.as_slice()];
+    if codec == OggCodec::Vorbis {
+        tail_packets.push(&packets[packets.len() - 1]);
+    }
+
+    let first = &pages[0];
+    let mut out = Vec::with_capacity(data.len() + comment_packet.len());
+    let next = write_ogg_packets(
+        &mut out,
+        first.serial,
+        first.sequence,
+        OGG_BOS,
+        &[&packets[0]],
+    );
+    let next = write_ogg_packets(&mut out, first.serial, next, 0, &tail_packets);
+
+    let shift = next.wrapping_sub(first.sequence.wrapping_add(header_pages as u32));
+    for page in &pages[header_pages..] {
+        if page.serial == first.serial && shift != 0 {
+            write_ogg_page(
+                &mut out,
+                &OggPage {
+                    sequence: page.sequence.wrapping_add(shift),
+                    ..*page
+                },
+            );
+        } else {
+            out.extend_from_slice(page.raw);
+        }
+    }
+
+    let file_name = path.file_name().context("Invalid OGG file path")?;
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    std::fs::write(&temp_path, &out).context("Failed to write OGG file")?;
+    if let Ok(metadata) = std::fs::metadata(path) {
+        let _ = std::fs::set_permissions(&temp_path, metadata.permissions());
+    }
+    std::fs::rename(&temp_path, path)
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp_path);
+        })
+        .context("Failed to replace OGG file")?;
+    Ok(())
+}
+
+/// OGG Vorbis audio file implementation
+pub struct OggFile;
+
+impl AudioFile for OggFile {
+    fn format_name(&self) -> &'static str {
+        "ogg"
+    }
+
+    fn parse_metadata(&self, path: &Path) -> Result<AudioMetadata> {
+        use symphonia::core::io::MediaSourceStream;
+        use symphonia::core::meta::MetadataOptions;
+
+        let file = std::fs::File::open(path).context("Failed to open OGG file")?;
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let hint = probe_hint("ogg");
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &Default::default(), &MetadataOptions::default())
+            .context("Failed to probe OGG file")?;
+
+        let mut format = probed.format;
+        let mut metadata = probed.metadata;
+
+        let mut audio_metadata = AudioMetadata::new();
+
+        // Standard tags for OGG Vorbis (Vorbis comments - same as FLAC)
+        let standard_tags = [
+            "TITLE",
+            "ARTIST",
+            "ALBUM",
+            "ALBUMARTIST",
+            "GENRE",
+            "DATE",
+            "YEAR",
+            "TRACKNUMBER",
+            "TRACKTOTAL",
+            "TOTALTRACKS",
+            "DISCNUMBER",
+            "DISCTOTAL",
+            "TOTALDISCS",
+            "COMPOSER",
+            "COMMENT",
+            "DESCRIPTION",
+        ];
+
+        // Extract metadata from Vorbis comments
+        let format_metadata = format.metadata();
+        if let Some(metadata_rev) = format_metadata.current().map_or_else(
+            || metadata.get().and_then(|m| m.current().cloned()),
+            |x| Some(x).cloned(),
+        ) {
+            for tag in metadata_rev.tags() {
+                let key = tag.key.to_uppercase();
+                let value = tag.value.to_string();
+
+                tracing::debug!("OGG metadata tag: {} = {}", key, value);
+
+                match key.as_str() {
+                    "TITLE" => audio_metadata.title = Some(value),
+                    "ARTIST" => audio_metadata.artist = Some(value),
+                    "ALBUM" => audio_metadata.album = Some(value),
+                    "ALBUMARTIST" => audio_metadata.album_artist = Some(value),
+                    "GENRE" => audio_metadata.genre = Some(value),
+                    "DATE" | "YEAR" => audio_metadata.year = Some(value),
+                    "TRACKNUMBER" => audio_metadata.set_track_number(&value),
+                    "TRACKTOTAL" | "TOTALTRACKS" => audio_metadata.track_total = Some(value),
+                    "DISCNUMBER" => audio_metadata.set_disc_number(&value),
+                    "DISCTOTAL" | "TOTALDISCS" => audio_metadata.disc_total = Some(value),
+                    "COMPOSER" => audio_metadata.composer = Some(value),
+                    "COMMENT" | "DESCRIPTION" => audio_metadata.comment = Some(value),
+                    "COMPILATION" => audio_metadata.compilation = is_tag_flag_set(&value),
+                    _ => {
+                        if !standard_tags.contains(&key.as_str()) {
+                            audio_metadata.custom_fields.insert(key, value);
+                        }
+                    }
                 }
             }
         }
 
+        audio_metadata.duration_secs = format.default_track().and_then(track_duration_secs);
+        audio_metadata.lossless = format.default_track().is_some_and(is_lossless_codec);
+        audio_metadata.set_stream_properties(format.default_track(), path);
+
         Ok(audio_metadata)
     }
 
     fn write_metadata(&self, path: &Path, update: &MetadataUpdate) -> Result<()> {
-        // OGG Vorbis metadata writing requires external tools or specialized libraries
-        // For now, we'll return an error indicating this is not yet supported
-        // TODO: Implement OGG metadata writing using vorbis-comments or similar crate
-        anyhow::bail!(
-            "OGG metadata writing is not yet supported. File: {}",
-            path.display()
-        )
+        // Same field names as FLAC, which also stores Vorbis comments
+        let (track_number, track_total) = update.track();
+        let (disc_number, disc_total) = update.disc();
+        let fields = [
+            ("TITLE", update.title.clone()),
+            ("ARTIST", update.artist.clone()),
+            ("ALBUM", update.album.clone()),
+            ("ALBUMARTIST", update.album_artist.clone()),
+            ("GENRE", update.genre.clone()),
+            ("DATE", update.year.clone()),
+            ("TRACKNUMBER", track_number),
+            ("TRACKTOTAL", track_total),
+            ("DISCNUMBER", disc_number),
+            ("DISCTOTAL", disc_total),
+            ("COMPOSER", update.composer.clone()),
+            ("COMMENT", update.comment.clone()),
+        ];
+
+        rewrite_vorbis_comments(path, |comments| {
+            for (key, value) in fields {
+                if let Some(value) = value {
+                    comments.set(key, &value)?;
+                }
+            }
+            if let Some(custom_fields) = &update.custom_fields {
+                for (key, value) in custom_fields {
+                    comments.set(key, value)?;
+                }
+            }
+            Ok(())
+        })
+        .context("Failed to save OGG tags")
     }
 
     fn has_cover_art(&self, path: &Path) -> Result<bool> {
@@ -559,13 +1732,11 @@ impl AudioFile for OggFile {
         // We'll check this through Symphonia's metadata
         use symphonia::core::io::MediaSourceStream;
         use symphonia::core::meta::MetadataOptions;
-        use symphonia::core::probe::Hint;
 
         let file = std::fs::File::open(path).context("Failed to open OGG file")?;
         let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
-        let mut hint = Hint::new();
-        hint.with_extension("ogg");
+        let hint = probe_hint("ogg");
 
         let probed = symphonia::default::get_probe()
             .format(&hint, mss, &Default::default(), &MetadataOptions::default())
@@ -586,16 +1757,14 @@ impl AudioFile for OggFile {
         }
     }
 
-    fn get_cover_art(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+    fn get_cover_art(&self, path: &Path) -> Result<Option<(Vec<u8>, String)>> {
         use symphonia::core::io::MediaSourceStream;
         use symphonia::core::meta::MetadataOptions;
-        use symphonia::core::probe::Hint;
 
         let file = std::fs::File::open(path).context("Failed to open OGG file")?;
         let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
-        let mut hint = Hint::new();
-        hint.with_extension("ogg");
+        let hint = probe_hint("ogg");
 
         let probed = symphonia::default::get_probe()
             .format(&hint, mss, &Default::default(), &MetadataOptions::default())
@@ -611,7 +1780,7 @@ impl AudioFile for OggFile {
             |x| Some(x).cloned(),
         ) {
             if let Some(visual) = metadata_rev.visuals().first() {
-                Ok(Some(visual.data.to_vec()))
+                Ok(Some((visual.data.to_vec(), visual.media_type.clone())))
             } else {
                 Ok(None)
             }
@@ -637,6 +1806,101 @@ impl AudioFile for OggFile {
             path.display()
         )
     }
+
+    fn read_lyrics(&self, path: &Path) -> Result<Option<EmbeddedLyrics>> {
+        Ok(vorbis_comment_lyrics(&self.read_all_tags(path)?))
+    }
+
+    fn read_all_tags(&self, path: &Path) -> Result<Vec<(String, String)>> {
+        use symphonia::core::io::MediaSourceStream;
+        use symphonia::core::meta::{MetadataOptions, Value};
+
+        let file = std::fs::File::open(path).context("Failed to open OGG file")?;
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let hint = probe_hint("ogg");
+
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &Default::default(), &MetadataOptions::default())
+            .context("Failed to probe OGG file")?;
+
+        let mut format = probed.format;
+        let mut metadata = probed.metadata;
+
+        let mut tags = Vec::new();
+        let format_metadata = format.metadata();
+        if let Some(metadata_rev) = format_metadata.current().map_or_else(
+            || metadata.get().and_then(|m| m.current().cloned()),
+            |x| Some(x).cloned(),
+        ) {
+            for tag in metadata_rev.tags() {
+                let value = match &tag.value {
+                    Value::Binary(data) => binary_placeholder(data.len()),
+                    value => value.to_string(),
+                };
+                tags.push((tag.key.clone(), value));
+            }
+            for visual in metadata_rev.visuals() {
+                tags.push((
+                    "METADATA_BLOCK_PICTURE".to_string(),
+                    binary_placeholder(visual.data.len()),
+                ));
+            }
+        }
+
+        Ok(tags)
+    }
+}
+
+/// Ogg Opus audio file implementation.
+///
+/// Opus comments use the Vorbis comment layout and Symphonia's Ogg reader
+/// understands both codecs, so reading and tag writing go through [`OggFile`];
+/// the duration comes from the last granule position, less the pre-skip.
+pub struct OpusFile;
+
+impl AudioFile for OpusFile {
+    fn format_name(&self) -> &'static str {
+        "opus"
+    }
+
+    fn parse_metadata(&self, path: &Path) -> Result<AudioMetadata> {
+        OggFile.parse_metadata(path)
+    }
+
+    fn write_metadata(&self, path: &Path, update: &MetadataUpdate) -> Result<()> {
+        OggFile.write_metadata(path, update)
+    }
+
+    fn has_cover_art(&self, path: &Path) -> Result<bool> {
+        OggFile.has_cover_art(path)
+    }
+
+    fn get_cover_art(&self, path: &Path) -> Result<Option<(Vec<u8>, String)>> {
+        OggFile.get_cover_art(path)
+    }
+
+    fn set_cover_art(&self, path: &Path, _data: Vec<u8>, _mime_type: &str) -> Result<()> {
+        anyhow::bail!(
+            "Opus cover art writing is not yet supported. File: {}",
+            path.display()
+        )
+    }
+
+    fn remove_cover_art(&self, path: &Path) -> Result<()> {
+        anyhow::bail!(
+            "Opus cover art removal is not yet supported. File: {}",
+            path.display()
+        )
+    }
+
+    fn read_lyrics(&self, path: &Path) -> Result<Option<EmbeddedLyrics>> {
+        OggFile.read_lyrics(path)
+    }
+
+    fn read_all_tags(&self, path: &Path) -> Result<Vec<(String, String)>> {
+        OggFile.read_all_tags(path)
+    }
 }
 
 /// M4A (AAC) audio file implementation
@@ -650,13 +1914,11 @@ impl AudioFile for M4aFile {
     fn parse_metadata(&self, path: &Path) -> Result<AudioMetadata> {
         use symphonia::core::io::MediaSourceStream;
         use symphonia::core::meta::MetadataOptions;
-        use symphonia::core::probe::Hint;
 
         let file = std::fs::File::open(path).context("Failed to open M4A file")?;
         let mss = MediaSourceStream::new(Box::new(file), Default::default());
 
-        let mut hint = Hint::new();
-        hint.with_extension("m4a");
+        let hint = probe_hint("m4a");
 
         let probed = symphonia::default::get_probe()
             .format(&hint, mss, &Default::default(), &MetadataOptions::default())
@@ -703,12 +1965,21 @@ impl AudioFile for M4aFile {
                         "AART" | "ALBUMARTIST" => audio_metadata.album_artist = Some(value),
                         "©GEN" | "GENRE" => audio_metadata.genre = Some(value),
                         "©DAY" | "DATE" | "YEAR" => audio_metadata.year = Some(value),
-                        "TRKN" | "TRACKNUMBER" => audio_metadata.track_number = Some(value),
-                        "DISK" | "DISCNUMBER" => audio_metadata.disc_number = Some(value),
+                        "TRKN" | "TRACKNUMBER" => audio_metadata.set_track_number(&value),
+                        "DISK" | "DISCNUMBER" => audio_metadata.set_disc_number(&value),
                         "©WRT" | "COMPOSER" => audio_metadata.composer = Some(value),
                         "©CMT" | "COMMENT" => audio_metadata.comment = Some(value),
+                        "CPIL" | "COMPILATION" => {
+                            audio_metadata.compilation = is_tag_flag_set(&value)
+                        }
                         _ => {
-                            if !standard_tags.contains(&key.as_str()) {
+                            // Freeform atoms are reported as "<mean>:<name>"
+                            if let Some(name) = key
+                                .strip_prefix(M4A_FREEFORM_MEAN)
+                                .and_then(|k| k.strip_prefix(':'))
+                            {
+                                audio_metadata.custom_fields.insert(name.to_string(), value);
+                            } else if !standard_tags.contains(&key.as_str()) {
                                 audio_metadata.custom_fields.insert(key.to_string(), value);
                             }
                         }
@@ -717,14 +1988,9 @@ impl AudioFile for M4aFile {
             }
         }
 
-        // Get duration from the default track
-        if let Some(track) = format.default_track() {
-            if let Some(time_base) = track.codec_params.time_base {
-                if let Some(n_frames) = track.codec_params.n_frames {
-                    audio_metadata.duration_secs = Some(time_base.calc_time(n_frames).seconds);
-                }
-            }
-        }
+        audio_metadata.duration_secs = format.default_track().and_then(track_duration_secs);
+        audio_metadata.lossless = format.default_track().is_some_and(is_lossless_codec);
+        audio_metadata.set_stream_properties(format.default_track(), path);
 
         Ok(audio_metadata)
     }
@@ -754,15 +2020,20 @@ impl AudioFile for M4aFile {
         if let Some(ref year) = update.year {
             tag.set_year(year);
         }
-        if let Some(ref track_number) = update.track_number {
-            if let Ok(num) = track_number.parse::<u16>() {
-                tag.set_track_number(num);
-            }
+        // `trkn` and `disk` hold the number and total as a pair
+        let (track_number, track_total) = update.track();
+        if let Some(num) = track_number.and_then(|n| n.parse::<u16>().ok()) {
+            tag.set_track_number(num);
         }
-        if let Some(ref disc_number) = update.disc_number {
-            if let Ok(num) = disc_number.parse::<u16>() {
-                tag.set_disc_number(num);
-            }
+        if let Some(total) = track_total.and_then(|n| n.parse::<u16>().ok()) {
+            tag.set_total_tracks(total);
+        }
+        let (disc_number, disc_total) = update.disc();
+        if let Some(num) = disc_number.and_then(|n| n.parse::<u16>().ok()) {
+            tag.set_disc_number(num);
+        }
+        if let Some(total) = disc_total.and_then(|n| n.parse::<u16>().ok()) {
+            tag.set_total_discs(total);
         }
         if let Some(ref composer) = update.composer {
             tag.set_composer(composer);
@@ -771,6 +2042,16 @@ impl AudioFile for M4aFile {
             tag.set_comment(comment);
         }
 
+        // Custom fields are stored as freeform (----) atoms
+        if let Some(ref custom_fields) = update.custom_fields {
+            for (key, value) in custom_fields {
+                tag.set_data(
+                    mp4ameta::FreeformIdent::new(M4A_FREEFORM_MEAN, key),
+                    mp4ameta::Data::Utf8(value.clone()),
+                );
+            }
+        }
+
         tag.write_to_path(path).context("Failed to save M4A tags")?;
         Ok(())
     }
@@ -782,12 +2063,17 @@ impl AudioFile for M4aFile {
         Ok(tag.artworks().count() > 0)
     }
 
-    fn get_cover_art(&self, path: &Path) -> Result<Option<Vec<u8>>> {
-        use mp4ameta::Tag;
+    fn get_cover_art(&self, path: &Path) -> Result<Option<(Vec<u8>, String)>> {
+        use mp4ameta::{ImgFmt, Tag};
 
         let tag = Tag::read_from_path(path).context("Failed to read M4A tags")?;
         if let Some(artwork) = tag.artworks().next() {
-            Ok(Some(artwork.data.to_vec()))
+            let mime_type = match artwork.fmt {
+                ImgFmt::Bmp => "image/bmp",
+                ImgFmt::Jpeg => "image/jpeg",
+                ImgFmt::Png => "image/png",
+            };
+            Ok(Some((artwork.data.to_vec(), mime_type.to_string())))
         } else {
             Ok(None)
         }
@@ -820,15 +2106,1500 @@ impl AudioFile for M4aFile {
         tag.write_to_path(path).context("Failed to save M4A tags")?;
         Ok(())
     }
+
+    fn supports_embedded_lyrics(&self) -> bool {
+        true
+    }
+
+    fn write_lyrics(&self, path: &Path, lyrics: &str) -> Result<()> {
+        use mp4ameta::Tag;
+
+        let mut tag = Tag::read_from_path(path).context("Failed to read M4A tags")?;
+        tag.set_lyrics(lyrics);
+        tag.write_to_path(path).context("Failed to save M4A tags")?;
+        Ok(())
+    }
+
+    fn read_lyrics(&self, path: &Path) -> Result<Option<EmbeddedLyrics>> {
+        let tag = mp4ameta::Tag::read_from_path(path).context("Failed to read M4A tags")?;
+        Ok(tag
+            .lyrics()
+            .and_then(|lyrics| EmbeddedLyrics::detect(lyrics, None)))
+    }
+
+    fn read_all_tags(&self, path: &Path) -> Result<Vec<(String, String)>> {
+        use mp4ameta::Tag;
+
+        let tag = Tag::read_from_path(path).context("Failed to read M4A tags")?;
+
+        Ok(tag
+            .data()
+            .map(|(ident, data)| {
+                let value = match data.string() {
+                    Some(text) => text.to_string(),
+                    None => binary_placeholder(data.bytes().map_or(0, |b| b.len())),
+                };
+                (ident.to_string(), value)
+            })
+            .collect())
+    }
 }
 
-/// Factory function to create the appropriate AudioFile implementation based on file extension
-pub fn get_audio_file_handler(extension: &str) -> Option<Box<dyn AudioFile>> {
-    match extension.to_lowercase().as_str() {
-        "flac" => Some(Box::new(FlacFile)),
-        "mp3" => Some(Box::new(Mp3File)),
-        "ogg" => Some(Box::new(OggFile)),
-        "m4a" => Some(Box::new(M4aFile)),
-        _ => None,
+/// Value of an APEv2 tag item
+#[derive(Debug, Clone, PartialEq)]
+enum ApeItemValue {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// APEv2 tag item names holding cover art, in order of preference
+const APE_COVER_KEYS: [&str; 2] = ["Cover Art (Front)", "Cover Art (Other)"];
+
+/// Read the APEv2 tag at the end of a file (before an optional ID3v1 tag).
+///
+/// WavPack and Monkey's Audio both store their metadata this way. Returns the
+/// items in file order; a file without an APE tag yields an empty list.
+fn read_ape_tag(path: &Path) -> Result<Vec<(String, ApeItemValue)>> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    const FOOTER_LEN: u64 = 32;
+
+    let mut file = std::fs::File::open(path).context("Failed to open audio file")?;
+    let file_len = file.metadata()?.len();
+
+    // Skip a trailing ID3v1 tag, which some taggers append after the APE tag
+    let mut tag_end = file_len;
+    if file_len >= 128 {
+        let mut id3v1 = [0u8; 3];
+        file.seek(SeekFrom::Start(file_len - 128))?;
+        file.read_exact(&mut id3v1)?;
+        if &id3v1 == b"TAG" {
+            tag_end -= 128;
+        }
+    }
+    if tag_end < FOOTER_LEN {
+        return Ok(Vec::new());
+    }
+
+    let mut footer = [0u8; FOOTER_LEN as usize];
+    file.seek(SeekFrom::Start(tag_end - FOOTER_LEN))?;
+    file.read_exact(&mut footer)?;
+    if &footer[..8] != b"APETAGEX" {
+        return Ok(Vec::new());
+    }
+
+    let le_u32 = |bytes: &[u8]| u32::from_le_bytes(bytes[..4].try_into().unwrap());
+    // Size of the items plus the footer, excluding the optional header
+    let tag_size = u64::from(le_u32(&footer[12..]));
+    let item_count = le_u32(&footer[16..]);
+    if tag_size < FOOTER_LEN || tag_size > tag_end {
+        anyhow::bail!("Invalid APE tag size: {}", tag_size);
+    }
+
+    let mut items_data = vec![0u8; (tag_size - FOOTER_LEN) as usize];
+    file.seek(SeekFrom::Start(tag_end - tag_size))?;
+    file.read_exact(&mut items_data)?;
+
+    let mut items = Vec::new();
+    let mut rest = items_data.as_slice();
+    for _ in 0..item_count {
+        if rest.len() < 8 {
+            anyhow::bail!("Truncated APE tag item");
+        }
+        let value_len = le_u32(rest) as usize;
+        let flags = le_u32(&rest[4..]);
+        rest = &rest[8..];
+
+        let key_len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .context("Unterminated APE tag key")?;
+        let key = String::from_utf8_lossy(&rest[..key_len]).to_string();
+        rest = &rest[key_len + 1..];
+
+        if rest.len() < value_len {
+            anyhow::bail!("Truncated APE tag value for {}", key);
+        }
+        let data = &rest[..value_len];
+        rest = &rest[value_len..];
+
+        // Bits 1-2: 0 = UTF-8 text, 1 = binary, 2 = external locator (text)
+        let value = if (flags >> 1) & 0b11 == 1 {
+            ApeItemValue::Binary(data.to_vec())
+        } else {
+            ApeItemValue::Text(String::from_utf8_lossy(data).to_string())
+        };
+        items.push((key, value));
+    }
+
+    Ok(items)
+}
+
+/// Parse a ReplayGain value such as `-6.48 dB` or `+1.2` into decibels
+pub fn parse_replay_gain(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let number = match value.len().checked_sub(2).and_then(|i| value.get(i..)) {
+        Some(unit) if unit.eq_ignore_ascii_case("dB") => &value[..value.len() - 2],
+        _ => value,
+    };
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|gain| gain.is_finite())
+}
+
+/// Whether a boolean-ish tag value such as `COMPILATION=1` or `cpil=true` is set
+fn is_tag_flag_set(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "1" | "true" | "yes"
+    )
+}
+
+/// Build normalized metadata from APEv2 items, mapping keys like Vorbis comments
+fn ape_metadata(items: Vec<(String, ApeItemValue)>) -> AudioMetadata {
+    let mut audio_metadata = AudioMetadata::new();
+
+    for (key, value) in items {
+        let ApeItemValue::Text(value) = value else {
+            continue;
+        };
+        let key = key.to_uppercase();
+
+        tracing::debug!("APE metadata tag: {} = {}", key, value);
+
+        // APEv2 names some fields differently ("Album Artist", "Year", "Track", "Disc")
+        match key.replace(' ', "").as_str() {
+            "TITLE" => audio_metadata.title = Some(value),
+            "ARTIST" => audio_metadata.artist = Some(value),
+            "ALBUM" => audio_metadata.album = Some(value),
+            "ALBUMARTIST" => audio_metadata.album_artist = Some(value),
+            "GENRE" => audio_metadata.genre = Some(value),
+            "DATE" | "YEAR" => audio_metadata.year = Some(value),
+            "TRACK" | "TRACKNUMBER" => audio_metadata.set_track_number(&value),
+            "DISC" | "DISCNUMBER" => audio_metadata.set_disc_number(&value),
+            "COMPOSER" => audio_metadata.composer = Some(value),
+            "COMMENT" | "DESCRIPTION" => audio_metadata.comment = Some(value),
+            "COMPILATION" => audio_metadata.compilation = is_tag_flag_set(&value),
+            _ => {
+                audio_metadata.custom_fields.insert(key, value);
+            }
+        }
+    }
+
+    audio_metadata
+}
+
+/// MIME type of an image file from its extension; empty when unknown
+pub fn image_mime_from_name(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        _ => "",
+    }
+}
+
+/// Extract the front cover from APEv2 items. Cover items hold a
+/// NUL-terminated file name followed by the image data; the MIME type is
+/// taken from the file name's extension (empty if unknown).
+fn ape_cover_art(items: &[(String, ApeItemValue)]) -> Option<(Vec<u8>, String)> {
+    APE_COVER_KEYS.iter().find_map(|cover_key| {
+        items.iter().find_map(|(key, value)| match value {
+            ApeItemValue::Binary(data) if key.eq_ignore_ascii_case(cover_key) => {
+                let name_end = data.iter().position(|&b| b == 0);
+                let start = name_end.map_or(0, |i| i + 1);
+                let name = String::from_utf8_lossy(&data[..name_end.unwrap_or(0)]);
+                let mime_type = image_mime_from_name(Path::new(name.as_ref()));
+                Some((data[start..].to_vec(), mime_type.to_string()))
+            }
+            _ => None,
+        })
+    })
+}
+
+/// APEv2 items as raw key/value pairs
+fn ape_raw_tags(items: Vec<(String, ApeItemValue)>) -> Vec<(String, String)> {
+    items
+        .into_iter()
+        .map(|(key, value)| match value {
+            ApeItemValue::Text(text) => (key, text),
+            ApeItemValue::Binary(data) => (key, binary_placeholder(data.len())),
+        })
+        .collect()
+}
+
+/// Sample rates indexed by bits 23-26 of the WavPack block flags
+const WAVPACK_SAMPLE_RATES: [u32; 15] = [
+    6000, 8000, 9600, 11025, 12000, 16000, 22050, 24000, 32000, 44100, 48000, 64000, 88200, 96000,
+    192000,
+];
+
+/// Duration from the first WavPack block header, if it reports a total sample count
+fn wavpack_duration_secs(path: &Path) -> Result<Option<u64>> {
+    use std::io::Read;
+
+    let mut header = [0u8; 32];
+    std::fs::File::open(path)
+        .context("Failed to open WavPack file")?
+        .read_exact(&mut header)
+        .context("Failed to read WavPack header")?;
+    if &header[..4] != b"wvpk" {
+        anyhow::bail!("Not a WavPack file");
+    }
+
+    let le_u32 = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+    let total_samples = le_u32(12);
+    if total_samples == u32::MAX {
+        // Unknown length (e.g. encoded from a pipe)
+        return Ok(None);
+    }
+    // Newer encoders store the upper 8 bits of the sample count separately
+    let total_samples = u64::from(header[11]) << 32 | u64::from(total_samples);
+
+    let flags = le_u32(24);
+    let Some(&sample_rate) = WAVPACK_SAMPLE_RATES.get(((flags >> 23) & 0xF) as usize) else {
+        // Custom sample rates live in a metadata sub-block; not worth decoding for a duration
+        return Ok(None);
+    };
+
+    Ok(plausible_duration(total_samples / u64::from(sample_rate)))
+}
+
+/// Duration from a Monkey's Audio header (both the pre-3.98 and current layouts)
+fn ape_duration_secs(path: &Path) -> Result<Option<u64>> {
+    use std::io::Read;
+
+    let mut header = [0u8; 76];
+    let mut file = std::fs::File::open(path).context("Failed to open APE file")?;
+    let len = file
+        .read(&mut header)
+        .context("Failed to read APE header")?;
+    if len < 32 || &header[..4] != b"MAC " {
+        anyhow::bail!("Not a Monkey's Audio file");
+    }
+
+    let le_u16 = |offset: usize| u16::from_le_bytes(header[offset..offset + 2].try_into().unwrap());
+    let le_u32 = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+    let version = le_u16(4);
+
+    let (blocks_per_frame, final_frame_blocks, total_frames, sample_rate) = if version >= 3980 {
+        // APE_DESCRIPTOR followed by APE_HEADER at `descriptor_bytes`
+        let descriptor_bytes = le_u32(8) as usize;
+        if descriptor_bytes + 24 > len {
+            anyhow::bail!("Truncated APE header");
+        }
+        let h = descriptor_bytes;
+        (le_u32(h + 4), le_u32(h + 8), le_u32(h + 12), le_u32(h + 20))
+    } else {
+        let compression_level = le_u16(6);
+        let blocks_per_frame = if version >= 3950 {
+            73728 * 4
+        } else if version >= 3900 || (version >= 3800 && compression_level == 4000) {
+            73728
+        } else {
+            9216
+        };
+        (blocks_per_frame, le_u32(28), le_u32(24), le_u32(12))
+    };
+
+    if total_frames == 0 || sample_rate == 0 {
+        return Ok(None);
+    }
+    let total_blocks =
+        u64::from(total_frames - 1) * u64::from(blocks_per_frame) + u64::from(final_frame_blocks);
+
+    Ok(plausible_duration(total_blocks / u64::from(sample_rate)))
+}
+
+/// WavPack audio file implementation (read-only; metadata from APEv2 tags)
+pub struct WavPackFile;
+
+impl AudioFile for WavPackFile {
+    fn format_name(&self) -> &'static str {
+        "wv"
+    }
+
+    fn parse_metadata(&self, path: &Path) -> Result<AudioMetadata> {
+        let mut audio_metadata =
+            ape_metadata(read_ape_tag(path).context("Failed to read WavPack tags")?);
+        audio_metadata.duration_secs = wavpack_duration_secs(path)?;
+        audio_metadata.lossless = true;
+        audio_metadata.set_stream_properties(None, path);
+        Ok(audio_metadata)
+    }
+
+    fn write_metadata(&self, path: &Path, _update: &MetadataUpdate) -> Result<()> {
+        anyhow::bail!(
+            "WavPack metadata writing is not yet supported. File: {}",
+            path.display()
+        )
+    }
+
+    fn has_cover_art(&self, path: &Path) -> Result<bool> {
+        Ok(self.get_cover_art(path)?.is_some())
+    }
+
+    fn get_cover_art(&self, path: &Path) -> Result<Option<(Vec<u8>, String)>> {
+        let items = read_ape_tag(path).context("Failed to read WavPack tags")?;
+        Ok(ape_cover_art(&items))
+    }
+
+    fn set_cover_art(&self, path: &Path, _data: Vec<u8>, _mime_type: &str) -> Result<()> {
+        anyhow::bail!(
+            "WavPack cover art writing is not yet supported. File: {}",
+            path.display()
+        )
+    }
+
+    fn remove_cover_art(&self, path: &Path) -> Result<()> {
+        anyhow::bail!(
+            "WavPack cover art removal is not yet supported. File: {}",
+            path.display()
+        )
+    }
+
+    fn read_all_tags(&self, path: &Path) -> Result<Vec<(String, String)>> {
+        Ok(ape_raw_tags(
+            read_ape_tag(path).context("Failed to read WavPack tags")?,
+        ))
+    }
+
+    fn packet_duration_secs(&self, _path: &Path) -> Result<Option<u64>> {
+        // No WavPack demuxer is available to count packets
+        Ok(None)
+    }
+}
+
+/// Monkey's Audio (APE) file implementation (read-only; metadata from APEv2 tags)
+pub struct ApeFile;
+
+impl AudioFile for ApeFile {
+    fn format_name(&self) -> &'static str {
+        "ape"
+    }
+
+    fn parse_metadata(&self, path: &Path) -> Result<AudioMetadata> {
+        let mut audio_metadata =
+            ape_metadata(read_ape_tag(path).context("Failed to read APE tags")?);
+        audio_metadata.duration_secs = ape_duration_secs(path)?;
+        audio_metadata.lossless = true;
+        audio_metadata.set_stream_properties(None, path);
+        Ok(audio_metadata)
+    }
+
+    fn write_metadata(&self, path: &Path, _update: &MetadataUpdate) -> Result<()> {
+        anyhow::bail!(
+            "APE metadata writing is not yet supported. File: {}",
+            path.display()
+        )
+    }
+
+    fn has_cover_art(&self, path: &Path) -> Result<bool> {
+        Ok(self.get_cover_art(path)?.is_some())
+    }
+
+    fn get_cover_art(&self, path: &Path) -> Result<Option<(Vec<u8>, String)>> {
+        let items = read_ape_tag(path).context("Failed to read APE tags")?;
+        Ok(ape_cover_art(&items))
+    }
+
+    fn set_cover_art(&self, path: &Path, _data: Vec<u8>, _mime_type: &str) -> Result<()> {
+        anyhow::bail!(
+            "APE cover art writing is not yet supported. File: {}",
+            path.display()
+        )
+    }
+
+    fn remove_cover_art(&self, path: &Path) -> Result<()> {
+        anyhow::bail!(
+            "APE cover art removal is not yet supported. File: {}",
+            path.display()
+        )
+    }
+
+    fn read_all_tags(&self, path: &Path) -> Result<Vec<(String, String)>> {
+        Ok(ape_raw_tags(
+            read_ape_tag(path).context("Failed to read APE tags")?,
+        ))
+    }
+
+    fn packet_duration_secs(&self, _path: &Path) -> Result<Option<u64>> {
+        // No Monkey's Audio demuxer is available to count packets
+        Ok(None)
+    }
+}
+
+/// Creates the handler for one audio format
+pub type AudioFileFactory = Box<dyn Fn() -> Box<dyn AudioFile> + Send + Sync>;
+
+/// Audio file handlers by file extension.
+///
+/// [`AudioFormatRegistry::default`] holds the built-in formats; other
+/// formats (or replacements for built-in ones) are added with
+/// [`register`](Self::register) and handed to the library via
+/// [`MusicLibraryBuilder::formats`](crate::library::MusicLibraryBuilder::formats).
+pub struct AudioFormatRegistry {
+    /// Lowercase extension without the dot -> handler factory
+    factories: HashMap<String, AudioFileFactory>,
+}
+
+impl AudioFormatRegistry {
+    /// A registry without any formats
+    pub fn empty() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Handle files with extension `ext` (case-insensitive, with or without
+    /// the leading dot) with the handlers `factory` creates, replacing any
+    /// handler already registered for it
+    pub fn register<F>(&mut self, ext: &str, factory: F) -> &mut Self
+    where
+        F: Fn() -> Box<dyn AudioFile> + Send + Sync + 'static,
+    {
+        self.factories.insert(
+            ext.trim_start_matches('.').to_lowercase(),
+            Box::new(factory),
+        );
+        self
+    }
+
+    /// Handler for files with extension `ext`, if the format is registered
+    pub fn handler(&self, ext: &str) -> Option<Box<dyn AudioFile>> {
+        self.factories
+            .get(&ext.to_lowercase())
+            .map(|factory| factory())
+    }
+
+    /// Registered extensions, sorted
+    pub fn extensions(&self) -> Vec<&str> {
+        let mut extensions: Vec<&str> = self.factories.keys().map(String::as_str).collect();
+        extensions.sort_unstable();
+        extensions
+    }
+}
+
+impl Default for AudioFormatRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register("flac", || Box::new(FlacFile))
+            .register("mp3", || Box::new(Mp3File))
+            .register("ogg", || Box::new(OggFile))
+            .register("opus", || Box::new(OpusFile))
+            .register("m4a", || Box::new(M4aFile))
+            .register("wv", || Box::new(WavPackFile))
+            .register("ape", || Box::new(ApeFile));
+        registry
+    }
+}
+
+impl std::fmt::Debug for AudioFormatRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AudioFormatRegistry")
+            .field("extensions", &self.extensions())
+            .finish()
+    }
+}
+
+static DEFAULT_FORMATS: std::sync::LazyLock<std::sync::Arc<AudioFormatRegistry>> =
+    std::sync::LazyLock::new(|| std::sync::Arc::new(AudioFormatRegistry::default()));
+
+/// Shared registry of the built-in formats
+pub fn default_formats() -> std::sync::Arc<AudioFormatRegistry> {
+    DEFAULT_FORMATS.clone()
+}
+
+/// Factory function to create the appropriate AudioFile implementation based on file extension.
+/// Kept for compatibility; resolves through [`default_formats`].
+#[allow(dead_code)] // The server resolves handlers through its library's registry
+pub fn get_audio_file_handler(extension: &str) -> Option<Box<dyn AudioFile>> {
+    DEFAULT_FORMATS.handler(extension)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Write a tagless MP3 made of silent MPEG-1 Layer III frames
+    pub(crate) fn write_silent_mp3(path: &Path) {
+        let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+        frame.resize(417, 0);
+        let data: Vec<u8> = frame.iter().cycle().take(417 * 40).copied().collect();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, data).unwrap();
+    }
+
+    /// Wrap a payload in an MP4 box header
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    /// Wrap a payload in an MP4 full box header (version 0, no flags)
+    fn mp4_full_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        mp4_box(kind, &[&[0u8; 4][..], payload].concat())
+    }
+
+    /// Write a minimal tagless M4A with a single, empty AAC track
+    fn write_empty_m4a(path: &Path) {
+        let matrix: Vec<u8> = [0x0001_0000u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+
+        let mut mvhd = vec![0u8; 8];
+        mvhd.extend_from_slice(&1000u32.to_be_bytes());
+        mvhd.extend_from_slice(&1000u32.to_be_bytes());
+        mvhd.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        mvhd.extend_from_slice(&0x0100u16.to_be_bytes());
+        mvhd.extend_from_slice(&[0u8; 10]);
+        mvhd.extend_from_slice(&matrix);
+        mvhd.extend_from_slice(&[0u8; 24]);
+        mvhd.extend_from_slice(&2u32.to_be_bytes());
+
+        let mut tkhd = vec![0u8; 8];
+        tkhd.extend_from_slice(&1u32.to_be_bytes());
+        tkhd.extend_from_slice(&[0u8; 4]);
+        tkhd.extend_from_slice(&1000u32.to_be_bytes());
+        tkhd.extend_from_slice(&[0u8; 12]);
+        tkhd.extend_from_slice(&0x0100u16.to_be_bytes());
+        tkhd.extend_from_slice(&[0u8; 2]);
+        tkhd.extend_from_slice(&matrix);
+        tkhd.extend_from_slice(&[0u8; 8]);
+
+        let mut mdhd = vec![0u8; 8];
+        mdhd.extend_from_slice(&44100u32.to_be_bytes());
+        mdhd.extend_from_slice(&44100u32.to_be_bytes());
+        mdhd.extend_from_slice(&[0x55, 0xC4, 0, 0]);
+
+        let mut hdlr = vec![0u8; 4];
+        hdlr.extend_from_slice(b"soun");
+        hdlr.extend_from_slice(&[0u8; 13]);
+
+        // ES descriptor -> decoder config (AAC) -> AudioSpecificConfig (LC, 44.1 kHz, stereo)
+        let dec_specific = [&[0x05, 2][..], &[0x12, 0x10]].concat();
+        let dec_config = [
+            &[0x04, (13 + dec_specific.len()) as u8, 0x40, 0x15][..],
+            &[0u8; 11],
+            &dec_specific,
+        ]
+        .concat();
+        let es = [
+            &[0x03, (3 + dec_config.len() + 3) as u8, 0, 1, 0][..],
+            &dec_config,
+            &[0x06, 1, 2],
+        ]
+        .concat();
+
+        let mut mp4a = vec![0u8; 6];
+        mp4a.extend_from_slice(&1u16.to_be_bytes());
+        mp4a.extend_from_slice(&[0u8; 8]);
+        mp4a.extend_from_slice(&2u16.to_be_bytes());
+        mp4a.extend_from_slice(&16u16.to_be_bytes());
+        mp4a.extend_from_slice(&[0u8; 4]);
+        mp4a.extend_from_slice(&(44100u32 << 16).to_be_bytes());
+        mp4a.extend(mp4_full_box(b"esds", &es));
+
+        let stsd = mp4_full_box(
+            b"stsd",
+            &[&1u32.to_be_bytes()[..], &mp4_box(b"mp4a", &mp4a)].concat(),
+        );
+        let stbl = mp4_box(
+            b"stbl",
+            &[
+                stsd,
+                mp4_full_box(b"stts", &[0u8; 4]),
+                mp4_full_box(b"stsc", &[0u8; 4]),
+                mp4_full_box(b"stsz", &[0u8; 8]),
+                mp4_full_box(b"stco", &[0u8; 4]),
+            ]
+            .concat(),
+        );
+        let minf = mp4_box(b"minf", &[mp4_full_box(b"smhd", &[0u8; 4]), stbl].concat());
+        let mdia = mp4_box(
+            b"mdia",
+            &[
+                mp4_full_box(b"mdhd", &mdhd),
+                mp4_full_box(b"hdlr", &hdlr),
+                minf,
+            ]
+            .concat(),
+        );
+        let trak = mp4_box(b"trak", &[mp4_full_box(b"tkhd", &tkhd), mdia].concat());
+        let moov = mp4_box(b"moov", &[mp4_full_box(b"mvhd", &mvhd), trak].concat());
+        let ftyp = mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom");
+
+        std::fs::write(path, [ftyp, moov, mp4_box(b"mdat", &[])].concat()).unwrap();
+    }
+
+    fn custom_field_update(key: &str, value: &str) -> MetadataUpdate {
+        MetadataUpdate {
+            title: None,
+            artist: None,
+            album: None,
+            album_artist: None,
+            genre: None,
+            year: None,
+            track_number: None,
+            track_total: None,
+            disc_number: None,
+            disc_total: None,
+            composer: None,
+            comment: None,
+            custom_fields: Some(HashMap::from([(key.to_string(), value.to_string())])),
+        }
+    }
+
+    #[test]
+    fn test_mp3_custom_field_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.mp3");
+        write_silent_mp3(&path);
+
+        Mp3File
+            .write_metadata(&path, &custom_field_update("Mood", "Mellow"))
+            .unwrap();
+        let metadata = Mp3File.parse_metadata(&path).unwrap();
+
+        assert_eq!(
+            metadata.custom_fields.get("Mood").map(String::as_str),
+            Some("Mellow")
+        );
+    }
+
+    #[test]
+    fn test_split_number_total() {
+        let split = |value| split_number_total(value);
+        assert_eq!(split("3"), (Some("3".to_string()), None));
+        assert_eq!(
+            split(" 3 / 12 "),
+            (Some("3".to_string()), Some("12".to_string()))
+        );
+        assert_eq!(split("/5"), (None, Some("5".to_string())));
+        assert_eq!(split("3/"), (Some("3".to_string()), None));
+        assert_eq!(split(""), (None, None));
+
+        // An explicit total wins over one embedded in the number
+        assert_eq!(
+            number_and_total(Some("3/12"), Some("13")),
+            (Some("3".to_string()), Some("13".to_string()))
+        );
+        assert_eq!(number_and_total(None, None), (None, None));
+    }
+
+    #[test]
+    fn test_mp3_track_and_disc_totals() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.mp3");
+        write_silent_mp3(&path);
+
+        let update = MetadataUpdate {
+            track_number: Some("3/12".to_string()),
+            disc_number: Some("1".to_string()),
+            disc_total: Some("2".to_string()),
+            ..custom_field_update("Mood", "Mellow")
+        };
+        Mp3File.write_metadata(&path, &update).unwrap();
+
+        let metadata = Mp3File.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.track_number.as_deref(), Some("3"));
+        assert_eq!(metadata.track_total.as_deref(), Some("12"));
+        assert_eq!(metadata.disc_number.as_deref(), Some("1"));
+        assert_eq!(metadata.disc_total.as_deref(), Some("2"));
+        // ID3 keeps both in one frame
+        let tags = Mp3File.read_all_tags(&path).unwrap();
+        assert!(tags.contains(&("TRCK".to_string(), "3/12".to_string())));
+        assert!(tags.contains(&("TPOS".to_string(), "1/2".to_string())));
+
+        // A bare number keeps the total already in the file
+        let update = MetadataUpdate {
+            track_number: Some("4".to_string()),
+            ..custom_field_update("Mood", "Mellow")
+        };
+        Mp3File.write_metadata(&path, &update).unwrap();
+        let metadata = Mp3File.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.track_number.as_deref(), Some("4"));
+        assert_eq!(metadata.track_total.as_deref(), Some("12"));
+    }
+
+    #[test]
+    fn test_m4a_track_and_disc_totals() {
+        use symphonia::core::meta::StandardTagKey;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.m4a");
+        write_empty_m4a(&path);
+
+        let update = MetadataUpdate {
+            track_number: Some("3/12".to_string()),
+            disc_number: Some("/2".to_string()),
+            ..custom_field_update("Mood", "Mellow")
+        };
+        M4aFile.write_metadata(&path, &update).unwrap();
+
+        let tag = mp4ameta::Tag::read_from_path(&path).unwrap();
+        assert_eq!(tag.track(), (Some(3), Some(12)));
+        assert_eq!(tag.total_discs(), Some(2));
+
+        // Symphonia reports `trkn` and `disk` as separate number and total keys
+        let mut metadata = AudioMetadata::new();
+        metadata.update_from_std_key(StandardTagKey::TrackNumber, "3".to_string());
+        metadata.update_from_std_key(StandardTagKey::TrackTotal, "12".to_string());
+        metadata.update_from_std_key(StandardTagKey::DiscNumber, "1/2".to_string());
+        assert_eq!(metadata.track_number.as_deref(), Some("3"));
+        assert_eq!(metadata.track_total.as_deref(), Some("12"));
+        assert_eq!(metadata.disc_number.as_deref(), Some("1"));
+        assert_eq!(metadata.disc_total.as_deref(), Some("2"));
+    }
+
+    #[test]
+    fn test_m4a_custom_field_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.m4a");
+        write_empty_m4a(&path);
+
+        M4aFile
+            .write_metadata(&path, &custom_field_update("Mood", "Mellow"))
+            .unwrap();
+        let metadata = M4aFile.parse_metadata(&path).unwrap();
+
+        assert_eq!(
+            metadata.custom_fields.get("Mood").map(String::as_str),
+            Some("Mellow")
+        );
+    }
+
+    /// Vorbis comment header packet holding `entries`
+    fn vorbis_comment_packet(entries: &[&str]) -> Vec<u8> {
+        VorbisComments {
+            vendor: b"test encoder".to_vec(),
+            entries: entries.iter().map(|e| e.as_bytes().to_vec()).collect(),
+        }
+        .to_packet(OggCodec::Vorbis)
+    }
+
+    /// Write an Ogg Vorbis file with the given comment header (or none), a
+    /// placeholder setup header and a few audio pages
+    fn write_ogg_vorbis(path: &Path, comment: Option<Vec<u8>>) -> Vec<u8> {
+        let mut ident = vec![1];
+        ident.extend_from_slice(VORBIS_SIGNATURE);
+        ident.extend_from_slice(&0u32.to_le_bytes()); // version
+        ident.push(2); // channels
+        ident.extend_from_slice(&44100u32.to_le_bytes());
+        ident.extend_from_slice(&[0; 12]); // bitrates
+        ident.push(0xB8); // block sizes 256 and 2048
+        ident.push(1); // framing
+        let mut setup = vec![5];
+        setup.extend_from_slice(VORBIS_SIGNATURE);
+        setup.extend_from_slice(&[0; 16]);
+
+        let mut out = Vec::new();
+        let next = write_ogg_packets(&mut out, 7, 0, OGG_BOS, &[&ident]);
+        let headers: Vec<&[u8]> = comment
+            .iter()
+            .map(Vec::as_slice)
+            .chain([&setup[..]])
+            .collect();
+        let first_audio_page = write_ogg_packets(&mut out, 7, next, 0, &headers);
+
+        let audio: Vec<u8> = (0..3000u32).map(|i| (i % 251) as u8 & !1).collect();
+        for (index, chunk) in audio.chunks(1000).enumerate() {
+            let mut segments = vec![255; chunk.len() / 255];
+            segments.push((chunk.len() % 255) as u8);
+            write_ogg_page(
+                &mut out,
+                &OggPage {
+                    header_type: if index == 2 { 0x04 } else { 0 },
+                    granule: (index as u64 + 1) * 44100,
+                    serial: 7,
+                    sequence: first_audio_page + index as u32,
+                    segments: &segments,
+                    body: chunk,
+                    raw: &[],
+                },
+            );
+        }
+        std::fs::write(path, &out).unwrap();
+        audio
+    }
+
+    /// Bodies of the audio pages, which must survive a tag rewrite untouched
+    fn ogg_audio(path: &Path) -> Vec<u8> {
+        let data = std::fs::read(path).unwrap();
+        let pages = read_ogg_pages(&data).unwrap();
+        let (_, header_pages) = match OggCodec::detect(&pages[0]) {
+            OggCodec::Vorbis => vorbis_header_packets(&pages).unwrap(),
+            OggCodec::Opus => opus_header_packets(&pages).unwrap(),
+        };
+        let sequences: Vec<u32> = pages.iter().map(|page| page.sequence).collect();
+        assert_eq!(sequences, (0..pages.len() as u32).collect::<Vec<_>>());
+        pages[header_pages..]
+            .iter()
+            .flat_map(|page| page.body.iter().copied())
+            .collect()
+    }
+
+    #[test]
+    fn test_ogg_metadata_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.ogg");
+        let audio = write_ogg_vorbis(
+            &path,
+            Some(vorbis_comment_packet(&[
+                "title=Old",
+                "MOOD=Calm",
+                "LABEL=Indie",
+            ])),
+        );
+        assert_eq!(
+            OggFile.parse_metadata(&path).unwrap().title.as_deref(),
+            Some("Old")
+        );
+
+        let update = MetadataUpdate {
+            title: Some("New Title".to_string()),
+            artist: Some("Artist".to_string()),
+            album: Some("Album".to_string()),
+            album_artist: Some("Album Artist".to_string()),
+            genre: Some("Jazz".to_string()),
+            year: Some("2024".to_string()),
+            track_number: Some("3/12".to_string()),
+            track_total: None,
+            disc_number: Some("1".to_string()),
+            disc_total: Some("2".to_string()),
+            composer: Some("Composer".to_string()),
+            comment: Some("Nice".to_string()),
+            custom_fields: Some(HashMap::from([("Mood".to_string(), "Mellow".to_string())])),
+        };
+        OggFile.write_metadata(&path, &update).unwrap();
+
+        let metadata = OggFile.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("New Title"));
+        assert_eq!(metadata.artist.as_deref(), Some("Artist"));
+        assert_eq!(metadata.album.as_deref(), Some("Album"));
+        assert_eq!(metadata.album_artist.as_deref(), Some("Album Artist"));
+        assert_eq!(metadata.genre.as_deref(), Some("Jazz"));
+        assert_eq!(metadata.year.as_deref(), Some("2024"));
+        assert_eq!(metadata.track_number.as_deref(), Some("3"));
+        assert_eq!(metadata.track_total.as_deref(), Some("12"));
+        assert_eq!(metadata.disc_number.as_deref(), Some("1"));
+        assert_eq!(metadata.disc_total.as_deref(), Some("2"));
+        assert_eq!(metadata.composer.as_deref(), Some("Composer"));
+        assert_eq!(metadata.comment.as_deref(), Some("Nice"));
+        assert_eq!(metadata.custom_fields["MOOD"], "Mellow");
+        assert_eq!(metadata.custom_fields["LABEL"], "Indie");
+        assert_eq!(ogg_audio(&path), audio);
+
+        // Field names must be valid Vorbis comment keys
+        assert!(
+            OggFile
+                .write_metadata(&path, &custom_field_update("A=B", "x"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_ogg_metadata_without_comment_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.ogg");
+        let audio = write_ogg_vorbis(&path, None);
+
+        OggFile
+            .write_metadata(&path, &custom_field_update("Mood", "Mellow"))
+            .unwrap();
+        let metadata = OggFile.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.custom_fields["MOOD"], "Mellow");
+        assert_eq!(ogg_audio(&path), audio);
+    }
+
+    #[test]
+    fn test_ogg_large_comment_spans_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.ogg");
+        let audio = write_ogg_vorbis(&path, Some(vorbis_comment_packet(&["TITLE=Short"])));
+
+        // Over 255 segments, so the comment header needs a second page and
+        // every audio page is renumbered
+        let long = "x".repeat(70_000);
+        OggFile
+            .write_metadata(&path, &custom_field_update("NOTES", &long))
+            .unwrap();
+        let metadata = OggFile.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Short"));
+        assert_eq!(metadata.custom_fields["NOTES"], long);
+        assert_eq!(ogg_audio(&path), audio);
+    }
+
+    /// Write an Ogg Opus file with the given comment entries and 20 ms CELT
+    /// packets adding up to `frames` samples at 48 kHz after a 312 sample pre-skip
+    fn write_ogg_opus(path: &Path, entries: &[&str], frames: u64) -> Vec<u8> {
+        const PRE_SKIP: u16 = 312;
+        const PACKET_SAMPLES: u64 = 960;
+
+        let mut head = OPUS_HEAD_SIGNATURE.to_vec();
+        head.push(1); // version
+        head.push(2); // channels
+        head.extend_from_slice(&PRE_SKIP.to_le_bytes());
+        head.extend_from_slice(&44100u32.to_le_bytes()); // original sample rate
+        head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+        head.push(0); // channel mapping family
+        let tags = VorbisComments {
+            vendor: b"test encoder".to_vec(),
+            entries: entries.iter().map(|e| e.as_bytes().to_vec()).collect(),
+        }
+        .to_packet(OggCodec::Opus);
+
+        let mut out = Vec::new();
+        let next = write_ogg_packets(&mut out, 9, 0, OGG_BOS, &[&head]);
+        let first_audio_page = write_ogg_packets(&mut out, 9, next, 0, &[&tags]);
+
+        // TOC byte 0xF8: CELT fullband, 20 ms, one frame per packet
+        let packet_count = (frames + u64::from(PRE_SKIP)).div_ceil(PACKET_SAMPLES);
+        let packets: Vec<Vec<u8>> = (0..packet_count)
+            .map(|i| vec![0xF8, i as u8, 0x55, 0xAA])
+            .collect();
+        let mut granule = 0;
+        let mut audio = Vec::new();
+        for (index, chunk) in packets.chunks(50).enumerate() {
+            granule += chunk.len() as u64 * PACKET_SAMPLES;
+            let segments: Vec<u8> = chunk.iter().map(|p| p.len() as u8).collect();
+            let body = chunk.concat();
+            let last = (index + 1) * 50 >= packets.len();
+            write_ogg_page(
+                &mut out,
+                &OggPage {
+                    header_type: if last { 0x04 } else { 0 },
+                    // The last page ends at the real length, trimming the final packet
+                    granule: if last {
+                        frames + u64::from(PRE_SKIP)
+                    } else {
+                        granule
+                    },
+                    serial: 9,
+                    sequence: first_audio_page + index as u32,
+                    segments: &segments,
+                    body: &body,
+                    raw: &[],
+                },
+            );
+            audio.extend_from_slice(&body);
+        }
+        std::fs::write(path, &out).unwrap();
+        audio
+    }
+
+    #[test]
+    fn test_opus_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.opus");
+        let audio = write_ogg_opus(
+            &path,
+            &[
+                "TITLE=Opus Song",
+                "artist=Opus Artist",
+                "MOOD=Calm",
+                "Lyrics=Opus words",
+            ],
+            48_000 * 185,
+        );
+
+        let handler = AudioFormatRegistry::default().handler("OPUS").unwrap();
+        assert_eq!(handler.format_name(), "opus");
+        let lyrics = handler.read_lyrics(&path).unwrap().unwrap();
+        assert_eq!(lyrics.content, "Opus words");
+        let metadata = handler.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Opus Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("Opus Artist"));
+        assert_eq!(metadata.duration_secs, Some(185));
+        assert!(!metadata.lossless);
+        assert!(!handler.has_cover_art(&path).unwrap());
+        assert_eq!(audio_mime_type("opus"), Some("audio/opus"));
+
+        // Tags are rewritten in the OpusTags packet, leaving the audio alone
+        handler
+            .write_metadata(&path, &custom_field_update("Mood", "Mellow"))
+            .unwrap();
+        let metadata = handler.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Opus Song"));
+        assert_eq!(metadata.custom_fields["MOOD"], "Mellow");
+        assert_eq!(metadata.duration_secs, Some(185));
+        assert_eq!(ogg_audio(&path), audio);
+    }
+
+    #[test]
+    fn test_audio_mime_type() {
+        assert_eq!(audio_mime_type("FLAC"), Some("audio/flac"));
+        assert_eq!(audio_mime_type("txt"), None);
+        // Every handled format gets a MIME type in its probe hint
+        for extension in default_formats().extensions() {
+            assert!(audio_mime_type(extension).is_some(), "{}", extension);
+        }
+    }
+
+    #[test]
+    fn test_parse_file_whose_extension_and_container_disagree() {
+        use id3::TagLike;
+
+        // An MP3 stream saved with an .m4a name: the MP4 reader would reject it,
+        // so its ID3 tags are only found when the container is probed by content
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("actually-mp3.m4a");
+        write_silent_mp3(&path);
+        let mut tag = id3::Tag::new();
+        tag.set_title("Misnamed Song");
+        tag.set_artist("Misnamed Artist");
+        tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+
+        let metadata = M4aFile.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Misnamed Song"));
+        assert_eq!(metadata.artist.as_deref(), Some("Misnamed Artist"));
+        assert_eq!(metadata.duration_secs, Some(1));
+        assert!(!metadata.lossless);
+    }
+
+    #[test]
+    fn test_mp3_compilation_flag() {
+        use id3::TagLike;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.mp3");
+        write_silent_mp3(&path);
+        assert!(!Mp3File.parse_metadata(&path).unwrap().compilation);
+
+        let mut tag = id3::Tag::new();
+        tag.set_text("TCMP", "1");
+        tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+
+        assert!(Mp3File.parse_metadata(&path).unwrap().compilation);
+    }
+
+    #[test]
+    fn test_mp3_is_lossy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.mp3");
+        write_silent_mp3(&path);
+        assert!(!Mp3File.parse_metadata(&path).unwrap().lossless);
+    }
+
+    #[test]
+    fn test_mp3_stream_properties() {
+        use id3::TagLike;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.mp3");
+        write_silent_mp3(&path);
+
+        // A large tag doesn't count towards the bitrate
+        let mut tag = id3::Tag::new();
+        tag.set_title("Song");
+        tag.add_frame(id3::frame::Picture {
+            mime_type: "image/jpeg".to_string(),
+            picture_type: id3::frame::PictureType::CoverFront,
+            description: String::new(),
+            data: vec![0xFF; 64 * 1024],
+        });
+        tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+
+        let metadata = Mp3File.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.sample_rate, Some(44100));
+        assert_eq!(metadata.channels, Some(2));
+        assert_eq!(metadata.bits_per_sample, None);
+        assert_eq!(metadata.bitrate_kbps, Some(128));
+    }
+
+    #[test]
+    fn test_audio_data_len_skips_container_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Only the `mdat` payload counts, not a `moov` holding a large cover
+        let m4a = dir.path().join("track.m4a");
+        let cover = mp4_box(b"udta", &vec![0xFF; 64 * 1024]);
+        let data = [
+            mp4_box(b"ftyp", b"M4A \0\0\0\0M4A isom"),
+            mp4_box(b"moov", &cover),
+            mp4_box(b"mdat", &[0x55; 1000]),
+        ]
+        .concat();
+        std::fs::write(&m4a, data).unwrap();
+        assert_eq!(audio_data_len(&m4a).unwrap(), 1000);
+
+        // Nor do Ogg comment pages carrying a cover picture
+        let plain = dir.path().join("plain.opus");
+        let with_cover = dir.path().join("cover.opus");
+        write_ogg_opus(&plain, &["TITLE=Song"], 48_000 * 60);
+        let picture = format!("METADATA_BLOCK_PICTURE={}", "A".repeat(96 * 1024));
+        write_ogg_opus(&with_cover, &["TITLE=Song", &picture], 48_000 * 60);
+        assert!(std::fs::metadata(&with_cover).unwrap().len() > 96 * 1024);
+        assert_eq!(
+            audio_data_len(&with_cover).unwrap(),
+            audio_data_len(&plain).unwrap()
+        );
+        let handler = AudioFormatRegistry::default().handler("opus").unwrap();
+        let bitrate = |path| handler.parse_metadata(path).unwrap().bitrate_kbps;
+        assert!(bitrate(&plain).is_some());
+        assert_eq!(bitrate(&with_cover), bitrate(&plain));
+    }
+
+    #[test]
+    fn test_average_bitrate() {
+        assert_eq!(average_bitrate_kbps(16000 * 3, 3.0), Some(128));
+        assert_eq!(average_bitrate_kbps(1000, 0.0), None);
+        assert_eq!(average_bitrate_kbps(0, 3.0), None);
+    }
+
+    #[test]
+    fn test_mp3_write_lyrics() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.mp3");
+        write_silent_mp3(&path);
+
+        Mp3File.write_lyrics(&path, "[00:01.00]First").unwrap();
+        Mp3File.write_lyrics(&path, "[00:01.00]Second").unwrap();
+
+        let tag = id3::Tag::read_from_path(&path).unwrap();
+        let lyrics: Vec<_> = tag.lyrics().map(|l| l.text.as_str()).collect();
+        assert_eq!(lyrics, ["[00:01.00]Second"]);
+        assert!(!OggFile.supports_embedded_lyrics());
+        assert!(OggFile.write_lyrics(&path, "text").is_err());
+    }
+
+    #[test]
+    fn test_mp3_read_synced_lyrics() {
+        use id3::TagLike;
+        use id3::frame::{SynchronisedLyrics, SynchronisedLyricsType, TimestampFormat};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.mp3");
+        write_silent_mp3(&path);
+        assert_eq!(Mp3File.read_lyrics(&path).unwrap(), None);
+
+        Mp3File.write_lyrics(&path, "Plain line").unwrap();
+        assert_eq!(
+            Mp3File.read_lyrics(&path).unwrap(),
+            Some(EmbeddedLyrics {
+                content: "Plain line".to_string(),
+                format: LyricFormat::Plain,
+                language: Some("eng".to_string()),
+            })
+        );
+
+        // A SYLT frame next to the USLT one wins
+        let mut tag = id3::Tag::read_from_path(&path).unwrap();
+        tag.add_frame(SynchronisedLyrics {
+            lang: "XXX".to_string(),
+            timestamp_format: TimestampFormat::Ms,
+            content_type: SynchronisedLyricsType::Lyrics,
+            description: String::new(),
+            content: vec![
+                (65_432, "\nSecond".to_string()),
+                (1_500, "First".to_string()),
+                (3_725_010, "Much later".to_string()),
+            ],
+        });
+        tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+        assert_eq!(
+            Mp3File.read_lyrics(&path).unwrap(),
+            Some(EmbeddedLyrics {
+                content: "[00:01.50]First\n[01:05.43]Second\n[62:05.01]Much later".to_string(),
+                format: LyricFormat::Lrc,
+                language: None,
+            })
+        );
+
+        // Writing lyrics replaces the synced ones too
+        Mp3File.write_lyrics(&path, "New").unwrap();
+        let lyrics = Mp3File.read_lyrics(&path).unwrap().unwrap();
+        assert_eq!(lyrics.content, "New");
+    }
+
+    #[test]
+    fn test_vorbis_comment_lyrics() {
+        let tags = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(vorbis_comment_lyrics(&tags(&[("TITLE", "Song")])), None);
+
+        let lyrics = vorbis_comment_lyrics(&tags(&[
+            ("UNSYNCEDLYRICS", "Plain"),
+            ("lyrics", " [00:01.00]Synced\n"),
+        ]))
+        .unwrap();
+        assert_eq!(lyrics.content, "[00:01.00]Synced");
+        assert_eq!(lyrics.format, LyricFormat::Lrc);
+
+        // Blank values fall through to the next key
+        let lyrics =
+            vorbis_comment_lyrics(&tags(&[("LYRICS", " "), ("UNSYNCED LYRICS", "Plain")])).unwrap();
+        assert_eq!(lyrics.content, "Plain");
+        assert_eq!(lyrics.format, LyricFormat::Plain);
+        assert_eq!(lyrics.language, None);
+    }
+
+    #[test]
+    fn test_replay_gain() {
+        assert_eq!(parse_replay_gain("-6.48 dB"), Some(-6.48));
+        assert_eq!(parse_replay_gain("+1.20 DB"), Some(1.2));
+        assert_eq!(parse_replay_gain("0.5"), Some(0.5));
+        assert_eq!(parse_replay_gain("loud"), None);
+        assert_eq!(parse_replay_gain("NaN dB"), None);
+
+        let mut metadata = AudioMetadata::new();
+        metadata
+            .custom_fields
+            .insert("replaygain_track_gain".to_string(), "-3.10 dB".to_string());
+        assert_eq!(metadata.replay_gain("TRACK"), Some(-3.1));
+        assert_eq!(metadata.replay_gain("ALBUM"), None);
+    }
+
+    #[test]
+    fn test_is_tag_flag_set() {
+        assert!(is_tag_flag_set("1"));
+        assert!(is_tag_flag_set(" True "));
+        assert!(!is_tag_flag_set("0"));
+        assert!(!is_tag_flag_set(""));
+    }
+
+    #[test]
+    fn test_mp3_read_all_tags_includes_frame_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.mp3");
+        write_silent_mp3(&path);
+
+        let mut update = custom_field_update("Mood", "Mellow");
+        update.title = Some("Song".to_string());
+        Mp3File.write_metadata(&path, &update).unwrap();
+        Mp3File
+            .set_cover_art(&path, vec![0xFF, 0xD8, 0xFF, 0xE0], "image/jpeg")
+            .unwrap();
+
+        let tags = Mp3File.read_all_tags(&path).unwrap();
+
+        assert!(tags.contains(&("TIT2".to_string(), "Song".to_string())));
+        assert!(
+            tags.iter()
+                .any(|(k, v)| k == "TXXX" && v.contains("Mellow"))
+        );
+        assert!(tags.contains(&("APIC".to_string(), "<binary 4 bytes>".to_string())));
+    }
+
+    #[test]
+    fn test_mp3_header_duration_estimate() {
+        // 128 kbps MPEG-1 Layer III: 16000 bytes of audio per second
+        let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+        frame.resize(400, 0);
+        let audio: Vec<u8> = frame.iter().cycle().take(16000 * 3).copied().collect();
+
+        let mut id3 = b"ID3\x03\x00\x00\x00\x00\x01\x00".to_vec();
+        id3.resize(10 + 128, 0);
+        let mut id3v1 = b"TAG".to_vec();
+        id3v1.resize(128, 0);
+
+        let data = [id3, audio, id3v1].concat();
+        let duration = mp3_header_duration_secs(&mut std::io::Cursor::new(data)).unwrap();
+
+        assert_eq!(duration, Some(3));
+    }
+
+    #[test]
+    fn test_mp3_header_bitrate_rejects_invalid_headers() {
+        assert_eq!(mp3_header_bitrate([0xFF, 0xFB, 0x90, 0x00]), Some(128));
+        assert_eq!(mp3_header_bitrate([0xFF, 0xF3, 0x90, 0x00]), Some(80));
+        assert_eq!(mp3_header_bitrate([0xFF, 0xFB, 0xF0, 0x00]), None);
+        assert_eq!(mp3_header_bitrate([0xFF, 0xFB, 0x9C, 0x00]), None);
+        assert_eq!(mp3_header_bitrate([0x49, 0x44, 0x33, 0x03]), None);
+    }
+
+    #[test]
+    fn test_packet_duration_matches_frame_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.mp3");
+        write_silent_mp3(&path);
+
+        assert_eq!(Mp3File.packet_duration_secs(&path).unwrap(), Some(1));
+    }
+
+    /// Build an APEv2 tag (items + footer) from (key, flags, value) items
+    fn ape_tag(items: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (key, flags, value) in items {
+            data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            data.extend_from_slice(&flags.to_le_bytes());
+            data.extend_from_slice(key.as_bytes());
+            data.push(0);
+            data.extend_from_slice(value);
+        }
+
+        let mut footer = b"APETAGEX".to_vec();
+        footer.extend_from_slice(&2000u32.to_le_bytes());
+        footer.extend_from_slice(&((data.len() + 32) as u32).to_le_bytes());
+        footer.extend_from_slice(&(items.len() as u32).to_le_bytes());
+        footer.extend_from_slice(&[0u8; 12]);
+
+        [data, footer].concat()
+    }
+
+    fn sample_ape_tag() -> Vec<u8> {
+        ape_tag(&[
+            ("Title", 0, "Live Track".as_bytes()),
+            ("Album Artist", 0, "The Band".as_bytes()),
+            ("Year", 0, "1999".as_bytes()),
+            ("Track", 0, "3/12".as_bytes()),
+            ("Catalog", 0, "ABC-123".as_bytes()),
+            ("Cover Art (Front)", 2, b"cover.jpg\0\xFF\xD8\xFF\xE0"),
+        ])
+    }
+
+    /// WavPack block header for a 44.1 kHz stream with `total_samples` samples
+    fn wavpack_header(total_samples: u32) -> Vec<u8> {
+        let mut header = b"wvpk".to_vec();
+        header.extend_from_slice(&24u32.to_le_bytes()); // block size
+        header.extend_from_slice(&0x410u16.to_le_bytes()); // version
+        header.extend_from_slice(&[0, 0]); // block index / total samples high bytes
+        header.extend_from_slice(&total_samples.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes()); // block index
+        header.extend_from_slice(&total_samples.to_le_bytes()); // block samples
+        header.extend_from_slice(&(9u32 << 23).to_le_bytes()); // flags: 44100 Hz
+        header.extend_from_slice(&0u32.to_le_bytes()); // crc
+        header
+    }
+
+    #[test]
+    fn test_format_registry() {
+        let registry = AudioFormatRegistry::default();
+        assert_eq!(
+            registry.extensions(),
+            ["ape", "flac", "m4a", "mp3", "ogg", "opus", "wv"]
+        );
+        assert!(registry.handler("MP3").is_some());
+        assert!(registry.handler("wav").is_none());
+
+        let mut registry = AudioFormatRegistry::empty();
+        registry.register(".MPGA", || Box::new(Mp3File));
+        assert!(registry.handler("mpga").is_some());
+        assert!(registry.handler("mp3").is_none());
+    }
+
+    #[test]
+    fn test_wavpack_metadata_and_cover() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.wv");
+        std::fs::write(
+            &path,
+            [wavpack_header(44100 * 185), sample_ape_tag()].concat(),
+        )
+        .unwrap();
+
+        let handler = get_audio_file_handler("WV").unwrap();
+        let metadata = handler.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Live Track"));
+        assert_eq!(metadata.album_artist.as_deref(), Some("The Band"));
+        assert_eq!(metadata.year.as_deref(), Some("1999"));
+        assert_eq!(metadata.track_number.as_deref(), Some("3"));
+        assert_eq!(metadata.track_total.as_deref(), Some("12"));
+        assert_eq!(metadata.custom_fields["CATALOG"], "ABC-123");
+        assert_eq!(metadata.duration_secs, Some(185));
+
+        assert!(handler.has_cover_art(&path).unwrap());
+        assert_eq!(
+            handler.get_cover_art(&path).unwrap().unwrap(),
+            (vec![0xFF, 0xD8, 0xFF, 0xE0], "image/jpeg".to_string())
+        );
+        assert!(handler.read_all_tags(&path).unwrap().contains(&(
+            "Cover Art (Front)".to_string(),
+            "<binary 14 bytes>".to_string()
+        )));
+        let update: MetadataUpdate = serde_json::from_str(r#"{"title": "New"}"#).unwrap();
+        assert!(handler.write_metadata(&path, &update).is_err());
+    }
+
+    #[test]
+    fn test_wavpack_unknown_length_and_no_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.wv");
+        std::fs::write(&path, wavpack_header(u32::MAX)).unwrap();
+
+        let metadata = WavPackFile.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.duration_secs, None);
+        assert_eq!(metadata.title, None);
+        assert!(!WavPackFile.has_cover_art(&path).unwrap());
+    }
+
+    #[test]
+    fn test_ape_metadata_with_trailing_id3v1() {
+        // APE_DESCRIPTOR (52 bytes) + APE_HEADER (24 bytes), version 3.99
+        let mut header = b"MAC ".to_vec();
+        header.extend_from_slice(&3990u16.to_le_bytes());
+        header.extend_from_slice(&[0, 0]);
+        header.extend_from_slice(&52u32.to_le_bytes()); // descriptor bytes
+        header.extend_from_slice(&24u32.to_le_bytes()); // header bytes
+        header.resize(52, 0);
+        header.extend_from_slice(&2000u16.to_le_bytes()); // compression level
+        header.extend_from_slice(&0u16.to_le_bytes()); // format flags
+        header.extend_from_slice(&73728u32.to_le_bytes()); // blocks per frame
+        header.extend_from_slice(&22050u32.to_le_bytes()); // final frame blocks
+        header.extend_from_slice(&5u32.to_le_bytes()); // total frames
+        header.extend_from_slice(&16u16.to_le_bytes()); // bits per sample
+        header.extend_from_slice(&2u16.to_le_bytes()); // channels
+        header.extend_from_slice(&44100u32.to_le_bytes()); // sample rate
+
+        let mut id3v1 = b"TAG".to_vec();
+        id3v1.resize(128, 0);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.ape");
+        std::fs::write(&path, [header, sample_ape_tag(), id3v1].concat()).unwrap();
+
+        let metadata = ApeFile.parse_metadata(&path).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Live Track"));
+        // (4 * 73728 + 22050) / 44100
+        assert_eq!(metadata.duration_secs, Some(7));
+        assert!(ApeFile.has_cover_art(&path).unwrap());
+    }
+
+    #[test]
+    fn test_stream_layout() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // fLaC + STREAMINFO (34 bytes) + last block PADDING (8 bytes) + audio
+        let mut flac = b"fLaC".to_vec();
+        flac.extend_from_slice(&[0x00, 0, 0, 34]);
+        flac.extend_from_slice(&[0u8; 34]);
+        flac.extend_from_slice(&[0x81, 0, 0, 8]);
+        flac.extend_from_slice(&[0u8; 8]);
+        flac.extend_from_slice(&[0xFF, 0xF8, 0, 0]);
+        let flac_path = dir.path().join("track.flac");
+        std::fs::write(&flac_path, flac).unwrap();
+        assert_eq!(
+            stream_layout(&flac_path).unwrap(),
+            StreamLayout {
+                header_len: 54,
+                audio_start: 54,
+            }
+        );
+
+        let mut id3 = b"ID3\x03\x00\x00\x00\x00\x01\x00".to_vec();
+        id3.resize(10 + 128, 0);
+        let mp3_path = dir.path().join("track.mp3");
+        std::fs::write(&mp3_path, [id3, vec![0xFF, 0xFB, 0x90, 0x00]].concat()).unwrap();
+        assert_eq!(
+            stream_layout(&mp3_path).unwrap(),
+            StreamLayout {
+                header_len: 0,
+                audio_start: 138,
+            }
+        );
     }
 }
@@ -579,52 +579,30 @@ async fn add_tracks_to_playlist(
     playlist_id: &str,
     track_ids: Vec<String>,
 ) -> Result<()> {
-    // Get current playlist
-    let url = format!("{}/playlists/{}", server, playlist_id);
-    let response = reqwest::get(&url)
-        .await
-        .context("Failed to connect to server")?;
-
-    if response.status() == reqwest::StatusCode::NOT_FOUND {
-        anyhow::bail!("Playlist not found");
-    }
-
-    let mut playlist: Playlist = response.json().await.context("Failed to parse response")?;
-
-    // Add new tracks (avoid duplicates)
-    let mut added = 0;
-    for track_id in track_ids {
-        if !playlist.tracks.contains(&track_id) {
-            playlist.tracks.push(track_id);
-            added += 1;
-        }
-    }
-
-    if added == 0 {
-        println!("No new tracks added (all tracks already in playlist)");
-        return Ok(());
-    }
-
-    // Update playlist
+    let url = format!("{}/playlists/{}/tracks", server, playlist_id);
     let client = reqwest::Client::new();
-    let body = PlaylistUpdate {
-        name: None,
-        description: None,
-        tracks: Some(playlist.tracks),
-    };
 
+    // The server appends all tracks in one transaction, skipping ones already present
     let response = client
-        .put(&url)
-        .json(&body)
+        .post(&url)
+        .json(&serde_json::json!({ "track_ids": track_ids }))
         .send()
         .await
-        .context("Failed to update playlist")?;
+        .context("Failed to connect to server")?;
 
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        anyhow::bail!("Playlist or track not found");
+    }
     if !response.status().is_success() {
         anyhow::bail!("Server returned error: {}", response.status());
     }
 
-    println!("✓ Added {} track(s) to playlist!", added);
+    let playlist: Playlist = response.json().await.context("Failed to parse response")?;
+    println!(
+        "✓ Playlist '{}' now has {} track(s)",
+        playlist.name,
+        playlist.tracks.len()
+    );
 
     Ok(())
 }
@@ -634,47 +612,30 @@ async fn remove_tracks_from_playlist(
     playlist_id: &str,
     track_ids: Vec<String>,
 ) -> Result<()> {
-    // Get current playlist
-    let url = format!("{}/playlists/{}", server, playlist_id);
-    let response = reqwest::get(&url)
+    let url = format!("{}/playlists/{}/tracks", server, playlist_id);
+    let client = reqwest::Client::new();
+
+    let response = client
+        .delete(&url)
+        .json(&serde_json::json!({ "track_ids": track_ids }))
+        .send()
         .await
         .context("Failed to connect to server")?;
 
     if response.status() == reqwest::StatusCode::NOT_FOUND {
         anyhow::bail!("Playlist not found");
     }
+    if !response.status().is_success() {
+        anyhow::bail!("Server returned error: {}", response.status());
+    }
 
-    let mut playlist: Playlist = response.json().await.context("Failed to parse response")?;
-
-    // Remove tracks
-    let original_count = playlist.tracks.len();
-    playlist.tracks.retain(|id| !track_ids.contains(id));
-    let removed = original_count - playlist.tracks.len();
-
+    let result: serde_json::Value = response.json().await.context("Failed to parse response")?;
+    let removed = result["removed"].as_u64().unwrap_or(0);
     if removed == 0 {
         println!("No tracks removed (tracks not found in playlist)");
         return Ok(());
     }
 
-    // Update playlist
-    let client = reqwest::Client::new();
-    let body = PlaylistUpdate {
-        name: None,
-        description: None,
-        tracks: Some(playlist.tracks),
-    };
-
-    let response = client
-        .put(&url)
-        .json(&body)
-        .send()
-        .await
-        .context("Failed to update playlist")?;
-
-    if !response.status().is_success() {
-        anyhow::bail!("Server returned error: {}", response.status());
-    }
-
     println!("✓ Removed {} track(s) from playlist!", removed);
 
     Ok(())
@@ -1,6 +1,6 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use music_station::library::MusicLibrary;
+use music_station::library::{DATA_DIR_NAME, MusicLibrary};
 use music_station::lyrics::{LyricDatabase, LyricFormat};
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -102,7 +102,7 @@ async fn main() -> Result<()> {
     library.scan().await.context("Failed to scan library")?;
 
     // Initialize lyrics database
-    let db_path = cli.library.join(".music-station").join("lyrics.db");
+    let db_path = cli.library.join(DATA_DIR_NAME).join("lyrics.db");
     if !db_path.exists() {
         anyhow::bail!(
             "Lyrics database not found at {}. Have you run the server and added lyrics?",
@@ -1,6 +1,6 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use music_station::library::MusicLibrary;
+use music_station::library::{DATA_DIR_NAME, MusicLibrary};
 use music_station::lyrics::LyricDatabase;
 use music_station::playlist::PlaylistDatabase;
 use std::collections::HashMap;
@@ -88,7 +88,7 @@ async fn main() -> Result<()> {
     }
 
     // Migrate lyrics database
-    let db_path = cli.library.join(".music-station").join("lyrics.db");
+    let db_path = cli.library.join(DATA_DIR_NAME).join("lyrics.db");
     if db_path.exists() {
         tracing::info!("Migrating lyrics database: {}", db_path.display());
 
@@ -106,27 +106,30 @@ async fn main() -> Result<()> {
 
         for old_track_id in track_ids_with_lyrics {
             if let Some(new_track_id) = id_mapping.get(&old_track_id) {
-                // Get the lyric with old ID
-                if let Some(lyric) = lyrics_db.get_lyric(&old_track_id).await? {
+                // Get the lyrics (one per format) with old ID
+                let lyrics = lyrics_db.get_lyrics(&old_track_id).await?;
+                if !lyrics.is_empty() {
                     tracing::info!("  Migrating lyric: {} -> {}", old_track_id, new_track_id);
 
                     if !cli.dry_run {
-                        // Save lyric with new ID
-                        lyrics_db
-                            .save_lyric(
-                                new_track_id,
-                                lyric.content,
-                                lyric.format,
-                                lyric.language,
-                                lyric.source,
-                            )
-                            .await
-                            .context(format!(
-                                "Failed to save lyric with new ID: {}",
-                                new_track_id
-                            ))?;
-
-                        // Delete old lyric entry
+                        // Save lyrics with new ID
+                        for lyric in lyrics {
+                            lyrics_db
+                                .save_lyric(
+                                    new_track_id,
+                                    lyric.content,
+                                    lyric.format,
+                                    lyric.language,
+                                    lyric.source,
+                                )
+                                .await
+                                .context(format!(
+                                    "Failed to save lyric with new ID: {}",
+                                    new_track_id
+                                ))?;
+                        }
+
+                        // Delete old lyric entries
                         lyrics_db
                             .delete_lyric(&old_track_id)
                             .await
@@ -152,7 +155,7 @@ async fn main() -> Result<()> {
     }
 
     // Migrate playlist database
-    let playlist_db_path = cli.library.join(".music-station").join("playlists.db");
+    let playlist_db_path = cli.library.join(DATA_DIR_NAME).join("playlists.db");
     if playlist_db_path.exists() {
         tracing::info!(
             "Migrating playlist database: {}",
@@ -202,6 +205,7 @@ async fn main() -> Result<()> {
                             description: None,
                             tracks: Some(new_track_ids),
                         },
+                        None,
                     )
                     .await
                     .context(format!("Failed to update playlist: {}", playlist.id))?;
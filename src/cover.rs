@@ -0,0 +1,419 @@
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use serde::Deserialize;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Downscale cover art so neither side exceeds `max_dim` and re-encode it as JPEG.
+///
+/// Returns `None` when the image already fits within `max_dim`, so callers can
+/// leave the embedded art untouched.
+pub fn shrink_cover(image_data: &[u8], max_dim: u32, quality: u8) -> Result<Option<Vec<u8>>> {
+    let image = image::load_from_memory(image_data).context("Failed to decode cover art")?;
+
+    if image.width() <= max_dim && image.height() <= max_dim {
+        return Ok(None);
+    }
+
+    // `resize` preserves the aspect ratio and fits the image inside the bounds
+    let resized = image.resize(max_dim, max_dim, FilterType::Lanczos3);
+
+    let mut output = Vec::new();
+    JpegEncoder::new_with_quality(&mut output, quality.clamp(1, 100))
+        .encode_image(&resized.to_rgb8())
+        .context("Failed to encode cover art as JPEG")?;
+
+    Ok(Some(output))
+}
+
+/// Format cover art can be converted to by [`normalize_cover`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CoverFormat {
+    Jpeg,
+    Png,
+}
+
+impl CoverFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            CoverFormat::Jpeg => "image/jpeg",
+            CoverFormat::Png => "image/png",
+        }
+    }
+}
+
+/// JPEG quality used by [`normalize_cover`]
+const NORMALIZE_JPEG_QUALITY: u8 = 90;
+
+/// Apply the EXIF orientation of cover art to its pixels and re-encode it
+/// without EXIF or other metadata, so players that ignore EXIF don't show it
+/// sideways.
+///
+/// PNGs stay PNG and everything else becomes JPEG unless `format` is given.
+/// Returns the new image data and its MIME type.
+pub fn normalize_cover(
+    image_data: &[u8],
+    format: Option<CoverFormat>,
+) -> Result<(Vec<u8>, &'static str)> {
+    let reader = ImageReader::new(Cursor::new(image_data))
+        .with_guessed_format()
+        .context("Failed to read cover art")?;
+    let source_format = reader.format();
+    let mut decoder = reader
+        .into_decoder()
+        .context("Failed to decode cover art")?;
+    let orientation = decoder
+        .orientation()
+        .context("Failed to read cover art orientation")?;
+    let mut image = DynamicImage::from_decoder(decoder).context("Failed to decode cover art")?;
+    image.apply_orientation(orientation);
+
+    let format = format.unwrap_or(match source_format {
+        Some(ImageFormat::Png) => CoverFormat::Png,
+        _ => CoverFormat::Jpeg,
+    });
+    let mut output = Vec::new();
+    match format {
+        CoverFormat::Jpeg => JpegEncoder::new_with_quality(&mut output, NORMALIZE_JPEG_QUALITY)
+            .encode_image(&image.to_rgb8())
+            .context("Failed to encode cover art as JPEG")?,
+        CoverFormat::Png => image
+            .write_to(&mut Cursor::new(&mut output), ImageFormat::Png)
+            .context("Failed to encode cover art as PNG")?,
+    }
+
+    Ok((output, format.mime_type()))
+}
+
+/// Directory under the library data directory used by default for cached covers
+pub const COVER_CACHE_DIR_NAME: &str = "covers";
+
+/// Extension of cache entries, so stray files in the directory are left alone
+const CACHE_ENTRY_EXTENSION: &str = "cover";
+
+/// On-disk cache of cover art extracted from audio files, keyed by track ID.
+///
+/// Each entry records the modification time of the audio file it was read
+/// from and is ignored once the file changes. Entries are touched on every
+/// hit, and the least recently used ones are deleted when the directory
+/// grows past `max_bytes`.
+#[derive(Debug, Clone)]
+pub struct CoverCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Serializes writes and eviction
+    write_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl CoverCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            write_lock: Arc::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Cached cover and MIME type for `track_id`, if one was stored while the
+    /// audio file had modification time `source_modified`
+    pub async fn get(
+        &self,
+        track_id: &str,
+        source_modified: SystemTime,
+    ) -> Option<(Vec<u8>, String)> {
+        let path = self.entry_path(track_id);
+        let result = tokio::task::spawn_blocking(move || read_entry(&path, source_modified)).await;
+
+        match result {
+            Ok(Ok(entry)) => entry,
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to read cached cover for {}: {}", track_id, e);
+                None
+            }
+            Err(e) => {
+                tracing::warn!("Cover cache read task failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Store a cover read from an audio file with modification time
+    /// `source_modified`, then evict old entries if the cache is over its limit
+    pub async fn put(
+        &self,
+        track_id: &str,
+        source_modified: SystemTime,
+        image_data: &[u8],
+        mime_type: &str,
+    ) -> Result<()> {
+        let _guard = self.write_lock.lock().await;
+
+        let dir = self.dir.clone();
+        let path = self.entry_path(track_id);
+        let entry = encode_entry(source_modified, image_data, mime_type)?;
+        let max_bytes = self.max_bytes;
+
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            let tmp_path = path.with_extension("tmp");
+            std::fs::write(&tmp_path, &entry)?;
+            std::fs::rename(&tmp_path, &path)?;
+            evict(&dir, max_bytes, &path)
+        })
+        .await?
+    }
+
+    fn entry_path(&self, track_id: &str) -> PathBuf {
+        // Track IDs are hex digests, but don't let anything else escape the directory
+        let name: String = track_id
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect();
+        self.dir.join(format!("{}.{}", name, CACHE_ENTRY_EXTENSION))
+    }
+}
+
+/// Entry layout: source mtime (u64 nanoseconds since the epoch, LE), MIME
+/// length (u8), MIME bytes, image bytes
+fn encode_entry(
+    source_modified: SystemTime,
+    image_data: &[u8],
+    mime_type: &str,
+) -> Result<Vec<u8>> {
+    let mime_len = u8::try_from(mime_type.len()).context("MIME type too long to cache")?;
+
+    let mut entry = Vec::with_capacity(9 + mime_type.len() + image_data.len());
+    entry.extend_from_slice(&mtime_nanos(source_modified).to_le_bytes());
+    entry.push(mime_len);
+    entry.extend_from_slice(mime_type.as_bytes());
+    entry.extend_from_slice(image_data);
+    Ok(entry)
+}
+
+fn read_entry(path: &Path, source_modified: SystemTime) -> Result<Option<(Vec<u8>, String)>> {
+    let entry = match std::fs::read(path) {
+        Ok(entry) => entry,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let Some((stamp, rest)) = entry.split_first_chunk::<8>() else {
+        return Ok(None);
+    };
+    if u64::from_le_bytes(*stamp) != mtime_nanos(source_modified) {
+        return Ok(None);
+    }
+    let Some((&mime_len, rest)) = rest.split_first() else {
+        return Ok(None);
+    };
+    let Some((mime, image_data)) = rest.split_at_checked(mime_len as usize) else {
+        return Ok(None);
+    };
+
+    // Mark as recently used for eviction. A cache that can't be written to,
+    // e.g. a read-only directory, still serves its entries
+    if let Err(e) = std::fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()))
+    {
+        tracing::debug!(
+            "Failed to touch cover cache entry {}: {}",
+            path.display(),
+            e
+        );
+    }
+
+    Ok(Some((
+        image_data.to_vec(),
+        String::from_utf8_lossy(mime).into_owned(),
+    )))
+}
+
+/// Delete the least recently used entries until the cache fits in
+/// `max_bytes`, never removing `keep` (the entry just written)
+fn evict(dir: &Path, max_bytes: u64, keep: &Path) -> Result<()> {
+    let mut entries = Vec::new();
+    let mut total = 0u64;
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(CACHE_ENTRY_EXTENSION) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        total += metadata.len();
+        if path != keep {
+            entries.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+
+    entries.sort_by_key(|(used, _, _)| *used);
+    for (_, len, path) in entries {
+        if total <= max_bytes {
+            break;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => total -= len,
+            Err(e) => tracing::warn!("Failed to evict cached cover {}: {}", path.display(), e),
+        }
+    }
+
+    Ok(())
+}
+
+fn mtime_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        RgbImage::from_pixel(width, height, image::Rgb([200, 40, 40]))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_shrink_cover_downscales_to_max_dim() {
+        let shrunk = shrink_cover(&png(1200, 600), 300, 85).unwrap().unwrap();
+
+        assert!(shrunk.starts_with(&[0xFF, 0xD8, 0xFF]));
+        let decoded = image::load_from_memory(&shrunk).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (300, 150));
+    }
+
+    #[test]
+    fn test_shrink_cover_skips_small_images() {
+        assert!(shrink_cover(&png(300, 300), 300, 85).unwrap().is_none());
+    }
+
+    /// A 4x2 JPEG whose EXIF says to rotate it 90° clockwise for display
+    fn rotated_jpeg() -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        JpegEncoder::new(&mut jpeg)
+            .encode_image(&RgbImage::from_pixel(4, 2, image::Rgb([10, 200, 10])))
+            .unwrap();
+
+        let mut exif = b"Exif\0\0II\x2A\0\x08\0\0\0".to_vec();
+        exif.extend_from_slice(&[1, 0]); // one IFD entry
+        exif.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0]); // Orientation = 6
+        exif.extend_from_slice(&[0, 0, 0, 0]); // no next IFD
+        let mut app1 = vec![0xFF, 0xE1];
+        app1.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+        app1.extend_from_slice(&exif);
+
+        jpeg.splice(2..2, app1);
+        jpeg
+    }
+
+    #[test]
+    fn test_normalize_cover_applies_exif_rotation() {
+        let original = rotated_jpeg();
+        assert!(original.windows(4).any(|w| w == b"Exif"));
+
+        let (normalized, mime_type) = normalize_cover(&original, None).unwrap();
+        assert_eq!(mime_type, "image/jpeg");
+        assert!(!normalized.windows(4).any(|w| w == b"Exif"));
+        let decoded = image::load_from_memory(&normalized).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (2, 4));
+    }
+
+    #[test]
+    fn test_normalize_cover_converts_format() {
+        let (data, mime_type) = normalize_cover(&png(3, 3), None).unwrap();
+        assert_eq!(mime_type, "image/png");
+        assert!(data.starts_with(&[0x89, 0x50, 0x4E, 0x47]));
+
+        let (data, mime_type) = normalize_cover(&png(3, 3), Some(CoverFormat::Jpeg)).unwrap();
+        assert_eq!(mime_type, "image/jpeg");
+        assert!(data.starts_with(&[0xFF, 0xD8, 0xFF]));
+
+        let (data, _) = normalize_cover(&rotated_jpeg(), Some(CoverFormat::Png)).unwrap();
+        assert!(data.starts_with(&[0x89, 0x50, 0x4E, 0x47]));
+        assert!(normalize_cover(b"not an image", None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cover_cache_invalidates_on_mtime_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CoverCache::new(dir.path().join("covers"), 1024 * 1024);
+        let modified = UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+
+        assert!(cache.get("abc", modified).await.is_none());
+        cache
+            .put("abc", modified, b"image", "image/png")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            cache.get("abc", modified).await,
+            Some((b"image".to_vec(), "image/png".to_string()))
+        );
+        let later = modified + std::time::Duration::from_secs(1);
+        assert!(cache.get("abc", later).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cover_cache_serves_read_only_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = CoverCache::new(dir.path().to_path_buf(), 1024);
+        let modified = UNIX_EPOCH;
+        cache
+            .put("abc", modified, b"image", "image/png")
+            .await
+            .unwrap();
+
+        let entry = dir.path().join("abc.cover");
+        let mut permissions = std::fs::metadata(&entry).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&entry, permissions).unwrap();
+
+        assert_eq!(
+            cache.get("abc", modified).await,
+            Some((b"image".to_vec(), "image/png".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cover_cache_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        // Room for two 109-byte entries (9-byte header + 100-byte image), not three
+        let cache = CoverCache::new(dir.path().to_path_buf(), 250);
+        let modified = UNIX_EPOCH;
+        let image = [0u8; 100];
+
+        cache.put("a", modified, &image, "").await.unwrap();
+        cache.put("b", modified, &image, "").await.unwrap();
+        // Make "a" the least recently used regardless of timestamp resolution
+        std::fs::File::options()
+            .write(true)
+            .open(dir.path().join("a.cover"))
+            .unwrap()
+            .set_modified(UNIX_EPOCH)
+            .unwrap();
+        cache.put("c", modified, &image, "").await.unwrap();
+
+        assert!(cache.get("a", modified).await.is_none());
+        assert!(cache.get("b", modified).await.is_some());
+        assert!(cache.get("c", modified).await.is_some());
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::{Context, Result};
+
+use crate::audio::parse_replay_gain;
+
+/// A parsed CUE sheet describing how one or more audio files split into tracks
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CueSheet {
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// From `REM DATE`
+    pub date: Option<String>,
+    /// From `REM GENRE`
+    pub genre: Option<String>,
+    /// From `REM REPLAYGAIN_ALBUM_GAIN`, in dB
+    pub replaygain_album_gain: Option<f64>,
+    pub files: Vec<CueFile>,
+}
+
+/// An audio file referenced by a `FILE` command, with the tracks it contains
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueFile {
+    /// File name as written in the sheet, relative to the sheet's directory
+    pub name: String,
+    pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// Offset of `INDEX 01` (or `INDEX 00` if there is no 01) within the file
+    pub start_ms: u64,
+    /// From `REM REPLAYGAIN_TRACK_GAIN` inside the track, in dB
+    pub replaygain_track_gain: Option<f64>,
+}
+
+impl CueFile {
+    /// End of the track at `index`: the start of the next track in the same file,
+    /// or `None` for the last track (which runs to the end of the file)
+    pub fn track_end_ms(&self, index: usize) -> Option<u64> {
+        self.tracks.get(index + 1).map(|t| t.start_ms)
+    }
+}
+
+/// Parse a CUE sheet. Unknown commands are ignored; tracks without an index are dropped.
+pub fn parse_cue(content: &str) -> Result<CueSheet> {
+    let mut sheet = CueSheet::default();
+    // Index 00 (pregap) is only used when a track has no index 01
+    let mut pending: Option<(CueTrack, Option<u64>, Option<u64>)> = None;
+
+    fn finish(sheet: &mut CueSheet, pending: &mut Option<(CueTrack, Option<u64>, Option<u64>)>) {
+        if let Some((mut track, index0, index1)) = pending.take()
+            && let Some(start_ms) = index1.or(index0)
+            && let Some(file) = sheet.files.last_mut()
+        {
+            track.start_ms = start_ms;
+            file.tracks.push(track);
+        }
+    }
+
+    for (line_no, line) in content.trim_start_matches('\u{feff}').lines().enumerate() {
+        let args = split_args(line);
+        let Some(command) = args.first() else {
+            continue;
+        };
+
+        match command.to_uppercase().as_str() {
+            "FILE" => {
+                finish(&mut sheet, &mut pending);
+                let name = args
+                    .get(1)
+                    .with_context(|| format!("Line {}: FILE without a name", line_no + 1))?;
+                sheet.files.push(CueFile {
+                    name: name.clone(),
+                    tracks: Vec::new(),
+                });
+            }
+            "TRACK" => {
+                finish(&mut sheet, &mut pending);
+                if sheet.files.is_empty() {
+                    anyhow::bail!("Line {}: TRACK before any FILE", line_no + 1);
+                }
+                let number = args
+                    .get(1)
+                    .and_then(|n| n.parse().ok())
+                    .with_context(|| format!("Line {}: invalid track number", line_no + 1))?;
+                pending = Some((
+                    CueTrack {
+                        number,
+                        title: None,
+                        performer: None,
+                        start_ms: 0,
+                        replaygain_track_gain: None,
+                    },
+                    None,
+                    None,
+                ));
+            }
+            "INDEX" => {
+                if let Some((_, index0, index1)) = &mut pending {
+                    let time = args
+                        .get(2)
+                        .and_then(|t| parse_msf(t))
+                        .with_context(|| format!("Line {}: invalid INDEX time", line_no + 1))?;
+                    match args.get(1).map(String::as_str) {
+                        Some("00") => *index0 = Some(time),
+                        Some("01") => *index1 = Some(time),
+                        _ => {}
+                    }
+                }
+            }
+            "TITLE" | "PERFORMER" => {
+                let value = args.get(1).cloned();
+                let is_title = command.eq_ignore_ascii_case("TITLE");
+                match &mut pending {
+                    Some((track, _, _)) if is_title => track.title = value,
+                    Some((track, _, _)) => track.performer = value,
+                    None if is_title => sheet.title = value,
+                    None => sheet.performer = value,
+                }
+            }
+            "REM" => {
+                let value = args.get(2);
+                match args.get(1).map(|k| k.to_uppercase()).as_deref() {
+                    Some("DATE") => sheet.date = value.cloned(),
+                    Some("GENRE") => sheet.genre = value.cloned(),
+                    Some("REPLAYGAIN_ALBUM_GAIN") => {
+                        sheet.replaygain_album_gain = value.and_then(|v| parse_replay_gain(v))
+                    }
+                    Some("REPLAYGAIN_TRACK_GAIN") => {
+                        if let Some((track, _, _)) = &mut pending {
+                            track.replaygain_track_gain = value.and_then(|v| parse_replay_gain(v));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    finish(&mut sheet, &mut pending);
+
+    Ok(sheet)
+}
+
+/// Split a CUE line into whitespace-separated arguments, honouring double quotes
+fn split_args(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            args.push(chars.by_ref().take_while(|&c| c != '"').collect());
+        } else {
+            let mut arg = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                arg.push(c);
+                chars.next();
+            }
+            args.push(arg);
+        }
+    }
+
+    args
+}
+
+/// Parse an `mm:ss:ff` timestamp (75 frames per second) into milliseconds
+fn parse_msf(value: &str) -> Option<u64> {
+    let mut parts = value.split(':').map(|p| p.parse::<u64>().ok());
+    let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some() || seconds >= 60 || frames >= 75 {
+        return None;
+    }
+    Some((minutes * 60 + seconds) * 1000 + frames * 1000 / 75)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = "\u{feff}REM GENRE \"Classical\"
+REM DATE 1998
+REM REPLAYGAIN_ALBUM_GAIN -7.25 dB
+PERFORMER \"Orchestra\"
+TITLE \"Live at the Hall\"
+FILE \"Live at the Hall.flac\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"Overture\"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE \"Second Movement\"
+    PERFORMER \"Soloist\"
+    REM REPLAYGAIN_TRACK_GAIN -5.10 dB
+    INDEX 00 04:58:00
+    INDEX 01 05:00:37
+  TRACK 03 AUDIO
+    TITLE \"Finale\"
+    INDEX 00 12:30:00
+";
+
+    #[test]
+    fn test_parse_cue_sheet() {
+        let sheet = parse_cue(SHEET).unwrap();
+
+        assert_eq!(sheet.title.as_deref(), Some("Live at the Hall"));
+        assert_eq!(sheet.performer.as_deref(), Some("Orchestra"));
+        assert_eq!(sheet.date.as_deref(), Some("1998"));
+        assert_eq!(sheet.genre.as_deref(), Some("Classical"));
+        assert_eq!(sheet.replaygain_album_gain, Some(-7.25));
+        assert_eq!(sheet.files.len(), 1);
+
+        let file = &sheet.files[0];
+        assert_eq!(file.name, "Live at the Hall.flac");
+        assert_eq!(
+            file.tracks,
+            [
+                CueTrack {
+                    number: 1,
+                    title: Some("Overture".to_string()),
+                    performer: None,
+                    start_ms: 0,
+                    replaygain_track_gain: None,
+                },
+                CueTrack {
+                    number: 2,
+                    title: Some("Second Movement".to_string()),
+                    performer: Some("Soloist".to_string()),
+                    // INDEX 01 wins over the pregap; 37 frames = 493 ms
+                    start_ms: 300_493,
+                    replaygain_track_gain: Some(-5.1),
+                },
+                CueTrack {
+                    number: 3,
+                    title: Some("Finale".to_string()),
+                    performer: None,
+                    start_ms: 750_000,
+                    replaygain_track_gain: None,
+                },
+            ]
+        );
+        assert_eq!(file.track_end_ms(0), Some(300_493));
+        assert_eq!(file.track_end_ms(2), None);
+    }
+
+    #[test]
+    fn test_parse_cue_rejects_malformed_sheets() {
+        assert!(parse_cue("TRACK 01 AUDIO\n  INDEX 01 00:00:00").is_err());
+        assert!(parse_cue("FILE \"a.flac\" WAVE\n  TRACK 01 AUDIO\n  INDEX 01 00:61:00").is_err());
+    }
+
+    #[test]
+    fn test_parse_msf() {
+        assert_eq!(parse_msf("00:00:00"), Some(0));
+        assert_eq!(parse_msf("01:02:75"), None);
+        assert_eq!(parse_msf("61:00:15"), Some(3_660_200));
+        assert_eq!(parse_msf("1:2"), None);
+    }
+}
@@ -0,0 +1,108 @@
+//! Error type of the public [`MusicLibrary`](crate::library::MusicLibrary),
+//! [`LyricDatabase`](crate::lyrics::LyricDatabase) and
+//! [`PlaylistDatabase`](crate::playlist::PlaylistDatabase) APIs
+//!
+//! Internally the crate uses `anyhow`; errors are classified into a
+//! [`MusicStationError`] kind when they cross the public API, keeping the
+//! context messages added along the way.
+
+use crate::library::ScanCancelled;
+use crate::lyrics::fetcher::ProviderTimedOut;
+
+/// Errors returned by the library, lyrics and playlist APIs
+#[derive(Debug, thiserror::Error)]
+pub enum MusicStationError {
+    /// The named item (e.g. "Track <id>") doesn't exist
+    #[error("{0} not found")]
+    NotFound(String),
+    /// The request conflicts with the current state, e.g. a scan is already running
+    #[error("{0}")]
+    Conflict(String),
+    /// The operation can't be applied to this item, e.g. editing the tags of a CUE-sheet track
+    #[error("{0}")]
+    Unsupported(String),
+    /// A library scan was cancelled before it finished
+    #[error("Scan cancelled")]
+    Cancelled,
+    /// Reading or writing a file failed
+    #[error("{0:#}")]
+    Io(anyhow::Error),
+    /// Audio tags could not be read or written
+    #[error("{0:#}")]
+    Tag(anyhow::Error),
+    /// A database query failed
+    #[error("{0:#}")]
+    Db(anyhow::Error),
+    /// A lyrics provider failed or didn't answer in time
+    #[error("{0:#}")]
+    Provider(anyhow::Error),
+    /// Anything that doesn't fit the kinds above
+    #[error("{0:#}")]
+    Other(anyhow::Error),
+}
+
+/// Result of the public library, lyrics and playlist APIs
+pub type Result<T, E = MusicStationError> = std::result::Result<T, E>;
+
+impl From<anyhow::Error> for MusicStationError {
+    /// Classify by the outermost typed error in the chain, so an ID3 error
+    /// caused by an I/O error counts as a tag error
+    fn from(error: anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if cause.is::<ScanCancelled>() {
+                return MusicStationError::Cancelled;
+            }
+            if cause.is::<sqlx::Error>() {
+                return MusicStationError::Db(error);
+            }
+            if cause.is::<id3::Error>()
+                || cause.is::<metaflac::Error>()
+                || cause.is::<mp4ameta::Error>()
+                || cause.is::<symphonia::core::errors::Error>()
+            {
+                return MusicStationError::Tag(error);
+            }
+            if cause.is::<ProviderTimedOut>() {
+                return MusicStationError::Provider(error);
+            }
+            if cause.is::<std::io::Error>() {
+                return MusicStationError::Io(error);
+            }
+        }
+        MusicStationError::Other(error)
+    }
+}
+
+impl From<sqlx::Error> for MusicStationError {
+    fn from(error: sqlx::Error) -> Self {
+        MusicStationError::Db(error.into())
+    }
+}
+
+impl From<std::io::Error> for MusicStationError {
+    fn from(error: std::io::Error) -> Self {
+        MusicStationError::Io(error.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_classifies_by_outermost_typed_error() {
+        let io = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        let error: MusicStationError = Err::<(), _>(io)
+            .context("Failed to open file")
+            .unwrap_err()
+            .into();
+        assert!(matches!(error, MusicStationError::Io(_)));
+        assert_eq!(error.to_string(), "Failed to open file: denied");
+
+        let error: MusicStationError = anyhow::Error::new(ScanCancelled).into();
+        assert!(matches!(error, MusicStationError::Cancelled));
+        let error: MusicStationError = anyhow::anyhow!("something else").into();
+        assert!(matches!(error, MusicStationError::Other(_)));
+    }
+}
@@ -4,8 +4,13 @@
 //! including music library management and lyrics fetching capabilities.
 
 pub mod audio;
+pub mod cover;
+pub mod cue;
+pub mod error;
 pub mod library;
 pub mod lyrics;
 pub mod playlist;
 pub mod server;
 pub mod stats;
+pub mod transcode;
+pub mod watch;
@@ -1,16 +1,26 @@
+use crate::error::MusicStationError;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+use unicode_normalization::UnicodeNormalization;
 
-use crate::audio::get_audio_file_handler;
+use crate::audio::{AudioFile, AudioFormatRegistry, EmbeddedLyrics, default_formats};
+use crate::cue;
+use crate::stats::TrackStats;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Track {
     pub id: String,
     pub path: PathBuf,
+    /// File name including extension
+    pub filename: String,
+    /// Path relative to the library root
+    pub relative_path: String,
     pub title: Option<String>,
     pub artist: Option<String>,
     pub album: Option<String>,
@@ -26,7 +36,53 @@ pub struct Track {
     pub has_cover: bool,
     pub has_lyrics: bool,
     pub play_count: u64,
+    /// When the track was last played (RFC 3339), kept in the stats database
+    #[serde(default)]
+    pub last_played: Option<String>,
+    /// The file was gone the last time it was checked (on stream, on fetch, or
+    /// via `GET /tracks/missing`); cleared by a rescan or when it reappears
+    #[serde(default)]
+    pub missing: bool,
+    /// Tagged as part of a compilation (`COMPILATION`, `TCMP` or `cpil`)
+    #[serde(default)]
+    pub compilation: bool,
+    /// Stored with a lossless codec (FLAC, ALAC, WavPack or APE)
+    #[serde(default)]
+    pub lossless: bool,
+    /// Sample rate in Hz
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+    #[serde(default)]
+    pub channels: Option<u8>,
+    /// Bit depth; lossy codecs have none
+    #[serde(default)]
+    pub bits_per_sample: Option<u8>,
+    /// Average bitrate over the file's audio data
+    #[serde(default)]
+    pub bitrate_kbps: Option<u32>,
+    /// ReplayGain track adjustment in dB, from `REPLAYGAIN_TRACK_GAIN`
+    #[serde(default)]
+    pub replaygain_track_gain: Option<f64>,
+    /// ReplayGain album adjustment in dB, from `REPLAYGAIN_ALBUM_GAIN`
+    #[serde(default)]
+    pub replaygain_album_gain: Option<f64>,
     pub custom_fields: HashMap<String, String>,
+    /// Start of a CUE-sheet track within its (shared) file, in milliseconds.
+    /// `None` for tracks that span their whole file.
+    pub start_ms: Option<u64>,
+    /// End of a CUE-sheet track within its file; `None` if it runs to the end
+    /// of a file of unknown length
+    pub end_ms: Option<u64>,
+    /// Why the file could not be parsed. Such tracks only carry file
+    /// information and are kept when `keep_unparseable_files` is enabled.
+    #[serde(default)]
+    pub parse_error: Option<String>,
+    /// When the file entered the library (RFC 3339). Taken from the file's
+    /// creation time at first scan, or its modification time where the
+    /// filesystem has no creation time, then kept in the stats database so
+    /// rescans don't change it.
+    #[serde(default)]
+    pub date_added: String,
 }
 
 // Re-export the MetadataUpdate from audio module for API compatibility
@@ -34,15 +90,41 @@ pub use crate::audio::MetadataUpdate as TrackMetadataUpdate;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Album {
+    /// Stable identifier derived from the album name (safe for names containing `/`)
+    pub id: String,
     pub name: String,
+    /// Album artist; the various-artists label for compilations
     pub artist: Option<String>,
+    /// Tracks are tagged as a compilation, or by different artists without a
+    /// shared album artist (or with "Various Artists" as album artist)
+    pub compilation: bool,
+    /// Most common year among the tracks (ties go to the earliest)
+    pub year: Option<String>,
+    /// Earliest and latest track years, when the tracks span more than one year
+    pub year_range: Option<(String, String)>,
     pub track_count: usize,
     pub total_duration_secs: u64,
     pub tracks: Vec<Track>,
 }
 
+/// An album without its track list, for listings that load tracks lazily
+#[derive(Debug, Clone, Serialize)]
+pub struct AlbumSummary {
+    pub id: String,
+    pub name: String,
+    pub artist: Option<String>,
+    /// Most common year among the album's tracks
+    pub year: Option<String>,
+    pub track_count: usize,
+    pub total_duration_secs: u64,
+    /// Whether `GET /albums/:name/cover` has an image (embedded or folder art)
+    pub has_cover: bool,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Artist {
+    /// Stable identifier derived from the artist name (safe for names containing `/`)
+    pub id: String,
     pub name: String,
     pub album_count: usize,
     pub track_count: usize,
@@ -57,518 +139,4567 @@ pub struct LibraryStats {
     pub total_duration_secs: u64,
     pub total_size_bytes: u64,
     pub total_plays: u64,
+    pub lossless_tracks: usize,
+    pub lossy_tracks: usize,
+    pub lossless_size_bytes: u64,
+    pub lossy_size_bytes: u64,
 }
 
-#[derive(Clone)]
-pub struct MusicLibrary {
-    library_path: PathBuf,
-    tracks: Arc<RwLock<Vec<Track>>>,
-    albums_cache: Arc<RwLock<Option<Vec<Album>>>>,
-    artists_cache: Arc<RwLock<Option<Vec<Artist>>>>,
+/// Number of track changes retained for delta sync
+const CHANGE_LOG_CAPACITY: usize = 1024;
+
+/// Kind of change recorded in the library change log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeType {
+    Added,
+    Updated,
+    Removed,
 }
 
-impl MusicLibrary {
-    pub fn new(library_path: PathBuf) -> Self {
-        Self {
-            library_path,
-            tracks: Arc::new(RwLock::new(Vec::new())),
-            albums_cache: Arc::new(RwLock::new(None)),
-            artists_cache: Arc::new(RwLock::new(None)),
+/// Bounded log of track changes, each stamped with the library version it produced
+#[derive(Debug, Default)]
+struct ChangeLog {
+    /// Current library version; bumped once per recorded change
+    version: u64,
+    /// Highest version whose entry has been evicted from `entries`
+    evicted_through: u64,
+    entries: VecDeque<(u64, ChangeType, String)>,
+}
+
+impl ChangeLog {
+    fn record(&mut self, change: ChangeType, track_id: String) {
+        self.version += 1;
+        if self.entries.len() == CHANGE_LOG_CAPACITY
+            && let Some((version, _, _)) = self.entries.pop_front()
+        {
+            self.evicted_through = version;
         }
+        self.entries.push_back((self.version, change, track_id));
     }
+}
 
-    /// Invalidate the cached album and artist collections.
-    /// Must be called whenever the track list is mutated.
-    async fn invalidate_cache(&self) {
-        *self.albums_cache.write().await = None;
-        *self.artists_cache.write().await = None;
-    }
+/// Tracks added, updated and removed since a given library version
+#[derive(Debug, Clone, Serialize)]
+pub struct TrackChanges {
+    /// Library version the client should pass as `since` next time
+    pub version: u64,
+    /// The requested version is no longer covered by the change log;
+    /// the client must re-fetch the full track list
+    pub full_resync_required: bool,
+    pub added: Vec<Track>,
+    pub updated: Vec<Track>,
+    pub removed: Vec<String>,
+}
 
-    /// Scan the library folder for audio files (FLAC and MP3)
-    pub async fn scan(&self) -> Result<()> {
-        tracing::info!("Scanning library at: {}", self.library_path.display());
+/// A tag exactly as stored in the file, before normalization
+#[derive(Debug, Clone, Serialize)]
+pub struct RawTag {
+    pub key: String,
+    pub value: String,
+}
 
-        let mut tracks = Vec::new();
-        Box::pin(self.scan_directory(&self.library_path.clone(), &mut tracks)).await?;
+/// Outcome of optimizing a track's embedded cover art
+#[derive(Debug, Clone, Serialize)]
+pub struct CoverOptimization {
+    pub track_id: String,
+    pub original_size: usize,
+    pub new_size: usize,
+    /// Whether the cover was (or, in a dry run, would be) re-encoded
+    pub optimized: bool,
+}
 
-        let mut library_tracks = self.tracks.write().await;
-        *library_tracks = tracks;
-        drop(library_tracks);
-        self.invalidate_cache().await;
+/// Summary of a library-wide cover optimization run
+#[derive(Debug, Clone, Serialize)]
+pub struct CoverOptimizationReport {
+    pub dry_run: bool,
+    pub total_original_size: usize,
+    pub total_new_size: usize,
+    pub tracks: Vec<CoverOptimization>,
+}
 
-        let track_count = self.tracks.read().await.len();
-        tracing::info!("Scan complete. Found {} tracks", track_count);
-        Ok(())
-    }
+/// What happened to one track in a bulk lyrics embed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LyricsEmbedStatus {
+    Embedded,
+    /// Dry run: the file would have been written
+    WouldEmbed,
+    Skipped,
+    Failed,
+}
 
-    /// Recursively scan a directory for audio files
-    fn scan_directory<'a>(
-        &'a self,
-        dir: &'a Path,
-        tracks: &'a mut Vec<Track>,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + 'a>> {
-        Box::pin(async move {
-            let mut entries = tokio::fs::read_dir(dir)
-                .await
-                .context(format!("Failed to read directory: {}", dir.display()))?;
+/// Outcome of embedding a track's database lyrics into its file
+#[derive(Debug, Clone, Serialize)]
+pub struct LyricsEmbedResult {
+    pub track_id: String,
+    pub relative_path: String,
+    pub status: LyricsEmbedStatus,
+    /// Why the track was skipped or failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
 
-            while let Some(entry) = entries.next_entry().await? {
-                let path = entry.path();
-                let metadata = tokio::fs::metadata(&path).await?;
+/// Summary of a library-wide lyrics embed run
+#[derive(Debug, Clone, Serialize)]
+pub struct LyricsEmbedReport {
+    pub dry_run: bool,
+    /// Files written, or that would be written in a dry run
+    pub embedded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub tracks: Vec<LyricsEmbedResult>,
+}
 
-                if metadata.is_dir() {
-                    // Recursively scan subdirectories
-                    tracing::debug!("Scanning subdirectory: {}", path.display());
-                    self.scan_directory(&path, tracks).await?;
-                } else if metadata.is_file() {
-                    // Process audio files
-                    if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-                        match self.parse_audio_file(&path).await {
-                            Ok(track) => {
-                                tracing::info!(
-                                    "Found track: {} - {}",
-                                    track.artist.as_deref().unwrap_or("Unknown Artist"),
-                                    track.title.as_deref().unwrap_or("Unknown")
-                                );
-                                tracks.push(track);
-                            }
-                            Err(e) => {
-                                tracing::warn!("Failed to parse {}: {}", path.display(), e);
-                            }
-                        }
-                    }
-                }
-            }
+/// Summary of a [`MusicLibrary::refresh_flags`] run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FlagRefreshReport {
+    /// Audio files whose cover art was checked
+    pub files_checked: usize,
+    /// Tracks whose `has_cover` flag flipped
+    pub cover_updated: usize,
+    /// Tracks whose `has_lyrics` flag flipped
+    pub lyrics_updated: usize,
+}
 
-            Ok(())
-        })
+/// Cached result of grouping the tracks into albums and artists
+#[derive(Debug)]
+struct Groupings {
+    albums: Vec<Album>,
+    artists: Vec<Artist>,
+}
+
+/// Result of [`MusicLibrary::reindex`]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReindexReport {
+    pub tracks: usize,
+    pub albums: usize,
+    pub artists: usize,
+    /// Tracks whose lyrics flag or play count changed when reloading them
+    /// from the databases (set by `POST /reindex`)
+    pub flags_updated: usize,
+}
+
+/// Result of [`MusicLibrary::rescan`]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RescanReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    /// Files that were parsed because they are new or their size or
+    /// modification time changed; the others kept their previous track
+    pub parsed: usize,
+    /// Tracks in the library after the rescan
+    pub tracks: usize,
+}
+
+/// Size and modification time of an audio file, to tell whether it changed
+/// since it was last parsed
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileStamp {
+    size: u64,
+    modified: Option<std::time::SystemTime>,
+}
+
+impl FileStamp {
+    fn of(metadata: &std::fs::Metadata) -> Self {
+        Self {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        }
     }
+}
 
-    /// Parse an audio file (FLAC or MP3) and extract metadata
-    async fn parse_audio_file(&self, path: &Path) -> Result<Track> {
-        let metadata = tokio::fs::metadata(path).await?;
-        let file_size = metadata.len();
+/// What a scan collects while walking the library
+#[derive(Default)]
+struct ScanOutput {
+    tracks: Vec<Track>,
+    errors: Vec<ScanError>,
+    /// Stamp of every audio file found
+    stamps: HashMap<PathBuf, FileStamp>,
+    /// Every directory walked, including the library root
+    dirs: HashSet<PathBuf>,
+    /// Files that were parsed rather than reused from the previous scan
+    parsed: usize,
+    /// Lyrics found in the tags of the parsed files, by track ID
+    lyrics: HashMap<String, EmbeddedLyrics>,
+}
 
-        // Get the appropriate audio file handler based on extension
-        let ext = path
-            .extension()
-            .and_then(|s| s.to_str())
-            .ok_or_else(|| anyhow::anyhow!("No file extension"))?;
+/// When a file entered the library as RFC 3339: its creation (birth) time,
+/// or its modification time on filesystems that don't record one
+fn file_date_added(metadata: &std::fs::Metadata) -> String {
+    metadata
+        .created()
+        .or_else(|_| metadata.modified())
+        .map(chrono::DateTime::<chrono::Utc>::from)
+        .unwrap_or_else(|_| chrono::Utc::now())
+        .to_rfc3339()
+}
 
-        let handler = get_audio_file_handler(ext)
-            .ok_or_else(|| anyhow::anyhow!("Unsupported file format: {}", ext))?;
+/// Copy what lives in the databases rather than the file from the previous
+/// version of a re-parsed track: the lyrics flag, play stats and date added
+fn carry_over_library_state(track: &mut Track, old: &Track) {
+    track.has_lyrics = old.has_lyrics;
+    track.play_count = old.play_count;
+    track.last_played = old.last_played.clone();
+    track.date_added = old.date_added.clone();
+}
 
-        // Parse metadata and check cover art in a blocking task
-        // (audio libraries use synchronous I/O internally)
-        let path_owned = path.to_path_buf();
-        let (audio_metadata, has_cover) = tokio::task::spawn_blocking(move || {
-            let audio_metadata = handler
-                .parse_metadata(&path_owned)
-                .context("Failed to parse audio metadata")?;
-            let has_cover = handler.has_cover_art(&path_owned).unwrap_or(false);
-            Ok::<_, anyhow::Error>((audio_metadata, has_cover))
-        })
-        .await??;
+/// One bucket of an alphabetical jump-list over a sorted name list
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NameIndexEntry {
+    /// Upper-case letter, `#`, or a pinyin initial
+    pub key: String,
+    /// Number of names in the bucket
+    pub count: usize,
+    /// Position of the bucket's first name in the sorted list
+    pub offset: usize,
+}
 
-        // Generate a unique ID from the relative path (relative to library directory)
-        // This ensures consistent IDs regardless of where the library is mounted
-        let relative_path = path
-            .strip_prefix(&self.library_path)
-            .unwrap_or(path)
-            .to_string_lossy();
-        let id = format!("{:x}", md5::compute(relative_path.as_bytes()));
+/// Field a search suggestion was taken from, or a track search is limited to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionKind {
+    Title,
+    Artist,
+    Album,
+}
 
-        Ok(Track {
-            id,
-            path: path.to_path_buf(),
-            title: audio_metadata.title,
-            artist: audio_metadata.artist,
-            album: audio_metadata.album,
-            album_artist: audio_metadata.album_artist,
-            genre: audio_metadata.genre,
-            year: audio_metadata.year,
-            track_number: audio_metadata.track_number,
-            disc_number: audio_metadata.disc_number,
-            composer: audio_metadata.composer,
-            comment: audio_metadata.comment,
-            duration_secs: audio_metadata.duration_secs,
-            file_size,
-            has_cover,
-            has_lyrics: false, // Will be updated when lyrics database is queried
-            play_count: 0,     // Will be updated when stats database is queried
-            custom_fields: audio_metadata.custom_fields,
-        })
-    }
+/// A distinct title, artist or album matching a search prefix
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Suggestion {
+    pub value: String,
+    pub kind: SuggestionKind,
+}
 
-    /// Get all tracks in the library
-    pub async fn get_tracks(&self) -> Vec<Track> {
-        self.tracks.read().await.clone()
-    }
+/// Fold a value for prefix matching: NFKC also maps full-width Latin, as
+/// typed with CJK input methods, to its ASCII form
+fn suggestion_key(value: &str) -> String {
+    value.trim().nfkc().collect::<String>().to_lowercase()
+}
 
-    /// Get a specific track by ID
-    pub async fn get_track(&self, id: &str) -> Option<Track> {
-        self.tracks
-            .read()
-            .await
-            .iter()
-            .find(|t| t.id == id)
-            .cloned()
+/// Lowest similarity of a misspelled query to a value or one of its words
+const FUZZY_MATCH_THRESHOLD: f32 = 0.7;
+
+/// How well a folded value matches a folded query: 1.0 when equal, then
+/// prefix, substring and finally typo-tolerant matches (scaled below
+/// substrings); 0.0 for no match
+fn search_score(value: &str, query: &str) -> f32 {
+    if value == query {
+        return 1.0;
+    }
+    if value.starts_with(query) {
+        return 0.9;
+    }
+    if value.contains(query) {
+        return 0.8;
     }
 
-    /// Get the library path
-    #[allow(dead_code)]
-    pub fn library_path(&self) -> &Path {
-        &self.library_path
+    let best = std::iter::once(value)
+        .chain(value.split_whitespace())
+        .map(|candidate| edit_similarity(candidate, query))
+        .fold(0.0, f32::max);
+    if best >= FUZZY_MATCH_THRESHOLD {
+        best * 0.7
+    } else {
+        0.0
     }
+}
 
-    /// Update the has_lyrics flag for a track
-    pub async fn update_track_lyrics_status(&self, track_id: &str, has_lyrics: bool) {
-        let mut tracks = self.tracks.write().await;
-        if let Some(track) = tracks.iter_mut().find(|t| t.id == track_id) {
-            track.has_lyrics = has_lyrics;
-        }
-        drop(tracks);
-        self.invalidate_cache().await;
+/// 1 minus the Levenshtein distance relative to the longer string
+fn edit_similarity(a: &str, b: &str) -> f32 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let max_len = a.len().max(b.len());
+    if max_len == 0 {
+        return 0.0;
     }
 
-    /// Update the play count for a track
-    pub async fn update_track_play_count(&self, track_id: &str, play_count: u64) {
-        let mut tracks = self.tracks.write().await;
-        if let Some(track) = tracks.iter_mut().find(|t| t.id == track_id) {
-            track.play_count = play_count;
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
         }
-        drop(tracks);
-        self.invalidate_cache().await;
     }
+    1.0 - row[b.len()] as f32 / max_len as f32
+}
 
-    /// Build the album list from tracks (uncached computation).
-    fn build_albums(tracks: &[Track]) -> Vec<Album> {
-        let mut albums_map: HashMap<String, Vec<Track>> = HashMap::new();
+/// Lifecycle state of the most recent library scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanState {
+    /// No scan has run yet
+    #[default]
+    Idle,
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
 
-        for track in tracks.iter() {
-            let album_name = track
-                .album
-                .clone()
-                .unwrap_or_else(|| "Unknown Album".to_string());
-            albums_map
-                .entry(album_name)
-                .or_default()
-                .push(track.clone());
-        }
+/// Status of the most recent (or current) library scan
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScanStatus {
+    pub state: ScanState,
+    /// RFC 3339 timestamps
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    /// Tracks found by the last completed scan
+    pub track_count: Option<usize>,
+    /// Failure reason when `state` is `failed`
+    pub error: Option<String>,
+}
 
-        let mut albums: Vec<Album> = albums_map
-            .into_iter()
-            .map(|(name, tracks)| {
-                let artist = tracks.first().and_then(|t| t.artist.clone());
-                let total_duration_secs = tracks.iter().filter_map(|t| t.duration_secs).sum();
-                let track_count = tracks.len();
+/// Scan status plus the token used to cancel the running scan
+#[derive(Debug, Default)]
+struct ScanControl {
+    status: ScanStatus,
+    cancel: Option<CancellationToken>,
+    /// Whether any scan has finished, successfully or not
+    finished_once: bool,
+}
 
-                Album {
-                    name,
-                    artist,
-                    track_count,
-                    total_duration_secs,
-                    tracks,
-                }
-            })
-            .collect();
+/// Error returned by [`MusicLibrary::scan`] when the scan was cancelled
+#[derive(Debug)]
+pub struct ScanCancelled;
 
-        albums.sort_by(|a, b| a.name.cmp(&b.name));
-        albums
+impl std::fmt::Display for ScanCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Scan cancelled")
     }
+}
 
-    /// Build the artist list from albums (uncached computation).
-    fn build_artists(albums: &[Album]) -> Vec<Artist> {
-        let mut artists_map: HashMap<String, Vec<Album>> = HashMap::new();
+impl std::error::Error for ScanCancelled {}
 
-        for album in albums.iter() {
-            let artist_name = album
-                .artist
-                .clone()
-                .unwrap_or_else(|| "Unknown Artist".to_string());
-            artists_map
-                .entry(artist_name)
-                .or_default()
-                .push(album.clone());
-        }
+/// A file that was skipped during the last scan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanError {
+    /// Path relative to the library root
+    pub path: String,
+    pub error: String,
+}
 
-        let mut artists: Vec<Artist> = artists_map
-            .into_iter()
-            .map(|(name, albums)| {
-                let album_count = albums.len();
-                let track_count = albums.iter().map(|a| a.track_count).sum();
+/// Tracks parsed by [`MusicLibrary::inspect`], which are not added to the library
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InspectReport {
+    /// Sorted by relative path
+    pub tracks: Vec<Track>,
+    /// Files that could not be parsed
+    pub errors: Vec<ScanError>,
+}
 
-                Artist {
-                    name,
-                    album_count,
-                    track_count,
-                    albums,
-                }
-            })
-            .collect();
+/// Image files used as album art when no track in the album has embedded art
+const FOLDER_COVER_NAMES: &[&str] = &["folder.jpg", "cover.jpg", "folder.png", "cover.png"];
 
-        artists.sort_by(|a, b| a.name.cmp(&b.name));
-        artists
-    }
+/// Name of the directory inside the library that holds the server's databases
+pub const DATA_DIR_NAME: &str = ".music-station";
 
-    /// Get all albums in the library (cached).
-    pub async fn get_albums(&self) -> Vec<Album> {
-        {
-            let cache = self.albums_cache.read().await;
-            if let Some(ref albums) = *cache {
-                return albums.clone();
-            }
+/// How tracks without an album tag are grouped into albums
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UntaggedGrouping {
+    /// Collect all untagged tracks into a single "unknown" album
+    #[default]
+    Single,
+    /// Group untagged tracks by the name of their parent folder
+    ParentFolder,
+}
+
+/// Configuration for how the library groups and labels tracks
+#[derive(Debug, Clone)]
+pub struct LibraryConfig {
+    /// Album name used for tracks without an album tag
+    pub unknown_album_label: String,
+    /// Artist name used for albums without an artist tag
+    pub unknown_artist_label: String,
+    /// Artist name shown for compilation albums
+    pub various_artists_label: String,
+    /// Grouping policy for tracks without an album tag
+    pub untagged_grouping: UntaggedGrouping,
+    /// Ignore case when looking up albums and artists by name
+    pub case_insensitive_lookup: bool,
+    /// File and directory name patterns skipped during scan (`*` and `?` wildcards)
+    pub exclude_patterns: Vec<String>,
+    /// Maximum time spent parsing a single file before it is skipped
+    pub parse_timeout: Duration,
+    /// Read every packet to compute durations the container does not report (slow)
+    pub decode_duration_fallback: bool,
+    /// Leading articles ignored when grouping and sorting artists, so "The Beatles"
+    /// and "Beatles" are one artist sorted under B. Empty disables this.
+    pub leading_articles: Vec<String>,
+    /// Keep audio files that fail to parse (empty or truncated) as tracks
+    /// with only file information and a `parse_error`, instead of dropping them
+    pub keep_unparseable_files: bool,
+    /// Bucket names starting with a Chinese character under its pinyin initial
+    /// in the name indexes, instead of under `#`
+    pub pinyin_index: bool,
+    /// Hash used to derive track IDs from relative paths
+    pub track_id_hash: TrackIdHash,
+    /// File extensions scanned, lowercase without the dot. `None` scans every
+    /// file and keeps those a handler can parse.
+    pub extensions: Option<Vec<String>>,
+    /// Files within a directory parsed at once during scan
+    pub scan_concurrency: usize,
+    /// Use folder images (e.g. `folder.jpg`) for albums without embedded art
+    pub cover_fallback: bool,
+}
+
+impl Default for LibraryConfig {
+    fn default() -> Self {
+        Self {
+            unknown_album_label: "Unknown Album".to_string(),
+            unknown_artist_label: "Unknown Artist".to_string(),
+            various_artists_label: "Various Artists".to_string(),
+            untagged_grouping: UntaggedGrouping::Single,
+            case_insensitive_lookup: false,
+            exclude_patterns: default_exclude_patterns(),
+            parse_timeout: Duration::from_secs(30),
+            decode_duration_fallback: false,
+            leading_articles: Vec::new(),
+            keep_unparseable_files: false,
+            pinyin_index: false,
+            track_id_hash: TrackIdHash::default(),
+            extensions: None,
+            scan_concurrency: 1,
+            cover_fallback: true,
         }
+    }
+}
 
-        let tracks = self.tracks.read().await;
-        let albums = Self::build_albums(&tracks);
-        *self.albums_cache.write().await = Some(albums.clone());
-        albums
+/// Hash used to derive track IDs. Every database keys tracks by ID, so the
+/// choice is recorded in the data directory (see [`resolve_track_id_hash`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrackIdHash {
+    /// 32 hex digits; the original scheme
+    #[default]
+    Md5,
+    /// Full 256-bit BLAKE3 digest, 64 hex digits
+    Blake3,
+    /// SHA-256 truncated to 128 bits, 32 hex digits like MD5
+    Sha256,
+}
+
+impl TrackIdHash {
+    pub fn name(self) -> &'static str {
+        match self {
+            TrackIdHash::Md5 => "md5",
+            TrackIdHash::Blake3 => "blake3",
+            TrackIdHash::Sha256 => "sha256",
+        }
     }
 
-    /// Get all artists with their albums (cached).
-    pub async fn get_artists(&self) -> Vec<Artist> {
-        {
-            let cache = self.artists_cache.read().await;
-            if let Some(ref artists) = *cache {
-                return artists.clone();
+    /// Hex digest of `source`
+    pub fn hash(self, source: &str) -> String {
+        match self {
+            TrackIdHash::Md5 => format!("{:x}", md5::compute(source.as_bytes())),
+            TrackIdHash::Blake3 => blake3::hash(source.as_bytes()).to_hex().to_string(),
+            TrackIdHash::Sha256 => {
+                use sha2::Digest;
+                sha2::Sha256::digest(source.as_bytes())[..16]
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect()
             }
         }
-
-        let albums = self.get_albums().await;
-        let artists = Self::build_artists(&albums);
-        *self.artists_cache.write().await = Some(artists.clone());
-        artists
     }
+}
 
-    /// Get a specific album by name
-    pub async fn get_album(&self, album_name: &str) -> Option<Album> {
-        self.get_albums()
-            .await
-            .into_iter()
-            .find(|a| a.name == album_name)
-    }
+impl std::str::FromStr for TrackIdHash {
+    type Err = anyhow::Error;
 
-    /// Get a specific artist by name
-    pub async fn get_artist(&self, artist_name: &str) -> Option<Artist> {
-        self.get_artists()
-            .await
-            .into_iter()
-            .find(|a| a.name == artist_name)
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "md5" => Ok(TrackIdHash::Md5),
+            "blake3" => Ok(TrackIdHash::Blake3),
+            "sha256" => Ok(TrackIdHash::Sha256),
+            other => anyhow::bail!(
+                "Unknown track ID hash '{}' (expected md5, blake3 or sha256)",
+                other
+            ),
+        }
     }
+}
 
-    /// Get library statistics
-    pub async fn get_stats(&self) -> LibraryStats {
-        let tracks = self.tracks.read().await;
-        let albums = self.get_albums().await;
-        let artists = self.get_artists().await;
+/// File in the data directory recording which [`TrackIdHash`] the databases use
+pub const TRACK_ID_HASH_FILE: &str = "track-id-hash";
 
-        let total_duration_secs = tracks.iter().filter_map(|t| t.duration_secs).sum();
-        let total_size_bytes = tracks.iter().map(|t| t.file_size).sum();
-        let total_plays = tracks.iter().map(|t| t.play_count).sum();
+/// Decide the track ID hash for a data directory and record it there.
+///
+/// The recorded hash wins when `requested` is `None`. Data directories from
+/// before the hash was configurable hold MD5 IDs, so one with databases but
+/// no record counts as MD5. Requesting a different hash than the databases
+/// use is an error, since every stored lyric, play count and playlist entry
+/// would lose its track.
+pub fn resolve_track_id_hash(
+    data_dir: &Path,
+    requested: Option<TrackIdHash>,
+) -> Result<TrackIdHash> {
+    let record = data_dir.join(TRACK_ID_HASH_FILE);
+    let stored = match std::fs::read_to_string(&record) {
+        Ok(name) => Some(
+            name.parse::<TrackIdHash>()
+                .with_context(|| format!("Invalid {}", record.display()))?,
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let has_databases = std::fs::read_dir(data_dir).is_ok_and(|entries| {
+                entries
+                    .flatten()
+                    .any(|entry| entry.path().extension().is_some_and(|ext| ext == "db"))
+            });
+            has_databases.then_some(TrackIdHash::Md5)
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", record.display())),
+    };
 
-        LibraryStats {
-            total_tracks: tracks.len(),
-            total_albums: albums.len(),
-            total_artists: artists.len(),
-            total_duration_secs,
-            total_size_bytes,
-            total_plays,
+    let hash = match (stored, requested) {
+        (Some(stored), Some(requested)) if stored != requested => anyhow::bail!(
+            "The databases in {} use {} track IDs, but {} was requested",
+            data_dir.display(),
+            stored.name(),
+            requested.name()
+        ),
+        (Some(hash), _) | (None, Some(hash)) => hash,
+        (None, None) => TrackIdHash::default(),
+    };
+
+    std::fs::create_dir_all(data_dir)
+        .with_context(|| format!("Failed to create {}", data_dir.display()))?;
+    std::fs::write(&record, hash.name())
+        .with_context(|| format!("Failed to write {}", record.display()))?;
+    Ok(hash)
+}
+
+/// Patterns excluded from scanning by default: dot-entries (including the
+/// `.music-station` data directory) and Synology `@eaDir` thumbnail folders
+pub fn default_exclude_patterns() -> Vec<String> {
+    vec![".*".to_string(), "@eaDir".to_string()]
+}
+
+/// Articles stripped from artist names when article merging is enabled
+/// without an explicit list. `L'` matches without a following space.
+pub fn default_leading_articles() -> Vec<String> {
+    [
+        "The", "A", "An", "Le", "La", "Les", "L'", "Der", "Die", "Das", "El", "Los", "Las", "Il",
+        "Lo",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+/// GB2312 codes where each pinyin initial starts. Level-1 hanzi (0xB0A1 to
+/// 0xD7F9) are ordered by pinyin; there are no syllables starting with I, U or V.
+const PINYIN_INITIALS: [(u16, char); 23] = [
+    (0xB0A1, 'A'),
+    (0xB0C5, 'B'),
+    (0xB2C1, 'C'),
+    (0xB4EE, 'D'),
+    (0xB6EA, 'E'),
+    (0xB7A2, 'F'),
+    (0xB8C1, 'G'),
+    (0xB9FE, 'H'),
+    (0xBBF7, 'J'),
+    (0xBFA6, 'K'),
+    (0xC0AC, 'L'),
+    (0xC2E8, 'M'),
+    (0xC4C3, 'N'),
+    (0xC5B6, 'O'),
+    (0xC5BE, 'P'),
+    (0xC6DA, 'Q'),
+    (0xC8BB, 'R'),
+    (0xC8F6, 'S'),
+    (0xCBFA, 'T'),
+    (0xCDDA, 'W'),
+    (0xCEF4, 'X'),
+    (0xD1B9, 'Y'),
+    (0xD4D1, 'Z'),
+];
+
+/// Pinyin initial of a common (GB2312 level-1) Chinese character. Rarer and
+/// traditional-only characters return `None`.
+fn pinyin_initial(c: char) -> Option<char> {
+    let mut buf = [0; 4];
+    let (bytes, _, had_errors) = encoding_rs::GBK.encode(c.encode_utf8(&mut buf));
+    let [high, low] = bytes[..] else {
+        return None;
+    };
+    let code = u16::from_be_bytes([high, low]);
+    if had_errors || !(0xB0A1..=0xD7F9).contains(&code) {
+        return None;
+    }
+    PINYIN_INITIALS
+        .iter()
+        .rev()
+        .find(|(start, _)| code >= *start)
+        .map(|(_, initial)| *initial)
+}
+
+/// Match a name against a glob pattern supporting `*` and `?`
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            // Backtrack: let the last `*` absorb one more character
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
         }
     }
 
-    /// Update metadata for a track
-    pub async fn update_track_metadata(
-        &self,
-        id: &str,
-        update: TrackMetadataUpdate,
-    ) -> Result<Track> {
-        // Find the track
-        let track = {
-            let tracks = self.tracks.read().await;
-            tracks
-                .iter()
-                .find(|t| t.id == id)
-                .cloned()
-                .ok_or_else(|| anyhow::anyhow!("Track not found: {}", id))?
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl LibraryConfig {
+    /// Album name to group a track under, applying the untagged policy
+    fn album_name_for(&self, track: &Track) -> String {
+        if let Some(album) = &track.album {
+            return album.clone();
+        }
+
+        match self.untagged_grouping {
+            UntaggedGrouping::Single => self.unknown_album_label.clone(),
+            UntaggedGrouping::ParentFolder => track
+                .path
+                .parent()
+                .and_then(|p| p.file_name())
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| self.unknown_album_label.clone()),
+        }
+    }
+
+    /// Check whether a directory entry should be skipped during scan
+    fn is_excluded(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name().map(|n| n.to_string_lossy()) else {
+            return false;
         };
+        self.exclude_patterns
+            .iter()
+            .any(|pattern| glob_match(pattern, &name))
+    }
 
-        tracing::debug!(
-            "Updating metadata for track: {} ({})",
-            track.title.as_deref().unwrap_or("Unknown"),
-            track.path.display()
-        );
+    /// Check whether files with extension `ext` are scanned
+    fn scans_extension(&self, ext: &str) -> bool {
+        self.extensions
+            .as_ref()
+            .is_none_or(|extensions| extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+    }
 
-        // Update the audio file metadata
-        self.write_audio_metadata(&track.path, &update)
-            .await
-            .context(format!(
-                "Failed to write metadata to file: {}",
-                track.path.display()
-            ))?;
+    /// Remove a configured leading article from an artist name ("The Beatles"
+    /// and "Beatles, The" both become "Beatles"). Articles match case-insensitively
+    /// and a name that is only an article is left alone.
+    fn strip_article<'a>(&self, name: &'a str) -> &'a str {
+        let name = name.trim();
+        for article in &self.leading_articles {
+            let Some(prefix) = name.get(..article.len()) else {
+                continue;
+            };
+            if !prefix.eq_ignore_ascii_case(article) {
+                continue;
+            }
+            let rest = &name[article.len()..];
+            if article.ends_with('\'') || rest.starts_with(char::is_whitespace) {
+                let rest = rest.trim_start();
+                if !rest.is_empty() {
+                    return rest;
+                }
+            }
+        }
+        for article in &self.leading_articles {
+            if let Some((rest, suffix)) = name.rsplit_once(", ")
+                && suffix.eq_ignore_ascii_case(article)
+                && !rest.trim().is_empty()
+            {
+                return rest.trim();
+            }
+        }
+        name
+    }
 
-        // Re-parse the file to get updated metadata
-        let mut updated_track = self
-            .parse_audio_file(&track.path)
-            .await
-            .context("Failed to re-parse file after update")?;
+    /// Jump-list bucket for a name: its first letter without accents
+    /// ("Émile" -> "E"), the pinyin initial of a Chinese name when enabled,
+    /// or `#` for digits, symbols and other scripts
+    fn index_key(&self, name: &str) -> String {
+        let Some(first) = name.trim().chars().next() else {
+            return "#".to_string();
+        };
+        let base = std::iter::once(first).nfd().next().unwrap_or(first);
+        if base.is_ascii_alphabetic() {
+            return base.to_ascii_uppercase().to_string();
+        }
+        if self.pinyin_index
+            && let Some(initial) = pinyin_initial(first)
+        {
+            return initial.to_string();
+        }
+        "#".to_string()
+    }
 
-        // Preserve the has_lyrics flag from the original track
-        // (it's stored in the lyrics database, not in the audio file)
-        updated_track.has_lyrics = track.has_lyrics;
+    /// Key artists are grouped by: the name itself, or with article merging
+    /// enabled, the normalized name without its leading article
+    fn artist_key(&self, name: &str) -> String {
+        if self.leading_articles.is_empty() {
+            name.to_string()
+        } else {
+            self.normalize_name(self.strip_article(name))
+        }
+    }
+
+    /// Normalize an album or artist name for lookup comparison.
+    ///
+    /// Applies Unicode NFC (tags written on macOS are often NFD), trims
+    /// surrounding whitespace and optionally folds case.
+    fn normalize_name(&self, name: &str) -> String {
+        let normalized: String = name.trim().nfc().collect();
+        if self.case_insensitive_lookup {
+            normalized.to_lowercase()
+        } else {
+            normalized
+        }
+    }
+}
+
+/// Whether an album's tracks form a compilation: any track carries the
+/// compilation tag, the shared album artist is "Various Artists", or the track
+/// artists differ and there is no shared album artist
+fn is_compilation(tracks: &[Track], config: &LibraryConfig) -> bool {
+    if tracks.iter().any(|t| t.compilation) {
+        return true;
+    }
 
-        // Update in-memory track list
+    let is_various = |name: &str| {
+        let name = name.trim();
+        name.eq_ignore_ascii_case("Various Artists")
+            || name.eq_ignore_ascii_case(&config.various_artists_label)
+    };
+    let album_artists: HashSet<&str> = tracks
+        .iter()
+        .filter_map(|t| t.album_artist.as_deref())
+        .map(str::trim)
+        .collect();
+    let shared_album_artist = match (album_artists.len(), album_artists.iter().next()) {
+        (1, Some(name)) if tracks.iter().all(|t| t.album_artist.is_some()) => Some(*name),
+        _ => None,
+    };
+    if shared_album_artist.is_some_and(is_various) {
+        return true;
+    }
+
+    let artists: HashSet<&str> = tracks
+        .iter()
+        .filter_map(|t| t.artist.as_deref())
+        .map(str::trim)
+        .collect();
+    artists.len() > 1 && shared_album_artist.is_none()
+}
+
+/// Year and year range of an album from its tracks' year tags, using the
+/// leading four digits of each tag (`2001-05-14` counts as 2001). The year is
+/// the most common one, ties going to the earliest; the range is only set
+/// when the tracks disagree.
+fn album_years(tracks: &[Track]) -> (Option<String>, Option<(String, String)>) {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for year in tracks.iter().filter_map(|t| t.year.as_deref()) {
+        let year = year.trim();
+        if let Some(prefix) = year.get(..4)
+            && prefix.bytes().all(|b| b.is_ascii_digit())
         {
-            let mut tracks = self.tracks.write().await;
-            if let Some(pos) = tracks.iter().position(|t| t.id == id) {
-                tracks[pos] = updated_track.clone();
-            }
+            *counts.entry(prefix).or_default() += 1;
         }
-        self.invalidate_cache().await;
+    }
 
-        tracing::info!(
-            "Updated metadata for track: {} ({})",
-            updated_track.title.as_deref().unwrap_or("Unknown"),
-            id
+    let year = counts
+        .iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)))
+        .map(|(year, _)| year.to_string());
+    let range = match (counts.keys().min(), counts.keys().max()) {
+        (Some(first), Some(last)) if first != last => Some((first.to_string(), last.to_string())),
+        _ => None,
+    };
+    (year, range)
+}
+
+impl Album {
+    /// The album's tracks in play order: by disc, then track number, with
+    /// untagged tracks after the numbered ones and ties broken by start time
+    /// (CUE tracks) and path
+    pub fn play_order(&self) -> Vec<&Track> {
+        let mut tracks: Vec<&Track> = self.tracks.iter().collect();
+        tracks.sort_by_key(|t| {
+            (
+                leading_number(t.disc_number.as_deref()).unwrap_or(1),
+                leading_number(t.track_number.as_deref()).unwrap_or(u32::MAX),
+                t.start_ms,
+                t.relative_path.clone(),
+            )
+        });
+        tracks
+    }
+}
+
+/// Number at the start of a track or disc tag (`3` from `3/12`)
+fn leading_number(value: Option<&str>) -> Option<u32> {
+    let value = value?.trim();
+    let end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+/// Whether `path` exists. Errors other than "not found" (e.g. permissions)
+/// count as present so a track is only flagged missing when it really is gone.
+async fn file_exists(path: &Path) -> bool {
+    tokio::fs::try_exists(path).await.unwrap_or(true)
+}
+
+/// Generate a stable, URL-safe ID for an album or artist name
+pub fn name_id(name: &str) -> String {
+    format!("{:x}", md5::compute(name.as_bytes()))
+}
+
+#[derive(Clone)]
+pub struct MusicLibrary {
+    library_path: PathBuf,
+    config: Arc<LibraryConfig>,
+    /// Handlers for the audio formats the library reads and writes
+    formats: Arc<AudioFormatRegistry>,
+    tracks: Arc<RwLock<Vec<Track>>>,
+    groupings_cache: Arc<RwLock<Option<Arc<Groupings>>>>,
+    /// Times the album and artist groupings were computed
+    #[cfg(test)]
+    grouping_builds: Arc<std::sync::atomic::AtomicUsize>,
+    scan_errors: Arc<RwLock<Vec<ScanError>>>,
+    /// Stamps of the audio files seen by the last scan, for [`Self::rescan`]
+    file_stamps: Arc<RwLock<HashMap<PathBuf, FileStamp>>>,
+    /// Directories walked by the last scan, to recognise removed ones
+    known_dirs: Arc<RwLock<HashSet<PathBuf>>>,
+    /// Embedded lyrics read by scans and not yet taken by [`Self::take_embedded_lyrics`]
+    embedded_lyrics: Arc<RwLock<HashMap<String, EmbeddedLyrics>>>,
+    /// Album ID -> ID of the track whose cover represents the album
+    album_cover_cache: Arc<RwLock<HashMap<String, Option<String>>>>,
+    changes: Arc<RwLock<ChangeLog>>,
+    scan_control: Arc<RwLock<ScanControl>>,
+    /// Woken whenever a scan finishes, for [`Self::wait_for_scan`]
+    scan_finished: Arc<tokio::sync::Notify>,
+}
+
+/// Fluent configuration for a [`MusicLibrary`], starting from
+/// [`LibraryConfig::default`].
+///
+/// ```
+/// use music_station::library::{MusicLibrary, TrackIdHash};
+///
+/// let library = MusicLibrary::builder("/srv/music")
+///     .extensions(["flac", "mp3"])
+///     .exclude_patterns(["@eaDir", "*.tmp"])
+///     .scan_concurrency(4)
+///     .id_strategy(TrackIdHash::Blake3)
+///     .cover_fallback(false)
+///     .build();
+///
+/// assert_eq!(library.config().scan_concurrency, 4);
+/// assert_eq!(library.config().track_id_hash, TrackIdHash::Blake3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MusicLibraryBuilder {
+    library_path: PathBuf,
+    config: LibraryConfig,
+    formats: Arc<AudioFormatRegistry>,
+}
+
+impl MusicLibraryBuilder {
+    pub fn new(library_path: impl Into<PathBuf>) -> Self {
+        Self {
+            library_path: library_path.into(),
+            config: LibraryConfig::default(),
+            formats: default_formats(),
+        }
+    }
+
+    /// Replace the whole configuration, e.g. one built from command-line
+    /// options, before adjusting individual settings
+    pub fn config(mut self, config: LibraryConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Only scan files with these extensions (case-insensitive, with or
+    /// without the leading dot)
+    ///
+    /// ```
+    /// # use music_station::library::MusicLibrary;
+    /// let library = MusicLibrary::builder("/srv/music")
+    ///     .extensions([".FLAC", "ape"])
+    ///     .build();
+    /// assert_eq!(
+    ///     library.config().extensions,
+    ///     Some(vec!["flac".to_string(), "ape".to_string()])
+    /// );
+    /// ```
+    pub fn extensions<I, S>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.config.extensions = Some(
+            extensions
+                .into_iter()
+                .map(|ext| ext.as_ref().trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
         );
+        self
+    }
 
-        Ok(updated_track)
+    /// Replace the default exclude patterns (`*` and `?` wildcards)
+    pub fn exclude_patterns<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.exclude_patterns = patterns.into_iter().map(Into::into).collect();
+        self
     }
 
-    /// Write metadata to an audio file (FLAC or MP3)
-    async fn write_audio_metadata(&self, path: &Path, update: &TrackMetadataUpdate) -> Result<()> {
-        let ext = path
-            .extension()
-            .and_then(|s| s.to_str())
-            .ok_or_else(|| anyhow::anyhow!("No file extension"))?;
+    /// Parse up to `concurrency` files of a directory at once (at least 1)
+    pub fn scan_concurrency(mut self, concurrency: usize) -> Self {
+        self.config.scan_concurrency = concurrency.max(1);
+        self
+    }
 
-        tracing::debug!("Writing metadata to {} file: {}", ext, path.display());
+    /// Hash used to derive track IDs; see [`resolve_track_id_hash`] for
+    /// keeping it consistent with existing databases
+    pub fn id_strategy(mut self, hash: TrackIdHash) -> Self {
+        self.config.track_id_hash = hash;
+        self
+    }
 
-        let handler = get_audio_file_handler(ext)
-            .ok_or_else(|| anyhow::anyhow!("Unsupported file format: {}", ext))?;
+    /// Whether albums without embedded art fall back to folder images
+    pub fn cover_fallback(mut self, enabled: bool) -> Self {
+        self.config.cover_fallback = enabled;
+        self
+    }
 
-        let path_owned = path.to_path_buf();
-        let update_owned = update.clone();
-        tokio::task::spawn_blocking(move || {
-            handler
-                .write_metadata(&path_owned, &update_owned)
-                .context(format!("Failed to write metadata to {}", path_owned.display()))
-        })
-        .await?
+    /// Read and write audio files with the handlers in `formats` instead of
+    /// the built-in ones
+    ///
+    /// ```
+    /// use music_station::audio::{AudioFormatRegistry, Mp3File};
+    /// use music_station::library::MusicLibrary;
+    ///
+    /// let mut formats = AudioFormatRegistry::default();
+    /// formats.register("mpga", || Box::new(Mp3File));
+    /// let library = MusicLibrary::builder("/srv/music").formats(formats).build();
+    /// assert!(library.formats().handler("mpga").is_some());
+    /// ```
+    pub fn formats(mut self, formats: impl Into<Arc<AudioFormatRegistry>>) -> Self {
+        self.formats = formats.into();
+        self
     }
 
-    /// Get cover art from an audio file (FLAC or MP3)
-    pub async fn get_cover_art(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+    pub fn build(self) -> MusicLibrary {
+        let mut library = MusicLibrary::with_config(self.library_path, self.config);
+        library.formats = self.formats;
+        library
+    }
+}
+
+impl MusicLibrary {
+    /// Create a library with the default configuration
+    ///
+    /// ```
+    /// use music_station::library::MusicLibrary;
+    ///
+    /// let library = MusicLibrary::new("/srv/music".into());
+    /// assert!(library.config().cover_fallback);
+    /// ```
+    pub fn new(library_path: PathBuf) -> Self {
+        Self {
+            library_path,
+            config: Arc::new(LibraryConfig::default()),
+            formats: default_formats(),
+            tracks: Arc::new(RwLock::new(Vec::new())),
+            groupings_cache: Arc::new(RwLock::new(None)),
+            #[cfg(test)]
+            grouping_builds: Default::default(),
+            scan_errors: Arc::new(RwLock::new(Vec::new())),
+            file_stamps: Arc::new(RwLock::new(HashMap::new())),
+            known_dirs: Arc::new(RwLock::new(HashSet::new())),
+            embedded_lyrics: Arc::new(RwLock::new(HashMap::new())),
+            album_cover_cache: Arc::new(RwLock::new(HashMap::new())),
+            changes: Arc::new(RwLock::new(ChangeLog::default())),
+            scan_control: Arc::new(RwLock::new(ScanControl::default())),
+            scan_finished: Arc::new(tokio::sync::Notify::new()),
+        }
+    }
+
+    /// Start configuring a library; see [`MusicLibraryBuilder`]
+    pub fn builder(library_path: impl Into<PathBuf>) -> MusicLibraryBuilder {
+        MusicLibraryBuilder::new(library_path)
+    }
+
+    /// Create a library with custom grouping and labeling options
+    pub fn with_config(library_path: PathBuf, config: LibraryConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            ..Self::new(library_path)
+        }
+    }
+
+    /// Configuration the library was created with
+    pub fn config(&self) -> &LibraryConfig {
+        &self.config
+    }
+
+    /// Audio formats the library reads and writes
+    pub fn formats(&self) -> &AudioFormatRegistry {
+        &self.formats
+    }
+
+    /// Run a synchronous audio handler operation on the blocking thread pool.
+    ///
+    /// The audio libraries (symphonia, metaflac, id3, mp4ameta) do synchronous
+    /// file I/O and decoding, so every handler call goes through here to keep
+    /// the async runtime responsive.
+    async fn run_handler<T, F>(&self, path: &Path, op: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn AudioFile, &Path) -> Result<T> + Send + 'static,
+    {
         let ext = path
             .extension()
             .and_then(|s| s.to_str())
             .ok_or_else(|| anyhow::anyhow!("No file extension"))?;
 
-        let handler = get_audio_file_handler(ext)
+        let handler = self
+            .formats
+            .handler(ext)
             .ok_or_else(|| anyhow::anyhow!("Unsupported file format: {}", ext))?;
 
         let path_owned = path.to_path_buf();
-        tokio::task::spawn_blocking(move || handler.get_cover_art(&path_owned)).await?
+        tokio::task::spawn_blocking(move || op(handler.as_ref(), &path_owned)).await?
     }
 
-    /// Set cover art for an audio file (FLAC or MP3)
-    pub async fn set_cover_art(
-        &self,
-        id: &str,
-        image_data: Vec<u8>,
-        mime_type: &str,
-    ) -> Result<()> {
-        // Find the track
-        let track = {
-            let tracks = self.tracks.read().await;
-            tracks
-                .iter()
-                .find(|t| t.id == id)
-                .cloned()
-                .ok_or_else(|| anyhow::anyhow!("Track not found"))?
-        };
+    /// Invalidate the cached album and artist collections.
+    /// Must be called whenever the track list is mutated.
+    async fn invalidate_cache(&self) {
+        *self.groupings_cache.write().await = None;
+        self.album_cover_cache.write().await.clear();
+    }
 
-        let ext = track
-            .path
-            .extension()
-            .and_then(|s| s.to_str())
-            .ok_or_else(|| anyhow::anyhow!("No file extension"))?;
+    /// Append track changes to the change log, bumping the library version
+    async fn record_changes(&self, changes: impl IntoIterator<Item = (ChangeType, String)>) {
+        let mut log = self.changes.write().await;
+        for (change, track_id) in changes {
+            log.record(change, track_id);
+        }
+    }
 
-        let handler = get_audio_file_handler(ext)
-            .ok_or_else(|| anyhow::anyhow!("Unsupported file format: {}", ext))?;
+    /// Current library version, incremented on every track change
+    pub async fn version(&self) -> u64 {
+        self.changes.read().await.version
+    }
 
-        let path_owned = track.path.clone();
-        let mime_type_owned = mime_type.to_string();
-        tokio::task::spawn_blocking(move || {
-            handler.set_cover_art(&path_owned, image_data, &mime_type_owned)
-        })
-        .await??;
+    /// Collapse the change log into the net changes since `since`.
+    /// A track added and then removed within the window is omitted entirely.
+    pub async fn changes_since(&self, since: u64) -> TrackChanges {
+        let (version, full_resync_required, net) = {
+            let log = self.changes.read().await;
+            let full_resync_required = since < log.evicted_through || since > log.version;
 
-        // Update in-memory track
-        let mut updated_track = self
-            .parse_audio_file(&track.path)
-            .await
-            .context("Failed to re-parse file after cover update")?;
+            // Track ID -> (first change, last change) within the window
+            let mut net: HashMap<String, (ChangeType, ChangeType)> = HashMap::new();
+            if !full_resync_required {
+                for (_, change, id) in log.entries.iter().filter(|(v, _, _)| *v > since) {
+                    net.entry(id.clone())
+                        .and_modify(|(_, last)| *last = *change)
+                        .or_insert((*change, *change));
+                }
+            }
+            (log.version, full_resync_required, net)
+        };
 
-        // Preserve the has_lyrics flag
-        updated_track.has_lyrics = track.has_lyrics;
+        let tracks = self.tracks.read().await;
+        let mut changes = TrackChanges {
+            version,
+            full_resync_required,
+            added: Vec::new(),
+            updated: Vec::new(),
+            removed: Vec::new(),
+        };
 
-        {
-            let mut tracks = self.tracks.write().await;
-            if let Some(pos) = tracks.iter().position(|t| t.id == id) {
-                tracks[pos] = updated_track;
+        for (id, (first, last)) in net {
+            match (first, last) {
+                (ChangeType::Added, ChangeType::Removed) => {}
+                (_, ChangeType::Removed) => changes.removed.push(id),
+                (first, _) => {
+                    if let Some(track) = tracks.iter().find(|t| t.id == id) {
+                        if first == ChangeType::Added {
+                            changes.added.push(track.clone());
+                        } else {
+                            changes.updated.push(track.clone());
+                        }
+                    }
+                }
             }
         }
-        self.invalidate_cache().await;
 
-        tracing::info!("Updated cover art for track: {}", id);
+        changes
+    }
 
+    /// Scan the library folder for audio files.
+    ///
+    /// The new track list is only swapped in once the whole scan succeeds; a
+    /// cancelled or failed scan leaves the previous list untouched. Fails if
+    /// another scan is already running.
+    pub async fn scan(&self) -> crate::error::Result<()> {
+        let cancel = self.begin_scan().await?;
+        self.finish_scan(&cancel, false).await?;
         Ok(())
     }
 
-    /// Remove cover art from an audio file (FLAC or MP3)
-    pub async fn remove_cover_art(&self, id: &str) -> Result<()> {
-        // Find the track
-        let track = {
-            let tracks = self.tracks.read().await;
-            tracks
-                .iter()
-                .find(|t| t.id == id)
-                .cloned()
-                .ok_or_else(|| anyhow::anyhow!("Track not found"))?
+    /// Scan the library folder again, only parsing files that are new or whose
+    /// size or modification time changed since the last scan.
+    ///
+    /// Unchanged files keep their previous track, and tracks whose files are
+    /// gone are dropped. Like [`Self::scan`], the result is swapped in at
+    /// once, and it fails if another scan is already running.
+    pub async fn rescan(&self) -> crate::error::Result<RescanReport> {
+        let cancel = self.begin_scan().await?;
+        Ok(self.finish_scan(&cancel, true).await?)
+    }
+
+    /// Start a scan in a background task. The scan is already marked as
+    /// running when this returns, so status and readiness checks made right
+    /// afterwards see it. Fails if another scan is already running.
+    pub async fn spawn_scan(
+        &self,
+    ) -> crate::error::Result<tokio::task::JoinHandle<crate::error::Result<()>>> {
+        let cancel = self.begin_scan().await?;
+        let library = self.clone();
+        Ok(tokio::spawn(async move {
+            library.finish_scan(&cancel, false).await?;
+            Ok(())
+        }))
+    }
+
+    /// Whether the track list has been loaded, i.e. the first scan is not still running.
+    /// A library that has never been scanned counts as loaded (and empty).
+    pub async fn is_loaded(&self) -> bool {
+        let control = self.scan_control.read().await;
+        control.finished_once || control.status.state != ScanState::Running
+    }
+
+    /// Wait until no scan is running. Returns at once if none is.
+    pub async fn wait_for_scan(&self) {
+        loop {
+            // Registered before the check so a scan finishing in between still wakes us
+            let finished = self.scan_finished.notified();
+            if self.scan_control.read().await.status.state != ScanState::Running {
+                return;
+            }
+            finished.await;
+        }
+    }
+
+    /// Mark a scan as running and hand out its cancellation token
+    async fn begin_scan(&self) -> crate::error::Result<CancellationToken> {
+        let mut control = self.scan_control.write().await;
+        if control.status.state == ScanState::Running {
+            return Err(MusicStationError::Conflict(
+                "A scan is already in progress".to_string(),
+            ));
+        }
+        let cancel = CancellationToken::new();
+        control.cancel = Some(cancel.clone());
+        control.status = ScanStatus {
+            state: ScanState::Running,
+            started_at: Some(chrono::Utc::now().to_rfc3339()),
+            ..ScanStatus::default()
         };
+        Ok(cancel)
+    }
 
-        let ext = track
-            .path
-            .extension()
-            .and_then(|s| s.to_str())
-            .ok_or_else(|| anyhow::anyhow!("No file extension"))?;
+    /// Run a scan started by [`Self::begin_scan`] and record its outcome
+    async fn finish_scan(
+        &self,
+        cancel: &CancellationToken,
+        incremental: bool,
+    ) -> Result<RescanReport> {
+        let result = self.run_scan(cancel, incremental).await;
 
-        let handler = get_audio_file_handler(ext)
-            .ok_or_else(|| anyhow::anyhow!("Unsupported file format: {}", ext))?;
+        let mut control = self.scan_control.write().await;
+        control.cancel = None;
+        control.finished_once = true;
+        control.status.finished_at = Some(chrono::Utc::now().to_rfc3339());
+        match &result {
+            Ok(report) => {
+                control.status.state = ScanState::Completed;
+                control.status.track_count = Some(report.tracks);
+            }
+            Err(e) if e.is::<ScanCancelled>() => {
+                tracing::info!("Scan cancelled; keeping the previous track list");
+                control.status.state = ScanState::Cancelled;
+            }
+            Err(e) => {
+                control.status.state = ScanState::Failed;
+                control.status.error = Some(format!("{:#}", e));
+            }
+        }
+        drop(control);
+        self.scan_finished.notify_waiters();
 
-        let path_owned = track.path.clone();
-        tokio::task::spawn_blocking(move || handler.remove_cover_art(&path_owned)).await??;
+        result
+    }
 
-        // Update in-memory track
-        let mut updated_track = self
-            .parse_audio_file(&track.path)
-            .await
-            .context("Failed to re-parse file after cover removal")?;
+    /// Request cancellation of the running scan. Returns `false` if no scan is running.
+    pub async fn cancel_scan(&self) -> bool {
+        match &self.scan_control.read().await.cancel {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
 
-        // Preserve the has_lyrics flag
-        updated_track.has_lyrics = track.has_lyrics;
+    /// Status of the current or most recent scan
+    pub async fn scan_status(&self) -> ScanStatus {
+        self.scan_control.read().await.status.clone()
+    }
 
-        {
-            let mut tracks = self.tracks.write().await;
-            if let Some(pos) = tracks.iter().position(|t| t.id == id) {
-                tracks[pos] = updated_track;
+    /// Build the new track list and swap it in, reporting what changed. An
+    /// incremental scan reuses the tracks of files that didn't change. Any
+    /// error before the swap leaves the tracks and scan errors untouched.
+    async fn run_scan(
+        &self,
+        cancel: &CancellationToken,
+        incremental: bool,
+    ) -> Result<RescanReport> {
+        tracing::info!("Scanning library at: {}", self.library_path.display());
+
+        let reusable = if incremental {
+            self.reusable_tracks().await
+        } else {
+            HashMap::new()
+        };
+        let mut output = ScanOutput::default();
+        Box::pin(self.scan_directory(&self.library_path.clone(), cancel, &reusable, &mut output))
+            .await?;
+        let ScanOutput {
+            mut tracks,
+            errors,
+            stamps,
+            dirs,
+            parsed,
+            lyrics,
+        } = output;
+        let track_count = tracks.len();
+        let error_count = errors.len();
+
+        let mut library_tracks = self.tracks.write().await;
+        let previous: HashMap<&str, &Track> =
+            library_tracks.iter().map(|t| (t.id.as_str(), t)).collect();
+
+        for track in &mut tracks {
+            if let Some(old) = previous.get(track.id.as_str()) {
+                carry_over_library_state(track, old);
+            }
+        }
+
+        let current: HashSet<&str> = tracks.iter().map(|t| t.id.as_str()).collect();
+
+        let mut changes = Vec::new();
+        for track in &tracks {
+            match previous.get(track.id.as_str()) {
+                None => changes.push((ChangeType::Added, track.id.clone())),
+                Some(old) if *old != track => changes.push((ChangeType::Updated, track.id.clone())),
+                Some(_) => {}
             }
         }
+        for id in previous.keys().filter(|id| !current.contains(*id)) {
+            changes.push((ChangeType::Removed, id.to_string()));
+        }
+
+        let count = |kind| changes.iter().filter(|(change, _)| *change == kind).count();
+        let report = RescanReport {
+            added: count(ChangeType::Added),
+            updated: count(ChangeType::Updated),
+            removed: count(ChangeType::Removed),
+            parsed,
+            tracks: track_count,
+        };
+
+        // Replace tracks and scan errors together so readers never see a mix
+        *library_tracks = tracks;
+        *self.scan_errors.write().await = errors;
+        *self.file_stamps.write().await = stamps;
+        *self.known_dirs.write().await = dirs;
+        self.embedded_lyrics.write().await.extend(lyrics);
+        drop(library_tracks);
         self.invalidate_cache().await;
+        self.record_changes(changes).await;
 
-        tracing::info!("Removed cover art for track: {}", id);
+        tracing::info!(
+            "Scan complete. Found {} tracks ({} files parsed), skipped {} files",
+            track_count,
+            parsed,
+            error_count
+        );
+        Ok(report)
+    }
+
+    /// Whole-file tracks by path, with the stamp their file had when parsed.
+    /// CUE-sheet tracks aren't reusable: their file is parsed again and the
+    /// sheet re-applied.
+    async fn reusable_tracks(&self) -> HashMap<PathBuf, (FileStamp, Track)> {
+        let stamps = self.file_stamps.read().await;
+        self.tracks
+            .read()
+            .await
+            .iter()
+            .filter(|t| t.start_ms.is_none() && t.parse_error.is_none())
+            .filter_map(|t| Some((t.path.clone(), (*stamps.get(&t.path)?, t.clone()))))
+            .collect()
+    }
+
+    /// Recursively scan a directory for audio files
+    fn scan_directory<'a>(
+        &'a self,
+        dir: &'a Path,
+        cancel: &'a CancellationToken,
+        reusable: &'a HashMap<PathBuf, (FileStamp, Track)>,
+        output: &'a mut ScanOutput,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>> {
+        use futures::StreamExt;
+
+        Box::pin(async move {
+            let mut entries = tokio::fs::read_dir(dir)
+                .await
+                .context(format!("Failed to read directory: {}", dir.display()))?;
+            output.dirs.insert(dir.to_path_buf());
+            let mut cue_sheets = Vec::new();
+            let mut audio_files = Vec::new();
+
+            while let Some(entry) = entries.next_entry().await? {
+                if cancel.is_cancelled() {
+                    return Err(ScanCancelled.into());
+                }
+
+                let path = entry.path();
+
+                // Never descend into our own data directory, whatever the exclude list says
+                if path == self.data_dir() {
+                    tracing::debug!("Skipping data directory: {}", path.display());
+                    continue;
+                }
+
+                if self.config.is_excluded(&path) {
+                    tracing::debug!("Skipping excluded path: {}", path.display());
+                    continue;
+                }
+
+                // A broken symlink or unreadable entry is skipped, not fatal to the scan
+                let metadata = match tokio::fs::metadata(&path).await {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        tracing::warn!("Skipping {}: {}", path.display(), e);
+                        output.errors.push(ScanError {
+                            path: self.relative_path(&path),
+                            error: format!("Failed to read metadata: {}", e),
+                        });
+                        continue;
+                    }
+                };
+
+                if metadata.is_dir() {
+                    // Recursively scan subdirectories
+                    tracing::debug!("Scanning subdirectory: {}", path.display());
+                    self.scan_directory(&path, cancel, reusable, output).await?;
+                } else if metadata.is_file() {
+                    if path
+                        .extension()
+                        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+                    {
+                        // Applied once the whole directory has been parsed
+                        cue_sheets.push(path);
+                        continue;
+                    }
+
+                    // Audio files are parsed once the directory has been listed;
+                    // covers, logs and other files without a handler are skipped
+                    if path
+                        .extension()
+                        .and_then(|s| s.to_str())
+                        .is_some_and(|ext| self.scans_audio_extension(ext))
+                    {
+                        audio_files.push((path, metadata));
+                    }
+                }
+            }
+
+            let mut parsed_files = futures::stream::iter(audio_files)
+                .map(|(path, metadata)| async move {
+                    let stamp = FileStamp::of(&metadata);
+                    let previous = reusable
+                        .get(&path)
+                        .filter(|(previous_stamp, _)| *previous_stamp == stamp);
+                    let parsed = match previous {
+                        Some((_, track)) => Ok((
+                            Track {
+                                missing: false,
+                                ..track.clone()
+                            },
+                            None,
+                        )),
+                        None => self.parse_audio_file_within_timeout(&path).await,
+                    };
+                    (path, metadata, stamp, previous.is_none(), parsed)
+                })
+                .buffered(self.config.scan_concurrency.max(1));
+
+            while let Some((path, metadata, stamp, was_parsed, parsed)) = parsed_files.next().await
+            {
+                if cancel.is_cancelled() {
+                    return Err(ScanCancelled.into());
+                }
+                if was_parsed {
+                    output.parsed += 1;
+                }
+                output.stamps.insert(path.clone(), stamp);
+
+                match parsed {
+                    Ok((track, lyrics)) => {
+                        if let Some(lyrics) = lyrics {
+                            output.lyrics.insert(track.id.clone(), lyrics);
+                        }
+                        tracing::info!(
+                            "Found track: {} - {}",
+                            track
+                                .artist
+                                .as_deref()
+                                .unwrap_or(&self.config.unknown_artist_label),
+                            track.title.as_deref().unwrap_or("Unknown")
+                        );
+                        output.tracks.push(track);
+                    }
+                    Err(e) => {
+                        tracing::warn!("Failed to parse {}: {}", path.display(), e);
+                        if self.config.keep_unparseable_files
+                            && let Some(track) = self.unparsed_track(&path, &metadata, &e)
+                        {
+                            output.tracks.push(track);
+                        }
+                        output.errors.push(ScanError {
+                            path: self.relative_path(&path),
+                            error: e.to_string(),
+                        });
+                    }
+                }
+            }
+
+            for cue_path in cue_sheets {
+                if let Err(e) = self.apply_cue_sheet(&cue_path, &mut output.tracks).await {
+                    tracing::warn!("Failed to apply CUE sheet {}: {}", cue_path.display(), e);
+                    output.errors.push(ScanError {
+                        path: self.relative_path(&cue_path),
+                        error: format!("{:#}", e),
+                    });
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Replace the whole-file tracks referenced by a CUE sheet with one
+    /// virtual track per sheet entry
+    async fn apply_cue_sheet(&self, cue_path: &Path, tracks: &mut Vec<Track>) -> Result<()> {
+        let content = tokio::fs::read(cue_path)
+            .await
+            .context("Failed to read CUE sheet")?;
+        let sheet = cue::parse_cue(&String::from_utf8_lossy(&content))?;
+        let dir = cue_path.parent().unwrap_or(&self.library_path);
+
+        for cue_file in &sheet.files {
+            let referenced = dir.join(&cue_file.name);
+            // Sheets often still name the original rip (e.g. `.wav`) after transcoding
+            let Some(pos) = tracks
+                .iter()
+                .position(|t| t.path == referenced && t.start_ms.is_none())
+                .or_else(|| {
+                    tracks.iter().position(|t| {
+                        t.start_ms.is_none()
+                            && t.path.parent() == Some(dir)
+                            && t.path.file_stem() == referenced.file_stem()
+                    })
+                })
+            else {
+                anyhow::bail!("Referenced file not found: {}", cue_file.name);
+            };
+
+            let file_track = tracks.remove(pos);
+            let file_end_ms = file_track.duration_secs.map(|secs| secs * 1000);
+            tracing::debug!(
+                "Splitting {} into {} CUE tracks",
+                file_track.path.display(),
+                cue_file.tracks.len()
+            );
+
+            for (index, cue_track) in cue_file.tracks.iter().enumerate() {
+                let end_ms = cue_file.track_end_ms(index).or(file_end_ms);
+                let id_source = format!("{}#{}", file_track.relative_path, cue_track.number);
+
+                tracks.push(Track {
+                    id: self.compute_track_id(&id_source),
+                    title: cue_track
+                        .title
+                        .clone()
+                        .or_else(|| Some(format!("Track {:02}", cue_track.number))),
+                    artist: cue_track
+                        .performer
+                        .clone()
+                        .or_else(|| sheet.performer.clone())
+                        .or_else(|| file_track.artist.clone()),
+                    album: sheet.title.clone().or_else(|| file_track.album.clone()),
+                    album_artist: sheet
+                        .performer
+                        .clone()
+                        .or_else(|| file_track.album_artist.clone()),
+                    genre: sheet.genre.clone().or_else(|| file_track.genre.clone()),
+                    year: sheet.date.clone().or_else(|| file_track.year.clone()),
+                    track_number: Some(cue_track.number.to_string()),
+                    duration_secs: end_ms.map(|end| end.saturating_sub(cue_track.start_ms) / 1000),
+                    // The file's own track gain covers the whole image
+                    replaygain_track_gain: cue_track.replaygain_track_gain,
+                    replaygain_album_gain: sheet
+                        .replaygain_album_gain
+                        .or(file_track.replaygain_album_gain),
+                    start_ms: Some(cue_track.start_ms),
+                    end_ms,
+                    ..file_track.clone()
+                });
+            }
+        }
 
         Ok(())
     }
+
+    /// Duration of a file split by a CUE sheet, if every track on it has a known end
+    pub async fn cue_file_duration_ms(&self, path: &Path) -> Option<u64> {
+        let tracks = self.tracks.read().await;
+        tracks
+            .iter()
+            .filter(|t| t.path == path)
+            .map(|t| t.end_ms)
+            .collect::<Option<Vec<u64>>>()?
+            .into_iter()
+            .max()
+    }
+
+    /// Parse an audio file for a scan, along with the lyrics embedded in its
+    /// tags, giving up after the configured parse timeout
+    async fn parse_audio_file_within_timeout(
+        &self,
+        path: &Path,
+    ) -> Result<(Track, Option<EmbeddedLyrics>)> {
+        tokio::time::timeout(
+            self.config.parse_timeout,
+            self.parse_audio_file_with_lyrics(path, true),
+        )
+        .await
+        .unwrap_or_else(|_| {
+            Err(anyhow::anyhow!(
+                "Timed out after {}s",
+                self.config.parse_timeout.as_secs_f64()
+            ))
+        })
+    }
+
+    /// Parse the audio files under `path` (a directory or a single file) the
+    /// way a scan would, without changing the library's tracks. Lyrics flags
+    /// and play counts are not filled in.
+    pub async fn inspect(&self, path: &Path) -> crate::error::Result<InspectReport> {
+        let metadata = tokio::fs::metadata(path)
+            .await
+            .context(format!("Failed to read metadata: {}", path.display()))?;
+
+        let mut report = InspectReport::default();
+        if metadata.is_dir() {
+            // Never cancelled; the caller can drop the future instead
+            let cancel = CancellationToken::new();
+            let mut output = ScanOutput::default();
+            Box::pin(self.scan_directory(path, &cancel, &HashMap::new(), &mut output)).await?;
+            report.tracks = output.tracks;
+            report.errors = output.errors;
+        } else {
+            match self.parse_audio_file_within_timeout(path).await {
+                Ok((track, _)) => report.tracks.push(track),
+                Err(e) => report.errors.push(ScanError {
+                    path: self.relative_path(path),
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        report
+            .tracks
+            .sort_by(|a, b| (&a.relative_path, a.start_ms).cmp(&(&b.relative_path, b.start_ms)));
+        Ok(report)
+    }
+
+    /// Parse an audio file (FLAC or MP3) and extract metadata
+    async fn parse_audio_file(&self, path: &Path) -> Result<Track> {
+        Ok(self.parse_audio_file_with_lyrics(path, false).await?.0)
+    }
+
+    /// [`Self::parse_audio_file`], also reading the embedded lyrics while the
+    /// file's handler is at hand if `read_lyrics` is set
+    async fn parse_audio_file_with_lyrics(
+        &self,
+        path: &Path,
+        read_lyrics: bool,
+    ) -> Result<(Track, Option<EmbeddedLyrics>)> {
+        let metadata = tokio::fs::metadata(path).await?;
+        let file_size = metadata.len();
+
+        let decode_duration_fallback = self.config.decode_duration_fallback;
+        let (audio_metadata, has_cover, lyrics) = self
+            .run_handler(path, move |handler, path| {
+                let mut audio_metadata = handler
+                    .parse_metadata(path)
+                    .context("Failed to parse audio metadata")?;
+                if audio_metadata.duration_secs.is_none() && decode_duration_fallback {
+                    audio_metadata.duration_secs =
+                        handler.packet_duration_secs(path).unwrap_or_else(|e| {
+                            tracing::debug!("Packet duration fallback failed: {}", e);
+                            None
+                        });
+                }
+                let has_cover = handler.has_cover_art(path).unwrap_or(false);
+                let lyrics = if read_lyrics {
+                    handler.read_lyrics(path).unwrap_or_else(|e| {
+                        tracing::debug!("Failed to read lyrics of {}: {}", path.display(), e);
+                        None
+                    })
+                } else {
+                    None
+                };
+                Ok((audio_metadata, has_cover, lyrics))
+            })
+            .await?;
+
+        // Generate a unique ID from the relative path (relative to library directory)
+        // This ensures consistent IDs regardless of where the library is mounted
+        let relative_path = self.relative_path(path);
+        let id = self.compute_track_id(&relative_path);
+
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let replaygain_track_gain = audio_metadata.replay_gain("TRACK");
+        let replaygain_album_gain = audio_metadata.replay_gain("ALBUM");
+
+        let track = Track {
+            id,
+            path: path.to_path_buf(),
+            filename,
+            relative_path,
+            title: audio_metadata.title,
+            artist: audio_metadata.artist,
+            album: audio_metadata.album,
+            album_artist: audio_metadata.album_artist,
+            genre: audio_metadata.genre,
+            year: audio_metadata.year,
+            track_number: audio_metadata.track_number,
+            disc_number: audio_metadata.disc_number,
+            composer: audio_metadata.composer,
+            comment: audio_metadata.comment,
+            duration_secs: audio_metadata.duration_secs,
+            file_size,
+            has_cover,
+            has_lyrics: false, // Will be updated when lyrics database is queried
+            play_count: 0,     // Will be updated when stats database is queried
+            last_played: None,
+            missing: false,
+            compilation: audio_metadata.compilation,
+            lossless: audio_metadata.lossless,
+            sample_rate: audio_metadata.sample_rate,
+            channels: audio_metadata.channels,
+            bits_per_sample: audio_metadata.bits_per_sample,
+            bitrate_kbps: audio_metadata.bitrate_kbps,
+            replaygain_track_gain,
+            replaygain_album_gain,
+            custom_fields: audio_metadata.custom_fields,
+            start_ms: None,
+            end_ms: None,
+            parse_error: None,
+            date_added: file_date_added(&metadata),
+        };
+        Ok((track, lyrics))
+    }
+
+    /// Track for an audio file that exists but could not be parsed, carrying
+    /// only file information. `None` for files that aren't audio at all.
+    fn unparsed_track(
+        &self,
+        path: &Path,
+        metadata: &std::fs::Metadata,
+        error: &anyhow::Error,
+    ) -> Option<Track> {
+        let ext = path.extension().and_then(|s| s.to_str())?;
+        self.formats.handler(ext)?;
+
+        let relative_path = self.relative_path(path);
+        Some(Track {
+            id: self.compute_track_id(&relative_path),
+            path: path.to_path_buf(),
+            filename: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            relative_path,
+            title: None,
+            artist: None,
+            album: None,
+            album_artist: None,
+            genre: None,
+            year: None,
+            track_number: None,
+            disc_number: None,
+            composer: None,
+            comment: None,
+            duration_secs: None,
+            file_size: metadata.len(),
+            has_cover: false,
+            has_lyrics: false,
+            play_count: 0,
+            last_played: None,
+            missing: false,
+            compilation: false,
+            lossless: false,
+            sample_rate: None,
+            channels: None,
+            bits_per_sample: None,
+            bitrate_kbps: None,
+            replaygain_track_gain: None,
+            replaygain_album_gain: None,
+            custom_fields: HashMap::new(),
+            start_ms: None,
+            end_ms: None,
+            parse_error: Some(format!("{:#}", error)),
+            date_added: file_date_added(metadata),
+        })
+    }
+
+    /// Get all tracks in the library
+    pub async fn get_tracks(&self) -> Vec<Track> {
+        self.tracks.read().await.clone()
+    }
+
+    /// Get the track at `index` in library order, for walking the list without cloning it
+    pub async fn get_track_at(&self, index: usize) -> Option<Track> {
+        self.tracks.read().await.get(index).cloned()
+    }
+
+    /// Get a specific track by ID
+    pub async fn get_track(&self, id: &str) -> Option<Track> {
+        self.tracks
+            .read()
+            .await
+            .iter()
+            .find(|t| t.id == id)
+            .cloned()
+    }
+
+    /// Find the track stored in a file, given its path relative to the library
+    /// root. For a file split by a CUE sheet this is its first track.
+    pub async fn get_track_by_path(&self, relative: &Path) -> Option<Track> {
+        self.tracks
+            .read()
+            .await
+            .iter()
+            .filter(|t| Path::new(&t.relative_path) == relative)
+            .min_by_key(|t| t.start_ms.unwrap_or(0))
+            .cloned()
+    }
+
+    /// The part of an absolute path below the library root; `None` if it
+    /// isn't inside the library
+    pub async fn strip_library_root(&self, path: &Path) -> Option<PathBuf> {
+        match path.strip_prefix(&self.library_path) {
+            Ok(relative) => Some(relative.to_path_buf()),
+            // The root may be configured as a relative path or through a symlink
+            Err(_) => {
+                let root = tokio::fs::canonicalize(&self.library_path).await.ok()?;
+                let path = tokio::fs::canonicalize(path).await.ok()?;
+                Some(path.strip_prefix(root).ok()?.to_path_buf())
+            }
+        }
+    }
+
+    /// Get a track by ID after checking that its file still exists, updating
+    /// the `missing` flag of every track stored in that file
+    pub async fn verify_track(&self, id: &str) -> Option<Track> {
+        let track = self.get_track(id).await?;
+        let missing = !file_exists(&track.path).await;
+        if track.missing == missing {
+            return Some(track);
+        }
+        self.set_missing(HashMap::from([(track.path, missing)]))
+            .await;
+        self.get_track(id).await
+    }
+
+    /// Check every track's file and return the tracks whose file is gone
+    pub async fn find_missing_tracks(&self) -> Vec<Track> {
+        let paths: HashSet<PathBuf> = self
+            .tracks
+            .read()
+            .await
+            .iter()
+            .map(|t| t.path.clone())
+            .collect();
+
+        let mut status = HashMap::new();
+        for path in paths {
+            let missing = !file_exists(&path).await;
+            status.insert(path, missing);
+        }
+        self.set_missing(status).await;
+
+        self.tracks
+            .read()
+            .await
+            .iter()
+            .filter(|t| t.missing)
+            .cloned()
+            .collect()
+    }
+
+    /// Apply file existence results, recording an update for each flag that flips.
+    /// The write lock is only taken when some flag does flip.
+    async fn set_missing(&self, status: HashMap<PathBuf, bool>) {
+        let flips = |track: &Track| status.get(&track.path).is_some_and(|&m| m != track.missing);
+        if !self.tracks.read().await.iter().any(flips) {
+            return;
+        }
+
+        let mut tracks = self.tracks.write().await;
+        let mut changed = Vec::new();
+        for track in tracks.iter_mut() {
+            if let Some(&missing) = status.get(&track.path)
+                && track.missing != missing
+            {
+                if missing {
+                    tracing::warn!(
+                        "File for track {} is missing: {}",
+                        track.id,
+                        track.path.display()
+                    );
+                }
+                track.missing = missing;
+                changed.push((ChangeType::Updated, track.id.clone()));
+            }
+        }
+        drop(tracks);
+
+        if !changed.is_empty() {
+            self.invalidate_cache().await;
+            self.record_changes(changed).await;
+        }
+    }
+
+    /// Re-check `has_cover` from each file and set `has_lyrics` from
+    /// `tracks_with_lyrics`, without re-parsing metadata. Much cheaper than a
+    /// rescan when only cover art or lyrics changed outside the server.
+    pub async fn refresh_flags(
+        &self,
+        tracks_with_lyrics: &HashSet<String>,
+        concurrency: usize,
+    ) -> FlagRefreshReport {
+        use futures::StreamExt;
+
+        let paths: HashSet<PathBuf> = self
+            .tracks
+            .read()
+            .await
+            .iter()
+            .filter(|t| !t.missing)
+            .map(|t| t.path.clone())
+            .collect();
+        let files_checked = paths.len();
+
+        // Unreadable tags count as no cover, as during a scan
+        let covers: HashMap<PathBuf, bool> = futures::stream::iter(paths)
+            .map(|path| async move {
+                let has_cover = self
+                    .run_handler(&path, |handler, path| {
+                        Ok(handler.has_cover_art(path).unwrap_or(false))
+                    })
+                    .await
+                    .unwrap_or(false);
+                (path, has_cover)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut report = FlagRefreshReport {
+            files_checked,
+            ..Default::default()
+        };
+
+        let mut tracks = self.tracks.write().await;
+        let mut changed = Vec::new();
+        for track in tracks.iter_mut() {
+            let mut updated = false;
+            if let Some(&has_cover) = covers.get(&track.path)
+                && track.has_cover != has_cover
+            {
+                track.has_cover = has_cover;
+                report.cover_updated += 1;
+                updated = true;
+            }
+            let has_lyrics = tracks_with_lyrics.contains(&track.id);
+            if track.has_lyrics != has_lyrics {
+                track.has_lyrics = has_lyrics;
+                report.lyrics_updated += 1;
+                updated = true;
+            }
+            if updated {
+                changed.push((ChangeType::Updated, track.id.clone()));
+            }
+        }
+        drop(tracks);
+
+        if !changed.is_empty() {
+            self.invalidate_cache().await;
+            self.record_changes(changed).await;
+        }
+        report
+    }
+
+    /// Get the library path
+    pub fn library_path(&self) -> &Path {
+        &self.library_path
+    }
+
+    /// Directory holding the lyrics, playlist and stats databases
+    pub fn data_dir(&self) -> PathBuf {
+        self.library_path.join(DATA_DIR_NAME)
+    }
+
+    /// Whether a change at `path` can affect the track list: it is inside the
+    /// library but not its data directory or an excluded folder, and is an
+    /// audio file a scan would read, a CUE sheet, or a directory. A path that
+    /// is gone counts as a directory if the last scan walked it.
+    pub async fn affects_tracks(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.library_path) else {
+            return false;
+        };
+        if path.starts_with(self.data_dir())
+            || relative
+                .components()
+                .any(|c| self.config.is_excluded(Path::new(c.as_os_str())))
+        {
+            return false;
+        }
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("cue") => true,
+            Some(ext) if self.scans_audio_extension(ext) => true,
+            _ => match std::fs::symlink_metadata(path) {
+                Ok(metadata) => metadata.is_dir(),
+                Err(_) => self.known_dirs.read().await.contains(path),
+            },
+        }
+    }
+
+    /// Whether the library already has the current contents of the audio file
+    /// at `path`: its size and modification time match those of the last
+    /// scan or edit. The server's own tag, cover and lyrics writes leave their
+    /// files up to date.
+    pub async fn is_up_to_date(&self, path: &Path) -> bool {
+        let Ok(metadata) = tokio::fs::metadata(path).await else {
+            return false;
+        };
+        self.file_stamps.read().await.get(path) == Some(&FileStamp::of(&metadata))
+    }
+
+    /// Whether scans read files with extension `ext`: it is configured to be
+    /// scanned and a registered format handles it
+    fn scans_audio_extension(&self, ext: &str) -> bool {
+        self.config.scans_extension(ext) && self.formats.handler(ext).is_some()
+    }
+
+    /// Path of a file relative to the library root, as used for track IDs
+    fn relative_path(&self, path: &Path) -> String {
+        path.strip_prefix(&self.library_path)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Track ID for a file's relative path (or `path#N` for CUE tracks),
+    /// using the configured [`TrackIdHash`]
+    pub fn compute_track_id(&self, id_source: &str) -> String {
+        self.config.track_id_hash.hash(id_source)
+    }
+
+    /// Files skipped during the most recent scan, with the reason
+    pub async fn get_scan_errors(&self) -> Vec<ScanError> {
+        self.scan_errors.read().await.clone()
+    }
+
+    /// Update the has_lyrics flag for a track
+    pub async fn update_track_lyrics_status(&self, track_id: &str, has_lyrics: bool) {
+        let mut tracks = self.tracks.write().await;
+        let changed = match tracks.iter_mut().find(|t| t.id == track_id) {
+            Some(track) if track.has_lyrics != has_lyrics => {
+                track.has_lyrics = has_lyrics;
+                true
+            }
+            _ => false,
+        };
+        drop(tracks);
+        if changed {
+            self.invalidate_cache().await;
+            self.record_changes([(ChangeType::Updated, track_id.to_string())])
+                .await;
+        }
+    }
+
+    /// Replace tracks' `date_added` with the stored dates, keyed by track ID
+    pub async fn update_track_dates_added(&self, dates: &HashMap<String, String>) {
+        let mut tracks = self.tracks.write().await;
+        let mut changed = Vec::new();
+        for track in tracks.iter_mut() {
+            if let Some(date) = dates.get(&track.id)
+                && track.date_added != *date
+            {
+                track.date_added = date.clone();
+                changed.push((ChangeType::Updated, track.id.clone()));
+            }
+        }
+        drop(tracks);
+
+        if !changed.is_empty() {
+            self.invalidate_cache().await;
+            self.record_changes(changed).await;
+        }
+    }
+
+    /// Set every track's `has_lyrics`, `play_count` and `last_played` from
+    /// the databases' view in one pass; tracks missing from `stats` were never
+    /// played. Returns how many tracks changed.
+    pub async fn reload_db_flags(
+        &self,
+        tracks_with_lyrics: &HashSet<String>,
+        stats: &HashMap<String, TrackStats>,
+    ) -> usize {
+        self.update_tracks(|track| {
+            let has_lyrics = tracks_with_lyrics.contains(&track.id);
+            let lyrics_changed = track.has_lyrics != has_lyrics;
+            track.has_lyrics = has_lyrics;
+            Self::apply_track_stats(track, stats.get(&track.id)) || lyrics_changed
+        })
+        .await
+    }
+
+    /// Set every track's `play_count` and `last_played` from the stats
+    /// database; tracks missing from `stats` were never played. Returns how
+    /// many tracks changed.
+    pub async fn update_track_stats(&self, stats: &HashMap<String, TrackStats>) -> usize {
+        self.update_tracks(|track| Self::apply_track_stats(track, stats.get(&track.id)))
+            .await
+    }
+
+    /// Copy play statistics onto a track, returning whether it changed
+    fn apply_track_stats(track: &mut Track, stats: Option<&TrackStats>) -> bool {
+        let (play_count, last_played) =
+            stats.map_or((0, None), |s| (s.play_count, s.last_played.clone()));
+        let changed = track.play_count != play_count || track.last_played != last_played;
+        track.play_count = play_count;
+        track.last_played = last_played;
+        changed
+    }
+
+    /// Apply `update` to every track, recording those it reports as changed
+    async fn update_tracks(&self, mut update: impl FnMut(&mut Track) -> bool) -> usize {
+        let mut tracks = self.tracks.write().await;
+        let changed: Vec<(ChangeType, String)> = tracks
+            .iter_mut()
+            .filter_map(|track| update(track).then(|| (ChangeType::Updated, track.id.clone())))
+            .collect();
+        drop(tracks);
+
+        let count = changed.len();
+        if count > 0 {
+            self.invalidate_cache().await;
+            self.record_changes(changed).await;
+        }
+        count
+    }
+
+    /// Drop the cached album and artist groupings and rebuild them from the
+    /// current tracks
+    pub async fn reindex(&self) -> ReindexReport {
+        self.invalidate_cache().await;
+        let groupings = self.compute_groupings().await;
+        ReindexReport {
+            tracks: self.tracks.read().await.len(),
+            albums: groupings.albums.len(),
+            artists: groupings.artists.len(),
+            ..Default::default()
+        }
+    }
+
+    /// Most recently added tracks, newest first, optionally only those added
+    /// within the last `days` days
+    pub async fn recently_added(&self, limit: usize, days: Option<u64>) -> Vec<Track> {
+        let since = days.map(|days| chrono::Utc::now() - chrono::Duration::days(days as i64));
+        let mut tracks: Vec<(chrono::DateTime<chrono::Utc>, Track)> = self
+            .tracks
+            .read()
+            .await
+            .iter()
+            .filter_map(|track| {
+                let added = chrono::DateTime::parse_from_rfc3339(&track.date_added)
+                    .ok()?
+                    .to_utc();
+                since
+                    .is_none_or(|since| added >= since)
+                    .then(|| (added, track.clone()))
+            })
+            .collect();
+
+        tracks.sort_by(|(a_added, a), (b_added, b)| {
+            b_added
+                .cmp(a_added)
+                .then_with(|| a.relative_path.cmp(&b.relative_path))
+        });
+        tracks.into_iter().take(limit).map(|(_, t)| t).collect()
+    }
+
+    /// Distinct titles, artists and albums starting with `query` (ignoring
+    /// case and Unicode form), most common first with play count as the
+    /// tiebreaker
+    pub async fn suggest(&self, query: &str, limit: usize) -> Vec<Suggestion> {
+        let prefix = suggestion_key(query);
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+
+        // (kind, folded value) -> (first spelling seen, track count, total plays)
+        let mut matches: HashMap<(SuggestionKind, String), (String, usize, u64)> = HashMap::new();
+        for track in self.tracks.read().await.iter() {
+            let fields = [
+                (SuggestionKind::Title, &track.title),
+                (SuggestionKind::Artist, &track.artist),
+                (SuggestionKind::Album, &track.album),
+            ];
+            for (kind, value) in fields {
+                let Some(value) = value.as_deref() else {
+                    continue;
+                };
+                let key = suggestion_key(value);
+                if !key.starts_with(&prefix) {
+                    continue;
+                }
+                let entry = matches
+                    .entry((kind, key))
+                    .or_insert_with(|| (value.trim().to_string(), 0, 0));
+                entry.1 += 1;
+                entry.2 += track.play_count;
+            }
+        }
+
+        let mut ranked: Vec<_> = matches.into_iter().collect();
+        ranked.sort_by(|((a_kind, a_key), a), ((b_kind, b_key), b)| {
+            (b.1, b.2)
+                .cmp(&(a.1, a.2))
+                .then_with(|| a_key.cmp(b_key))
+                .then_with(|| a_kind.cmp(b_kind))
+        });
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|((kind, _), (value, _, _))| Suggestion { value, kind })
+            .collect()
+    }
+
+    /// Tracks whose title, artist or album (or only `field`) matches `query`,
+    /// ignoring case and Unicode form, best match first. Exact, prefix and
+    /// substring matches rank above misspellings; ties go to the more played
+    /// track. Tracks are scored on a blocking thread from a snapshot, so the
+    /// library isn't locked while they are.
+    pub async fn search(
+        &self,
+        query: &str,
+        field: Option<SuggestionKind>,
+        limit: usize,
+    ) -> Vec<Track> {
+        let query = suggestion_key(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let tracks = self.tracks.read().await.clone();
+        let search = tokio::task::spawn_blocking(move || {
+            Self::rank_search_matches(&tracks, &query, field, limit)
+        });
+        search.await.unwrap_or_else(|e| {
+            tracing::error!("Track search failed: {}", e);
+            Vec::new()
+        })
+    }
+
+    /// Score `tracks` against a normalized `query` for [`Self::search`]
+    fn rank_search_matches(
+        tracks: &[Track],
+        query: &str,
+        field: Option<SuggestionKind>,
+        limit: usize,
+    ) -> Vec<Track> {
+        let mut matches: Vec<(f32, Track)> = tracks
+            .iter()
+            .filter_map(|track| {
+                let fields = [
+                    (SuggestionKind::Title, &track.title),
+                    (SuggestionKind::Artist, &track.artist),
+                    (SuggestionKind::Album, &track.album),
+                ];
+                let score = fields
+                    .into_iter()
+                    .filter(|(kind, _)| field.is_none_or(|field| field == *kind))
+                    .filter_map(|(_, value)| value.as_deref())
+                    .map(|value| search_score(&suggestion_key(value), query))
+                    .fold(0.0, f32::max);
+                (score > 0.0).then(|| (score, track.clone()))
+            })
+            .collect();
+
+        matches.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .total_cmp(a_score)
+                .then_with(|| b.play_count.cmp(&a.play_count))
+                .then_with(|| a.relative_path.cmp(&b.relative_path))
+        });
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(_, track)| track)
+            .collect()
+    }
+
+    /// Update the play count for a track, leaving its last played time alone
+    #[deprecated(note = "use `record_track_play`, which also sets `last_played`")]
+    #[allow(dead_code)] // Kept for library users; the server records plays
+    pub async fn update_track_play_count(&self, track_id: &str, play_count: u64) {
+        self.update_tracks(|track| {
+            let changed = track.id == track_id && track.play_count != play_count;
+            if changed {
+                track.play_count = play_count;
+            }
+            changed
+        })
+        .await;
+    }
+
+    /// Record a play of a track: its new play count, last played now
+    pub async fn record_track_play(&self, track_id: &str, play_count: u64) {
+        let mut tracks = self.tracks.write().await;
+        let changed = match tracks.iter_mut().find(|t| t.id == track_id) {
+            Some(track) => {
+                track.play_count = play_count;
+                track.last_played = Some(chrono::Utc::now().to_rfc3339());
+                true
+            }
+            None => false,
+        };
+        drop(tracks);
+        if changed {
+            self.invalidate_cache().await;
+            self.record_changes([(ChangeType::Updated, track_id.to_string())])
+                .await;
+        }
+    }
+
+    /// Build the album list from tracks (uncached computation).
+    fn build_albums(tracks: &[Track], config: &LibraryConfig) -> Vec<Album> {
+        let mut albums_map: HashMap<String, Vec<Track>> = HashMap::new();
+
+        for track in tracks.iter() {
+            let album_name = config.album_name_for(track);
+            albums_map
+                .entry(album_name)
+                .or_default()
+                .push(track.clone());
+        }
+
+        let mut albums: Vec<Album> = albums_map
+            .into_iter()
+            .map(|(name, tracks)| {
+                let compilation = is_compilation(&tracks, config);
+                let artist = if compilation {
+                    Some(config.various_artists_label.clone())
+                } else {
+                    tracks.first().and_then(|t| t.artist.clone())
+                };
+                let total_duration_secs = tracks.iter().filter_map(|t| t.duration_secs).sum();
+                let track_count = tracks.len();
+                let (year, year_range) = album_years(&tracks);
+
+                Album {
+                    id: name_id(&name),
+                    name,
+                    artist,
+                    compilation,
+                    year,
+                    year_range,
+                    track_count,
+                    total_duration_secs,
+                    tracks,
+                }
+            })
+            .collect();
+
+        albums.sort_by(|a, b| a.name.cmp(&b.name));
+        albums
+    }
+
+    /// Build the artist list from albums (uncached computation).
+    fn build_artists(albums: &[Album], config: &LibraryConfig) -> Vec<Artist> {
+        // Group key -> albums with the artist name as written on each
+        let mut artists_map: HashMap<String, Vec<(String, Album)>> = HashMap::new();
+
+        for album in albums.iter() {
+            let artist_name = album
+                .artist
+                .clone()
+                .unwrap_or_else(|| config.unknown_artist_label.clone());
+            artists_map
+                .entry(config.artist_key(&artist_name))
+                .or_default()
+                .push((artist_name, album.clone()));
+        }
+
+        let mut artists: Vec<Artist> = artists_map
+            .into_values()
+            .map(|entries| {
+                let name = Self::preferred_artist_name(&entries);
+                let albums: Vec<Album> = entries.into_iter().map(|(_, album)| album).collect();
+                let album_count = albums.len();
+                let track_count = albums.iter().map(|a| a.track_count).sum();
+
+                Artist {
+                    id: name_id(&name),
+                    name,
+                    album_count,
+                    track_count,
+                    albums,
+                }
+            })
+            .collect();
+
+        artists.sort_by(|a, b| {
+            config
+                .strip_article(&a.name)
+                .cmp(config.strip_article(&b.name))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        artists
+    }
+
+    /// Display name for artists merged under one key: the spelling with the most
+    /// tracks, preferring the longer form (usually the one with the article) on ties
+    fn preferred_artist_name(entries: &[(String, Album)]) -> String {
+        let mut track_counts: HashMap<&str, usize> = HashMap::new();
+        for (name, album) in entries {
+            *track_counts.entry(name).or_default() += album.track_count;
+        }
+        track_counts
+            .into_iter()
+            .max_by(|(a, a_count), (b, b_count)| {
+                a_count
+                    .cmp(b_count)
+                    .then_with(|| a.len().cmp(&b.len()))
+                    .then_with(|| b.cmp(a))
+            })
+            .map(|(name, _)| name.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Albums and artists grouped from the current tracks, computed once
+    /// per track change and shared until the next one
+    async fn compute_groupings(&self) -> Arc<Groupings> {
+        if let Some(groupings) = self.groupings_cache.read().await.as_ref() {
+            return groupings.clone();
+        }
+
+        // Checked again under the write lock so concurrent callers build it once
+        let mut cache = self.groupings_cache.write().await;
+        if let Some(groupings) = cache.as_ref() {
+            return groupings.clone();
+        }
+        #[cfg(test)]
+        self.grouping_builds
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        let albums = Self::build_albums(&self.tracks.read().await, &self.config);
+        let artists = Self::build_artists(&albums, &self.config);
+        let groupings = Arc::new(Groupings { albums, artists });
+        *cache = Some(groupings.clone());
+        groupings
+    }
+
+    /// Get all albums in the library (cached).
+    pub async fn get_albums(&self) -> Vec<Album> {
+        self.compute_groupings().await.albums.clone()
+    }
+
+    /// Get all artists with their albums (cached).
+    pub async fn get_artists(&self) -> Vec<Artist> {
+        self.compute_groupings().await.artists.clone()
+    }
+
+    /// Jump-list over `get_artists`, in the same order (ignoring leading
+    /// articles when they are configured)
+    pub async fn get_artist_index(&self) -> Vec<NameIndexEntry> {
+        let artists = self.get_artists().await;
+        self.name_index(artists.iter().map(|a| self.config.strip_article(&a.name)))
+    }
+
+    /// Jump-list over `get_albums`, in the same order
+    pub async fn get_album_index(&self) -> Vec<NameIndexEntry> {
+        let albums = self.get_albums().await;
+        self.name_index(albums.iter().map(|a| a.name.as_str()))
+    }
+
+    /// Group consecutive names by bucket. Names are sorted by code point, so a
+    /// bucket can recur (e.g. "apple" after "Zed"); its count then covers every
+    /// occurrence and its offset points at the first.
+    fn name_index<'a>(&self, names: impl Iterator<Item = &'a str>) -> Vec<NameIndexEntry> {
+        let mut index: Vec<NameIndexEntry> = Vec::new();
+        for (offset, name) in names.enumerate() {
+            let key = self.config.index_key(name);
+            match index.iter_mut().find(|entry| entry.key == key) {
+                Some(entry) => entry.count += 1,
+                None => index.push(NameIndexEntry {
+                    key,
+                    count: 1,
+                    offset,
+                }),
+            }
+        }
+        index
+    }
+
+    /// Get a specific album by name (normalized comparison). Reads the cached
+    /// groupings when they are built, otherwise groups only the album's tracks.
+    pub async fn get_album(&self, album_name: &str) -> Option<Album> {
+        let wanted = self.config.normalize_name(album_name);
+        let matches = |name: &str| self.config.normalize_name(name) == wanted;
+        if let Some(groupings) = self.groupings_cache.read().await.as_ref() {
+            return groupings.albums.iter().find(|a| matches(&a.name)).cloned();
+        }
+
+        let tracks: Vec<Track> = self
+            .tracks
+            .read()
+            .await
+            .iter()
+            .filter(|t| matches(&self.config.album_name_for(t)))
+            .cloned()
+            .collect();
+        Self::build_albums(&tracks, &self.config)
+            .into_iter()
+            .find(|a| matches(&a.name))
+    }
+
+    /// Get a specific artist by name (normalized comparison). Reads the cached
+    /// groupings when they are built, otherwise groups only the albums the
+    /// artist could be credited with.
+    pub async fn get_artist(&self, artist_name: &str) -> Option<Artist> {
+        let wanted = self
+            .config
+            .normalize_name(self.config.strip_article(artist_name));
+        let matches =
+            |name: &str| self.config.normalize_name(self.config.strip_article(name)) == wanted;
+        if let Some(groupings) = self.groupings_cache.read().await.as_ref() {
+            return groupings.artists.iter().find(|a| matches(&a.name)).cloned();
+        }
+
+        // An album is credited to one of its track artists, or to the
+        // various-artists label, so only albums with such a track can match
+        let tracks = self.tracks.read().await;
+        let any_album = matches(&self.config.various_artists_label);
+        let album_names: HashSet<String> = tracks
+            .iter()
+            .filter(|t| {
+                any_album
+                    || matches(
+                        t.artist
+                            .as_deref()
+                            .unwrap_or(&self.config.unknown_artist_label),
+                    )
+            })
+            .map(|t| self.config.album_name_for(t))
+            .collect();
+        let tracks: Vec<Track> = tracks
+            .iter()
+            .filter(|t| album_names.contains(&self.config.album_name_for(t)))
+            .cloned()
+            .collect();
+
+        let albums = Self::build_albums(&tracks, &self.config);
+        Self::build_artists(&albums, &self.config)
+            .into_iter()
+            .find(|a| matches(&a.name))
+    }
+
+    /// Get summaries of an artist's albums, sorted by year (undated last) then name
+    pub async fn get_artist_albums(&self, artist_name: &str) -> Option<Vec<AlbumSummary>> {
+        let artist = self.get_artist(artist_name).await?;
+
+        let mut summaries = Vec::with_capacity(artist.albums.len());
+        for album in &artist.albums {
+            summaries.push(AlbumSummary {
+                id: album.id.clone(),
+                name: album.name.clone(),
+                artist: album.artist.clone(),
+                year: album.year.clone(),
+                track_count: album.track_count,
+                total_duration_secs: album.total_duration_secs,
+                has_cover: self.album_cover_track(album).await.is_some(),
+            });
+        }
+
+        summaries.sort_by(|a, b| {
+            (a.year.is_none(), &a.year, &a.name).cmp(&(b.year.is_none(), &b.year, &b.name))
+        });
+        Some(summaries)
+    }
+
+    /// Get a specific album by its name-derived ID
+    pub async fn get_album_by_id(&self, album_id: &str) -> Option<Album> {
+        self.get_albums()
+            .await
+            .into_iter()
+            .find(|a| a.id == album_id)
+    }
+
+    /// Get a specific artist by its name-derived ID
+    pub async fn get_artist_by_id(&self, artist_id: &str) -> Option<Artist> {
+        self.get_artists()
+            .await
+            .into_iter()
+            .find(|a| a.id == artist_id)
+    }
+
+    /// Find the track whose cover represents an album: the first track with
+    /// embedded art, otherwise the first track with a folder image next to it
+    pub async fn album_cover_track(&self, album: &Album) -> Option<Track> {
+        if let Some(cached) = self.album_cover_cache.read().await.get(&album.id) {
+            return cached
+                .as_ref()
+                .and_then(|id| album.tracks.iter().find(|t| &t.id == id).cloned());
+        }
+
+        let mut cover_track = album.tracks.iter().find(|t| t.has_cover).cloned();
+        if cover_track.is_none() && self.config.cover_fallback {
+            for track in &album.tracks {
+                if Self::folder_cover_path(&track.path).await.is_some() {
+                    cover_track = Some(track.clone());
+                    break;
+                }
+            }
+        }
+
+        self.album_cover_cache
+            .write()
+            .await
+            .insert(album.id.clone(), cover_track.as_ref().map(|t| t.id.clone()));
+        cover_track
+    }
+
+    /// Report which of `ids` have cover art without reading any image data.
+    ///
+    /// Track IDs reflect embedded art (what `/cover/:id` serves). Album IDs
+    /// also count folder images such as `folder.jpg`, resolved once and cached.
+    /// Unknown IDs map to `false`.
+    pub async fn check_covers(&self, ids: &[String]) -> HashMap<String, bool> {
+        let wanted: HashSet<&str> = ids.iter().map(String::as_str).collect();
+        let mut covers: HashMap<String, bool> = {
+            let tracks = self.tracks.read().await;
+            tracks
+                .iter()
+                .filter(|t| wanted.contains(t.id.as_str()))
+                .map(|t| (t.id.clone(), t.has_cover))
+                .collect()
+        };
+
+        if covers.len() < wanted.len() {
+            for album in self.get_albums().await {
+                if wanted.contains(album.id.as_str()) && !covers.contains_key(&album.id) {
+                    let has_cover = self.album_cover_track(&album).await.is_some();
+                    covers.insert(album.id, has_cover);
+                }
+            }
+        }
+
+        for id in wanted {
+            covers.entry(id.to_string()).or_insert(false);
+        }
+        covers
+    }
+
+    /// Get the representative cover art for an album with its stored MIME
+    /// type (from the extension for folder images)
+    pub async fn get_album_art(
+        &self,
+        album: &Album,
+    ) -> crate::error::Result<Option<(Vec<u8>, String)>> {
+        let Some(track) = self.album_cover_track(album).await else {
+            return Ok(None);
+        };
+
+        if track.has_cover
+            && let Some(cover) = self.get_cover_art(&track.path).await?
+        {
+            return Ok(Some(cover));
+        }
+
+        if !self.config.cover_fallback {
+            return Ok(None);
+        }
+        match Self::folder_cover_path(&track.path).await {
+            Some(cover_path) => Ok(Some((
+                tokio::fs::read(&cover_path).await.context(format!(
+                    "Failed to read folder cover: {}",
+                    cover_path.display()
+                ))?,
+                crate::audio::image_mime_from_name(&cover_path).to_string(),
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// Locate a folder image (e.g. `folder.jpg`) in the directory of a track
+    async fn folder_cover_path(track_path: &Path) -> Option<PathBuf> {
+        let dir = track_path.parent()?;
+        for name in FOLDER_COVER_NAMES {
+            let candidate = dir.join(name);
+            if tokio::fs::try_exists(&candidate).await.unwrap_or(false) {
+                return Some(candidate);
+            }
+        }
+        None
+    }
+
+    /// Get library statistics
+    pub async fn get_stats(&self) -> LibraryStats {
+        let groupings = self.compute_groupings().await;
+        let tracks = self.tracks.read().await;
+
+        let total_duration_secs = tracks.iter().filter_map(|t| t.duration_secs).sum();
+        let total_size_bytes = tracks.iter().map(|t| t.file_size).sum();
+        let total_plays = tracks.iter().map(|t| t.play_count).sum();
+        let (lossless, lossy): (Vec<&Track>, Vec<&Track>) = tracks.iter().partition(|t| t.lossless);
+
+        LibraryStats {
+            total_tracks: tracks.len(),
+            total_albums: groupings.albums.len(),
+            total_artists: groupings.artists.len(),
+            total_duration_secs,
+            total_size_bytes,
+            total_plays,
+            lossless_tracks: lossless.len(),
+            lossy_tracks: lossy.len(),
+            lossless_size_bytes: lossless.iter().map(|t| t.file_size).sum(),
+            lossy_size_bytes: lossy.iter().map(|t| t.file_size).sum(),
+        }
+    }
+
+    /// Update metadata for a track
+    pub async fn update_track_metadata(
+        &self,
+        id: &str,
+        update: TrackMetadataUpdate,
+    ) -> crate::error::Result<Track> {
+        // Find the track
+        let track = {
+            let tracks = self.tracks.read().await;
+            tracks
+                .iter()
+                .find(|t| t.id == id)
+                .cloned()
+                .ok_or_else(|| MusicStationError::NotFound(format!("Track {}", id)))?
+        };
+
+        if track.start_ms.is_some() {
+            return Err(MusicStationError::Unsupported(format!(
+                "Track {} comes from a CUE sheet; edit the .cue file instead",
+                id
+            )));
+        }
+
+        tracing::debug!(
+            "Updating metadata for track: {} ({})",
+            track.title.as_deref().unwrap_or("Unknown"),
+            track.path.display()
+        );
+
+        // Update the audio file metadata
+        self.write_audio_metadata(&track.path, &update)
+            .await
+            .context(format!(
+                "Failed to write metadata to file: {}",
+                track.path.display()
+            ))?;
+
+        let updated_track = self
+            .refresh_track(&track)
+            .await
+            .context("Failed to re-parse file after update")?;
+
+        tracing::info!(
+            "Updated metadata for track: {} ({})",
+            updated_track.title.as_deref().unwrap_or("Unknown"),
+            id
+        );
+
+        Ok(updated_track)
+    }
+
+    /// Re-parse `track`'s file after the server wrote to it and swap the result
+    /// into the track list, keeping the state that lives in the databases
+    async fn refresh_track(&self, track: &Track) -> Result<Track> {
+        let mut updated_track = self.parse_audio_file(&track.path).await?;
+        carry_over_library_state(&mut updated_track, track);
+        // So rescans and the watcher know the edited file is already applied
+        if let Ok(metadata) = tokio::fs::metadata(&track.path).await {
+            self.file_stamps
+                .write()
+                .await
+                .insert(track.path.clone(), FileStamp::of(&metadata));
+        }
+
+        {
+            let mut tracks = self.tracks.write().await;
+            if let Some(pos) = tracks.iter().position(|t| t.id == track.id) {
+                tracks[pos] = updated_track.clone();
+            }
+        }
+        self.invalidate_cache().await;
+        self.record_changes([(ChangeType::Updated, track.id.clone())])
+            .await;
+
+        Ok(updated_track)
+    }
+
+    /// Write metadata to an audio file (FLAC or MP3)
+    async fn write_audio_metadata(&self, path: &Path, update: &TrackMetadataUpdate) -> Result<()> {
+        tracing::debug!("Writing metadata to file: {}", path.display());
+
+        let update_owned = update.clone();
+        self.run_handler(path, move |handler, path| {
+            handler
+                .write_metadata(path, &update_owned)
+                .context(format!("Failed to write metadata to {}", path.display()))
+        })
+        .await
+    }
+
+    /// Get cover art from an audio file (FLAC or MP3)
+    pub async fn get_cover_art(
+        &self,
+        path: &Path,
+    ) -> crate::error::Result<Option<(Vec<u8>, String)>> {
+        Ok(self
+            .run_handler(path, |handler, path| handler.get_cover_art(path))
+            .await?)
+    }
+
+    /// Lyrics found in the tags of the files parsed by scans since the last
+    /// call, by track ID. Reused files aren't read again, so each file's
+    /// lyrics are handed out once per change.
+    pub async fn take_embedded_lyrics(&self) -> HashMap<String, EmbeddedLyrics> {
+        std::mem::take(&mut *self.embedded_lyrics.write().await)
+    }
+
+    /// Read every tag stored in an audio file, in file order where the format has one
+    pub async fn read_all_tags(&self, path: &Path) -> crate::error::Result<Vec<RawTag>> {
+        let tags = self
+            .run_handler(path, |handler, path| handler.read_all_tags(path))
+            .await?;
+        Ok(tags
+            .into_iter()
+            .map(|(key, value)| RawTag { key, value })
+            .collect())
+    }
+
+    /// Set cover art for an audio file (FLAC or MP3)
+    pub async fn set_cover_art(
+        &self,
+        id: &str,
+        image_data: Vec<u8>,
+        mime_type: &str,
+    ) -> crate::error::Result<()> {
+        // Find the track
+        let track = {
+            let tracks = self.tracks.read().await;
+            tracks
+                .iter()
+                .find(|t| t.id == id)
+                .cloned()
+                .ok_or_else(|| MusicStationError::NotFound(format!("Track {}", id)))?
+        };
+
+        if track.start_ms.is_some() {
+            return Err(MusicStationError::Unsupported(format!(
+                "Track {} shares its file with other CUE-sheet tracks",
+                id
+            )));
+        }
+
+        let mime_type_owned = mime_type.to_string();
+        self.run_handler(&track.path, move |handler, path| {
+            handler.set_cover_art(path, image_data, &mime_type_owned)
+        })
+        .await?;
+
+        self.refresh_track(&track)
+            .await
+            .context("Failed to re-parse file after cover update")?;
+
+        tracing::info!("Updated cover art for track: {}", id);
+
+        Ok(())
+    }
+
+    /// Downscale and re-encode a track's embedded cover as JPEG if it exceeds `max_dim`.
+    /// Returns `None` when the track has no embedded cover.
+    pub async fn optimize_cover(
+        &self,
+        id: &str,
+        max_dim: u32,
+        quality: u8,
+        dry_run: bool,
+    ) -> crate::error::Result<Option<CoverOptimization>> {
+        let track = self
+            .get_track(id)
+            .await
+            .ok_or_else(|| MusicStationError::NotFound(format!("Track {}", id)))?;
+
+        if track.start_ms.is_some() {
+            return Err(MusicStationError::Unsupported(format!(
+                "Track {} shares its file with other CUE-sheet tracks",
+                id
+            )));
+        }
+
+        let Some((image_data, _)) = self.get_cover_art(&track.path).await? else {
+            return Ok(None);
+        };
+        let original_size = image_data.len();
+
+        let shrunk = tokio::task::spawn_blocking(move || {
+            crate::cover::shrink_cover(&image_data, max_dim, quality)
+        })
+        .await
+        .context("Cover optimization task failed")??;
+
+        let Some(new_data) = shrunk else {
+            tracing::debug!("Cover for track {} already within {}px", id, max_dim);
+            return Ok(Some(CoverOptimization {
+                track_id: id.to_string(),
+                original_size,
+                new_size: original_size,
+                optimized: false,
+            }));
+        };
+        let new_size = new_data.len();
+
+        if !dry_run {
+            self.set_cover_art(id, new_data, "image/jpeg").await?;
+            tracing::info!(
+                "Optimized cover for track {}: {} -> {} bytes",
+                id,
+                original_size,
+                new_size
+            );
+        }
+
+        Ok(Some(CoverOptimization {
+            track_id: id.to_string(),
+            original_size,
+            new_size,
+            optimized: true,
+        }))
+    }
+
+    /// Optimize the embedded cover of every track that has one, except CUE-sheet
+    /// tracks, which share their file. Tracks that fail are logged and left out
+    /// of the report.
+    pub async fn optimize_covers(
+        &self,
+        max_dim: u32,
+        quality: u8,
+        dry_run: bool,
+    ) -> CoverOptimizationReport {
+        let ids: Vec<String> = self
+            .tracks
+            .read()
+            .await
+            .iter()
+            .filter(|t| t.has_cover && t.start_ms.is_none())
+            .map(|t| t.id.clone())
+            .collect();
+
+        let mut results = Vec::new();
+        for id in ids {
+            match self.optimize_cover(&id, max_dim, quality, dry_run).await {
+                Ok(Some(result)) => results.push(result),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to optimize cover for track {}: {}", id, e),
+            }
+        }
+
+        CoverOptimizationReport {
+            dry_run,
+            total_original_size: results.iter().map(|r| r.original_size).sum(),
+            total_new_size: results.iter().map(|r| r.new_size).sum(),
+            tracks: results,
+        }
+    }
+
+    /// Write lyrics into a track's file (FLAC, MP3 and M4A) and refresh the track
+    pub async fn embed_lyrics(&self, id: &str, lyrics: String) -> crate::error::Result<()> {
+        let track = self
+            .get_track(id)
+            .await
+            .ok_or_else(|| MusicStationError::NotFound(format!("Track {}", id)))?;
+
+        if track.start_ms.is_some() {
+            return Err(MusicStationError::Unsupported(format!(
+                "Track {} shares its file with other CUE-sheet tracks",
+                id
+            )));
+        }
+
+        self.run_handler(&track.path, move |handler, path| {
+            handler.write_lyrics(path, &lyrics)
+        })
+        .await?;
+
+        self.refresh_track(&track)
+            .await
+            .context("Failed to re-parse file after embedding lyrics")?;
+
+        tracing::info!("Embedded lyrics in track: {}", id);
+
+        Ok(())
+    }
+
+    /// Embed `(track ID, lyrics)` pairs into their files, writing up to
+    /// `concurrency` files at once. Tracks whose format can't hold lyrics, or
+    /// whose extension isn't in `formats` (when given), are skipped.
+    pub async fn embed_lyrics_bulk(
+        &self,
+        lyrics: Vec<(String, String)>,
+        formats: Option<&[String]>,
+        dry_run: bool,
+        concurrency: usize,
+    ) -> LyricsEmbedReport {
+        use futures::StreamExt;
+
+        let mut tracks: Vec<LyricsEmbedResult> = futures::stream::iter(lyrics)
+            .map(|(id, content)| async move {
+                let track = self.get_track(&id).await;
+                let relative_path = track
+                    .as_ref()
+                    .map(|t| t.relative_path.clone())
+                    .unwrap_or_default();
+                let (status, reason) = match self
+                    .embed_lyrics_checked(&id, track, content, formats, dry_run)
+                    .await
+                {
+                    Ok(Ok(())) if dry_run => (LyricsEmbedStatus::WouldEmbed, None),
+                    Ok(Ok(())) => (LyricsEmbedStatus::Embedded, None),
+                    Ok(Err(reason)) => (LyricsEmbedStatus::Skipped, Some(reason)),
+                    Err(e) => {
+                        tracing::warn!("Failed to embed lyrics for track {}: {:#}", id, e);
+                        (LyricsEmbedStatus::Failed, Some(format!("{:#}", e)))
+                    }
+                };
+                LyricsEmbedResult {
+                    track_id: id,
+                    relative_path,
+                    status,
+                    reason,
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+        tracks.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+
+        let count = |status| tracks.iter().filter(|t| t.status == status).count();
+        LyricsEmbedReport {
+            dry_run,
+            embedded: count(LyricsEmbedStatus::Embedded) + count(LyricsEmbedStatus::WouldEmbed),
+            skipped: count(LyricsEmbedStatus::Skipped),
+            failed: count(LyricsEmbedStatus::Failed),
+            tracks,
+        }
+    }
+
+    /// Embed one track's lyrics unless it has to be skipped, in which case the
+    /// inner `Err` holds the reason
+    async fn embed_lyrics_checked(
+        &self,
+        id: &str,
+        track: Option<Track>,
+        lyrics: String,
+        formats: Option<&[String]>,
+        dry_run: bool,
+    ) -> Result<std::result::Result<(), String>> {
+        let Some(track) = track else {
+            return Ok(Err("track is not in the library".to_string()));
+        };
+        let ext = track
+            .path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if formats.is_some_and(|formats| !formats.iter().any(|f| f.eq_ignore_ascii_case(&ext))) {
+            return Ok(Err(format!("format {} not selected", ext)));
+        }
+        if !self
+            .formats
+            .handler(&ext)
+            .is_some_and(|h| h.supports_embedded_lyrics())
+        {
+            return Ok(Err(format!(
+                "embedding lyrics in {} files is not supported",
+                ext
+            )));
+        }
+        if track.start_ms.is_some() {
+            return Ok(Err("CUE-sheet track shares its file".to_string()));
+        }
+        if track.missing {
+            return Ok(Err("file is missing".to_string()));
+        }
+
+        if !dry_run {
+            self.embed_lyrics(id, lyrics).await?;
+        }
+        Ok(Ok(()))
+    }
+
+    /// Remove cover art from an audio file (FLAC or MP3)
+    pub async fn remove_cover_art(&self, id: &str) -> crate::error::Result<()> {
+        // Find the track
+        let track = {
+            let tracks = self.tracks.read().await;
+            tracks
+                .iter()
+                .find(|t| t.id == id)
+                .cloned()
+                .ok_or_else(|| MusicStationError::NotFound(format!("Track {}", id)))?
+        };
+
+        if track.start_ms.is_some() {
+            return Err(MusicStationError::Unsupported(format!(
+                "Track {} shares its file with other CUE-sheet tracks",
+                id
+            )));
+        }
+
+        self.run_handler(&track.path, |handler, path| handler.remove_cover_art(path))
+            .await?;
+
+        self.refresh_track(&track)
+            .await
+            .context("Failed to re-parse file after cover removal")?;
+
+        tracing::info!("Removed cover art for track: {}", id);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::tests::write_silent_mp3;
+
+    fn track(id: &str, album: &str, artist: &str) -> Track {
+        Track {
+            id: id.to_string(),
+            path: PathBuf::from(format!("/music/{}.flac", id)),
+            filename: format!("{}.flac", id),
+            relative_path: format!("{}.flac", id),
+            title: Some(id.to_string()),
+            artist: Some(artist.to_string()),
+            album: Some(album.to_string()),
+            album_artist: None,
+            genre: None,
+            year: None,
+            track_number: None,
+            disc_number: None,
+            composer: None,
+            comment: None,
+            duration_secs: Some(60),
+            file_size: 0,
+            has_cover: false,
+            has_lyrics: false,
+            play_count: 0,
+            last_played: None,
+            missing: false,
+            compilation: false,
+            lossless: false,
+            sample_rate: None,
+            channels: None,
+            bits_per_sample: None,
+            bitrate_kbps: None,
+            replaygain_track_gain: None,
+            replaygain_album_gain: None,
+            custom_fields: HashMap::new(),
+            start_ms: None,
+            end_ms: None,
+            parse_error: None,
+            date_added: String::new(),
+        }
+    }
+
+    async fn library_with(config: LibraryConfig, tracks: Vec<Track>) -> MusicLibrary {
+        let library = MusicLibrary::with_config(PathBuf::from("/music"), config);
+        *library.tracks.write().await = tracks;
+        library
+    }
+
+    #[tokio::test]
+    async fn test_get_album_matches_nfd_against_nfc() {
+        // "Café" stored decomposed (e + U+0301), queried precomposed
+        let library = library_with(
+            LibraryConfig::default(),
+            vec![track("1", "Cafe\u{0301}", "Artist")],
+        )
+        .await;
+
+        let album = library.get_album("Caf\u{00e9}").await;
+        assert!(album.is_some());
+        assert_eq!(album.unwrap().track_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_artist_ignores_trailing_whitespace() {
+        let library = library_with(
+            LibraryConfig::default(),
+            vec![track("1", "Abbey Road", "The Beatles ")],
+        )
+        .await;
+
+        assert!(library.get_artist("The Beatles").await.is_some());
+        assert!(library.get_album("Abbey Road ").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_case_insensitive_lookup_is_opt_in() {
+        let tracks = vec![track("1", "Abbey Road", "The Beatles")];
+
+        let library = library_with(LibraryConfig::default(), tracks.clone()).await;
+        assert!(library.get_album("abbey road").await.is_none());
+
+        let config = LibraryConfig {
+            case_insensitive_lookup: true,
+            ..LibraryConfig::default()
+        };
+        let library = library_with(config, tracks).await;
+        assert!(library.get_album("abbey road").await.is_some());
+        assert!(library.get_artist("THE BEATLES").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_suggest() {
+        let played = |id: &str, album: &str, artist: &str, play_count| Track {
+            play_count,
+            ..track(id, album, artist)
+        };
+        let library = library_with(
+            LibraryConfig::default(),
+            vec![
+                played("Abbey Road Medley", "Abbey Road", "The Beatles", 0),
+                played("Something", "Abbey Road", "The Beatles", 0),
+                played("Dancing Queen", "Arrival", "ABBA", 3),
+                played("晴天", "叶惠美", "周杰伦", 0),
+                played("七里香", "七里香", "周杰伦", 0),
+            ],
+        )
+        .await;
+        let suggestion = |value: &str, kind| Suggestion {
+            value: value.to_string(),
+            kind,
+        };
+
+        assert_eq!(
+            library.suggest("abb", 10).await,
+            [
+                suggestion("Abbey Road", SuggestionKind::Album),
+                suggestion("ABBA", SuggestionKind::Artist),
+                suggestion("Abbey Road Medley", SuggestionKind::Title),
+            ]
+        );
+        // Full-width input folds to ASCII
+        assert_eq!(library.suggest("ＡＢＢＡ", 10).await.len(), 1);
+        assert_eq!(
+            library.suggest("周", 10).await,
+            [suggestion("周杰伦", SuggestionKind::Artist)]
+        );
+        assert_eq!(
+            library.suggest("七里", 10).await,
+            [
+                suggestion("七里香", SuggestionKind::Title),
+                suggestion("七里香", SuggestionKind::Album),
+            ]
+        );
+        assert_eq!(library.suggest("abb", 1).await.len(), 1);
+        assert!(library.suggest("  ", 10).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_artist_index() {
+        let tracks: Vec<Track> = [
+            "2Pac",
+            "ABBA",
+            "Adele",
+            "The Beatles",
+            "Björk",
+            "周杰伦",
+            "陈奕迅",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, artist)| track(&i.to_string(), artist, artist))
+        .collect();
+        let entry = |key: &str, count, offset| NameIndexEntry {
+            key: key.to_string(),
+            count,
+            offset,
+        };
+
+        let config = LibraryConfig {
+            leading_articles: default_leading_articles(),
+            ..LibraryConfig::default()
+        };
+        let library = library_with(config.clone(), tracks.clone()).await;
+        // Chinese names sort last but share the `#` bucket with "2Pac"
+        assert_eq!(
+            library.get_artist_index().await,
+            [entry("#", 3, 0), entry("A", 2, 1), entry("B", 2, 3)]
+        );
+
+        let config = LibraryConfig {
+            pinyin_index: true,
+            ..config
+        };
+        let library = library_with(config, tracks).await;
+        let index = library.get_artist_index().await;
+        assert_eq!(
+            index,
+            [
+                entry("#", 1, 0),
+                entry("A", 2, 1),
+                entry("B", 2, 3),
+                entry("Z", 1, 5),
+                entry("C", 1, 6),
+            ]
+        );
+        // Offsets line up with the artist list
+        let artists = library.get_artists().await;
+        assert_eq!(artists[index[3].offset].name, "周杰伦");
+    }
+
+    #[test]
+    fn test_pinyin_initial() {
+        assert_eq!(pinyin_initial('阿'), Some('A'));
+        assert_eq!(pinyin_initial('周'), Some('Z'));
+        assert_eq!(pinyin_initial('王'), Some('W'));
+        assert_eq!(pinyin_initial('A'), None);
+        // Traditional-only characters are not in GB2312
+        assert_eq!(pinyin_initial('陳'), None);
+    }
+
+    #[tokio::test]
+    async fn test_get_artist_by_id_with_slash_in_name() {
+        let library = library_with(
+            LibraryConfig::default(),
+            vec![track("1", "Back in Black", "AC/DC")],
+        )
+        .await;
+
+        let artist = library.get_artist_by_id(&name_id("AC/DC")).await.unwrap();
+        assert_eq!(artist.name, "AC/DC");
+        assert_eq!(artist.id, name_id("AC/DC"));
+        assert!(
+            library
+                .get_album_by_id(&name_id("Back in Black"))
+                .await
+                .is_some()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leading_articles_merge_and_sort_artists() {
+        let tracks = vec![
+            track("1", "Abbey Road", "The Beatles"),
+            track("2", "Abbey Road", "The Beatles"),
+            track("3", "Help!", "Beatles"),
+            track("4", "Rumours", "Fleetwood Mac"),
+            track("5", "Ágætis byrjun", "Sigur Rós"),
+            track("6", "Hounds of Love", "Kate Bush"),
+            track("7", "The The", "The"),
+        ];
+
+        // Off by default: article variants stay separate and sort as written
+        let library = library_with(LibraryConfig::default(), tracks.clone()).await;
+        let names: Vec<String> = library
+            .get_artists()
+            .await
+            .into_iter()
+            .map(|a| a.name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Beatles",
+                "Fleetwood Mac",
+                "Kate Bush",
+                "Sigur Rós",
+                "The",
+                "The Beatles"
+            ]
+        );
+
+        let config = LibraryConfig {
+            leading_articles: default_leading_articles(),
+            ..LibraryConfig::default()
+        };
+        let library = library_with(config, tracks).await;
+        let artists = library.get_artists().await;
+        let names: Vec<&str> = artists.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "The Beatles",
+                "Fleetwood Mac",
+                "Kate Bush",
+                "Sigur Rós",
+                "The"
+            ]
+        );
+        assert_eq!(artists[0].album_count, 2);
+        assert_eq!(artists[0].track_count, 3);
+
+        let beatles = library.get_artist("Beatles").await.unwrap();
+        assert_eq!(beatles.name, "The Beatles");
+        assert_eq!(
+            library.get_artist("The Beatles").await.unwrap().id,
+            beatles.id
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search() {
+        let played = |id: &str, album: &str, artist: &str, play_count| Track {
+            play_count,
+            ..track(id, album, artist)
+        };
+        let library = library_with(
+            LibraryConfig::default(),
+            vec![
+                played("Yesterday", "Help!", "The Beatles", 0),
+                played("Let It Be", "Let It Be", "The Beatles", 0),
+                played("Help!", "Help!", "The Beatles", 0),
+                played("Helpless", "After the Gold Rush", "Neil Young", 2),
+                played("Dancing Queen", "Arrival", "ABBA", 0),
+                played("晴天", "叶惠美", "周杰伦", 0),
+            ],
+        )
+        .await;
+        let ids =
+            |tracks: Vec<Track>| -> Vec<String> { tracks.into_iter().map(|t| t.id).collect() };
+
+        // Prefix matches in any field tie and go by plays, then path
+        assert_eq!(
+            ids(library.search("help", None, 10).await),
+            ["Helpless", "Help!", "Yesterday"]
+        );
+        // Exact matches rank above prefixes
+        assert_eq!(
+            ids(library.search("help!", None, 10).await),
+            ["Help!", "Yesterday"]
+        );
+        assert_eq!(
+            ids(library.search("  QUEEN ", None, 10).await),
+            ["Dancing Queen"]
+        );
+        assert_eq!(ids(library.search("天", None, 10).await), ["晴天"]);
+
+        // Misspelled words still match
+        assert_eq!(
+            ids(library.search("beatels", None, 10).await),
+            ["Help!", "Let It Be", "Yesterday"]
+        );
+        assert!(search_score("the beatles", "beatels") < search_score("the beatles", "beat"));
+
+        // Restricted to one field
+        assert_eq!(
+            ids(library
+                .search("help", Some(SuggestionKind::Title), 10)
+                .await),
+            ["Helpless", "Help!"]
+        );
+        assert!(
+            library
+                .search("beatles", Some(SuggestionKind::Album), 10)
+                .await
+                .is_empty()
+        );
+
+        assert_eq!(library.search("help", None, 1).await.len(), 1);
+        assert!(library.search(" ", None, 10).await.is_empty());
+        assert!(library.search("zzzz", None, 10).await.is_empty());
+    }
+
+    #[test]
+    fn test_edit_similarity() {
+        assert_eq!(edit_similarity("abc", "abc"), 1.0);
+        assert_eq!(edit_similarity("kitten", "sitting"), 1.0 - 3.0 / 7.0);
+        assert_eq!(edit_similarity("", ""), 0.0);
+        assert_eq!(edit_similarity("晴天", "晴"), 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_get_artist_and_album_match_full_grouping() {
+        let with_album_artist = |id: &str, album: &str, artist: &str, album_artist: &str| Track {
+            album_artist: Some(album_artist.to_string()),
+            ..track(id, album, artist)
+        };
+        let untagged = Track {
+            artist: None,
+            album: None,
+            ..track("u1", "", "")
+        };
+        let tracks = vec![
+            track("1", "Abbey Road", "The Beatles"),
+            track("2", "Abbey Road", "The Beatles"),
+            track("3", "Help!", "beatles"),
+            track("4", "Rumours", "Fleetwood Mac"),
+            track("5", "Mixtape", "Kate Bush"),
+            track("6", "Mixtape", "Fleetwood Mac"),
+            with_album_artist("7", "Hits", "Kate Bush", "Various Artists"),
+            track("8", "rumours", "Fleetwood Mac"),
+            untagged,
+        ];
+        let names = [
+            "The Beatles",
+            "beatles",
+            "Fleetwood Mac",
+            "Kate Bush",
+            "Various Artists",
+            "Unknown Artist",
+            "Nobody",
+        ];
+        let albums = [
+            "Abbey Road",
+            "RUMOURS",
+            "Mixtape",
+            "Unknown Album",
+            "Nothing",
+        ];
+        // Albums and artists aren't `PartialEq`; compare them as served
+        fn json(value: impl Serialize) -> serde_json::Value {
+            serde_json::to_value(value).unwrap()
+        }
+
+        for config in [
+            LibraryConfig::default(),
+            LibraryConfig {
+                leading_articles: default_leading_articles(),
+                case_insensitive_lookup: true,
+                ..LibraryConfig::default()
+            },
+        ] {
+            let library = library_with(config.clone(), tracks.clone()).await;
+            let mut targeted = Vec::new();
+            for name in names {
+                targeted.push(library.get_artist(name).await.map(json));
+            }
+            for name in albums {
+                targeted.push(library.get_album(name).await.map(json));
+            }
+            assert_eq!(
+                library
+                    .grouping_builds
+                    .load(std::sync::atomic::Ordering::Relaxed),
+                0
+            );
+
+            // Same answers as a lookup over the full grouping
+            let artists = library.get_artists().await;
+            let all_albums = library.get_albums().await;
+            let mut expected = Vec::new();
+            for name in names {
+                let wanted = config.normalize_name(config.strip_article(name));
+                expected.push(
+                    artists
+                        .iter()
+                        .find(|a| config.normalize_name(config.strip_article(&a.name)) == wanted)
+                        .map(json),
+                );
+            }
+            for name in albums {
+                let wanted = config.normalize_name(name);
+                expected.push(
+                    all_albums
+                        .iter()
+                        .find(|a| config.normalize_name(&a.name) == wanted)
+                        .map(json),
+                );
+            }
+            assert_eq!(targeted, expected);
+            assert!(targeted.iter().filter(|found| found.is_some()).count() >= 8);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stats_group_tracks_once() {
+        let library = library_with(
+            LibraryConfig::default(),
+            vec![
+                track("1", "Abbey Road", "The Beatles"),
+                track("2", "Let It Be", "The Beatles"),
+            ],
+        )
+        .await;
+        let builds = || {
+            library
+                .grouping_builds
+                .load(std::sync::atomic::Ordering::Relaxed)
+        };
+
+        let stats = library.get_stats().await;
+        assert_eq!((stats.total_albums, stats.total_artists), (2, 1));
+        assert_eq!(builds(), 1);
+        library.get_artists().await;
+        library.get_albums().await;
+        assert_eq!(builds(), 1);
+
+        library.record_track_play("1", 5).await;
+        library.get_stats().await;
+        assert_eq!(builds(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stats_split_lossless_and_lossy() {
+        let sized = |id: &str, lossless: bool, file_size: u64| Track {
+            lossless,
+            file_size,
+            ..track(id, "Album", "Artist")
+        };
+        let library = library_with(
+            LibraryConfig::default(),
+            vec![
+                sized("1", true, 30_000_000),
+                sized("2", true, 25_000_000),
+                sized("3", false, 8_000_000),
+            ],
+        )
+        .await;
+
+        let stats = library.get_stats().await;
+        assert_eq!(stats.total_tracks, 3);
+        assert_eq!(stats.total_size_bytes, 63_000_000);
+        assert_eq!((stats.lossless_tracks, stats.lossy_tracks), (2, 1));
+        assert_eq!(stats.lossless_size_bytes, 55_000_000);
+        assert_eq!(stats.lossy_size_bytes, 8_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_get_artist_albums_sorted_by_year() {
+        let with_year = |id: &str, album: &str, year: Option<&str>| Track {
+            year: year.map(String::from),
+            ..track(id, album, "Artist")
+        };
+        let library = library_with(
+            LibraryConfig::default(),
+            vec![
+                with_year("1", "Later", Some("1999-03-01")),
+                with_year("2", "Later", Some("1999")),
+                with_year("3", "Later", Some("2004")),
+                with_year("4", "Debut", Some("1995")),
+                with_year("5", "Demos", None),
+                with_year("6", "B-Sides", Some("1995")),
+                track("7", "Elsewhere", "Other"),
+            ],
+        )
+        .await;
+
+        let albums = library.get_artist_albums("Artist").await.unwrap();
+        let listed: Vec<(&str, Option<&str>)> = albums
+            .iter()
+            .map(|a| (a.name.as_str(), a.year.as_deref()))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("B-Sides", Some("1995")),
+                ("Debut", Some("1995")),
+                ("Later", Some("1999")),
+                ("Demos", None),
+            ]
+        );
+        assert_eq!(albums[2].track_count, 3);
+        assert!(!albums[2].has_cover);
+
+        let later = library.get_album("Later").await.unwrap();
+        assert_eq!(later.year.as_deref(), Some("1999"));
+        assert_eq!(
+            later.year_range,
+            Some(("1999".to_string(), "2004".to_string()))
+        );
+        let debut = library.get_album("Debut").await.unwrap();
+        assert_eq!(
+            (debut.year.as_deref(), debut.year_range),
+            (Some("1995"), None)
+        );
+        assert!(library.get_artist_albums("Nobody").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_compilation_albums() {
+        let with_album_artist = |id: &str, album: &str, artist: &str, album_artist: &str| Track {
+            album_artist: Some(album_artist.to_string()),
+            ..track(id, album, artist)
+        };
+        let tagged = Track {
+            compilation: true,
+            ..track("t1", "Tagged", "Solo")
+        };
+        let library = library_with(
+            LibraryConfig::default(),
+            vec![
+                track("m1", "Mixtape", "Artist A"),
+                track("m2", "Mixtape", "Artist B"),
+                with_album_artist("v1", "Hits", "Artist A", "various artists"),
+                with_album_artist("f1", "Featuring", "Band", "Band"),
+                with_album_artist("f2", "Featuring", "Band feat. Guest", "Band"),
+                track("s1", "Solo Album", "Solo"),
+                track("s2", "Solo Album", "Solo "),
+                tagged,
+            ],
+        )
+        .await;
+
+        let compilations: Vec<(String, bool, Option<String>)> = library
+            .get_albums()
+            .await
+            .into_iter()
+            .map(|a| (a.name, a.compilation, a.artist))
+            .collect();
+        let various = Some("Various Artists".to_string());
+        assert_eq!(
+            compilations,
+            [
+                ("Featuring".to_string(), false, Some("Band".to_string())),
+                ("Hits".to_string(), true, various.clone()),
+                ("Mixtape".to_string(), true, various.clone()),
+                ("Solo Album".to_string(), false, Some("Solo".to_string())),
+                ("Tagged".to_string(), true, various),
+            ]
+        );
+        assert_eq!(
+            library
+                .get_artist("Various Artists")
+                .await
+                .unwrap()
+                .album_count,
+            3
+        );
+    }
+
+    #[test]
+    fn test_album_years_prefers_most_common_then_earliest() {
+        let tracks_with = |years: &[&str]| -> Vec<Track> {
+            years
+                .iter()
+                .enumerate()
+                .map(|(i, year)| Track {
+                    year: Some(year.to_string()),
+                    ..track(&i.to_string(), "Album", "Artist")
+                })
+                .collect()
+        };
+
+        assert_eq!(album_years(&[]), (None, None));
+        assert_eq!(
+            album_years(&tracks_with(&["2003", "2001", "2003", "unknown"])),
+            (
+                Some("2003".to_string()),
+                Some(("2001".to_string(), "2003".to_string()))
+            )
+        );
+        assert_eq!(
+            album_years(&tracks_with(&["2010", "2008"])).0.as_deref(),
+            Some("2008")
+        );
+    }
+
+    #[test]
+    fn test_album_play_order() {
+        let numbered = |id: &str, disc: Option<&str>, number: Option<&str>| Track {
+            disc_number: disc.map(str::to_string),
+            track_number: number.map(str::to_string),
+            relative_path: format!("Album/{}.mp3", id),
+            ..track(id, "Album", "Artist")
+        };
+        let album = Album {
+            id: name_id("Album"),
+            name: "Album".to_string(),
+            artist: None,
+            compilation: false,
+            year: None,
+            year_range: None,
+            track_count: 5,
+            total_duration_secs: 0,
+            tracks: vec![
+                numbered("untagged", None, None),
+                numbered("d2t1", Some("2/2"), Some("1")),
+                numbered("d1t10", Some("1"), Some("10/12")),
+                numbered("d1t2", None, Some("2/12")),
+                numbered("d1t1", Some("1/2"), Some("01")),
+            ],
+        };
+
+        let order: Vec<&str> = album.play_order().iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(order, ["d1t1", "d1t2", "d1t10", "untagged", "d2t1"]);
+    }
+
+    #[test]
+    fn test_track_id_hash() {
+        assert_eq!(
+            TrackIdHash::Md5.hash("a.mp3"),
+            format!("{:x}", md5::compute("a.mp3"))
+        );
+        assert_eq!(TrackIdHash::Blake3.hash("a.mp3").len(), 64);
+        // SHA-256 of "abc", truncated to 128 bits
+        assert_eq!(
+            TrackIdHash::Sha256.hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223"
+        );
+        assert_eq!(
+            "BLAKE3".parse::<TrackIdHash>().unwrap(),
+            TrackIdHash::Blake3
+        );
+        assert!("crc32".parse::<TrackIdHash>().is_err());
+    }
+
+    #[test]
+    fn test_resolve_track_id_hash() {
+        // A fresh data directory takes the requested hash and records it
+        let fresh = tempfile::tempdir().unwrap();
+        let dir = fresh.path().join(DATA_DIR_NAME);
+        assert_eq!(
+            resolve_track_id_hash(&dir, Some(TrackIdHash::Blake3)).unwrap(),
+            TrackIdHash::Blake3
+        );
+        assert_eq!(
+            resolve_track_id_hash(&dir, None).unwrap(),
+            TrackIdHash::Blake3
+        );
+        assert!(resolve_track_id_hash(&dir, Some(TrackIdHash::Md5)).is_err());
+
+        // Databases without a record predate the setting and use MD5
+        let legacy = tempfile::tempdir().unwrap();
+        std::fs::write(legacy.path().join("lyrics.db"), b"").unwrap();
+        assert!(resolve_track_id_hash(legacy.path(), Some(TrackIdHash::Sha256)).is_err());
+        assert_eq!(
+            resolve_track_id_hash(legacy.path(), None).unwrap(),
+            TrackIdHash::Md5
+        );
+    }
+
+    #[test]
+    fn test_strip_article() {
+        let config = LibraryConfig {
+            leading_articles: default_leading_articles(),
+            ..LibraryConfig::default()
+        };
+        assert_eq!(config.strip_article("The Beatles"), "Beatles");
+        assert_eq!(config.strip_article("the  beatles "), "beatles");
+        assert_eq!(config.strip_article("Beatles, The"), "Beatles");
+        assert_eq!(config.strip_article("L'Arc~en~Ciel"), "Arc~en~Ciel");
+        assert_eq!(config.strip_article("Los Lobos"), "Lobos");
+        // Articles must be whole words, and a bare article is kept
+        assert_eq!(config.strip_article("A-ha"), "A-ha");
+        assert_eq!(
+            config.strip_article("Theory of a Deadman"),
+            "Theory of a Deadman"
+        );
+        assert_eq!(config.strip_article("The"), "The");
+        assert_eq!(
+            LibraryConfig::default().strip_article("The Beatles"),
+            "The Beatles"
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(".*", ".music-station"));
+        assert!(glob_match("@eaDir", "@eaDir"));
+        assert!(glob_match("Sample*", "Samples"));
+        assert!(glob_match("*.tmp", "song.mp3.tmp"));
+        assert!(glob_match("disc?", "disc1"));
+        assert!(!glob_match(".*", "Album"));
+        assert!(!glob_match("Sample*", "My Samples"));
+        assert!(!glob_match("disc?", "disc10"));
+    }
+
+    #[tokio::test]
+    async fn test_scan_skips_excluded_subtrees() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("Album/01.mp3"));
+        write_silent_mp3(&dir.path().join("Album/@eaDir/01.mp3"));
+        write_silent_mp3(&dir.path().join(".music-station/stray.mp3"));
+        write_silent_mp3(&dir.path().join("Samples/loop.mp3"));
+
+        let mut config = LibraryConfig::default();
+        config.exclude_patterns.push("Samples".to_string());
+        let library = MusicLibrary::with_config(dir.path().to_path_buf(), config);
+        library.scan().await.unwrap();
+
+        let tracks = library.get_tracks().await;
+        assert_eq!(tracks.len(), 1);
+        assert!(tracks[0].path.ends_with("Album/01.mp3"));
+    }
+
+    #[tokio::test]
+    async fn test_affects_tracks() {
+        let library = MusicLibrary::new(PathBuf::from("/music"));
+        assert!(
+            library
+                .affects_tracks(Path::new("/music/Album/01.flac"))
+                .await
+        );
+        assert!(
+            library
+                .affects_tracks(Path::new("/music/Album/album.CUE"))
+                .await
+        );
+        assert!(
+            !library
+                .affects_tracks(Path::new("/music/Album/notes.txt"))
+                .await
+        );
+        assert!(
+            !library
+                .affects_tracks(Path::new("/music/.music-station/lyrics.db"))
+                .await
+        );
+        assert!(
+            !library
+                .affects_tracks(Path::new("/music/Album/@eaDir/01.flac"))
+                .await
+        );
+        assert!(
+            !library
+                .affects_tracks(Path::new("/elsewhere/01.flac"))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_affects_tracks_when_directory_is_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let album = dir.path().join("Greatest Hits Vol. 2");
+        write_silent_mp3(&album.join("01.mp3"));
+        std::fs::write(dir.path().join("cover.jpg"), b"jpeg").unwrap();
+        std::fs::write(dir.path().join("README"), b"notes").unwrap();
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        assert!(library.affects_tracks(&album).await);
+        assert!(!library.affects_tracks(&dir.path().join("cover.jpg")).await);
+        assert!(!library.affects_tracks(&dir.path().join("README")).await);
+
+        // Once removed, a directory the scan walked still counts; files don't
+        std::fs::remove_dir_all(&album).unwrap();
+        std::fs::remove_file(dir.path().join("cover.jpg")).unwrap();
+        std::fs::remove_file(dir.path().join("README")).unwrap();
+        assert!(library.affects_tracks(&album).await);
+        assert!(!library.affects_tracks(&dir.path().join("cover.jpg")).await);
+        assert!(!library.affects_tracks(&dir.path().join("README")).await);
+        assert!(
+            !library
+                .affects_tracks(&dir.path().join("Never Scanned"))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rescan_only_parses_changed_files() {
+        use id3::TagLike;
+
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["01.mp3", "02.mp3", "03.mp3"] {
+            write_silent_mp3(&dir.path().join("Album").join(name));
+        }
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+
+        let id = |name: &str| library.compute_track_id(&format!("Album/{}", name));
+        library
+            .update_track_lyrics_status(&id("02.mp3"), true)
+            .await;
+        let stats = HashMap::from([(
+            id("02.mp3"),
+            TrackStats {
+                play_count: 3,
+                last_played: None,
+            },
+        )]);
+        library.update_track_stats(&stats).await;
+
+        // Nothing changed: no file is parsed again
+        let report = library.rescan().await.unwrap();
+        assert_eq!(
+            (report.added, report.updated, report.removed, report.parsed),
+            (0, 0, 0, 0)
+        );
+
+        std::fs::remove_file(dir.path().join("Album/01.mp3")).unwrap();
+        write_silent_mp3(&dir.path().join("Album/04.mp3"));
+        let mut tag = id3::Tag::new();
+        tag.set_title("Retitled");
+        tag.write_to_path(dir.path().join("Album/03.mp3"), id3::Version::Id3v24)
+            .unwrap();
+
+        let report = library.rescan().await.unwrap();
+        assert_eq!(
+            (report.added, report.updated, report.removed, report.parsed),
+            (1, 1, 1, 2)
+        );
+        assert_eq!(report.tracks, 3);
+
+        assert!(library.get_track(&id("01.mp3")).await.is_none());
+        assert!(library.get_track(&id("04.mp3")).await.is_some());
+        let retitled = library.get_track(&id("03.mp3")).await.unwrap();
+        assert_eq!(retitled.title.as_deref(), Some("Retitled"));
+        let kept = library.get_track(&id("02.mp3")).await.unwrap();
+        assert!(kept.has_lyrics);
+        assert_eq!(kept.play_count, 3);
+    }
+
+    #[tokio::test]
+    async fn test_scan_skips_data_dir_without_exclude_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("01.mp3"));
+        write_silent_mp3(&dir.path().join(DATA_DIR_NAME).join("stray.mp3"));
+
+        let config = LibraryConfig {
+            exclude_patterns: Vec::new(),
+            ..LibraryConfig::default()
+        };
+        let library = MusicLibrary::with_config(dir.path().to_path_buf(), config);
+        library.scan().await.unwrap();
+
+        assert_eq!(library.get_tracks().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_builder_limits_scanned_extensions() {
+        let dir = tempfile::tempdir().unwrap();
+        for n in 1..=3 {
+            write_silent_mp3(&dir.path().join(format!("Album/{:02}.mp3", n)));
+        }
+        std::fs::write(dir.path().join("Album/04.flac"), b"not a flac file").unwrap();
+
+        let library = MusicLibrary::builder(dir.path())
+            .extensions(["MP3"])
+            .scan_concurrency(2)
+            .build();
+        library.scan().await.unwrap();
+
+        // The broken FLAC is never opened, so it isn't a scan error either
+        assert_eq!(library.get_tracks().await.len(), 3);
+        assert!(library.get_scan_errors().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scan_uses_registered_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("01.mp3"));
+        std::fs::copy(dir.path().join("01.mp3"), dir.path().join("02.mpga")).unwrap();
+
+        let mut formats = AudioFormatRegistry::empty();
+        formats.register("mpga", || Box::new(crate::audio::Mp3File));
+        let library = MusicLibrary::builder(dir.path()).formats(formats).build();
+        library.scan().await.unwrap();
+
+        let tracks = library.get_tracks().await;
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].filename, "02.mpga");
+    }
+
+    #[tokio::test]
+    async fn test_scan_records_unparseable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("Album/01.mp3"));
+        std::fs::write(dir.path().join("Album/02.flac"), b"not a flac file").unwrap();
+        // Files no format handles are skipped, not reported
+        std::fs::write(dir.path().join("Album/cover.jpg"), b"jpeg").unwrap();
+        std::fs::write(dir.path().join("Album/rip.log"), b"log").unwrap();
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+
+        assert_eq!(library.get_tracks().await.len(), 1);
+        let errors = library.get_scan_errors().await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "Album/02.flac");
+    }
+
+    #[tokio::test]
+    async fn test_scan_splits_files_with_cue_sheets() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("Live/live.mp3"));
+        write_silent_mp3(&dir.path().join("Live/bonus.mp3"));
+        // The sheet still names the original rip
+        std::fs::write(
+            dir.path().join("Live/live.cue"),
+            "PERFORMER \"The Band\"\nTITLE \"Live\"\nFILE \"live.wav\" WAVE\n\
+             TRACK 01 AUDIO\n TITLE \"Intro\"\n INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n TITLE \"Encore\"\n PERFORMER \"Guest\"\n INDEX 01 00:00:30\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("Live/missing.cue"),
+            "FILE \"gone.flac\" WAVE\n TRACK 01 AUDIO\n INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+
+        let mut tracks = library.get_tracks().await;
+        tracks.sort_by_key(|t| (t.filename.clone(), t.start_ms));
+        assert_eq!(tracks.len(), 3);
+        assert_eq!(tracks[0].filename, "bonus.mp3");
+        assert_eq!(tracks[0].start_ms, None);
+
+        let (intro, encore) = (&tracks[1], &tracks[2]);
+        assert_eq!(intro.path, encore.path);
+        assert_ne!(intro.id, encore.id);
+        assert_eq!(
+            intro.id,
+            format!("{:x}", md5::compute("Live/live.mp3#1".as_bytes()))
+        );
+        assert_eq!(intro.title.as_deref(), Some("Intro"));
+        assert_eq!(intro.artist.as_deref(), Some("The Band"));
+        assert_eq!(intro.album.as_deref(), Some("Live"));
+        assert_eq!(intro.track_number.as_deref(), Some("1"));
+        assert_eq!((intro.start_ms, intro.end_ms), (Some(0), Some(400)));
+        assert_eq!(encore.artist.as_deref(), Some("Guest"));
+        assert_eq!(encore.album_artist.as_deref(), Some("The Band"));
+        assert_eq!((encore.start_ms, encore.end_ms), (Some(400), Some(1000)));
+
+        assert_eq!(library.cue_file_duration_ms(&encore.path).await, Some(1000));
+
+        let errors = library.get_scan_errors().await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "Live/missing.cue");
+
+        // Tags live in the shared file, so CUE tracks can't be edited individually
+        let update: TrackMetadataUpdate = serde_json::from_str(r#"{"title": "New"}"#).unwrap();
+        assert!(matches!(
+            library.update_track_metadata(&intro.id, update).await,
+            Err(MusicStationError::Unsupported(_))
+        ));
+        assert!(matches!(
+            library
+                .set_cover_art(&intro.id, vec![0xFF, 0xD8, 0xFF, 0xE0], "image/jpeg")
+                .await,
+            Err(MusicStationError::Unsupported(_))
+        ));
+        assert!(matches!(
+            library.remove_cover_art(&encore.id).await,
+            Err(MusicStationError::Unsupported(_))
+        ));
+        assert!(matches!(
+            library.optimize_cover(&encore.id, 1000, 85, true).await,
+            Err(MusicStationError::Unsupported(_))
+        ));
+        assert_eq!(library.get_tracks().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_album_cover_falls_back_to_folder_image() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("Album/01.mp3"));
+        std::fs::write(
+            dir.path().join("Album/folder.jpg"),
+            [0xFF, 0xD8, 0xFF, 0xE0],
+        )
+        .unwrap();
+        write_silent_mp3(&dir.path().join("Other/01.mp3"));
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        let mut albums = library.get_albums().await;
+        assert_eq!(albums.len(), 1);
+        let album = albums.remove(0);
+
+        let cover_track = library.album_cover_track(&album).await.unwrap();
+        assert!(cover_track.path.ends_with("Album/01.mp3"));
+        assert_eq!(
+            library.get_album_art(&album).await.unwrap(),
+            Some((vec![0xFF, 0xD8, 0xFF, 0xE0], "image/jpeg".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_covers() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("Album/01.mp3"));
+        std::fs::write(dir.path().join("Album/folder.jpg"), [0xFF, 0xD8]).unwrap();
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        let track_id = library.get_tracks().await[0].id.clone();
+        let album_id = library.get_albums().await[0].id.clone();
+
+        let covers = library
+            .check_covers(&[track_id.clone(), album_id.clone(), "missing".to_string()])
+            .await;
+
+        // The track has no embedded art, but its album falls back to folder.jpg
+        assert_eq!(covers.len(), 3);
+        assert!(!covers[&track_id]);
+        assert!(covers[&album_id]);
+        assert!(!covers["missing"]);
+    }
+
+    #[tokio::test]
+    async fn test_track_paths_relative_to_library() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("top.mp3"));
+        write_silent_mp3(&dir.path().join("Artist/Album/CD1/01 Intro.mp3"));
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+
+        let mut tracks = library.get_tracks().await;
+        tracks.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        let nested = Path::new("Artist")
+            .join("Album")
+            .join("CD1")
+            .join("01 Intro.mp3");
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0].filename, "01 Intro.mp3");
+        assert_eq!(tracks[0].relative_path, nested.to_string_lossy());
+        assert_eq!(tracks[1].filename, "top.mp3");
+        assert_eq!(tracks[1].relative_path, "top.mp3");
+        assert_eq!(
+            tracks[0].id,
+            format!("{:x}", md5::compute(tracks[0].relative_path.as_bytes()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_changes_since_reports_net_changes() {
+        let track_id = |relative_path: &str| format!("{:x}", md5::compute(relative_path));
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("a.mp3"));
+        write_silent_mp3(&dir.path().join("b.mp3"));
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        let initial = library.changes_since(0).await;
+        assert_eq!(initial.version, 2);
+        assert_eq!(initial.added.len(), 2);
+        assert!(!initial.full_resync_required);
+
+        std::fs::remove_file(dir.path().join("b.mp3")).unwrap();
+        write_silent_mp3(&dir.path().join("c.mp3"));
+        library.scan().await.unwrap();
+        let a_id = track_id("a.mp3");
+        library.record_track_play(&a_id, 3).await;
+
+        let changes = library.changes_since(initial.version).await;
+        assert_eq!(changes.added.len(), 1);
+        assert_eq!(changes.added[0].filename, "c.mp3");
+        assert_eq!(changes.removed, vec![track_id("b.mp3")]);
+        assert_eq!(changes.updated.len(), 1);
+        assert_eq!(changes.updated[0].id, a_id);
+
+        // "c.mp3" was added after version 0 and later removed: it drops out entirely
+        std::fs::remove_file(dir.path().join("c.mp3")).unwrap();
+        library.scan().await.unwrap();
+        let changes = library.changes_since(initial.version).await;
+        assert!(changes.added.is_empty());
+        assert_eq!(changes.removed, vec![track_id("b.mp3")]);
+    }
+
+    #[tokio::test]
+    async fn test_changes_since_requires_resync_outside_window() {
+        let library = library_with(LibraryConfig::default(), vec![track("t", "A", "X")]).await;
+        for count in 1..=(CHANGE_LOG_CAPACITY as u64 + 5) {
+            library.record_track_play("t", count).await;
+        }
+        let version = library.version().await;
+
+        assert!(library.changes_since(0).await.full_resync_required);
+        assert!(
+            library
+                .changes_since(version + 1)
+                .await
+                .full_resync_required
+        );
+        let recent = library.changes_since(version - 1).await;
+        assert!(!recent.full_resync_required);
+        assert_eq!(recent.updated.len(), 1);
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn test_update_track_play_count_leaves_last_played() {
+        let library = library_with(LibraryConfig::default(), vec![track("t", "A", "X")]).await;
+        let version = library.version().await;
+
+        library.update_track_play_count("t", 2).await;
+        let track = library.get_track("t").await.unwrap();
+        assert_eq!((track.play_count, track.last_played), (2, None));
+        assert_eq!(library.changes_since(version).await.updated.len(), 1);
+
+        // An unchanged count isn't recorded as a change
+        let version = library.version().await;
+        library.update_track_play_count("t", 2).await;
+        assert!(library.changes_since(version).await.updated.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_spawn_scan_reports_loading() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("a.mp3"));
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        assert!(library.is_loaded().await);
+
+        let handle = library.spawn_scan().await.unwrap();
+        assert_eq!(library.scan_status().await.state, ScanState::Running);
+        assert!(!library.is_loaded().await);
+        assert!(library.spawn_scan().await.is_err());
+
+        handle.await.unwrap().unwrap();
+        assert!(library.is_loaded().await);
+        assert_eq!(library.get_tracks().await.len(), 1);
+
+        // Rescans keep serving the previous list, so the library stays loaded
+        let handle = library.spawn_scan().await.unwrap();
+        assert!(library.is_loaded().await);
+        library.wait_for_scan().await;
+        assert_eq!(library.scan_status().await.state, ScanState::Completed);
+        handle.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_keep_unparseable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("good.mp3"));
+        std::fs::write(dir.path().join("empty.flac"), b"").unwrap();
+        std::fs::write(dir.path().join("half.mp3"), b"ID3\x04").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"not audio").unwrap();
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        assert_eq!(library.get_tracks().await.len(), 1);
+
+        let config = LibraryConfig {
+            keep_unparseable_files: true,
+            ..LibraryConfig::default()
+        };
+        let library = MusicLibrary::with_config(dir.path().to_path_buf(), config);
+        library.scan().await.unwrap();
+
+        let mut tracks = library.get_tracks().await;
+        tracks.sort_by(|a, b| a.filename.cmp(&b.filename));
+        let names: Vec<_> = tracks.iter().map(|t| t.filename.as_str()).collect();
+        assert_eq!(names, ["empty.flac", "good.mp3", "half.mp3"]);
+
+        let empty = &tracks[0];
+        assert!(empty.parse_error.is_some());
+        assert_eq!(empty.file_size, 0);
+        assert_eq!(empty.duration_secs, None);
+        assert_eq!(tracks[2].file_size, 4);
+        assert!(tracks[1].parse_error.is_none());
+
+        // Still reported as scan errors
+        let errors = library.get_scan_errors().await;
+        assert!(errors.iter().any(|e| e.path == "empty.flac"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_tracks() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("a.mp3"));
+        write_silent_mp3(&dir.path().join("b.mp3"));
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        assert!(library.find_missing_tracks().await.is_empty());
+        let version = library.version().await;
+
+        std::fs::remove_file(dir.path().join("a.mp3")).unwrap();
+        let missing = library.find_missing_tracks().await;
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].filename, "a.mp3");
+        assert!(missing[0].missing);
+        let changes = library.changes_since(version).await;
+        assert_eq!(changes.updated.len(), 1);
+
+        // The flag clears once the file is back
+        write_silent_mp3(&dir.path().join("a.mp3"));
+        let track = library.verify_track(&missing[0].id).await.unwrap();
+        assert!(!track.missing);
+        assert!(library.verify_track("nope").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_recently_added() {
+        let now = chrono::Utc::now();
+        let added = |id: &str, days_ago: i64| Track {
+            date_added: (now - chrono::Duration::days(days_ago)).to_rfc3339(),
+            ..track(id, "Album", "Artist")
+        };
+        let library = library_with(
+            LibraryConfig::default(),
+            vec![
+                added("old", 40),
+                added("new", 1),
+                added("mid", 10),
+                // Never stored: excluded rather than sorted as the oldest
+                track("unknown", "Album", "Artist"),
+            ],
+        )
+        .await;
+
+        let ids = |tracks: Vec<Track>| tracks.into_iter().map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(library.recently_added(10, None).await),
+            ["new", "mid", "old"]
+        );
+        assert_eq!(ids(library.recently_added(2, None).await), ["new", "mid"]);
+        assert_eq!(
+            ids(library.recently_added(10, Some(30)).await),
+            ["new", "mid"]
+        );
+
+        let version = library.version().await;
+        library
+            .update_track_dates_added(&HashMap::from([("unknown".to_string(), now.to_rfc3339())]))
+            .await;
+        assert_eq!(
+            ids(library.changes_since(version).await.updated),
+            ["unknown"]
+        );
+        assert_eq!(ids(library.recently_added(1, None).await), ["unknown"]);
+    }
+
+    #[tokio::test]
+    async fn test_file_edits_keep_database_state() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("a.mp3"));
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        let id = library.get_tracks().await[0].id.clone();
+
+        let date_added = "2020-01-01T00:00:00+00:00".to_string();
+        library
+            .update_track_dates_added(&HashMap::from([(id.clone(), date_added.clone())]))
+            .await;
+        library.update_track_lyrics_status(&id, true).await;
+        library.record_track_play(&id, 3).await;
+        let before = library.get_track(&id).await.unwrap();
+
+        let update: TrackMetadataUpdate = serde_json::from_str(r#"{"title": "New"}"#).unwrap();
+        let updated = library.update_track_metadata(&id, update).await.unwrap();
+        assert_eq!(updated.title.as_deref(), Some("New"));
+        library
+            .set_cover_art(&id, vec![0xFF, 0xD8, 0xFF, 0xE0], "image/jpeg")
+            .await
+            .unwrap();
+        library
+            .embed_lyrics(&id, "Hello".to_string())
+            .await
+            .unwrap();
+        library.remove_cover_art(&id).await.unwrap();
+
+        let after = library.get_track(&id).await.unwrap();
+        assert_eq!(after.title.as_deref(), Some("New"));
+        assert_eq!(after.date_added, date_added);
+        assert!(after.has_lyrics);
+        assert_eq!(after.play_count, 3);
+        assert_eq!(after.last_played, before.last_played);
+        assert!(after.last_played.is_some());
+
+        // The edits are already applied, so a rescan has nothing to parse
+        assert!(library.is_up_to_date(&after.path).await);
+        assert_eq!(library.rescan().await.unwrap().parsed, 0);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_flags() {
+        use id3::TagLike;
+
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("a.mp3"));
+        write_silent_mp3(&dir.path().join("b.mp3"));
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        let id_of = |name: &str| format!("{:x}", md5::compute(name.as_bytes()));
+        assert!(!library.get_track(&id_of("a.mp3")).await.unwrap().has_cover);
+        let version = library.version().await;
+
+        // Cover art added by an external tool
+        let path = dir.path().join("a.mp3");
+        let mut tag = id3::Tag::read_from_path(&path).unwrap_or_default();
+        tag.add_frame(id3::frame::Picture {
+            mime_type: "image/png".to_string(),
+            picture_type: id3::frame::PictureType::CoverFront,
+            description: String::new(),
+            data: vec![0x89, b'P', b'N', b'G'],
+        });
+        tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+
+        let report = library
+            .refresh_flags(&HashSet::from([id_of("b.mp3")]), 2)
+            .await;
+        assert_eq!(report.files_checked, 2);
+        assert_eq!(report.cover_updated, 1);
+        assert_eq!(report.lyrics_updated, 1);
+        assert!(library.get_track(&id_of("a.mp3")).await.unwrap().has_cover);
+        assert!(library.get_track(&id_of("b.mp3")).await.unwrap().has_lyrics);
+        assert_eq!(library.changes_since(version).await.updated.len(), 2);
+
+        // Nothing changed since, so nothing is recorded
+        let version = library.version().await;
+        let report = library
+            .refresh_flags(&HashSet::from([id_of("b.mp3")]), 2)
+            .await;
+        assert_eq!((report.cover_updated, report.lyrics_updated), (0, 0));
+        assert_eq!(library.version().await, version);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_scan_keeps_previous_tracks() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("a.mp3"));
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        assert_eq!(library.scan_status().await.state, ScanState::Completed);
+        assert!(!library.cancel_scan().await);
+
+        write_silent_mp3(&dir.path().join("b.mp3"));
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let err = library.run_scan(&cancel, false).await.unwrap_err();
+
+        assert!(err.is::<ScanCancelled>());
+        assert_eq!(library.get_tracks().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_scan_keeps_previous_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("music");
+        write_silent_mp3(&root.join("a.mp3"));
+        std::fs::write(root.join("broken.flac"), b"not a flac file").unwrap();
+
+        let library = MusicLibrary::new(root.clone());
+        library.scan().await.unwrap();
+        let a_id = library.get_tracks().await[0].id.clone();
+        library.record_track_play(&a_id, 7).await;
+        let version = library.version().await;
+
+        // A library folder that can't be read aborts the scan
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(library.scan().await.is_err());
+        assert_eq!(library.scan_status().await.state, ScanState::Failed);
+
+        let tracks = library.get_tracks().await;
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(tracks[0].play_count, 7);
+        assert_eq!(library.get_scan_errors().await.len(), 1);
+        assert_eq!(library.version().await, version);
+    }
+
+    #[tokio::test]
+    async fn test_scan_skips_dangling_symlink() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("a.mp3"));
+        std::os::unix::fs::symlink(
+            dir.path().join("missing.mp3"),
+            dir.path().join("dangling.mp3"),
+        )
+        .unwrap();
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        assert_eq!(library.get_tracks().await.len(), 1);
+        let errors = library.get_scan_errors().await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "dangling.mp3");
+    }
+
+    #[tokio::test]
+    async fn test_rescan_preserves_lyrics_and_play_counts() {
+        let dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&dir.path().join("a.mp3"));
+
+        let library = MusicLibrary::new(dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        let id = library.get_tracks().await[0].id.clone();
+        library.update_track_lyrics_status(&id, true).await;
+        library.record_track_play(&id, 4).await;
+        let version = library.version().await;
+
+        library.scan().await.unwrap();
+
+        let track = library.get_track(&id).await.unwrap();
+        assert!(track.has_lyrics);
+        assert_eq!(track.play_count, 4);
+        assert_eq!(library.version().await, version);
+    }
 }
@@ -1,8 +1,11 @@
+pub mod fetch_queue;
 pub mod fetcher;
+pub mod genius_provider;
 pub mod music_search_provider;
 pub mod providers;
 
-use anyhow::{Context, Result};
+use crate::error::Result;
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
 use std::path::Path;
@@ -38,10 +41,16 @@ impl LyricFormat {
     }
 
     pub fn from_str(s: &str) -> Self {
+        Self::parse(s).unwrap_or(LyricFormat::Plain)
+    }
+
+    /// Strict counterpart of `from_str`: `None` for unknown format names
+    pub fn parse(s: &str) -> Option<Self> {
         match s.to_lowercase().as_str() {
-            "lrc" => LyricFormat::Lrc,
-            "lrc_word" | "lrcword" | "word" | "extended" => LyricFormat::LrcWord,
-            _ => LyricFormat::Plain,
+            "plain" => Some(LyricFormat::Plain),
+            "lrc" => Some(LyricFormat::Lrc),
+            "lrc_word" | "lrcword" | "word" | "extended" => Some(LyricFormat::LrcWord),
+            _ => None,
         }
     }
 
@@ -75,6 +84,48 @@ impl LyricFormat {
     }
 }
 
+/// `lyrics` table; a track has at most one lyric per format
+const CREATE_LYRICS_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS lyrics (
+        track_id TEXT NOT NULL,
+        content TEXT NOT NULL,
+        format TEXT NOT NULL,
+        language TEXT,
+        source TEXT,
+        created_at TEXT NOT NULL,
+        updated_at TEXT NOT NULL,
+        PRIMARY KEY (track_id, format)
+    )
+"#;
+
+/// Orders a track's lyrics by preference: word-level LRC, LRC, then plain text
+const FORMAT_PREFERENCE: &str =
+    "CASE format WHEN 'lrc_word' THEN 0 WHEN 'lrc' THEN 1 ELSE 2 END, updated_at DESC";
+
+type LyricRow = (
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    String,
+    String,
+);
+
+fn lyric_from_row(
+    (track_id, content, format, language, source, created_at, updated_at): LyricRow,
+) -> Lyric {
+    Lyric {
+        track_id,
+        content,
+        format: LyricFormat::from_str(&format),
+        language,
+        source,
+        created_at,
+        updated_at,
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LyricDatabase {
     pool: SqlitePool,
@@ -107,27 +158,61 @@ impl LyricDatabase {
 
     /// Initialize database schema
     async fn initialize(&self) -> Result<()> {
+        self.migrate_single_lyric_layout().await?;
+
+        sqlx::query(CREATE_LYRICS_TABLE)
+            .execute(&self.pool)
+            .await
+            .context("Failed to create lyrics table")?;
+
+        self.initialize_fetch_queue().await?;
+
+        Ok(())
+    }
+
+    /// Databases from before multiple formats per track keyed `lyrics` by
+    /// `track_id` alone. Rebuild such a table with the `(track_id, format)` key.
+    async fn migrate_single_lyric_layout(&self) -> Result<()> {
+        let key_columns = sqlx::query_as::<_, (String,)>(
+            "SELECT name FROM pragma_table_info('lyrics') WHERE pk > 0",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to read lyrics table layout")?;
+        if key_columns.len() != 1 {
+            return Ok(());
+        }
+
+        tracing::info!("Migrating lyrics table to allow one lyric per format");
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("ALTER TABLE lyrics RENAME TO lyrics_single")
+            .execute(&mut *tx)
+            .await
+            .context("Failed to rename old lyrics table")?;
+        sqlx::query(CREATE_LYRICS_TABLE)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to create lyrics table")?;
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS lyrics (
-                track_id TEXT PRIMARY KEY,
-                content TEXT NOT NULL,
-                format TEXT NOT NULL,
-                language TEXT,
-                source TEXT,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            )
+            INSERT INTO lyrics (track_id, content, format, language, source, created_at, updated_at)
+            SELECT track_id, content, format, language, source, created_at, updated_at
+            FROM lyrics_single
             "#,
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await
-        .context("Failed to create lyrics table")?;
+        .context("Failed to copy lyrics")?;
+        sqlx::query("DROP TABLE lyrics_single")
+            .execute(&mut *tx)
+            .await
+            .context("Failed to drop old lyrics table")?;
+        tx.commit().await?;
 
         Ok(())
     }
 
-    /// Save or update lyrics for a track
+    /// Save or update a track's lyrics in `format`, keeping those in other formats
     pub async fn save_lyric(
         &self,
         track_id: &str,
@@ -142,9 +227,8 @@ impl LyricDatabase {
             r#"
             INSERT INTO lyrics (track_id, content, format, language, source, created_at, updated_at)
             VALUES (?, ?, ?, ?, ?, ?, ?)
-            ON CONFLICT(track_id) DO UPDATE SET
+            ON CONFLICT(track_id, format) DO UPDATE SET
                 content = excluded.content,
-                format = excluded.format,
                 language = excluded.language,
                 source = excluded.source,
                 updated_at = excluded.updated_at
@@ -172,45 +256,68 @@ impl LyricDatabase {
         })
     }
 
-    /// Get lyrics for a specific track
+    /// Get the preferred lyrics of a track: word-level LRC, then LRC, then plain text
     pub async fn get_lyric(&self, track_id: &str) -> Result<Option<Lyric>> {
-        let row = sqlx::query_as::<
-            _,
-            (
-                String,
-                String,
-                String,
-                Option<String>,
-                Option<String>,
-                String,
-                String,
-            ),
-        >(
+        let row = sqlx::query_as::<_, LyricRow>(&format!(
             r#"
             SELECT track_id, content, format, language, source, created_at, updated_at
             FROM lyrics
             WHERE track_id = ?
+            ORDER BY {}
+            LIMIT 1
+            "#,
+            FORMAT_PREFERENCE
+        ))
+        .bind(track_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to fetch lyric")?;
+
+        Ok(row.map(lyric_from_row))
+    }
+
+    /// Get a track's lyrics in one format
+    pub async fn get_lyric_in_format(
+        &self,
+        track_id: &str,
+        format: &LyricFormat,
+    ) -> Result<Option<Lyric>> {
+        let row = sqlx::query_as::<_, LyricRow>(
+            r#"
+            SELECT track_id, content, format, language, source, created_at, updated_at
+            FROM lyrics
+            WHERE track_id = ? AND format = ?
             "#,
         )
         .bind(track_id)
+        .bind(format.as_str())
         .fetch_optional(&self.pool)
         .await
         .context("Failed to fetch lyric")?;
 
-        Ok(row.map(
-            |(track_id, content, format, language, source, created_at, updated_at)| Lyric {
-                track_id,
-                content,
-                format: LyricFormat::from_str(&format),
-                language,
-                source,
-                created_at,
-                updated_at,
-            },
+        Ok(row.map(lyric_from_row))
+    }
+
+    /// Get every stored lyric of a track, preferred format first
+    pub async fn get_lyrics(&self, track_id: &str) -> Result<Vec<Lyric>> {
+        let rows = sqlx::query_as::<_, LyricRow>(&format!(
+            r#"
+            SELECT track_id, content, format, language, source, created_at, updated_at
+            FROM lyrics
+            WHERE track_id = ?
+            ORDER BY {}
+            "#,
+            FORMAT_PREFERENCE
         ))
+        .bind(track_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to fetch lyrics")?;
+
+        Ok(rows.into_iter().map(lyric_from_row).collect())
     }
 
-    /// Delete lyrics for a track
+    /// Delete all lyrics for a track
     pub async fn delete_lyric(&self, track_id: &str) -> Result<bool> {
         let result = sqlx::query(
             r#"
@@ -226,6 +333,22 @@ impl LyricDatabase {
         Ok(result.rows_affected() > 0)
     }
 
+    /// Delete a track's lyrics in one format, keeping the others
+    pub async fn delete_lyric_in_format(
+        &self,
+        track_id: &str,
+        format: &LyricFormat,
+    ) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM lyrics WHERE track_id = ? AND format = ?")
+            .bind(track_id)
+            .bind(format.as_str())
+            .execute(&self.pool)
+            .await
+            .context("Failed to delete lyric")?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     /// Check if a track has lyrics
     pub async fn has_lyric(&self, track_id: &str) -> Result<bool> {
         let count: (i64,) = sqlx::query_as(
@@ -245,7 +368,7 @@ impl LyricDatabase {
     pub async fn get_tracks_with_lyrics(&self) -> Result<Vec<String>> {
         let rows = sqlx::query_as::<_, (String,)>(
             r#"
-            SELECT track_id FROM lyrics ORDER BY updated_at DESC
+            SELECT track_id FROM lyrics GROUP BY track_id ORDER BY MAX(updated_at) DESC
             "#,
         )
         .fetch_all(&self.pool)
@@ -289,3 +412,95 @@ pub struct LyricUpload {
     pub language: Option<String>,
     pub source: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lyrics_per_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = LyricDatabase::new(dir.path().join("lyrics.db"))
+            .await
+            .unwrap();
+
+        db.save_lyric("t", "Translation".into(), LyricFormat::Plain, None, None)
+            .await
+            .unwrap();
+        db.save_lyric(
+            "t",
+            "[00:01.00]Original".into(),
+            LyricFormat::Lrc,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        db.save_lyric("t", "Translation v2".into(), LyricFormat::Plain, None, None)
+            .await
+            .unwrap();
+
+        let lyrics = db.get_lyrics("t").await.unwrap();
+        assert_eq!(lyrics.len(), 2);
+        assert_eq!(lyrics[0].format, LyricFormat::Lrc);
+        assert_eq!(lyrics[1].content, "Translation v2");
+        assert_eq!(
+            db.get_lyric("t").await.unwrap().unwrap().format,
+            LyricFormat::Lrc
+        );
+        assert_eq!(db.get_tracks_with_lyrics().await.unwrap(), ["t"]);
+
+        assert!(
+            db.delete_lyric_in_format("t", &LyricFormat::Lrc)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            db.get_lyric("t").await.unwrap().unwrap().format,
+            LyricFormat::Plain
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migrates_single_lyric_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lyrics.db");
+        {
+            let options = SqliteConnectOptions::from_str(&format!("sqlite:{}", path.display()))
+                .unwrap()
+                .create_if_missing(true);
+            let pool = SqlitePool::connect_with(options).await.unwrap();
+            sqlx::query(
+                r#"
+                CREATE TABLE lyrics (
+                    track_id TEXT PRIMARY KEY,
+                    content TEXT NOT NULL,
+                    format TEXT NOT NULL,
+                    language TEXT,
+                    source TEXT,
+                    created_at TEXT NOT NULL,
+                    updated_at TEXT NOT NULL
+                )
+                "#,
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+            sqlx::query("INSERT INTO lyrics VALUES ('t', 'Words', 'plain', 'en', NULL, 'x', 'x')")
+                .execute(&pool)
+                .await
+                .unwrap();
+            pool.close().await;
+        }
+
+        let db = LyricDatabase::new(&path).await.unwrap();
+        let lyric = db.get_lyric("t").await.unwrap().unwrap();
+        assert_eq!(lyric.content, "Words");
+        assert_eq!(lyric.language.as_deref(), Some("en"));
+
+        db.save_lyric("t", "[00:01.00]Words".into(), LyricFormat::Lrc, None, None)
+            .await
+            .unwrap();
+        assert_eq!(db.get_lyrics("t").await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,327 @@
+//! Persistent queue for the bulk "fetch missing lyrics" job
+//!
+//! The job and the state of every queued track live in the lyrics database,
+//! so a job that was running when the server stopped can be resumed on startup.
+
+use super::LyricDatabase;
+use crate::error::Result;
+use anyhow::Context;
+use serde::Serialize;
+
+/// Lifecycle of the fetch-missing job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FetchJobState {
+    /// Tracks are being fetched, or will be once the server restarts
+    Running,
+    /// Stopped by the user; pending tracks stay queued for `resume`
+    Cancelled,
+    /// Every queued track was processed
+    Completed,
+}
+
+impl FetchJobState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FetchJobState::Running => "running",
+            FetchJobState::Cancelled => "cancelled",
+            FetchJobState::Completed => "completed",
+        }
+    }
+
+    fn from_db(s: &str) -> Self {
+        match s {
+            "running" => FetchJobState::Running,
+            "cancelled" => FetchJobState::Cancelled,
+            _ => FetchJobState::Completed,
+        }
+    }
+}
+
+/// What happened to a queued track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchOutcome {
+    /// Lyrics were saved (or the track already had some)
+    Done,
+    /// The provider had no match confident enough to save
+    NotFound,
+    /// The search or fetch failed; the error is kept for the status endpoint
+    Failed,
+}
+
+impl FetchOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            FetchOutcome::Done => "done",
+            FetchOutcome::NotFound => "not_found",
+            FetchOutcome::Failed => "failed",
+        }
+    }
+}
+
+/// A track whose fetch failed
+#[derive(Debug, Clone, Serialize)]
+pub struct FetchFailure {
+    pub track_id: String,
+    pub error: String,
+}
+
+/// Persisted progress of the fetch-missing job
+#[derive(Debug, Clone, Serialize)]
+pub struct FetchJobStatus {
+    pub state: FetchJobState,
+    /// Provider the job searches
+    pub provider: Option<String>,
+    pub started_at: String,
+    pub updated_at: String,
+    pub total: u64,
+    pub pending: u64,
+    pub done: u64,
+    pub not_found: u64,
+    pub failed: u64,
+    /// Most recent failures first, capped at [`LyricDatabase::FETCH_FAILURES_SHOWN`]
+    pub failures: Vec<FetchFailure>,
+}
+
+impl LyricDatabase {
+    /// Failures listed in [`FetchJobStatus::failures`]
+    const FETCH_FAILURES_SHOWN: i64 = 50;
+
+    /// Create the job and queue tables
+    pub(super) async fn initialize_fetch_queue(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS lyrics_fetch_job (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                state TEXT NOT NULL,
+                provider TEXT,
+                started_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to create lyrics_fetch_job table")?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS lyrics_fetch_queue (
+                track_id TEXT PRIMARY KEY,
+                state TEXT NOT NULL,
+                error TEXT,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await
+        .context("Failed to create lyrics_fetch_queue table")?;
+
+        Ok(())
+    }
+
+    /// Replace any previous job with a running one over `track_ids`
+    pub async fn start_fetch_job(
+        &self,
+        provider: Option<&str>,
+        track_ids: &[String],
+    ) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM lyrics_fetch_queue")
+            .execute(&mut *tx)
+            .await
+            .context("Failed to clear lyrics fetch queue")?;
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO lyrics_fetch_job (id, state, provider, started_at, updated_at)
+            VALUES (1, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(FetchJobState::Running.as_str())
+        .bind(provider)
+        .bind(&now)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to save lyrics fetch job")?;
+
+        for track_id in track_ids {
+            sqlx::query(
+                "INSERT OR IGNORE INTO lyrics_fetch_queue (track_id, state, updated_at) VALUES (?, 'pending', ?)",
+            )
+            .bind(track_id)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to queue track for lyrics fetch")?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Progress of the current or last job; `None` if no job was ever started
+    pub async fn fetch_job_status(&self) -> Result<Option<FetchJobStatus>> {
+        let Some((state, provider, started_at, updated_at)) =
+            sqlx::query_as::<_, (String, Option<String>, String, String)>(
+                "SELECT state, provider, started_at, updated_at FROM lyrics_fetch_job WHERE id = 1",
+            )
+            .fetch_optional(&self.pool)
+            .await
+            .context("Failed to read lyrics fetch job")?
+        else {
+            return Ok(None);
+        };
+
+        let counts = sqlx::query_as::<_, (String, i64)>(
+            "SELECT state, COUNT(*) FROM lyrics_fetch_queue GROUP BY state",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to count lyrics fetch queue")?;
+        let count = |wanted: &str| {
+            counts
+                .iter()
+                .find(|(state, _)| state == wanted)
+                .map_or(0, |(_, n)| *n as u64)
+        };
+
+        let failures = sqlx::query_as::<_, (String, Option<String>)>(
+            r#"
+            SELECT track_id, error FROM lyrics_fetch_queue
+            WHERE state = 'failed'
+            ORDER BY updated_at DESC, track_id
+            LIMIT ?
+            "#,
+        )
+        .bind(Self::FETCH_FAILURES_SHOWN)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to list lyrics fetch failures")?
+        .into_iter()
+        .map(|(track_id, error)| FetchFailure {
+            track_id,
+            error: error.unwrap_or_default(),
+        })
+        .collect();
+
+        Ok(Some(FetchJobStatus {
+            state: FetchJobState::from_db(&state),
+            provider,
+            started_at,
+            updated_at,
+            total: counts.iter().map(|(_, n)| *n as u64).sum(),
+            pending: count("pending"),
+            done: count(FetchOutcome::Done.as_str()),
+            not_found: count(FetchOutcome::NotFound.as_str()),
+            failed: count(FetchOutcome::Failed.as_str()),
+            failures,
+        }))
+    }
+
+    /// Set the job state; returns false if no job exists
+    pub async fn set_fetch_job_state(&self, state: FetchJobState) -> Result<bool> {
+        let result =
+            sqlx::query("UPDATE lyrics_fetch_job SET state = ?, updated_at = ? WHERE id = 1")
+                .bind(state.as_str())
+                .bind(chrono::Utc::now().to_rfc3339())
+                .execute(&self.pool)
+                .await
+                .context("Failed to update lyrics fetch job")?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Put failed tracks back in the queue; returns how many were requeued
+    pub async fn requeue_failed_fetches(&self) -> Result<u64> {
+        let result = sqlx::query(
+            "UPDATE lyrics_fetch_queue SET state = 'pending', error = NULL, updated_at = ? WHERE state = 'failed'",
+        )
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .context("Failed to requeue failed lyrics fetches")?;
+        Ok(result.rows_affected())
+    }
+
+    /// Next track still waiting to be fetched, in queue order
+    pub async fn next_pending_fetch(&self) -> Result<Option<String>> {
+        let row = sqlx::query_as::<_, (String,)>(
+            "SELECT track_id FROM lyrics_fetch_queue WHERE state = 'pending' ORDER BY rowid LIMIT 1",
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to read lyrics fetch queue")?;
+        Ok(row.map(|(track_id,)| track_id))
+    }
+
+    /// Record the outcome of a queued track and touch the job
+    pub async fn finish_fetch(
+        &self,
+        track_id: &str,
+        outcome: FetchOutcome,
+        error: Option<&str>,
+    ) -> Result<()> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE lyrics_fetch_queue SET state = ?, error = ?, updated_at = ? WHERE track_id = ?",
+        )
+        .bind(outcome.as_str())
+        .bind(error)
+        .bind(&now)
+        .bind(track_id)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to record lyrics fetch result")?;
+        sqlx::query("UPDATE lyrics_fetch_job SET updated_at = ? WHERE id = 1")
+            .bind(&now)
+            .execute(&mut *tx)
+            .await
+            .context("Failed to update lyrics fetch job")?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fetch_queue_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lyrics.db");
+
+        let db = LyricDatabase::new(&path).await.unwrap();
+        assert!(db.fetch_job_status().await.unwrap().is_none());
+        let ids: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+        db.start_fetch_job(Some("mock"), &ids).await.unwrap();
+        db.finish_fetch("a", FetchOutcome::Done, None)
+            .await
+            .unwrap();
+        db.finish_fetch("b", FetchOutcome::Failed, Some("boom"))
+            .await
+            .unwrap();
+        drop(db);
+
+        // A restarted server sees the same job and continues where it stopped
+        let db = LyricDatabase::new(&path).await.unwrap();
+        let status = db.fetch_job_status().await.unwrap().unwrap();
+        assert_eq!(status.state, FetchJobState::Running);
+        assert_eq!(status.provider.as_deref(), Some("mock"));
+        assert_eq!(
+            (status.total, status.pending, status.done, status.failed),
+            (3, 1, 1, 1)
+        );
+        assert_eq!(status.failures[0].error, "boom");
+        assert_eq!(db.next_pending_fetch().await.unwrap().as_deref(), Some("c"));
+
+        assert_eq!(db.requeue_failed_fetches().await.unwrap(), 1);
+        assert_eq!(db.next_pending_fetch().await.unwrap().as_deref(), Some("b"));
+    }
+}
@@ -7,6 +7,9 @@ use super::{Lyric, LyricFormat};
 use anyhow::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
 use std::time::Duration;
 
 /// Search query for finding lyrics online
@@ -67,6 +70,34 @@ pub struct LyricsSearchResult {
     pub duration: Option<Duration>,
     /// Match confidence score (0.0 to 1.0)
     pub confidence: f32,
+    /// What `confidence` was computed from, for providers that report it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<MatchBreakdown>,
+}
+
+/// The factors behind a search result's confidence
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchBreakdown {
+    /// The result's title contains the queried title
+    pub title_match: bool,
+    /// One of the result's artists contains the queried artist; `None` when
+    /// the query has no artist
+    pub artist_match: Option<bool>,
+    /// Result duration minus the queried duration, in seconds, when both are known
+    pub duration_delta_secs: Option<i64>,
+}
+
+/// A search result merged across providers by [`LyricsAggregator::search_all_merged`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergedSearchResult {
+    /// Provider of the highest-confidence copy, which `result.id` belongs to
+    pub provider: String,
+    /// Best copy of the result, rescored with the aggregator's own scale so
+    /// confidences are comparable across providers
+    #[serde(flatten)]
+    pub result: LyricsSearchResult,
+    /// Every provider that returned this song, best first
+    pub providers: Vec<String>,
 }
 
 /// Complete lyrics response from a provider
@@ -106,8 +137,6 @@ pub struct ProviderConfig {
     pub api_key: Option<String>,
     /// Request timeout in seconds
     pub timeout: Duration,
-    /// Whether to enable caching
-    pub enable_cache: bool,
     /// Maximum number of search results to return
     pub max_results: usize,
 }
@@ -117,12 +146,97 @@ impl Default for ProviderConfig {
         Self {
             api_key: None,
             timeout: Duration::from_secs(10),
-            enable_cache: true,
             max_results: 10,
         }
     }
 }
 
+/// What a lyrics provider can do, for clients choosing between providers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderInfo {
+    /// Provider name, as accepted by the lyrics endpoints' `provider` parameter
+    pub name: String,
+    /// Whether the provider can return synchronized (LRC) lyrics
+    pub supports_synced: bool,
+    /// Whether the provider only works with credentials
+    pub requires_auth: bool,
+    /// Whether credentials are configured for the provider
+    pub authenticated: bool,
+    /// Languages the provider's catalogue is strongest in (e.g. "zh", "en");
+    /// empty when it has no particular focus
+    pub regions: Vec<String>,
+}
+
+/// How long to wait for each lyrics provider before giving up on a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderTimeouts {
+    /// Used for providers without an override
+    pub default: Duration,
+    /// Provider name -> timeout
+    pub overrides: HashMap<String, Duration>,
+}
+
+impl Default for ProviderTimeouts {
+    fn default() -> Self {
+        Self {
+            default: Duration::from_secs(10),
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+impl ProviderTimeouts {
+    /// Override the timeout of one provider (builder pattern)
+    pub fn with_override(mut self, provider: impl Into<String>, timeout: Duration) -> Self {
+        self.overrides.insert(provider.into(), timeout);
+        self
+    }
+
+    /// Timeout that applies to `provider`
+    pub fn for_provider(&self, provider: &str) -> Duration {
+        self.overrides
+            .get(provider)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Error returned when a provider doesn't answer within its timeout
+#[derive(Debug)]
+pub struct ProviderTimedOut {
+    pub provider: String,
+    pub timeout: Duration,
+}
+
+impl std::fmt::Display for ProviderTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Provider {} did not respond within {:?}",
+            self.provider, self.timeout
+        )
+    }
+}
+
+impl std::error::Error for ProviderTimedOut {}
+
+/// Run a provider call, failing with [`ProviderTimedOut`] if it takes longer than `timeout`
+pub async fn with_provider_timeout<T>(
+    provider: &str,
+    timeout: Duration,
+    call: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::time::timeout(timeout, call)
+        .await
+        .unwrap_or_else(|_| {
+            Err(ProviderTimedOut {
+                provider: provider.to_string(),
+                timeout,
+            }
+            .into())
+        })
+}
+
 /// Main trait for lyrics providers
 ///
 /// Implement this trait to create a new lyrics source (e.g., Genius, Netease, etc.)
@@ -141,6 +255,20 @@ pub trait LyricsProvider: Send + Sync {
         false
     }
 
+    /// Describe this provider's capabilities
+    ///
+    /// The default reports no credentials and no regional focus; override it
+    /// to fill those in.
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            name: self.name().to_string(),
+            supports_synced: self.supports_synced(),
+            requires_auth: self.requires_auth(),
+            authenticated: false,
+            regions: Vec::new(),
+        }
+    }
+
     /// Search for lyrics matching the query
     /// Returns a list of possible matches sorted by relevance
     async fn search(&self, query: &LyricsQuery) -> Result<Vec<LyricsSearchResult>>;
@@ -193,25 +321,47 @@ pub trait LyricsProvider: Send + Sync {
 
 /// Aggregates multiple lyrics providers with fallback logic
 pub struct LyricsAggregator {
-    providers: Vec<Box<dyn LyricsProvider>>,
+    providers: Vec<Arc<dyn LyricsProvider>>,
+    /// Providers searched at once by `search_all`; `None` searches all of them together
+    concurrency: Option<usize>,
+    /// How long each provider gets to answer a search or fetch
+    timeouts: ProviderTimeouts,
 }
 
 impl LyricsAggregator {
+    /// Results whose durations differ by at most this much are treated as the same song
+    const MERGE_DURATION_TOLERANCE: Duration = Duration::from_secs(3);
+
     /// Create a new empty aggregator
     pub fn new() -> Self {
         Self {
             providers: Vec::new(),
+            concurrency: None,
+            timeouts: ProviderTimeouts::default(),
         }
     }
 
     /// Add a provider to the aggregator (builder pattern)
-    pub fn add_provider(mut self, provider: Box<dyn LyricsProvider>) -> Self {
+    pub fn add_provider(mut self, provider: Arc<dyn LyricsProvider>) -> Self {
         self.providers.push(provider);
         self
     }
 
+    /// Limit how many providers `search_all` queries at once, to go easy on
+    /// rate-limited upstreams (at least one)
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency.max(1));
+        self
+    }
+
+    /// Set how long each provider gets before it is reported as failed
+    pub fn with_timeouts(mut self, timeouts: ProviderTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
     /// Add a provider by reference
-    pub fn register(&mut self, provider: Box<dyn LyricsProvider>) {
+    pub fn register(&mut self, provider: Arc<dyn LyricsProvider>) {
         self.providers.push(provider);
     }
 
@@ -228,7 +378,10 @@ impl LyricsAggregator {
         for provider in &self.providers {
             tracing::debug!("Trying provider: {}", provider.name());
 
-            match provider.search_and_fetch(query).await {
+            let timeout = self.timeouts.for_provider(provider.name());
+            match with_provider_timeout(provider.name(), timeout, provider.search_and_fetch(query))
+                .await
+            {
                 Ok(Some(lyrics)) => {
                     tracing::info!("✓ Found lyrics from provider: {}", provider.name());
                     return Ok(Some(lyrics));
@@ -260,28 +413,176 @@ impl LyricsAggregator {
             .find(|p| p.name() == provider_name)
             .ok_or_else(|| anyhow::anyhow!("Provider '{}' not found", provider_name))?;
 
-        provider.search_and_fetch(query).await
+        let timeout = self.timeouts.for_provider(provider.name());
+        with_provider_timeout(provider.name(), timeout, provider.search_and_fetch(query)).await
     }
 
-    /// Search all providers in parallel and return all results
+    /// Search all providers in parallel and return all results, in provider order
     ///
-    /// Useful for showing users multiple options to choose from.
+    /// Useful for showing users multiple options to choose from. At most
+    /// `with_concurrency` providers are queried at once, and a provider that
+    /// doesn't answer within its timeout is reported as a [`ProviderTimedOut`]
+    /// error so the others' results are still returned.
     pub async fn search_all(
         &self,
         query: &LyricsQuery,
     ) -> Vec<(String, Result<Vec<LyricsSearchResult>>)> {
-        use futures::future::join_all;
+        use futures::stream::{self, StreamExt};
 
-        let futures = self.providers.iter().map(|provider| {
-            let provider_name = provider.name().to_string();
-            let query = query.clone();
-            async move {
-                let result = provider.search(&query).await;
-                (provider_name, result)
+        let concurrency = self.concurrency.unwrap_or(self.providers.len()).max(1);
+        // Built up front rather than in `StreamExt::map`, which would make the
+        // returned future not `Send` enough for axum handlers
+        let searches: Vec<_> = self
+            .providers
+            .iter()
+            .enumerate()
+            .map(|(index, provider)| {
+                let provider = Arc::clone(provider);
+                let timeout = self.timeouts.for_provider(provider.name());
+                async move {
+                    let provider_name = provider.name().to_string();
+                    let result =
+                        with_provider_timeout(&provider_name, timeout, provider.search(query))
+                            .await;
+                    (index, provider_name, result)
+                }
+            })
+            .collect();
+        let mut results: Vec<_> = stream::iter(searches)
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+
+        results.sort_by_key(|(index, _, _)| *index);
+        results
+            .into_iter()
+            .map(|(_, name, result)| (name, result))
+            .collect()
+    }
+
+    /// Search all providers and return one deduplicated, ranked list
+    ///
+    /// Confidence scales differ between providers, so every result is rescored
+    /// against the query with [`Self::merged_confidence`] instead of keeping
+    /// the provider's own score. Results below `min_confidence` are dropped, and
+    /// near-identical results (same title and artist, durations within a few
+    /// seconds) are collapsed into the copy with the highest confidence; ties go
+    /// to the provider registered first. Failing providers are logged and skipped.
+    pub async fn search_all_merged(
+        &self,
+        query: &LyricsQuery,
+        min_confidence: f32,
+    ) -> Vec<MergedSearchResult> {
+        let mut candidates: Vec<(String, LyricsSearchResult)> = Vec::new();
+
+        for (provider_name, results) in self.search_all(query).await {
+            let results = match results {
+                Ok(results) => results,
+                Err(e) => {
+                    tracing::warn!("Provider {} search failed: {:?}", provider_name, e);
+                    continue;
+                }
+            };
+
+            candidates.extend(
+                results
+                    .into_iter()
+                    .map(|mut result| {
+                        let breakdown = Self::match_breakdown(query, &result);
+                        result.confidence = Self::merged_confidence(&breakdown);
+                        result.breakdown = Some(breakdown);
+                        result
+                    })
+                    .filter(|r| r.confidence >= min_confidence)
+                    .map(|r| (provider_name.clone(), r)),
+            );
+        }
+
+        // Highest confidence first, so the first copy of each song is the one
+        // kept; the sort is stable, so equal scores keep provider order
+        candidates.sort_by(|a, b| b.1.confidence.total_cmp(&a.1.confidence));
+
+        let mut merged: Vec<MergedSearchResult> = Vec::new();
+        for (provider, result) in candidates {
+            match merged
+                .iter_mut()
+                .find(|m| Self::is_same_song(&m.result, &result))
+            {
+                Some(existing) => {
+                    if !existing.providers.contains(&provider) {
+                        existing.providers.push(provider);
+                    }
+                }
+                None => merged.push(MergedSearchResult {
+                    providers: vec![provider.clone()],
+                    provider,
+                    result,
+                }),
             }
-        });
+        }
 
-        join_all(futures).await
+        merged
+    }
+
+    /// Lowercase a title or artist and drop punctuation/whitespace for duplicate detection
+    fn merge_key(value: &str) -> String {
+        value
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+
+    /// Compare a result with the query the same way for every provider
+    fn match_breakdown(query: &LyricsQuery, result: &LyricsSearchResult) -> MatchBreakdown {
+        let title = Self::merge_key(&query.title);
+        MatchBreakdown {
+            title_match: Self::merge_key(&result.title).contains(&title),
+            artist_match: query.artist.as_ref().map(|artist| {
+                let (queried, found) = (Self::merge_key(artist), Self::merge_key(&result.artist));
+                found.contains(&queried) || queried.contains(&found)
+            }),
+            duration_delta_secs: query
+                .duration
+                .zip(result.duration)
+                .map(|(queried, found)| found.as_secs() as i64 - queried.as_secs() as i64),
+        }
+    }
+
+    /// Confidence on the scale shared by all providers in
+    /// [`Self::search_all_merged`]: 0.6 for a title match, 0.3 for an artist
+    /// match and 0.1 for a duration within a few seconds of the query's. An
+    /// artist or duration the query (or the result) doesn't have counts as a match.
+    fn merged_confidence(breakdown: &MatchBreakdown) -> f32 {
+        // Summed in tenths so a full match is exactly 1.0
+        let mut tenths = 0u8;
+        if breakdown.title_match {
+            tenths += 6;
+        }
+        if breakdown.artist_match != Some(false) {
+            tenths += 3;
+        }
+        let tolerance = Self::MERGE_DURATION_TOLERANCE.as_secs();
+        if breakdown
+            .duration_delta_secs
+            .is_none_or(|delta| delta.unsigned_abs() <= tolerance)
+        {
+            tenths += 1;
+        }
+        f32::from(tenths) / 10.0
+    }
+
+    /// Whether two results from different providers describe the same song
+    fn is_same_song(a: &LyricsSearchResult, b: &LyricsSearchResult) -> bool {
+        if Self::merge_key(&a.title) != Self::merge_key(&b.title)
+            || Self::merge_key(&a.artist) != Self::merge_key(&b.artist)
+        {
+            return false;
+        }
+        match (a.duration, b.duration) {
+            (Some(a), Some(b)) => a.abs_diff(b) <= Self::MERGE_DURATION_TOLERANCE,
+            _ => true,
+        }
     }
 
     /// Check health of all providers
@@ -350,6 +651,7 @@ mod tests {
                     album: query.album.clone(),
                     duration: query.duration,
                     confidence: 0.9,
+                    breakdown: None,
                 }])
             } else {
                 anyhow::bail!("Mock failure")
@@ -375,12 +677,12 @@ mod tests {
     #[tokio::test]
     async fn test_aggregator_fallback() {
         let aggregator = LyricsAggregator::new()
-            .add_provider(Box::new(MockProvider {
+            .add_provider(Arc::new(MockProvider {
                 name: "failing".to_string(),
                 should_succeed: false,
                 supports_lrc: false,
             }))
-            .add_provider(Box::new(MockProvider {
+            .add_provider(Arc::new(MockProvider {
                 name: "working".to_string(),
                 should_succeed: true,
                 supports_lrc: true,
@@ -395,6 +697,168 @@ mod tests {
         assert_eq!(lyrics.format, LyricFormat::Lrc);
     }
 
+    /// Provider returning a fixed list of (title, artist, duration secs, confidence)
+    struct FixedProvider {
+        name: &'static str,
+        results: Vec<(&'static str, &'static str, u64, f32)>,
+    }
+
+    #[async_trait]
+    impl LyricsProvider for FixedProvider {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn search(&self, _query: &LyricsQuery) -> Result<Vec<LyricsSearchResult>> {
+            Ok(self
+                .results
+                .iter()
+                .enumerate()
+                .map(
+                    |(i, (title, artist, secs, confidence))| LyricsSearchResult {
+                        id: format!("{}-{}", self.name, i),
+                        title: title.to_string(),
+                        artist: artist.to_string(),
+                        album: None,
+                        duration: Some(Duration::from_secs(*secs)),
+                        confidence: *confidence,
+                        breakdown: None,
+                    },
+                )
+                .collect())
+        }
+
+        async fn fetch(&self, _result_id: &str) -> Result<LyricsResponse> {
+            anyhow::bail!("not used")
+        }
+    }
+
+    /// Provider that takes `delay` to answer every search
+    struct SlowProvider {
+        name: &'static str,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl LyricsProvider for SlowProvider {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn search(&self, query: &LyricsQuery) -> Result<Vec<LyricsSearchResult>> {
+            tokio::time::sleep(self.delay).await;
+            FixedProvider {
+                name: self.name,
+                results: vec![("Song", "Artist", 200, 0.9)],
+            }
+            .search(query)
+            .await
+        }
+
+        async fn fetch(&self, _result_id: &str) -> Result<LyricsResponse> {
+            anyhow::bail!("not used")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_all_times_out_slow_providers() {
+        let slow = |name, millis| {
+            Arc::new(SlowProvider {
+                name,
+                delay: Duration::from_millis(millis),
+            })
+        };
+        let aggregator = LyricsAggregator::new()
+            .add_provider(slow("hung", 60_000))
+            .add_provider(slow("impatient", 100))
+            .add_provider(slow("quick", 20))
+            .add_provider(slow("quicker", 0))
+            .with_concurrency(2)
+            .with_timeouts(
+                ProviderTimeouts {
+                    default: Duration::from_millis(500),
+                    overrides: HashMap::new(),
+                }
+                .with_override("impatient", Duration::from_millis(10)),
+            );
+
+        let started = std::time::Instant::now();
+        let results = aggregator.search_all(&LyricsQuery::new("Song")).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // Provider order is kept regardless of completion order
+        let names: Vec<&str> = results.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["hung", "impatient", "quick", "quicker"]);
+        for (_, result) in &results[..2] {
+            let error = result.as_ref().unwrap_err();
+            assert!(
+                error.downcast_ref::<ProviderTimedOut>().is_some(),
+                "{}",
+                error
+            );
+        }
+        assert_eq!(results[2].1.as_ref().unwrap().len(), 1);
+        assert_eq!(results[3].1.as_ref().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_all_merged_rescores_and_dedups() {
+        let aggregator = LyricsAggregator::new()
+            .add_provider(Arc::new(FixedProvider {
+                name: "strict",
+                results: vec![
+                    ("Norwegian Wood", "The Beatles", 125, 0.5),
+                    ("Norwegian Wood (Live)", "The Beatles", 140, 0.25),
+                ],
+            }))
+            .add_provider(Arc::new(FixedProvider {
+                name: "generous",
+                results: vec![
+                    ("Norwegian wood", "the beatles", 126, 0.95),
+                    ("Norwegian Wood", "The Beatles", 300, 0.9),
+                    ("Something", "Someone", 200, 0.1),
+                ],
+            }))
+            // Only a weak match, which must not be ranked as if it were the best
+            .add_provider(Arc::new(FixedProvider {
+                name: "weak",
+                results: vec![("Something Else", "Nobody", 125, 0.2)],
+            }))
+            .add_provider(Arc::new(MockProvider {
+                name: "failing".to_string(),
+                should_succeed: false,
+                supports_lrc: false,
+            }));
+
+        let query = LyricsQuery::new("Norwegian Wood")
+            .with_artist("The Beatles")
+            .with_duration(Duration::from_secs(125));
+        let merged = aggregator.search_all_merged(&query, 0.5).await;
+
+        // Both full matches score 1.0 whatever the providers reported; "strict" wins the tie by order
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[0].provider, "strict");
+        assert_eq!(merged[0].result.id, "strict-0");
+        assert_eq!(merged[0].result.confidence, 1.0);
+        assert_eq!(merged[0].providers, ["strict", "generous"]);
+        assert_eq!(
+            merged[0].result.breakdown,
+            Some(MatchBreakdown {
+                title_match: true,
+                artist_match: Some(true),
+                duration_delta_secs: Some(0),
+            })
+        );
+
+        // The duration is off, so these lose the duration share of the score
+        assert_eq!(merged[1].result.id, "strict-1");
+        assert_eq!(merged[1].result.confidence, 0.9);
+        // Same title and artist, but too far off to be the same recording
+        assert_eq!(merged[2].result.id, "generous-1");
+        assert_eq!(merged[2].providers, ["generous"]);
+        assert_eq!(merged[2].result.confidence, 0.9);
+    }
+
     #[tokio::test]
     async fn test_query_builder() {
         let query = LyricsQuery::new("Song Title")
@@ -411,12 +875,12 @@ mod tests {
     #[tokio::test]
     async fn test_provider_names() {
         let aggregator = LyricsAggregator::new()
-            .add_provider(Box::new(MockProvider {
+            .add_provider(Arc::new(MockProvider {
                 name: "provider1".to_string(),
                 should_succeed: true,
                 supports_lrc: false,
             }))
-            .add_provider(Box::new(MockProvider {
+            .add_provider(Arc::new(MockProvider {
                 name: "provider2".to_string(),
                 should_succeed: true,
                 supports_lrc: true,
@@ -0,0 +1,402 @@
+//! Genius lyrics provider
+//!
+//! Genius only exposes song metadata through its API, so lyrics are read from
+//! the state embedded in the song page (`window.__PRELOADED_STATE__`).
+
+use super::LyricFormat;
+use super::fetcher::{
+    LyricsMetadata, LyricsProvider, LyricsQuery, LyricsResponse, LyricsSearchResult,
+    MatchBreakdown, ProviderConfig, ProviderInfo,
+};
+use super::music_search_provider::match_confidence;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use music_search_rs::TitleNormalizer;
+use regex::Regex;
+use serde::Deserialize;
+use std::sync::LazyLock;
+
+const GENIUS_API_BASE: &str = "https://api.genius.com";
+
+/// Start of the page script holding the embedded state as a JS string literal
+const PRELOADED_STATE_MARKER: &str = "window.__PRELOADED_STATE__ = JSON.parse('";
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    response: T,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    hits: Vec<SearchHit>,
+}
+
+#[derive(Deserialize)]
+struct SearchHit {
+    #[serde(rename = "type")]
+    kind: String,
+    result: Song,
+}
+
+#[derive(Deserialize)]
+struct SongResponse {
+    song: Song,
+}
+
+#[derive(Deserialize)]
+struct Song {
+    id: u64,
+    title: String,
+    primary_artist: Artist,
+    url: String,
+    #[serde(default)]
+    album: Option<Album>,
+}
+
+#[derive(Deserialize)]
+struct Artist {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Album {
+    name: String,
+}
+
+/// Provider for Genius (genius.com); plain-text lyrics only
+pub struct GeniusLyricsProvider {
+    client: reqwest::Client,
+    access_token: String,
+    max_results: usize,
+    normalizer: TitleNormalizer,
+}
+
+impl GeniusLyricsProvider {
+    /// Create a provider authenticated with the API token in `config.api_key`
+    pub fn new(config: ProviderConfig) -> Result<Self> {
+        let access_token = config
+            .api_key
+            .filter(|token| !token.trim().is_empty())
+            .context("Genius requires an API access token")?;
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .context("Failed to create Genius HTTP client")?;
+        Ok(Self {
+            client,
+            access_token,
+            max_results: config.max_results,
+            normalizer: TitleNormalizer::default(),
+        })
+    }
+
+    /// Set how titles and artists are cleaned before searching and scoring
+    pub fn with_normalizer(mut self, normalizer: TitleNormalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// GET an API endpoint and unwrap its `response` object
+    async fn api_get<T: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        params: &[(&str, &str)],
+    ) -> Result<T> {
+        let response = self
+            .client
+            .get(format!("{}{}", GENIUS_API_BASE, path))
+            .bearer_auth(&self.access_token)
+            .query(params)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Genius API request {} failed", path))?;
+        let body: ApiResponse<T> = response
+            .json()
+            .await
+            .with_context(|| format!("Unexpected Genius API response for {}", path))?;
+        Ok(body.response)
+    }
+
+    fn match_breakdown(&self, query: &LyricsQuery, song: &Song) -> MatchBreakdown {
+        let normalize = |value: &str| self.normalizer.normalize(value).to_lowercase();
+        MatchBreakdown {
+            title_match: normalize(&song.title).contains(&normalize(&query.title)),
+            artist_match: query
+                .artist
+                .as_ref()
+                .map(|artist| normalize(&song.primary_artist.name).contains(&normalize(artist))),
+            duration_delta_secs: None,
+        }
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for GeniusLyricsProvider {
+    fn name(&self) -> &str {
+        "genius"
+    }
+
+    fn supports_synced(&self) -> bool {
+        false // Genius only has plain text lyrics
+    }
+
+    fn requires_auth(&self) -> bool {
+        true
+    }
+
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            name: self.name().to_string(),
+            supports_synced: self.supports_synced(),
+            requires_auth: self.requires_auth(),
+            authenticated: true, // Can't be created without a token
+            regions: vec!["en".to_string()],
+        }
+    }
+
+    async fn search(&self, query: &LyricsQuery) -> Result<Vec<LyricsSearchResult>> {
+        let title = self.normalizer.normalize(&query.title);
+        let search_query = match &query.artist {
+            Some(artist) => format!("{} {}", title, self.normalizer.normalize(artist)),
+            None => title,
+        };
+
+        tracing::debug!("Genius search query: {}", search_query);
+
+        let search: SearchResponse = self.api_get("/search", &[("q", &search_query)]).await?;
+
+        let mut results: Vec<LyricsSearchResult> = search
+            .hits
+            .into_iter()
+            .filter(|hit| hit.kind == "song")
+            .take(self.max_results)
+            .map(|hit| {
+                let breakdown = self.match_breakdown(query, &hit.result);
+                LyricsSearchResult {
+                    id: hit.result.id.to_string(),
+                    title: hit.result.title,
+                    artist: hit.result.primary_artist.name,
+                    album: hit.result.album.map(|album| album.name),
+                    duration: None,
+                    confidence: match_confidence(&breakdown),
+                    breakdown: Some(breakdown),
+                }
+            })
+            .collect();
+
+        results.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap());
+
+        tracing::debug!("Found {} results from Genius", results.len());
+
+        Ok(results)
+    }
+
+    async fn fetch(&self, result_id: &str) -> Result<LyricsResponse> {
+        // Only a numeric ID may go into the API path, not e.g. `1/../../account`
+        let song_id: u64 = result_id
+            .parse()
+            .with_context(|| format!("Invalid Genius song ID: {:?}", result_id))?;
+        let song: SongResponse = self.api_get(&format!("/songs/{}", song_id), &[]).await?;
+        let url = song.song.url;
+
+        let page = self
+            .client
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to load Genius song page {}", url))?
+            .text()
+            .await?;
+
+        let content = extract_lyrics(&page)
+            .with_context(|| format!("Could not read lyrics from Genius page {}", url))?;
+
+        Ok(LyricsResponse {
+            content,
+            format: LyricFormat::Plain,
+            language: None,
+            source: "genius".to_string(),
+            url: Some(url),
+            metadata: LyricsMetadata {
+                contributor: Some("Genius".to_string()),
+                source_updated_at: None,
+                copyright: None,
+                notes: Some(format!("Song ID: {}", song_id)),
+            },
+        })
+    }
+}
+
+/// Pull the plain-text lyrics out of a Genius song page
+///
+/// Fails instead of guessing when the page no longer has the expected
+/// embedded state, so a Genius redesign doesn't store markup as lyrics.
+fn extract_lyrics(page: &str) -> Result<String> {
+    let start = page
+        .find(PRELOADED_STATE_MARKER)
+        .context("Genius page structure changed: no embedded page state")?
+        + PRELOADED_STATE_MARKER.len();
+    let state_json = unescape_js_string(&page[start..])
+        .context("Genius page structure changed: unterminated page state")?;
+    let state: serde_json::Value = serde_json::from_str(&state_json)
+        .context("Genius page structure changed: page state is not JSON")?;
+
+    let html = state
+        .pointer("/songPage/lyricsData/body/html")
+        .and_then(|html| html.as_str())
+        .context("Genius page structure changed: no lyrics in page state")?;
+
+    let lyrics = html_to_text(html);
+    if lyrics.is_empty() {
+        anyhow::bail!("Genius has no lyrics for this song");
+    }
+    Ok(lyrics)
+}
+
+/// Decode a single-quoted JS string literal up to its closing quote; `None`
+/// if the quote is missing
+fn unescape_js_string(literal: &str) -> Option<String> {
+    let mut out = String::with_capacity(literal.len());
+    let mut chars = literal.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => return Some(out),
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'b' => out.push('\u{8}'),
+                'f' => out.push('\u{c}'),
+                'v' => out.push('\u{b}'),
+                '0' => out.push('\0'),
+                'x' => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    out.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    out.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
+                '\n' => {} // Line continuation
+                other => out.push(other),
+            },
+            _ => out.push(c),
+        }
+    }
+    None
+}
+
+static LINE_BREAK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)<br\s*/?>").unwrap());
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").unwrap());
+static ENTITY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap());
+
+/// Convert the lyrics HTML to text: `<br>` becomes a newline, other tags are
+/// dropped and character references are decoded
+fn html_to_text(html: &str) -> String {
+    let text = LINE_BREAK.replace_all(html, "\n");
+    let text = TAG.replace_all(&text, "");
+    let text = ENTITY.replace_all(&text, |caps: &regex::Captures| {
+        let entity = &caps[1];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(|dec| dec.parse()))
+                .and_then(|code| code.ok())
+                .and_then(char::from_u32),
+        };
+        decoded.map_or_else(|| caps[0].to_string(), String::from)
+    });
+    text.trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_with_state(state: &serde_json::Value) -> String {
+        let json = state.to_string().replace('\\', "\\\\").replace('\'', "\\'");
+        format!(
+            "<html><script>{}{}');</script></html>",
+            PRELOADED_STATE_MARKER, json
+        )
+    }
+
+    #[test]
+    fn test_extract_lyrics() {
+        let state = serde_json::json!({
+            "songPage": {
+                "lyricsData": {
+                    "body": {
+                        "html": "<p>[Verse 1]<br>I'm <a href=\"/x\">\"here\"</a><br/>Rock &amp; roll &#x27;n&#39; more</p>"
+                    }
+                }
+            }
+        });
+
+        let lyrics = extract_lyrics(&page_with_state(&state)).unwrap();
+        assert_eq!(lyrics, "[Verse 1]\nI'm \"here\"\nRock & roll 'n' more");
+    }
+
+    #[test]
+    fn test_extract_lyrics_rejects_unknown_page() {
+        let error = extract_lyrics("<html><div class=\"Lyrics\">la la</div></html>").unwrap_err();
+        assert!(error.to_string().contains("structure changed"));
+
+        let state = serde_json::json!({ "songPage": { "title": "Song" } });
+        let error = extract_lyrics(&page_with_state(&state)).unwrap_err();
+        assert!(error.to_string().contains("no lyrics"));
+    }
+
+    #[test]
+    fn test_unescape_js_string() {
+        assert_eq!(
+            unescape_js_string(r#"a\'b\\n\x41é'); rest"#).as_deref(),
+            Some("a'b\\nAé")
+        );
+        assert_eq!(unescape_js_string("unterminated"), None);
+    }
+
+    #[test]
+    fn test_requires_token() {
+        assert!(GeniusLyricsProvider::new(ProviderConfig::default()).is_err());
+
+        let provider = GeniusLyricsProvider::new(ProviderConfig {
+            api_key: Some("token".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let info = provider.info();
+        assert!(info.requires_auth && info.authenticated);
+        assert!(!info.supports_synced);
+        assert_eq!(info.regions, ["en"]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rejects_non_numeric_id() {
+        let provider = GeniusLyricsProvider::new(ProviderConfig {
+            api_key: Some("token".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        // Rejected before any request is made
+        for id in ["1/../../account", "abc", ""] {
+            let error = provider.fetch(id).await.unwrap_err();
+            assert!(
+                error.to_string().contains("Invalid Genius song ID"),
+                "{}",
+                id
+            );
+        }
+    }
+}
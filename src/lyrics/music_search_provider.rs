@@ -1,22 +1,101 @@
 use super::LyricFormat;
 use super::fetcher::{
     LyricsMetadata, LyricsProvider, LyricsQuery, LyricsResponse, LyricsSearchResult,
+    MatchBreakdown, ProviderInfo,
 };
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use music_search_rs::{MusicApi, NetEaseMusicApi, QQMusicApi, SearchType};
+use music_search_rs::{
+    LyricVo, MusicApi, NetEaseMusicApi, QQMusicApi, SearchType, TitleNormalizer,
+};
 use std::time::Duration;
 
+/// Search keywords for a query: its title followed by its artist, both normalized
+fn search_query(query: &LyricsQuery, normalizer: &TitleNormalizer) -> String {
+    let title = normalizer.normalize(&query.title);
+    match &query.artist {
+        Some(artist) => format!("{} {}", title, normalizer.normalize(artist)),
+        None => title,
+    }
+}
+
+/// Compare a search hit with the query, using normalized names so "(feat. X)"
+/// or "[Remaster]" on either side doesn't prevent a match
+fn match_breakdown(
+    query: &LyricsQuery,
+    title: &str,
+    artists: &[String],
+    duration: Duration,
+    normalizer: &TitleNormalizer,
+) -> MatchBreakdown {
+    let normalize = |value: &str| normalizer.normalize(value).to_lowercase();
+    let artist_match = query.artist.as_ref().map(|query_artist| {
+        let query_artist = normalize(query_artist);
+        artists
+            .iter()
+            .any(|artist| normalize(artist).contains(&query_artist))
+    });
+
+    MatchBreakdown {
+        title_match: normalize(title).contains(&normalize(&query.title)),
+        artist_match,
+        duration_delta_secs: query
+            .duration
+            .map(|wanted| duration.as_secs() as i64 - wanted.as_secs() as i64),
+    }
+}
+
+/// How likely a search hit is the queried song. Duration is reported in the
+/// breakdown but not scored.
+pub(super) fn match_confidence(breakdown: &MatchBreakdown) -> f32 {
+    let mut confidence: f32 = 0.5; // Base confidence
+    if breakdown.title_match {
+        confidence += 0.3;
+    }
+    if breakdown.artist_match == Some(true) {
+        confidence += 0.2;
+    }
+    confidence.min(1.0_f32)
+}
+
+/// Pick the lyrics to serve: the original, else the translation, else the
+/// transliteration. Providers sometimes send empty strings instead of nothing,
+/// so blank lyrics count as missing. Also returns whether a translation exists.
+fn select_lyrics(lyric_data: LyricVo) -> Option<(String, bool)> {
+    let present = |lyrics: Option<String>| lyrics.filter(|l| !l.trim().is_empty());
+    let translation = present(lyric_data.translate_lyric);
+    let has_translation = translation.is_some();
+
+    present(lyric_data.lyric)
+        .or(translation)
+        .or_else(|| present(lyric_data.transliteration_lyric))
+        .map(|content| (content, has_translation))
+}
+
 /// Provider for NetEase Cloud Music (网易云音乐)
 pub struct NetEaseLyricsProvider {
     api: NetEaseMusicApi,
+    normalizer: TitleNormalizer,
+    /// Whether a login cookie was supplied
+    authenticated: bool,
 }
 
 impl NetEaseLyricsProvider {
     pub fn new(cookie: Option<String>) -> Result<Self> {
+        let authenticated = cookie.is_some();
         let api =
             NetEaseMusicApi::new(cookie).context("Failed to create NetEase Music API client")?;
-        Ok(Self { api })
+        Ok(Self {
+            api,
+            normalizer: TitleNormalizer::default(),
+            authenticated,
+        })
+    }
+
+    /// Set how titles and artists are cleaned before searching and scoring
+    pub fn with_normalizer(mut self, normalizer: TitleNormalizer) -> Self {
+        self.normalizer = normalizer;
+        self
     }
 }
 
@@ -34,13 +113,18 @@ impl LyricsProvider for NetEaseLyricsProvider {
         false // Can work without authentication, but some features require it
     }
 
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            name: self.name().to_string(),
+            supports_synced: self.supports_synced(),
+            requires_auth: self.requires_auth(),
+            authenticated: self.authenticated,
+            regions: vec!["zh".to_string()],
+        }
+    }
+
     async fn search(&self, query: &LyricsQuery) -> Result<Vec<LyricsSearchResult>> {
-        // Build search query from title and artist
-        let search_query = if let Some(artist) = &query.artist {
-            format!("{} {}", query.title, artist)
-        } else {
-            query.title.clone()
-        };
+        let search_query = search_query(query, &self.normalizer);
 
         tracing::debug!("NetEase search query: {}", search_query);
 
@@ -59,35 +143,23 @@ impl LyricsProvider for NetEaseLyricsProvider {
             .song_vos
             .into_iter()
             .map(|song| {
-                // Calculate confidence based on title and artist match
-                let mut confidence: f32 = 0.5; // Base confidence
-
-                // Increase confidence for title match
-                if song
-                    .title
-                    .to_lowercase()
-                    .contains(&query.title.to_lowercase())
-                {
-                    confidence += 0.3;
-                }
-
-                // Increase confidence for artist match
-                if let Some(query_artist) = &query.artist {
-                    for artist in &song.author_name {
-                        if artist.to_lowercase().contains(&query_artist.to_lowercase()) {
-                            confidence += 0.2;
-                            break;
-                        }
-                    }
-                }
+                let duration = Duration::from_millis(song.duration as u64);
+                let breakdown = match_breakdown(
+                    query,
+                    &song.title,
+                    &song.author_name,
+                    duration,
+                    &self.normalizer,
+                );
 
                 LyricsSearchResult {
                     id: song.display_id,
                     title: song.title,
                     artist: song.author_name.join(", "),
                     album: Some(song.album_name),
-                    duration: Some(Duration::from_millis(song.duration as u64)),
-                    confidence: confidence.min(1.0_f32),
+                    duration: Some(duration),
+                    confidence: match_confidence(&breakdown),
+                    breakdown: Some(breakdown),
                 }
             })
             .collect();
@@ -100,7 +172,7 @@ impl LyricsProvider for NetEaseLyricsProvider {
         tracing::debug!("Fetching NetEase lyrics for ID: {}", result_id);
 
         // Use the MusicApi trait method which returns ResultVo<LyricVo>
-        let result: music_search_rs::ResultVo<music_search_rs::LyricVo> =
+        let result: music_search_rs::ResultVo<LyricVo> =
             <NetEaseMusicApi as MusicApi>::get_lyric(&self.api, "", result_id, false).await?;
 
         if !result.success {
@@ -111,19 +183,14 @@ impl LyricsProvider for NetEaseLyricsProvider {
         }
 
         let lyric_data = result.data.context("No lyrics data returned")?;
-
-        // Prefer original lyrics, fall back to translated or transliteration
-        let content = lyric_data
-            .lyric
-            .or(lyric_data.translate_lyric.clone())
-            .or(lyric_data.transliteration_lyric.clone())
-            .context("No lyrics content available")?;
+        let (content, has_translation) =
+            select_lyrics(lyric_data).context("No lyrics content available")?;
 
         // Detect format from content (will detect plain, lrc, or lrc_word)
         let format = LyricFormat::detect_from_content(&content);
 
         // Determine language based on available translations
-        let language = if lyric_data.translate_lyric.is_some() {
+        let language = if has_translation {
             Some("zh".to_string()) // Has translation, likely Chinese
         } else {
             None
@@ -139,7 +206,7 @@ impl LyricsProvider for NetEaseLyricsProvider {
                 contributor: None,
                 source_updated_at: None,
                 copyright: Some("NetEase Cloud Music".to_string()),
-                notes: if lyric_data.translate_lyric.is_some() {
+                notes: if has_translation {
                     Some("Has translated lyrics available".to_string())
                 } else {
                     None
@@ -152,12 +219,26 @@ impl LyricsProvider for NetEaseLyricsProvider {
 /// Provider for QQ Music (QQ音乐)
 pub struct QQMusicLyricsProvider {
     api: QQMusicApi,
+    normalizer: TitleNormalizer,
+    /// Whether a login cookie was supplied
+    authenticated: bool,
 }
 
 impl QQMusicLyricsProvider {
     pub fn new(cookie: Option<String>) -> Result<Self> {
+        let authenticated = cookie.is_some();
         let api = QQMusicApi::new(cookie).context("Failed to create QQ Music API client")?;
-        Ok(Self { api })
+        Ok(Self {
+            api,
+            normalizer: TitleNormalizer::default(),
+            authenticated,
+        })
+    }
+
+    /// Set how titles and artists are cleaned before searching and scoring
+    pub fn with_normalizer(mut self, normalizer: TitleNormalizer) -> Self {
+        self.normalizer = normalizer;
+        self
     }
 }
 
@@ -175,13 +256,18 @@ impl LyricsProvider for QQMusicLyricsProvider {
         false
     }
 
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            name: self.name().to_string(),
+            supports_synced: self.supports_synced(),
+            requires_auth: self.requires_auth(),
+            authenticated: self.authenticated,
+            regions: vec!["zh".to_string()],
+        }
+    }
+
     async fn search(&self, query: &LyricsQuery) -> Result<Vec<LyricsSearchResult>> {
-        // Build search query from title and artist
-        let search_query = if let Some(artist) = &query.artist {
-            format!("{} {}", query.title, artist)
-        } else {
-            query.title.clone()
-        };
+        let search_query = search_query(query, &self.normalizer);
 
         tracing::debug!("QQMusic search query: {}", search_query);
 
@@ -200,35 +286,23 @@ impl LyricsProvider for QQMusicLyricsProvider {
             .song_vos
             .into_iter()
             .map(|song| {
-                // Calculate confidence based on title and artist match
-                let mut confidence: f32 = 0.5; // Base confidence
-
-                // Increase confidence for title match
-                if song
-                    .title
-                    .to_lowercase()
-                    .contains(&query.title.to_lowercase())
-                {
-                    confidence += 0.3;
-                }
-
-                // Increase confidence for artist match
-                if let Some(query_artist) = &query.artist {
-                    for artist in &song.author_name {
-                        if artist.to_lowercase().contains(&query_artist.to_lowercase()) {
-                            confidence += 0.2;
-                            break;
-                        }
-                    }
-                }
+                let duration = Duration::from_millis(song.duration as u64);
+                let breakdown = match_breakdown(
+                    query,
+                    &song.title,
+                    &song.author_name,
+                    duration,
+                    &self.normalizer,
+                );
 
                 LyricsSearchResult {
                     id: song.display_id,
                     title: song.title,
                     artist: song.author_name.join(", "),
                     album: Some(song.album_name),
-                    duration: Some(Duration::from_millis(song.duration as u64)),
-                    confidence: confidence.min(1.0_f32),
+                    duration: Some(duration),
+                    confidence: match_confidence(&breakdown),
+                    breakdown: Some(breakdown),
                 }
             })
             .collect();
@@ -241,7 +315,7 @@ impl LyricsProvider for QQMusicLyricsProvider {
         tracing::debug!("Fetching QQMusic lyrics for ID: {}", result_id);
 
         // Use the MusicApi trait method which returns ResultVo<LyricVo>
-        let result: music_search_rs::ResultVo<music_search_rs::LyricVo> =
+        let result: music_search_rs::ResultVo<LyricVo> =
             <QQMusicApi as MusicApi>::get_lyric(&self.api, result_id, "", false).await?;
 
         if !result.success {
@@ -252,19 +326,14 @@ impl LyricsProvider for QQMusicLyricsProvider {
         }
 
         let lyric_data = result.data.context("No lyrics data returned")?;
-
-        // Prefer original lyrics, fall back to translated or transliteration
-        let content = lyric_data
-            .lyric
-            .or(lyric_data.translate_lyric.clone())
-            .or(lyric_data.transliteration_lyric.clone())
-            .context("No lyrics content available")?;
+        let (content, has_translation) =
+            select_lyrics(lyric_data).context("No lyrics content available")?;
 
         // Detect format from content (will detect plain, lrc, or lrc_word)
         let format = LyricFormat::detect_from_content(&content);
 
         // Determine language based on available translations
-        let language = if lyric_data.translate_lyric.is_some() {
+        let language = if has_translation {
             Some("zh".to_string()) // Has translation, likely Chinese
         } else {
             None
@@ -280,7 +349,7 @@ impl LyricsProvider for QQMusicLyricsProvider {
                 contributor: None,
                 source_updated_at: None,
                 copyright: Some("QQ Music".to_string()),
-                notes: if lyric_data.translate_lyric.is_some() {
+                notes: if has_translation {
                     Some("Has translated lyrics available".to_string())
                 } else {
                     None
@@ -289,3 +358,66 @@ impl LyricsProvider for QQMusicLyricsProvider {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lyric_vo(lyric: &str, translation: Option<&str>) -> LyricVo {
+        LyricVo {
+            search_source: music_search_rs::SearchSource::NetEaseMusic,
+            lyric: Some(lyric.to_string()),
+            translate_lyric: translation.map(String::from),
+            transliteration_lyric: None,
+        }
+    }
+
+    #[test]
+    fn test_select_lyrics_skips_empty_original() {
+        assert_eq!(
+            select_lyrics(lyric_vo("", Some("[00:01.00]Translated"))),
+            Some(("[00:01.00]Translated".to_string(), true))
+        );
+        assert_eq!(
+            select_lyrics(lyric_vo("[00:01.00]Original", Some(""))),
+            Some(("[00:01.00]Original".to_string(), false))
+        );
+        assert_eq!(select_lyrics(lyric_vo(" \n", None)), None);
+    }
+
+    #[test]
+    fn test_match_breakdown() {
+        let query = LyricsQuery::new("Song (feat. Y) [2021 Remaster]")
+            .with_artist("Artist")
+            .with_duration(Duration::from_secs(200));
+        let artists = ["Other".to_string(), "Artist ft. Y".to_string()];
+
+        let breakdown = match_breakdown(
+            &query,
+            "Song",
+            &artists,
+            Duration::from_millis(203_400),
+            &TitleNormalizer::default(),
+        );
+        assert_eq!(
+            breakdown,
+            MatchBreakdown {
+                title_match: true,
+                artist_match: Some(true),
+                duration_delta_secs: Some(3),
+            }
+        );
+        assert_eq!(match_confidence(&breakdown), 1.0);
+
+        let breakdown = match_breakdown(
+            &LyricsQuery::new("Song"),
+            "Different",
+            &artists,
+            Duration::from_secs(200),
+            &TitleNormalizer::default(),
+        );
+        assert_eq!(breakdown.artist_match, None);
+        assert_eq!(breakdown.duration_delta_secs, None);
+        assert_eq!(match_confidence(&breakdown), 0.5);
+    }
+}
@@ -109,6 +109,7 @@ impl LyricsProvider for MockLyricsProvider {
                     album: query.album.clone(),
                     duration: query.duration,
                     confidence,
+                    breakdown: None,
                 });
             }
         }
@@ -148,7 +149,7 @@ impl LyricsProvider for MockLyricsProvider {
 /// A local file-based lyrics provider
 ///
 /// Reads lyrics from a local directory structure:
-/// ```
+/// ```text
 /// lyrics/
 ///   ├── artist1/
 ///   │   ├── song1.lrc
@@ -224,6 +225,7 @@ impl LocalLyricsProvider {
                                 } else {
                                     0.6
                                 },
+                                breakdown: None,
                             });
                         }
                     }
@@ -254,7 +256,17 @@ impl LyricsProvider for LocalLyricsProvider {
         use tokio::fs;
 
         let path = Path::new(result_id);
-        let content = fs::read_to_string(path).await?;
+
+        // Result ids are file paths, so refuse anything outside the lyrics root
+        let root = fs::canonicalize(&self.root_path).await?;
+        let resolved = fs::canonicalize(path)
+            .await
+            .map_err(|_| anyhow::anyhow!("Lyrics not found: {}", result_id))?;
+        if !resolved.starts_with(&root) {
+            anyhow::bail!("Lyrics path outside of lyrics directory: {}", result_id);
+        }
+
+        let content = fs::read_to_string(&resolved).await?;
 
         let format = if path.extension().and_then(|e| e.to_str()) == Some("lrc") {
             LyricFormat::Lrc
@@ -312,6 +324,25 @@ mod tests {
         assert!(lyrics.content.contains("Line 1"));
     }
 
+    #[tokio::test]
+    async fn test_local_provider_rejects_paths_outside_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("lyrics");
+        std::fs::create_dir_all(root.join("Artist")).unwrap();
+        std::fs::write(root.join("Artist/Song.lrc"), "[00:00.00]Hello").unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+
+        let provider = LocalLyricsProvider::new(&root);
+        let results = provider.search(&LyricsQuery::new("Song")).await.unwrap();
+        assert_eq!(results.len(), 1);
+
+        let lyrics = provider.fetch(&results[0].id).await.unwrap();
+        assert_eq!(lyrics.format, LyricFormat::Lrc);
+
+        let outside = root.join("../secret.txt");
+        assert!(provider.fetch(&outside.to_string_lossy()).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_similarity() {
         assert_eq!(MockLyricsProvider::similarity("test", "test"), 1.0);
@@ -1,18 +1,26 @@
 mod audio;
+mod cover;
+mod cue;
+mod error;
 mod library;
 mod lyrics;
 mod playlist;
 mod server;
 mod stats;
+mod transcode;
+mod watch;
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use library::MusicLibrary;
+use library::{LibraryConfig, MusicLibrary, UntaggedGrouping};
 use lyrics::LyricDatabase;
 use playlist::PlaylistDatabase;
 use stats::StatsDatabase;
 use std::path::PathBuf;
 
+/// Longest `--history-retention-days` accepted: 1000 years
+const MAX_HISTORY_RETENTION_DAYS: u64 = 365_000;
+
 #[derive(Parser)]
 #[command(name = "music-station")]
 #[command(about = "Music Station Server", long_about = None)]
@@ -24,6 +32,213 @@ struct Cli {
     /// Port to listen on
     #[arg(short, long, default_value = "3000")]
     port: u16,
+
+    /// Album name shown for tracks without an album tag
+    #[arg(long, default_value = "Unknown Album")]
+    unknown_album_label: String,
+
+    /// Artist name shown for albums without an artist tag
+    #[arg(long, default_value = "Unknown Artist")]
+    unknown_artist_label: String,
+
+    /// Artist name shown for compilation albums
+    #[arg(long, default_value = "Various Artists")]
+    various_artists_label: String,
+
+    /// Group tracks without an album tag by their parent folder name
+    #[arg(long)]
+    group_untagged_by_folder: bool,
+
+    /// Ignore case when looking up albums and artists by name
+    #[arg(long)]
+    case_insensitive_lookup: bool,
+
+    /// File or directory name pattern to skip during scan (`*` and `?` wildcards).
+    /// May be repeated; replaces the default list (dot-entries and Synology
+    /// `@eaDir` folders) when given.
+    #[arg(long = "scan-exclude", value_name = "PATTERN")]
+    scan_exclude: Vec<String>,
+
+    /// Only scan files with this extension (e.g. `flac`). May be repeated; by
+    /// default every format the server can read is scanned.
+    #[arg(long = "scan-extension", value_name = "EXT")]
+    scan_extensions: Vec<String>,
+
+    /// Parse up to this many files of a directory at once during scans
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..=64))]
+    scan_concurrency: u64,
+
+    /// Don't use folder images (`folder.jpg`, `cover.png`, ...) for tracks and
+    /// albums without embedded cover art
+    #[arg(long)]
+    no_cover_fallback: bool,
+
+    /// Keep audio files that fail to parse (e.g. empty or half-copied) as tracks
+    /// with a `parse_error` instead of leaving them out of the library
+    #[arg(long)]
+    keep_unparseable_files: bool,
+
+    /// Bucket Chinese album and artist names by pinyin initial in the
+    /// `/albums/index` and `/artists/index` jump-lists instead of under `#`
+    #[arg(long)]
+    pinyin_index: bool,
+
+    /// Hash for track IDs: md5, blake3 or sha256 (truncated to 128 bits). Defaults
+    /// to the one recorded in `.music-station`, or md5 for a new library.
+    #[arg(long, value_name = "HASH")]
+    track_id_hash: Option<library::TrackIdHash>,
+
+    /// Merge artists that differ only by a leading article ("The Beatles" / "Beatles")
+    /// and sort them ignoring it, using the built-in article list
+    #[arg(long)]
+    merge_artist_articles: bool,
+
+    /// Leading article to ignore for artist grouping and sorting. May be repeated;
+    /// replaces the built-in list and implies `--merge-artist-articles`.
+    #[arg(long = "artist-article", value_name = "ARTICLE")]
+    artist_articles: Vec<String>,
+
+    /// Seconds to spend parsing a single file before skipping it as a scan error
+    #[arg(long, default_value_t = 30)]
+    parse_timeout_secs: u64,
+
+    /// Read all packets of files whose headers lack a frame count to fill in missing durations (slow)
+    #[arg(long)]
+    decode_duration_fallback: bool,
+
+    /// Use an in-memory mock lyrics provider instead of the online ones (no network access)
+    #[arg(long, alias = "test-providers")]
+    offline: bool,
+
+    /// Directory of `<artist>/<title>.lrc` files to serve through the `local` lyrics provider
+    #[arg(long, value_name = "DIR")]
+    local_lyrics_dir: Option<PathBuf>,
+
+    /// Genius API access token; registers the `genius` lyrics provider (plain lyrics only)
+    #[arg(
+        long,
+        env = "GENIUS_ACCESS_TOKEN",
+        value_name = "TOKEN",
+        hide_env_values = true
+    )]
+    genius_token: Option<String>,
+
+    /// Lyrics provider used when search/fetch requests omit the provider or pass `default`
+    #[arg(long, value_name = "PROVIDER")]
+    default_lyrics_provider: Option<String>,
+
+    /// Keep "feat. X" credits in titles and artists when searching online lyrics providers
+    #[arg(long)]
+    lyrics_keep_featuring: bool,
+
+    /// Keep remaster/version/edit annotations in titles when searching online lyrics providers
+    #[arg(long)]
+    lyrics_keep_version_tags: bool,
+
+    /// Seconds a lyrics provider gets to answer a search or fetch before the request fails with 504
+    #[arg(long, default_value_t = 10)]
+    lyrics_timeout_secs: u64,
+
+    /// Per-provider override of `--lyrics-timeout-secs`, e.g. `netease=20`; may be repeated
+    #[arg(long = "lyrics-provider-timeout", value_name = "PROVIDER=SECS", value_parser = parse_provider_timeout)]
+    lyrics_provider_timeouts: Vec<(String, u64)>,
+
+    /// How many lyrics providers `/lyrics/search/all` queries at once (default: all of them)
+    #[arg(long, value_name = "N")]
+    lyrics_search_concurrency: Option<usize>,
+
+    /// Start with an empty library instead of scanning; trigger a scan later with `POST /scan`
+    #[arg(long, conflicts_with = "scan_async")]
+    no_scan_on_start: bool,
+
+    /// Start serving immediately and scan in the background (`GET /ready` returns 503 until done)
+    #[arg(long)]
+    scan_async: bool,
+
+    /// Watch the library folder and apply added, changed and removed files with an incremental rescan
+    #[arg(long)]
+    watch: bool,
+
+    /// Ignore repeated play reports for the same track within this many seconds (0 disables)
+    #[arg(long, default_value_t = 30)]
+    play_debounce_secs: u64,
+
+    /// `POST /tracks/:id/played` counts a play once this percentage of the track was played
+    #[arg(long, value_name = "PERCENT", default_value_t = 50.0, value_parser = parse_percent)]
+    play_completion_percent: f64,
+
+    /// ...or once playback reached this many seconds, whatever the track's length
+    #[arg(long, value_name = "SECS", default_value_t = 240)]
+    play_completion_secs: u64,
+
+    /// Keep the listening history for this many days (0 keeps it forever)
+    #[arg(
+        long,
+        value_name = "DAYS",
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u64).range(0..=MAX_HISTORY_RETENTION_DAYS)
+    )]
+    history_retention_days: u64,
+
+    /// Cap each `/stream` connection at this many bytes per second (0 = unlimited)
+    #[arg(long, value_name = "BYTES", default_value_t = 0)]
+    stream_rate_limit: u64,
+
+    /// Cache cover art extracted from audio files on disk, under `.music-station/covers`
+    /// unless `--cover-cache-dir` is given
+    #[arg(long)]
+    cover_cache: bool,
+
+    /// Directory for the cover cache; implies `--cover-cache`
+    #[arg(long, value_name = "DIR")]
+    cover_cache_dir: Option<PathBuf>,
+
+    /// Size limit of the cover cache in megabytes; least recently used covers are evicted
+    #[arg(long, value_name = "MB", default_value_t = 256)]
+    cover_cache_max_mb: u64,
+
+    /// `ffmpeg` executable used to encode `/stream/:id?transcode=opus`
+    #[arg(
+        long,
+        env = "MUSIC_STATION_FFMPEG",
+        value_name = "PATH",
+        default_value = "ffmpeg"
+    )]
+    ffmpeg: PathBuf,
+
+    /// How many transcodes may run at once; further requests get 503
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    max_transcodes: usize,
+
+    /// Directory with the web client served under `/web` (default: `static` next to
+    /// the executable, then in the source tree, then in the working directory)
+    #[arg(long, env = "MUSIC_STATION_WEB_DIR", value_name = "DIR")]
+    web_dir: Option<PathBuf>,
+}
+
+/// Parse a `PROVIDER=SECS` timeout override
+fn parse_provider_timeout(value: &str) -> Result<(String, u64), String> {
+    let (provider, secs) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected PROVIDER=SECS, got '{}'", value))?;
+    let secs = secs
+        .parse()
+        .map_err(|e| format!("invalid seconds '{}': {}", secs, e))?;
+    Ok((provider.to_string(), secs))
+}
+
+/// Parse a percentage between 0 and 100; NaN and infinities are rejected
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent: f64 = value
+        .parse()
+        .map_err(|e| format!("invalid percentage '{}': {}", value, e))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(format!(
+            "percentage must be between 0 and 100, got '{}'",
+            value
+        ));
+    }
+    Ok(percent)
 }
 
 #[tokio::main]
@@ -62,14 +277,59 @@ async fn main() -> Result<()> {
     tracing::info!("Starting Music Station");
     tracing::info!("Library path: {}", cli.library.display());
 
-    // Initialize music library
-    let library = MusicLibrary::new(cli.library.clone());
+    // Track IDs key every database, so keep using the hash they were created with
+    let track_id_hash = library::resolve_track_id_hash(
+        &cli.library.join(library::DATA_DIR_NAME),
+        cli.track_id_hash,
+    )?;
 
-    // Scan the library
-    library.scan().await.context("Failed to scan library")?;
+    // Initialize music library
+    let library_config = LibraryConfig {
+        unknown_album_label: cli.unknown_album_label.clone(),
+        unknown_artist_label: cli.unknown_artist_label.clone(),
+        various_artists_label: cli.various_artists_label.clone(),
+        untagged_grouping: if cli.group_untagged_by_folder {
+            UntaggedGrouping::ParentFolder
+        } else {
+            UntaggedGrouping::Single
+        },
+        case_insensitive_lookup: cli.case_insensitive_lookup,
+        parse_timeout: std::time::Duration::from_secs(cli.parse_timeout_secs),
+        decode_duration_fallback: cli.decode_duration_fallback,
+        keep_unparseable_files: cli.keep_unparseable_files,
+        pinyin_index: cli.pinyin_index,
+        leading_articles: if !cli.artist_articles.is_empty() {
+            cli.artist_articles.clone()
+        } else if cli.merge_artist_articles {
+            library::default_leading_articles()
+        } else {
+            Vec::new()
+        },
+        ..LibraryConfig::default()
+    };
+    let mut builder = MusicLibrary::builder(cli.library.clone())
+        .config(library_config)
+        .scan_concurrency(cli.scan_concurrency as usize)
+        .id_strategy(track_id_hash)
+        .cover_fallback(!cli.no_cover_fallback);
+    if !cli.scan_exclude.is_empty() {
+        builder = builder.exclude_patterns(cli.scan_exclude.clone());
+    }
+    if !cli.scan_extensions.is_empty() {
+        builder = builder.extensions(&cli.scan_extensions);
+    }
+    let library = builder.build();
+    tracing::info!("Track ID hash: {}", library.config().track_id_hash.name());
+    tracing::info!(
+        "Audio formats: {}",
+        library.formats().extensions().join(", ")
+    );
+    if let Some(extensions) = &library.config().extensions {
+        tracing::info!("Scanning only: {}", extensions.join(", "));
+    }
 
     // Initialize lyrics database
-    let db_path = cli.library.join(".music-station").join("lyrics.db");
+    let db_path = library.data_dir().join("lyrics.db");
     let lyrics_db = LyricDatabase::new(&db_path)
         .await
         .context("Failed to initialize lyrics database")?;
@@ -77,7 +337,7 @@ async fn main() -> Result<()> {
     tracing::info!("Lyrics database: {}", db_path.display());
 
     // Initialize playlist database
-    let playlist_db_path = cli.library.join(".music-station").join("playlists.db");
+    let playlist_db_path = library.data_dir().join("playlists.db");
     let playlist_db = PlaylistDatabase::new(&playlist_db_path)
         .await
         .context("Failed to initialize playlist database")?;
@@ -85,29 +345,94 @@ async fn main() -> Result<()> {
     tracing::info!("Playlist database: {}", playlist_db_path.display());
 
     // Initialize stats database
-    let stats_db_path = cli.library.join(".music-station").join("stats.db");
+    let stats_db_path = library.data_dir().join("stats.db");
     let stats_db = StatsDatabase::new(&stats_db_path)
         .await
         .context("Failed to initialize stats database")?;
 
     tracing::info!("Stats database: {}", stats_db_path.display());
 
-    // Update has_lyrics flags for all tracks
-    if let Ok(tracks_with_lyrics) = lyrics_db.get_tracks_with_lyrics().await {
-        for track_id in tracks_with_lyrics {
-            library.update_track_lyrics_status(&track_id, true).await;
-        }
+    // Scan the library, then update has_lyrics flags and play counts for all tracks
+    if cli.no_scan_on_start {
+        tracing::info!("Skipping initial scan; the library is empty until POST /scan");
+    } else if cli.scan_async {
+        let scan = library
+            .spawn_scan()
+            .await
+            .context("Failed to start library scan")?;
+        let (library, lyrics_db, stats_db) = (library.clone(), lyrics_db.clone(), stats_db.clone());
+        tokio::spawn(async move {
+            match scan.await {
+                Ok(Ok(())) => server::load_track_flags(&library, &lyrics_db, &stats_db).await,
+                Ok(Err(e)) => tracing::error!("Background library scan failed: {:#}", e),
+                Err(e) => tracing::error!("Background library scan task failed: {}", e),
+            }
+        });
+        tracing::info!("Scanning library in the background");
+    } else {
+        library.scan().await.context("Failed to scan library")?;
+        server::load_track_flags(&library, &lyrics_db, &stats_db).await;
     }
 
-    // Update play counts for all tracks
-    if let Ok(play_counts) = stats_db.get_all_play_counts().await {
-        for (track_id, count) in play_counts {
-            library.update_track_play_count(&track_id, count).await;
-        }
+    if cli.watch {
+        watch::watch_library(
+            library.clone(),
+            lyrics_db.clone(),
+            stats_db.clone(),
+            watch::WATCH_DEBOUNCE,
+        )
+        .context("Failed to start library watcher")?;
     }
 
+    let cover_cache = if cli.cover_cache || cli.cover_cache_dir.is_some() {
+        let dir = cli
+            .cover_cache_dir
+            .clone()
+            .unwrap_or_else(|| library.data_dir().join(cover::COVER_CACHE_DIR_NAME));
+        let cache = cover::CoverCache::new(dir, cli.cover_cache_max_mb * 1024 * 1024);
+        tracing::info!("Cover cache: {}", cache.dir().display());
+        Some(cache)
+    } else {
+        None
+    };
+
     // Create and start the server
-    let app = server::create_router(library, lyrics_db, playlist_db, stats_db);
+    let server_options = server::ServerOptions {
+        lyrics: server::LyricsProviderOptions {
+            offline: cli.offline,
+            local_lyrics_dir: cli.local_lyrics_dir,
+            genius_token: cli.genius_token,
+            default_provider: cli.default_lyrics_provider,
+            title_normalizer: music_search_rs::TitleNormalizer {
+                strip_featuring: !cli.lyrics_keep_featuring,
+                strip_version_tags: !cli.lyrics_keep_version_tags,
+            },
+            timeouts: cli.lyrics_provider_timeouts.into_iter().fold(
+                lyrics::fetcher::ProviderTimeouts {
+                    default: std::time::Duration::from_secs(cli.lyrics_timeout_secs),
+                    ..Default::default()
+                },
+                |timeouts, (provider, secs)| {
+                    timeouts.with_override(provider, std::time::Duration::from_secs(secs))
+                },
+            ),
+            search_concurrency: cli.lyrics_search_concurrency,
+        },
+        play_count_debounce: std::time::Duration::from_secs(cli.play_debounce_secs),
+        play_completion: server::PlayCompletion {
+            fraction: cli.play_completion_percent / 100.0,
+            max_position: std::time::Duration::from_secs(cli.play_completion_secs),
+        },
+        stream_rate_limit: cli.stream_rate_limit,
+        cover_cache,
+        transcoder: transcode::Transcoder::new(cli.ffmpeg, cli.max_transcodes),
+        web_dir: cli.web_dir,
+        history_retention: (cli.history_retention_days > 0)
+            .then(|| cli.history_retention_days.checked_mul(24 * 60 * 60))
+            .flatten()
+            .map(std::time::Duration::from_secs),
+    };
+    let app = server::create_router(library, lyrics_db, playlist_db, stats_db, server_options);
     let addr = format!("0.0.0.0:{}", cli.port);
 
     tracing::info!("Server listening on http://{}", addr);
@@ -1,4 +1,5 @@
-use anyhow::{Context, Result};
+use crate::error::{MusicStationError, Result};
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use std::path::Path;
@@ -11,6 +12,8 @@ pub struct Playlist {
     pub tracks: Vec<String>, // Track IDs
     pub created_at: String,
     pub updated_at: String,
+    /// Incremented on every change, including to the track list
+    pub version: i64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -26,6 +29,20 @@ pub struct PlaylistUpdate {
     pub tracks: Option<Vec<String>>,
 }
 
+/// Outcome of a conditional playlist update
+#[derive(Debug)]
+pub enum PlaylistUpdateResult {
+    Updated(Playlist),
+    NotFound,
+    /// The playlist changed since the version the caller expected
+    VersionMismatch {
+        current: i64,
+    },
+}
+
+/// `id, name, description, created_at, updated_at, version`
+type PlaylistRow = (String, String, Option<String>, String, String, i64);
+
 #[derive(Clone)]
 pub struct PlaylistDatabase {
     pool: SqlitePool,
@@ -61,7 +78,8 @@ impl PlaylistDatabase {
                 name TEXT NOT NULL UNIQUE,
                 description TEXT,
                 created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
+                updated_at TEXT NOT NULL,
+                version INTEGER NOT NULL DEFAULT 1
             )
             "#,
         )
@@ -69,13 +87,27 @@ impl PlaylistDatabase {
         .await
         .context("Failed to create playlists table")?;
 
+        // Databases created before playlists were versioned lack the column
+        let has_version = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('playlists') WHERE name = 'version'",
+        )
+        .fetch_one(&pool)
+        .await
+        .context("Failed to inspect playlists table")?;
+        if has_version == 0 {
+            sqlx::query("ALTER TABLE playlists ADD COLUMN version INTEGER NOT NULL DEFAULT 1")
+                .execute(&pool)
+                .await
+                .context("Failed to add playlist version column")?;
+        }
+
         sqlx::query(
             r#"
             CREATE TABLE IF NOT EXISTS playlist_tracks (
                 playlist_id TEXT NOT NULL,
                 track_id TEXT NOT NULL,
                 position INTEGER NOT NULL,
-                PRIMARY KEY (playlist_id, track_id),
+                PRIMARY KEY (playlist_id, position),
                 FOREIGN KEY (playlist_id) REFERENCES playlists(id) ON DELETE CASCADE
             )
             "#,
@@ -84,6 +116,17 @@ impl PlaylistDatabase {
         .await
         .context("Failed to create playlist_tracks table")?;
 
+        // Older databases keyed entries by track, which rules out duplicates
+        let keyed_by_track = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('playlist_tracks') WHERE name = 'track_id' AND pk > 0",
+        )
+        .fetch_one(&pool)
+        .await
+        .context("Failed to inspect playlist_tracks table")?;
+        if keyed_by_track > 0 {
+            Self::rekey_playlist_tracks(&pool).await?;
+        }
+
         // Create index for faster lookups
         sqlx::query(
             r#"
@@ -100,6 +143,42 @@ impl PlaylistDatabase {
         Ok(Self { pool })
     }
 
+    /// Rebuild `playlist_tracks` keyed by position, renumbering each playlist from 0
+    async fn rekey_playlist_tracks(pool: &SqlitePool) -> Result<()> {
+        tracing::info!("Migrating playlist_tracks to allow duplicate tracks");
+
+        let mut tx = pool.begin().await.context("Failed to start transaction")?;
+        let statements = [
+            r#"
+            CREATE TABLE playlist_tracks_new (
+                playlist_id TEXT NOT NULL,
+                track_id TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                PRIMARY KEY (playlist_id, position),
+                FOREIGN KEY (playlist_id) REFERENCES playlists(id) ON DELETE CASCADE
+            )
+            "#,
+            r#"
+            INSERT INTO playlist_tracks_new (playlist_id, track_id, position)
+            SELECT playlist_id, track_id,
+                ROW_NUMBER() OVER (PARTITION BY playlist_id ORDER BY position) - 1
+            FROM playlist_tracks
+            "#,
+            "DROP TABLE playlist_tracks",
+            "ALTER TABLE playlist_tracks_new RENAME TO playlist_tracks",
+        ];
+        for statement in statements {
+            sqlx::query(statement)
+                .execute(&mut *tx)
+                .await
+                .context("Failed to migrate playlist_tracks table")?;
+        }
+        tx.commit()
+            .await
+            .context("Failed to migrate playlist_tracks table")?;
+        Ok(())
+    }
+
     /// Create a new playlist
     pub async fn create_playlist(&self, create: PlaylistCreate) -> Result<Playlist> {
         let id = uuid::Uuid::new_v4().to_string();
@@ -127,12 +206,18 @@ impl PlaylistDatabase {
                 tracks: Vec::new(),
                 created_at: now.clone(),
                 updated_at: now,
+                version: 1,
             }),
             Err(e) => {
                 if e.to_string().contains("UNIQUE constraint failed") {
-                    anyhow::bail!("A playlist with the name '{}' already exists", create.name)
+                    Err(MusicStationError::Conflict(format!(
+                        "A playlist with the name '{}' already exists",
+                        create.name
+                    )))
                 } else {
-                    Err(e).context("Failed to insert playlist")
+                    Err(anyhow::Error::new(e)
+                        .context("Failed to insert playlist")
+                        .into())
                 }
             }
         }
@@ -140,9 +225,9 @@ impl PlaylistDatabase {
 
     /// Get all playlists
     pub async fn get_playlists(&self) -> Result<Vec<Playlist>> {
-        let playlists = sqlx::query_as::<_, (String, String, Option<String>, String, String)>(
+        let playlists = sqlx::query_as::<_, PlaylistRow>(
             r#"
-            SELECT id, name, description, created_at, updated_at
+            SELECT id, name, description, created_at, updated_at, version
             FROM playlists
             ORDER BY updated_at DESC
             "#,
@@ -152,7 +237,7 @@ impl PlaylistDatabase {
         .context("Failed to fetch playlists")?;
 
         let mut result = Vec::new();
-        for (id, name, description, created_at, updated_at) in playlists {
+        for (id, name, description, created_at, updated_at, version) in playlists {
             let tracks = self.get_playlist_tracks(&id).await?;
             result.push(Playlist {
                 id,
@@ -161,6 +246,7 @@ impl PlaylistDatabase {
                 tracks,
                 created_at,
                 updated_at,
+                version,
             });
         }
 
@@ -169,9 +255,9 @@ impl PlaylistDatabase {
 
     /// Get a specific playlist by ID
     pub async fn get_playlist(&self, id: &str) -> Result<Option<Playlist>> {
-        let row = sqlx::query_as::<_, (String, String, Option<String>, String, String)>(
+        let row = sqlx::query_as::<_, PlaylistRow>(
             r#"
-            SELECT id, name, description, created_at, updated_at
+            SELECT id, name, description, created_at, updated_at, version
             FROM playlists
             WHERE id = ?
             "#,
@@ -181,7 +267,7 @@ impl PlaylistDatabase {
         .await
         .context("Failed to fetch playlist")?;
 
-        if let Some((id, name, description, created_at, updated_at)) = row {
+        if let Some((id, name, description, created_at, updated_at, version)) = row {
             let tracks = self.get_playlist_tracks(&id).await?;
             Ok(Some(Playlist {
                 id,
@@ -190,6 +276,7 @@ impl PlaylistDatabase {
                 tracks,
                 created_at,
                 updated_at,
+                version,
             }))
         } else {
             Ok(None)
@@ -214,54 +301,76 @@ impl PlaylistDatabase {
         Ok(tracks.into_iter().map(|(track_id,)| track_id).collect())
     }
 
-    /// Update a playlist
+    /// Update a playlist in one transaction. When `expected_version` is given,
+    /// the update only applies if the stored version still matches.
     pub async fn update_playlist(
         &self,
         id: &str,
         update: PlaylistUpdate,
-    ) -> Result<Option<Playlist>> {
-        // Check if playlist exists
-        let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM playlists WHERE id = ?")
-            .bind(id)
-            .fetch_one(&self.pool)
+        expected_version: Option<i64>,
+    ) -> Result<PlaylistUpdateResult> {
+        let mut tx = self
+            .pool
+            .begin()
             .await
-            .context("Failed to check playlist existence")?;
-
-        if exists == 0 {
-            return Ok(None);
-        }
+            .context("Failed to start transaction")?;
 
+        // Bumping the version first takes the write lock, so the check and
+        // the changes below can't interleave with another update
         let now = chrono::Utc::now().to_rfc3339();
+        let bumped = sqlx::query(
+            r#"
+            UPDATE playlists
+            SET updated_at = ?, version = version + 1
+            WHERE id = ? AND (? IS NULL OR version = ?)
+            "#,
+        )
+        .bind(&now)
+        .bind(id)
+        .bind(expected_version)
+        .bind(expected_version)
+        .execute(&mut *tx)
+        .await
+        .context("Failed to update playlist version")?;
+
+        if bumped.rows_affected() == 0 {
+            let current =
+                sqlx::query_scalar::<_, i64>("SELECT version FROM playlists WHERE id = ?")
+                    .bind(id)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .context("Failed to check playlist existence")?;
+            return Ok(match current {
+                Some(current) => PlaylistUpdateResult::VersionMismatch { current },
+                None => PlaylistUpdateResult::NotFound,
+            });
+        }
 
         // Update playlist metadata if provided
         if update.name.is_some() || update.description.is_some() {
-            let current = self
-                .get_playlist(id)
-                .await?
-                .ok_or_else(|| anyhow::anyhow!("Playlist not found"))?;
-
-            let name = update.name.unwrap_or(current.name);
-            let description = update.description.or(current.description);
-
             let result = sqlx::query(
                 r#"
                 UPDATE playlists
-                SET name = ?, description = ?, updated_at = ?
+                SET name = COALESCE(?, name), description = COALESCE(?, description)
                 WHERE id = ?
                 "#,
             )
-            .bind(&name)
-            .bind(&description)
-            .bind(&now)
+            .bind(&update.name)
+            .bind(&update.description)
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await;
 
             if let Err(e) = result {
                 if e.to_string().contains("UNIQUE constraint failed") {
-                    anyhow::bail!("A playlist with the name '{}' already exists", name)
+                    return Err(MusicStationError::Conflict(format!(
+                        "A playlist with the name '{}' already exists",
+                        update.name.unwrap_or_default()
+                    )));
                 } else {
-                    return Err(e).context("Failed to update playlist");
+                    return Err(anyhow::Error::new(e)
+                        .context("Failed to update playlist")
+                        .into());
                 }
             }
         }
@@ -271,7 +380,7 @@ impl PlaylistDatabase {
             // Delete existing tracks
             sqlx::query("DELETE FROM playlist_tracks WHERE playlist_id = ?")
                 .bind(id)
-                .execute(&self.pool)
+                .execute(&mut *tx)
                 .await
                 .context("Failed to delete old playlist tracks")?;
 
@@ -286,22 +395,19 @@ impl PlaylistDatabase {
                 .bind(id)
                 .bind(track_id)
                 .bind(position as i64)
-                .execute(&self.pool)
+                .execute(&mut *tx)
                 .await
                 .context("Failed to insert playlist track")?;
             }
-
-            // Update the updated_at timestamp
-            sqlx::query("UPDATE playlists SET updated_at = ? WHERE id = ?")
-                .bind(&now)
-                .bind(id)
-                .execute(&self.pool)
-                .await
-                .context("Failed to update playlist timestamp")?;
         }
 
+        tx.commit().await.context("Failed to update playlist")?;
+
         // Return updated playlist
-        self.get_playlist(id).await
+        Ok(match self.get_playlist(id).await? {
+            Some(playlist) => PlaylistUpdateResult::Updated(playlist),
+            None => PlaylistUpdateResult::NotFound,
+        })
     }
 
     /// Delete a playlist
@@ -315,102 +421,213 @@ impl PlaylistDatabase {
         Ok(result.rows_affected() > 0)
     }
 
-    /// Add a track to a playlist
+    /// Add a track to a playlist. Unless `allow_duplicates` is set, a track
+    /// already in the playlist is left where it is.
     pub async fn add_track_to_playlist(
         &self,
         playlist_id: &str,
         track_id: &str,
+        allow_duplicates: bool,
     ) -> Result<Option<Playlist>> {
-        // Check if playlist exists
-        let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM playlists WHERE id = ?")
-            .bind(playlist_id)
-            .fetch_one(&self.pool)
+        self.add_tracks(playlist_id, &[track_id.to_string()], allow_duplicates)
             .await
-            .context("Failed to check playlist existence")?;
+    }
 
-        if exists == 0 {
+    /// Append tracks to a playlist in one transaction, in the given order.
+    /// Unless `allow_duplicates` is set, tracks already in the playlist (or
+    /// repeated in `track_ids`) are skipped. Returns `None` if the playlist
+    /// doesn't exist.
+    pub async fn add_tracks(
+        &self,
+        playlist_id: &str,
+        track_ids: &[String],
+        allow_duplicates: bool,
+    ) -> Result<Option<Playlist>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+
+        // Writing first takes the database write lock, so a concurrent edit
+        // can't interleave between reading the last position and inserting
+        let now = chrono::Utc::now().to_rfc3339();
+        let updated =
+            sqlx::query("UPDATE playlists SET updated_at = ?, version = version + 1 WHERE id = ?")
+                .bind(&now)
+                .bind(playlist_id)
+                .execute(&mut *tx)
+                .await
+                .context("Failed to update playlist timestamp")?;
+        if updated.rows_affected() == 0 {
             return Ok(None);
         }
 
-        // Get current max position
-        let max_position = sqlx::query_scalar::<_, Option<i64>>(
-            "SELECT MAX(position) FROM playlist_tracks WHERE playlist_id = ?",
-        )
-        .bind(playlist_id)
-        .fetch_one(&self.pool)
-        .await
-        .context("Failed to get max position")?
-        .unwrap_or(-1);
-
-        let new_position = max_position + 1;
-
-        // Insert track (ignore if already exists)
-        sqlx::query(
-            r#"
-            INSERT OR IGNORE INTO playlist_tracks (playlist_id, track_id, position)
-            VALUES (?, ?, ?)
-            "#,
-        )
-        .bind(playlist_id)
-        .bind(track_id)
-        .bind(new_position)
-        .execute(&self.pool)
-        .await
-        .context("Failed to add track to playlist")?;
-
-        // Update timestamp
-        let now = chrono::Utc::now().to_rfc3339();
-        sqlx::query("UPDATE playlists SET updated_at = ? WHERE id = ?")
-            .bind(&now)
+        for track_id in track_ids {
+            sqlx::query(
+                r#"
+                INSERT INTO playlist_tracks (playlist_id, track_id, position)
+                SELECT ?1, ?2, (
+                    SELECT COALESCE(MAX(position), -1) + 1
+                    FROM playlist_tracks WHERE playlist_id = ?1
+                )
+                WHERE ?3 OR NOT EXISTS (
+                    SELECT 1 FROM playlist_tracks WHERE playlist_id = ?1 AND track_id = ?2
+                )
+                "#,
+            )
             .bind(playlist_id)
-            .execute(&self.pool)
+            .bind(track_id)
+            .bind(allow_duplicates)
+            .execute(&mut *tx)
             .await
-            .context("Failed to update playlist timestamp")?;
+            .context("Failed to add track to playlist")?;
+        }
+
+        tx.commit()
+            .await
+            .context("Failed to add tracks to playlist")?;
 
         self.get_playlist(playlist_id).await
     }
 
-    /// Remove a track from a playlist
-    pub async fn remove_track_from_playlist(
+    /// Remove tracks from a playlist in one transaction, renumbering the
+    /// remaining positions once at the end. Every occurrence of a listed track
+    /// is removed. Returns the updated playlist and how many entries were
+    /// removed, or `None` if the playlist doesn't exist.
+    pub async fn remove_tracks(
         &self,
         playlist_id: &str,
-        track_id: &str,
-    ) -> Result<Option<Playlist>> {
-        let result =
-            sqlx::query("DELETE FROM playlist_tracks WHERE playlist_id = ? AND track_id = ?")
-                .bind(playlist_id)
-                .bind(track_id)
-                .execute(&self.pool)
-                .await
-                .context("Failed to remove track from playlist")?;
+        track_ids: &[String],
+    ) -> Result<Option<(Playlist, u64)>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+
+        let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM playlists WHERE id = ?")
+            .bind(playlist_id)
+            .fetch_one(&mut *tx)
+            .await
+            .context("Failed to check playlist existence")?;
+        if exists == 0 {
+            return Ok(None);
+        }
 
-        if result.rows_affected() > 0 {
-            // Reorder positions
-            let tracks = self.get_playlist_tracks(playlist_id).await?;
-            for (position, track_id) in tracks.iter().enumerate() {
+        let mut removed = 0;
+        for track_id in track_ids {
+            removed +=
+                sqlx::query("DELETE FROM playlist_tracks WHERE playlist_id = ? AND track_id = ?")
+                    .bind(playlist_id)
+                    .bind(track_id)
+                    .execute(&mut *tx)
+                    .await
+                    .context("Failed to remove track from playlist")?
+                    .rows_affected();
+        }
+
+        if removed > 0 {
+            let positions = sqlx::query_scalar::<_, i64>(
+                "SELECT position FROM playlist_tracks WHERE playlist_id = ? ORDER BY position",
+            )
+            .bind(playlist_id)
+            .fetch_all(&mut *tx)
+            .await
+            .context("Failed to fetch playlist tracks")?;
+
+            // Moving entries down in order never collides: only entries after
+            // the first gap move, and each one's new slot is already free
+            for (position, old_position) in positions.into_iter().enumerate() {
+                let position = position as i64;
+                if position == old_position {
+                    continue;
+                }
                 sqlx::query(
-                    "UPDATE playlist_tracks SET position = ? WHERE playlist_id = ? AND track_id = ?"
+                    "UPDATE playlist_tracks SET position = ? WHERE playlist_id = ? AND position = ?",
                 )
-                .bind(position as i64)
+                .bind(position)
                 .bind(playlist_id)
-                .bind(track_id)
-                .execute(&self.pool)
+                .bind(old_position)
+                .execute(&mut *tx)
                 .await
                 .context("Failed to update track position")?;
             }
 
-            // Update timestamp
             let now = chrono::Utc::now().to_rfc3339();
-            sqlx::query("UPDATE playlists SET updated_at = ? WHERE id = ?")
+            sqlx::query("UPDATE playlists SET updated_at = ?, version = version + 1 WHERE id = ?")
                 .bind(&now)
                 .bind(playlist_id)
-                .execute(&self.pool)
+                .execute(&mut *tx)
                 .await
                 .context("Failed to update playlist timestamp")?;
+        }
 
-            self.get_playlist(playlist_id).await
-        } else {
-            Ok(None)
+        tx.commit()
+            .await
+            .context("Failed to remove tracks from playlist")?;
+
+        Ok(self
+            .get_playlist(playlist_id)
+            .await?
+            .map(|playlist| (playlist, removed)))
+    }
+
+    /// Remove a track from a playlist. Returns `None` if the playlist doesn't
+    /// exist or doesn't contain the track.
+    pub async fn remove_track_from_playlist(
+        &self,
+        playlist_id: &str,
+        track_id: &str,
+    ) -> Result<Option<Playlist>> {
+        Ok(self
+            .remove_tracks(playlist_id, &[track_id.to_string()])
+            .await?
+            .filter(|(_, removed)| *removed > 0)
+            .map(|(playlist, _)| playlist))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_migrates_track_keyed_playlists() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("playlists.db");
+
+        // Schema from before duplicates were allowed
+        let pool = SqlitePool::connect(&format!("sqlite:{}?mode=rwc", db_path.display()))
+            .await
+            .unwrap();
+        for statement in [
+            "CREATE TABLE playlists (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE, \
+             description TEXT, created_at TEXT NOT NULL, updated_at TEXT NOT NULL)",
+            "CREATE TABLE playlist_tracks (playlist_id TEXT NOT NULL, track_id TEXT NOT NULL, \
+             position INTEGER NOT NULL, PRIMARY KEY (playlist_id, track_id))",
+            "INSERT INTO playlists VALUES ('p1', 'Old', NULL, 'then', 'then')",
+            "INSERT INTO playlist_tracks VALUES ('p1', 't2', 5), ('p1', 't1', 2)",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
         }
+        pool.close().await;
+
+        let db = PlaylistDatabase::new(&db_path).await.unwrap();
+        let playlist = db
+            .add_track_to_playlist("p1", "t1", true)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(playlist.tracks, ["t1", "t2", "t1"]);
+        assert_eq!(playlist.version, 2);
+
+        let (playlist, removed) = db
+            .remove_tracks("p1", &["t1".to_string()])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(playlist.tracks, ["t2"]);
     }
 }
@@ -6,26 +6,70 @@ use axum::{
     response::{IntoResponse, Response},
     routing::get,
 };
-use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use tokio_util::io::ReaderStream;
+use tokio_util::sync::CancellationToken;
+
+/// How long a provider health check result is reused
+const PROVIDER_HEALTH_TTL: Duration = Duration::from_secs(60);
+
+/// Upper bound for a single provider health check
+const PROVIDER_HEALTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pipe buffer between the track list serializer and the response body (64 KB)
+const LIST_STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Largest read per chunk when a stream is rate limited (64 KB)
+const STREAM_CHUNK_MAX: usize = 64 * 1024;
 
 /// Maximum upload size for cover art (10 MB)
 const MAX_COVER_SIZE: usize = 10 * 1024 * 1024;
 /// Maximum upload size for lyrics (1 MB)
 const MAX_LYRICS_SIZE: usize = 1024 * 1024;
+/// Longest track search query in characters; fuzzy scoring grows with its length
+const MAX_SEARCH_QUERY_CHARS: usize = 256;
+/// Response header of `GET /tracks` carrying the library version the list starts from
+const LIBRARY_VERSION_HEADER: header::HeaderName =
+    header::HeaderName::from_static("x-library-version");
+/// Provider name that selects the configured default lyrics provider
+const DEFAULT_PROVIDER_ALIAS: &str = "default";
+/// Every lyrics provider the server can register, to tell a provider that is
+/// unavailable in this configuration from a name that doesn't exist
+const KNOWN_PROVIDERS: &[&str] = &["netease", "qqmusic", "genius", "mock", "local"];
 use tower_http::cors::CorsLayer;
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
 
-use crate::library::{Album, Artist, LibraryStats, MusicLibrary, Track, TrackMetadataUpdate};
+use crate::cover::{CoverCache, CoverFormat};
+use crate::error::MusicStationError;
+use crate::library::{
+    Album, AlbumSummary, Artist, CoverOptimization, CoverOptimizationReport, FlagRefreshReport,
+    InspectReport, LibraryStats, LyricsEmbedReport, MusicLibrary, NameIndexEntry, RawTag,
+    ReindexReport, RescanReport, ScanError, ScanStatus, Suggestion, SuggestionKind, Track,
+    TrackChanges, TrackMetadataUpdate,
+};
+use crate::lyrics::fetch_queue::{FetchJobState, FetchJobStatus, FetchOutcome};
 use crate::lyrics::fetcher::LyricsProvider as LyricsProviderTrait;
 use crate::lyrics::fetcher::{
-    LyricsQuery, LyricsResponse, LyricsSearchResult as FetcherSearchResult,
+    LyricsAggregator, LyricsQuery, LyricsResponse, LyricsSearchResult as FetcherSearchResult,
+    MergedSearchResult, ProviderConfig, ProviderInfo, ProviderTimedOut, ProviderTimeouts,
+    with_provider_timeout,
 };
+use crate::lyrics::genius_provider::GeniusLyricsProvider;
 use crate::lyrics::music_search_provider::{NetEaseLyricsProvider, QQMusicLyricsProvider};
+use crate::lyrics::providers::{LocalLyricsProvider, MockLyricsProvider};
 use crate::lyrics::{Lyric, LyricDatabase, LyricFormat, LyricUpload};
-use crate::playlist::{Playlist, PlaylistCreate, PlaylistDatabase, PlaylistUpdate};
-use crate::stats::StatsDatabase;
+use crate::playlist::{
+    Playlist, PlaylistCreate, PlaylistDatabase, PlaylistUpdate, PlaylistUpdateResult,
+};
+use crate::stats::{HistoryEntry, StatsDatabase, TrackStats};
+use crate::transcode::{
+    DEFAULT_OPUS_BITRATE_KBPS, OPUS_BITRATES_KBPS, OpusOptions, TRANSCODE_SAMPLE_RATES, Transcoder,
+    UnsupportedSource,
+};
+use music_search_rs::TitleNormalizer;
 
 #[derive(Clone)]
 pub struct AppState {
@@ -33,8 +77,342 @@ pub struct AppState {
     pub lyrics_db: LyricDatabase,
     pub playlist_db: PlaylistDatabase,
     pub stats_db: StatsDatabase,
-    pub netease_provider: Option<std::sync::Arc<NetEaseLyricsProvider>>,
-    pub qqmusic_provider: Option<std::sync::Arc<QQMusicLyricsProvider>>,
+    /// Lyrics providers that were successfully initialized, in registration order
+    pub lyrics_providers: std::sync::Arc<Vec<std::sync::Arc<dyn LyricsProviderTrait>>>,
+    /// Provider used when a request omits `provider` or passes `default`
+    pub default_lyrics_provider: Option<String>,
+    /// All of `lyrics_providers`, for searches across every provider
+    pub lyrics_aggregator: std::sync::Arc<LyricsAggregator>,
+    /// How long each lyrics provider gets to answer a search or fetch
+    pub provider_timeouts: std::sync::Arc<ProviderTimeouts>,
+    /// Provider name -> (checked at, healthy)
+    pub provider_health: std::sync::Arc<tokio::sync::RwLock<HashMap<String, (Instant, bool)>>>,
+    pub play_count_debounce: Duration,
+    /// When `/tracks/:id/played` counts a play
+    pub play_completion: PlayCompletion,
+    /// Per-connection cap for `/stream` bodies in bytes per second (0 = unlimited)
+    pub stream_rate_limit: u64,
+    pub recent_plays: std::sync::Arc<tokio::sync::RwLock<RecentPlays>>,
+    /// History entries older than this are pruned as new plays are recorded
+    pub history_retention: Option<Duration>,
+    pub cover_cache: Option<CoverCache>,
+    /// Encodes `/stream/:id?transcode=opus` responses
+    pub transcoder: Transcoder,
+    /// Cancels the running fetch-missing worker; `None` when no worker is running
+    pub fetch_missing_worker: std::sync::Arc<tokio::sync::Mutex<Option<CancellationToken>>>,
+}
+
+/// (track ID, client ID) -> when a play was last counted
+type RecentPlays = HashMap<(String, Option<String>), Instant>;
+
+impl AppState {
+    /// Record a play of `track_id` unless one was already counted for the same
+    /// track and client within the debounce window. Returns whether to count it.
+    async fn debounce_play(&self, track_id: &str, client_id: Option<String>) -> bool {
+        if self.play_count_debounce.is_zero() {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut recent = self.recent_plays.write().await;
+        recent.retain(|_, counted_at| now.duration_since(*counted_at) < self.play_count_debounce);
+
+        let key = (track_id.to_string(), client_id);
+        if recent.contains_key(&key) {
+            return false;
+        }
+        recent.insert(key, now);
+        true
+    }
+
+    /// Look up a registered lyrics provider by name, resolving a missing name
+    /// or `default` to the configured default provider.
+    ///
+    /// Fails with 400 for an unknown name or when no name is given and there
+    /// is no default, and with 503 for a known provider that isn't registered
+    /// (offline mode, failed initialization, no Genius token).
+    fn lyrics_provider(
+        &self,
+        name: Option<&str>,
+    ) -> Result<std::sync::Arc<dyn LyricsProviderTrait>, StatusCode> {
+        let name = match name {
+            None | Some(DEFAULT_PROVIDER_ALIAS) => {
+                self.default_lyrics_provider.as_deref().ok_or_else(|| {
+                    tracing::warn!("No lyrics provider given and no default configured");
+                    StatusCode::BAD_REQUEST
+                })?
+            }
+            Some(name) => name,
+        };
+        if let Some(provider) = self.lyrics_providers.iter().find(|p| p.name() == name) {
+            return Ok(provider.clone());
+        }
+        if KNOWN_PROVIDERS.contains(&name) {
+            tracing::warn!("Lyrics provider {} is not available", name);
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        } else {
+            tracing::warn!("Unknown lyrics provider: {}", name);
+            Err(StatusCode::BAD_REQUEST)
+        }
+    }
+}
+
+/// Which lyrics providers the server registers
+#[derive(Debug, Clone, Default)]
+pub struct LyricsProviderOptions {
+    /// Skip the online providers and register an in-memory `mock` provider instead
+    pub offline: bool,
+    /// Directory of `<artist>/<title>.lrc|.txt` files served by the `local` provider
+    pub local_lyrics_dir: Option<std::path::PathBuf>,
+    /// Genius API access token; the `genius` provider is only registered when set
+    pub genius_token: Option<String>,
+    /// Provider used when lyrics requests don't name one
+    pub default_provider: Option<String>,
+    /// How the online providers clean titles and artists before searching and scoring
+    pub title_normalizer: TitleNormalizer,
+    /// How long each provider gets before a request fails with 504 Gateway Timeout
+    pub timeouts: ProviderTimeouts,
+    /// How many providers `/lyrics/search/all` queries at once; `None` queries all of them
+    pub search_concurrency: Option<usize>,
+}
+
+/// Server behaviour that is configurable from the command line
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    pub lyrics: LyricsProviderOptions,
+    /// Repeated play reports for the same track (and client) within this
+    /// window are ignored; zero disables debouncing
+    pub play_count_debounce: Duration,
+    /// How much of a track must be played before `/tracks/:id/played` counts it
+    pub play_completion: PlayCompletion,
+    /// Bytes per second each `/stream` response is paced to; zero means unlimited
+    pub stream_rate_limit: u64,
+    /// Where `/cover/:id` caches extracted cover art; `None` reads the audio file every time
+    pub cover_cache: Option<CoverCache>,
+    /// `ffmpeg` and the concurrency limit used for `/stream/:id?transcode=`
+    pub transcoder: Transcoder,
+    /// How long counted plays are kept in the listening history; `None` keeps them forever
+    pub history_retention: Option<Duration>,
+    /// Directory with the web client served under `/web`. `None` serves the
+    /// embedded client with the `embed-assets` feature, else [`default_web_dir`].
+    pub web_dir: Option<std::path::PathBuf>,
+}
+
+/// Directory name of the bundled web client
+const WEB_DIR_NAME: &str = "static";
+
+#[cfg(feature = "embed-assets")]
+mod embedded_web {
+    include!(concat!(env!("OUT_DIR"), "/web_assets.rs"));
+}
+
+/// Routes serving the web client under `/web`
+fn web_routes(web_dir: Option<std::path::PathBuf>) -> Router<AppState> {
+    #[cfg(feature = "embed-assets")]
+    if web_dir.is_none() {
+        tracing::info!("Serving the embedded web client");
+        return Router::new()
+            .route(
+                "/web/",
+                get(|headers: HeaderMap| embedded_web_asset("index.html".to_string(), headers)),
+            )
+            .route(
+                "/web/*path",
+                get(|Path(path): Path<String>, headers: HeaderMap| {
+                    embedded_web_asset(path, headers)
+                }),
+            );
+    }
+
+    let web_dir = web_dir.unwrap_or_else(default_web_dir);
+    if !web_dir.is_dir() {
+        tracing::warn!(
+            "Web client directory {} does not exist; /web will return 404 (set --web-dir)",
+            web_dir.display()
+        );
+    }
+    // The directory is only logged; clients don't need the server's paths
+    let not_found_dir = web_dir.clone();
+    let not_found = move |uri: axum::http::Uri| async move {
+        tracing::debug!(
+            "{} not found in web directory {}",
+            uri.path(),
+            not_found_dir.display()
+        );
+        (StatusCode::NOT_FOUND, "Not found")
+    };
+    let static_service = ServeDir::new(&web_dir).not_found_service(
+        axum::handler::HandlerWithoutStateExt::into_service(not_found),
+    );
+    Router::new().nest_service("/web", static_service)
+}
+
+/// Serve a file of the embedded web client. The files aren't fingerprinted, so
+/// browsers revalidate them on every use (`no-cache`) and get 304 while the
+/// `ETag` matches.
+#[cfg(feature = "embed-assets")]
+async fn embedded_web_asset(path: String, headers: HeaderMap) -> Response {
+    let Some((_, data)) = embedded_web::WEB_ASSETS
+        .iter()
+        .find(|(name, _)| *name == path)
+    else {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("{} is not part of the embedded web client", path),
+        )
+            .into_response();
+    };
+
+    let etag = format!("\"{:x}\"", md5::compute(data));
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|value| value.as_bytes() == etag.as_bytes())
+    {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
+    (
+        [
+            (header::CONTENT_TYPE, web_content_type(&path).to_string()),
+            (header::CACHE_CONTROL, "no-cache".to_string()),
+            (header::ETAG, etag),
+        ],
+        *data,
+    )
+        .into_response()
+}
+
+/// Content-Type of a web client file by extension
+#[cfg(feature = "embed-assets")]
+fn web_content_type(path: &str) -> &'static str {
+    let extension = std::path::Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Locate the bundled web client: next to the executable, then in the source
+/// tree it was built from, then relative to the working directory
+pub fn default_web_dir() -> std::path::PathBuf {
+    let beside_exe = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(WEB_DIR_NAME)));
+    let source_tree = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(WEB_DIR_NAME);
+    beside_exe
+        .into_iter()
+        .chain([source_tree])
+        .find(|dir| dir.is_dir())
+        .unwrap_or_else(|| std::path::PathBuf::from(WEB_DIR_NAME))
+}
+
+/// Scrobble-style completion rule: a play counts once half the track (or
+/// four minutes of a long one) has been played
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayCompletion {
+    /// Fraction of the track's duration that must be reached
+    pub fraction: f64,
+    /// Reaching this position counts regardless of the track's duration
+    pub max_position: Duration,
+}
+
+impl Default for PlayCompletion {
+    fn default() -> Self {
+        Self {
+            fraction: 0.5,
+            max_position: Duration::from_secs(4 * 60),
+        }
+    }
+}
+
+impl PlayCompletion {
+    /// Whether playing to `position` completes a track of `duration`. Without
+    /// a known duration only `max_position` applies.
+    pub fn is_complete(&self, position: Duration, duration: Option<Duration>) -> bool {
+        position >= self.max_position
+            || duration.is_some_and(|duration| position >= duration.mul_f64(self.fraction))
+    }
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            lyrics: LyricsProviderOptions::default(),
+            play_count_debounce: Duration::from_secs(30),
+            play_completion: PlayCompletion::default(),
+            stream_rate_limit: 0,
+            cover_cache: None,
+            transcoder: Transcoder::new("ffmpeg", 2),
+            history_retention: None,
+            web_dir: None,
+        }
+    }
+}
+
+/// Build the lyrics providers selected by `options`
+fn init_lyrics_providers(
+    options: &LyricsProviderOptions,
+) -> Vec<std::sync::Arc<dyn LyricsProviderTrait>> {
+    let mut providers: Vec<std::sync::Arc<dyn LyricsProviderTrait>> = Vec::new();
+
+    if options.offline {
+        tracing::info!("Offline mode: registering mock lyrics provider");
+        providers.push(std::sync::Arc::new(mock_lyrics_provider()));
+    } else {
+        match NetEaseLyricsProvider::new(None) {
+            Ok(provider) => providers.push(std::sync::Arc::new(
+                provider.with_normalizer(options.title_normalizer),
+            )),
+            Err(e) => tracing::warn!("Failed to initialize NetEase lyrics provider: {}", e),
+        }
+        match QQMusicLyricsProvider::new(None) {
+            Ok(provider) => providers.push(std::sync::Arc::new(
+                provider.with_normalizer(options.title_normalizer),
+            )),
+            Err(e) => tracing::warn!("Failed to initialize QQ Music lyrics provider: {}", e),
+        }
+        if let Some(token) = &options.genius_token {
+            let config = ProviderConfig {
+                api_key: Some(token.clone()),
+                timeout: options.timeouts.for_provider("genius"),
+                ..Default::default()
+            };
+            match GeniusLyricsProvider::new(config) {
+                Ok(provider) => providers.push(std::sync::Arc::new(
+                    provider.with_normalizer(options.title_normalizer),
+                )),
+                Err(e) => tracing::warn!("Failed to initialize Genius lyrics provider: {}", e),
+            }
+        }
+    }
+
+    if let Some(dir) = &options.local_lyrics_dir {
+        tracing::info!("Registering local lyrics provider at {}", dir.display());
+        providers.push(std::sync::Arc::new(LocalLyricsProvider::new(dir.clone())));
+    }
+
+    providers
+}
+
+/// In-memory provider with a fixed sample song, for running without network access
+fn mock_lyrics_provider() -> MockLyricsProvider {
+    MockLyricsProvider::new("mock").add_lyrics(
+        "Example Song",
+        "Example Artist",
+        "[00:00.00]First line\n[00:05.00]Second line\n[00:10.00]Third line".to_string(),
+        LyricFormat::Lrc,
+    )
 }
 
 pub fn create_router(
@@ -42,20 +420,27 @@ pub fn create_router(
     lyrics_db: LyricDatabase,
     playlist_db: PlaylistDatabase,
     stats_db: StatsDatabase,
+    options: ServerOptions,
 ) -> Router {
-    // Initialize lyrics providers
-    let netease_provider = NetEaseLyricsProvider::new(None)
-        .map(|p| std::sync::Arc::new(p))
-        .ok();
-    let qqmusic_provider = QQMusicLyricsProvider::new(None)
-        .map(|p| std::sync::Arc::new(p))
-        .ok();
+    let provider_options = options.lyrics;
+    let lyrics_providers = init_lyrics_providers(&provider_options);
+
+    if let Some(default) = &provider_options.default_provider
+        && !lyrics_providers.iter().any(|p| p.name() == default)
+    {
+        tracing::warn!(
+            "Default lyrics provider '{}' is not available; requests without a provider will fail",
+            default
+        );
+    }
 
-    if netease_provider.is_none() {
-        tracing::warn!("Failed to initialize NetEase lyrics provider");
+    let mut lyrics_aggregator =
+        LyricsAggregator::new().with_timeouts(provider_options.timeouts.clone());
+    if let Some(concurrency) = provider_options.search_concurrency {
+        lyrics_aggregator = lyrics_aggregator.with_concurrency(concurrency);
     }
-    if qqmusic_provider.is_none() {
-        tracing::warn!("Failed to initialize QQ Music lyrics provider");
+    for provider in &lyrics_providers {
+        lyrics_aggregator = lyrics_aggregator.add_provider(provider.clone());
     }
 
     let state = AppState {
@@ -63,21 +448,59 @@ pub fn create_router(
         lyrics_db,
         playlist_db,
         stats_db,
-        netease_provider,
-        qqmusic_provider,
+        lyrics_providers: std::sync::Arc::new(lyrics_providers),
+        default_lyrics_provider: provider_options.default_provider,
+        lyrics_aggregator: std::sync::Arc::new(lyrics_aggregator),
+        provider_timeouts: std::sync::Arc::new(provider_options.timeouts),
+        provider_health: Default::default(),
+        play_count_debounce: options.play_count_debounce,
+        play_completion: options.play_completion,
+        stream_rate_limit: options.stream_rate_limit,
+        recent_plays: Default::default(),
+        history_retention: options.history_retention,
+        cover_cache: options.cover_cache,
+        transcoder: options.transcoder,
+        fetch_missing_worker: Default::default(),
     };
 
-    // Serve static files from ./static directory
-    let static_service = ServeDir::new("static");
+    tokio::spawn(resume_fetch_missing(state.clone()));
 
     Router::new()
         .route("/", get(root))
+        .route("/ready", get(ready))
+        .route("/version", get(version))
         .route("/tracks", get(list_tracks))
+        .route("/tracks/changes", get(get_track_changes))
+        .route("/tracks/missing", get(list_missing_tracks))
+        .route("/tracks/by-path", get(get_track_by_path))
+        .route("/tracks/recently-added", get(list_recently_added))
+        .route(
+            "/tracks/refresh-flags",
+            axum::routing::post(refresh_track_flags),
+        )
         .route("/tracks/:id", get(get_track).put(update_track))
+        .route("/tracks/:id/raw-tags", get(get_raw_tags))
+        .route("/tracks/:id/detail", get(get_track_detail))
+        .route("/tracks/:id/lyrics-candidates", get(get_lyrics_candidates))
+        .route("/tracks/:id/download", get(download_track))
+        .route(
+            "/tracks/:id/cover/copy-from/:source_id",
+            axum::routing::post(copy_track_cover),
+        )
         .route(
             "/tracks/:id/play",
             axum::routing::post(increment_play_count),
         )
+        .route(
+            "/tracks/:id/played",
+            axum::routing::post(report_track_played),
+        )
+        .route(
+            "/tracks/:id/optimize-cover",
+            axum::routing::post(optimize_cover),
+        )
+        .route("/optimize-covers", axum::routing::post(optimize_covers))
+        .route("/covers/check", axum::routing::post(check_covers))
         .route("/stream/:id", get(stream_track))
         .route(
             "/cover/:id",
@@ -93,16 +516,58 @@ pub fn create_router(
                 .delete(delete_lyrics)
                 .layer(DefaultBodyLimit::max(MAX_LYRICS_SIZE)),
         )
+        .route("/lyrics/:id/all", get(get_all_lyrics))
         .route("/lyrics/search", get(search_lyrics))
+        .route("/lyrics/search/all", get(search_lyrics_all))
+        .route("/lyrics/embed-all", axum::routing::post(embed_all_lyrics))
+        .route(
+            "/lyrics/fetch-missing",
+            axum::routing::post(start_fetch_missing),
+        )
+        .route(
+            "/lyrics/fetch-missing/status",
+            get(get_fetch_missing_status),
+        )
+        .route(
+            "/lyrics/fetch-missing/cancel",
+            axum::routing::post(cancel_fetch_missing),
+        )
+        .route(
+            "/lyrics/fetch-missing/resume",
+            axum::routing::post(resume_fetch_missing_job),
+        )
+        .route("/lyrics/providers", get(list_lyrics_providers))
+        .route("/lyrics/providers/info", get(lyrics_providers_info))
         .route(
             "/lyrics/fetch/:provider/:song_id",
             get(fetch_lyrics_from_provider),
         )
         .route("/albums", get(list_albums))
+        .route("/albums/index", get(get_album_index))
         .route("/albums/:name", get(get_album))
+        .route("/albums/:name/cover", get(get_album_cover))
+        .route("/albums/:name/stream", get(stream_album))
+        .route(
+            "/albums/:name/cover/from-track/:id",
+            axum::routing::post(fill_album_cover),
+        )
+        .route("/albums/by-id/:id", get(get_album_by_id))
         .route("/artists", get(list_artists))
+        .route("/artists/index", get(get_artist_index))
         .route("/artists/:name", get(get_artist))
+        .route("/artists/:name/albums", get(get_artist_albums))
+        .route("/artists/by-id/:id", get(get_artist_by_id))
         .route("/stats", get(get_stats))
+        .route("/history", get(get_history))
+        .route("/suggest", get(suggest))
+        .route("/search", get(search_tracks))
+        .route("/scan", axum::routing::post(start_scan))
+        .route("/reindex", axum::routing::post(reindex))
+        .route("/rescan", axum::routing::post(rescan))
+        .route("/scan/status", get(get_scan_status))
+        .route("/scan/cancel", axum::routing::post(cancel_scan))
+        .route("/scan/errors", get(get_scan_errors))
+        .route("/inspect", get(inspect_path))
         .route("/playlists", get(list_playlists).post(create_playlist))
         .route(
             "/playlists/:id",
@@ -110,27 +575,259 @@ pub fn create_router(
                 .put(update_playlist)
                 .delete(delete_playlist),
         )
+        .route(
+            "/playlists/:id/tracks",
+            axum::routing::post(add_tracks_to_playlist).delete(remove_tracks_from_playlist),
+        )
         .route(
             "/playlists/:id/tracks/:track_id",
             axum::routing::post(add_track_to_playlist).delete(remove_track_from_playlist),
         )
-        .nest_service("/web", static_service)
+        .merge(web_routes(options.web_dir))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive())
         .with_state(state)
 }
 
+/// Set the `has_lyrics`, `play_count` and `date_added` fields of scanned tracks
+/// from the databases, storing the date of tracks seen for the first time.
+/// Lyrics the scans found embedded in newly parsed files are imported first
+/// for tracks without any in the database.
+pub async fn load_track_flags(
+    library: &MusicLibrary,
+    lyrics_db: &LyricDatabase,
+    stats_db: &StatsDatabase,
+) {
+    match lyrics_db.get_tracks_with_lyrics().await {
+        Ok(tracks_with_lyrics) => {
+            let imported = import_embedded_lyrics(library, lyrics_db, &tracks_with_lyrics).await;
+            for track_id in tracks_with_lyrics.iter().chain(&imported) {
+                library.update_track_lyrics_status(track_id, true).await;
+            }
+        }
+        Err(e) => tracing::warn!("Failed to load lyrics flags: {}", e),
+    }
+
+    match stats_db.get_all_stats().await {
+        Ok(stats) => {
+            library.update_track_stats(&stats).await;
+        }
+        Err(e) => tracing::warn!("Failed to load play counts: {}", e),
+    }
+
+    let dates: Vec<(String, String)> = library
+        .get_tracks()
+        .await
+        .into_iter()
+        .filter(|t| !t.date_added.is_empty())
+        .map(|t| (t.id, t.date_added))
+        .collect();
+    match stats_db.record_dates_added(&dates).await {
+        Ok(stored) => library.update_track_dates_added(&stored).await,
+        Err(e) => tracing::warn!("Failed to load dates added: {}", e),
+    }
+}
+
+/// Save the embedded lyrics read by the scans since the last call to the
+/// lyrics database for tracks not in `tracks_with_lyrics`, returning the IDs
+/// of the tracks that got some. CUE-sheet tracks are skipped: lyrics in their
+/// shared file belong to none of them.
+async fn import_embedded_lyrics(
+    library: &MusicLibrary,
+    lyrics_db: &LyricDatabase,
+    tracks_with_lyrics: &[String],
+) -> Vec<String> {
+    let mut found = library.take_embedded_lyrics().await;
+    for track_id in tracks_with_lyrics {
+        found.remove(track_id);
+    }
+    if found.is_empty() {
+        return Vec::new();
+    }
+
+    let mut imported = Vec::new();
+    for (track_id, lyrics) in found {
+        // Gone since the scan, or replaced by the tracks of a CUE sheet
+        if library
+            .get_track(&track_id)
+            .await
+            .is_none_or(|track| track.start_ms.is_some())
+        {
+            continue;
+        }
+        match lyrics_db
+            .save_lyric(
+                &track_id,
+                lyrics.content,
+                lyrics.format,
+                lyrics.language,
+                Some("embedded".to_string()),
+            )
+            .await
+        {
+            Ok(_) => imported.push(track_id),
+            Err(e) => tracing::warn!("Failed to import lyrics of {}: {}", track_id, e),
+        }
+    }
+    if !imported.is_empty() {
+        tracing::info!("Imported embedded lyrics of {} tracks", imported.len());
+    }
+    imported
+}
+
 /// Root endpoint
 async fn root() -> &'static str {
     "Music Station API v0.1.0"
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct TrackListQuery {
+    /// Tracks to leave out from the start of the list
+    skip: usize,
+    /// Most tracks to return; all of them when omitted
+    limit: Option<usize>,
+    /// Comma-separated `Track` fields to include, e.g. `id,title,artist`;
+    /// unknown names are ignored and the full object is returned when omitted
+    fields: Option<String>,
+}
+
 /// List all tracks
-async fn list_tracks(State(state): State<AppState>) -> Json<Vec<Track>> {
-    tracing::debug!("Fetching all tracks");
-    let tracks = state.library.get_tracks().await;
-    tracing::debug!("Returning {} tracks", tracks.len());
-    Json(tracks)
+///
+/// The JSON array is written one track at a time through a pipe instead of
+/// cloning and serializing the whole library at once, so peak memory stays
+/// bounded by a single track. The read lock is only held while copying each
+/// track; a rescan that finishes mid-response may shift the remaining entries.
+async fn list_tracks(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<TrackListQuery>,
+) -> Response {
+    tracing::debug!("Fetching all tracks: {:?}", query);
+
+    // Read before streaming starts, so changes made meanwhile are still
+    // reported by `GET /tracks/changes?since=<version>`
+    let version = state.library.version().await;
+    let (mut writer, reader) = tokio::io::duplex(LIST_STREAM_BUFFER_SIZE);
+    let library = state.library.clone();
+    tokio::spawn(async move {
+        if let Err(e) = write_track_list(&library, &query, &mut writer).await {
+            // Usually the client disconnected before the list was complete
+            tracing::debug!("Stopped streaming track list: {}", e);
+        }
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (LIBRARY_VERSION_HEADER, version.to_string()),
+        ],
+        Body::from_stream(ReaderStream::new(reader)),
+    )
+        .into_response()
+}
+
+/// Serialize the library's tracks selected by `query` as a JSON array into `writer`
+async fn write_track_list(
+    library: &MusicLibrary,
+    query: &TrackListQuery,
+    writer: &mut (impl tokio::io::AsyncWrite + Unpin),
+) -> std::io::Result<()> {
+    // An empty list (`fields=` or `fields=,`) selects every field, like no list
+    let fields: Option<Vec<&str>> = query
+        .fields
+        .as_deref()
+        .map(|fields| {
+            fields
+                .split(',')
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .filter(|fields| !fields.is_empty());
+    let limit = query.limit.unwrap_or(usize::MAX);
+    let mut buffer = Vec::new();
+    let mut count = 0;
+
+    writer.write_all(b"[").await?;
+    while count < limit {
+        let Some(track) = library.get_track_at(query.skip + count).await else {
+            break;
+        };
+        buffer.clear();
+        if count > 0 {
+            buffer.push(b',');
+        }
+        match &fields {
+            Some(fields) => serde_json::to_writer(&mut buffer, &project_fields(&track, fields)?)?,
+            None => serde_json::to_writer(&mut buffer, &track)?,
+        }
+        writer.write_all(&buffer).await?;
+        count += 1;
+    }
+    writer.write_all(b"]").await?;
+    writer.shutdown().await?;
+
+    tracing::debug!("Returned {} tracks", count);
+    Ok(())
+}
+
+/// JSON object with only the named fields of `value`; names it doesn't have are skipped
+fn project_fields(
+    value: &impl serde::Serialize,
+    fields: &[&str],
+) -> serde_json::Result<serde_json::Map<String, serde_json::Value>> {
+    let serde_json::Value::Object(mut object) = serde_json::to_value(value)? else {
+        return Ok(serde_json::Map::new());
+    };
+    Ok(fields
+        .iter()
+        .filter_map(|&field| object.remove_entry(field))
+        .collect())
+}
+
+#[derive(Debug, Deserialize)]
+struct TrackPathQuery {
+    /// File path relative to the library root, or absolute inside it
+    path: String,
+}
+
+/// Find the track stored in a file, for tools that know paths rather than IDs.
+/// Paths are checked like every other library path, so `..` is rejected.
+async fn get_track_by_path(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<TrackPathQuery>,
+) -> Result<Json<Track>, StatusCode> {
+    tracing::debug!("Fetching track by path: {}", query.path);
+    let requested = std::path::Path::new(&query.path);
+    let relative = if requested.is_absolute() {
+        state
+            .library
+            .strip_library_root(requested)
+            .await
+            .ok_or_else(|| {
+                tracing::warn!("Rejected path outside the library: {}", query.path);
+                StatusCode::BAD_REQUEST
+            })?
+    } else {
+        requested.to_path_buf()
+    };
+    let path = resolve_library_subpath(&state.library, &relative.to_string_lossy()).await?;
+    let relative: std::path::PathBuf = path
+        .strip_prefix(state.library.library_path())
+        .unwrap_or(&path)
+        .components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .collect();
+
+    state
+        .library
+        .get_track_by_path(&relative)
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            tracing::debug!("No track at path {}", query.path);
+            StatusCode::NOT_FOUND
+        })
 }
 
 /// Get a specific track by ID
@@ -141,7 +838,7 @@ async fn get_track(
     tracing::debug!("Fetching track with id: {}", id);
     let result = state
         .library
-        .get_track(&id)
+        .verify_track(&id)
         .await
         .map(Json)
         .ok_or(StatusCode::NOT_FOUND);
@@ -155,105 +852,773 @@ async fn get_track(
     result
 }
 
-/// Increment play count for a track
-async fn increment_play_count(
+/// Check every track's file and list the tracks whose file no longer exists
+async fn list_missing_tracks(State(state): State<AppState>) -> Json<Vec<Track>> {
+    let missing = state.library.find_missing_tracks().await;
+    tracing::debug!("{} tracks have missing files", missing.len());
+    Json(missing)
+}
+
+#[derive(Debug, Deserialize)]
+struct RecentlyAddedQuery {
+    #[serde(default = "default_recently_added_limit")]
+    limit: usize,
+    /// Only tracks added within this many days
+    days: Option<u64>,
+}
+
+fn default_recently_added_limit() -> usize {
+    50
+}
+
+/// List the most recently added tracks, newest first
+async fn list_recently_added(
     State(state): State<AppState>,
-    Path(id): Path<String>,
-) -> Result<Json<u64>, StatusCode> {
-    tracing::debug!("Incrementing play count for track: {}", id);
+    axum::extract::Query(query): axum::extract::Query<RecentlyAddedQuery>,
+) -> Json<Vec<Track>> {
+    Json(state.library.recently_added(query.limit, query.days).await)
+}
 
-    // Check if track exists
-    if state.library.get_track(&id).await.is_none() {
-        return Err(StatusCode::NOT_FOUND);
-    }
+/// Files checked at once by `/tracks/refresh-flags`
+const FLAG_REFRESH_CONCURRENCY: usize = 8;
 
-    let count = state
-        .stats_db
-        .increment_play_count(&id)
+/// Re-check `has_cover` from the files and `has_lyrics` from the lyrics
+/// database for every track, without a full rescan
+async fn refresh_track_flags(
+    State(state): State<AppState>,
+) -> Result<Json<FlagRefreshReport>, StatusCode> {
+    let tracks_with_lyrics: HashSet<String> = state
+        .lyrics_db
+        .get_tracks_with_lyrics()
         .await
         .map_err(|e| {
-            tracing::error!("Failed to increment play count: {}", e);
+            tracing::error!("Failed to load lyrics flags: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+        })?
+        .into_iter()
+        .collect();
 
-    // Update in-memory library
-    state.library.update_track_play_count(&id, count).await;
+    let report = state
+        .library
+        .refresh_flags(&tracks_with_lyrics, FLAG_REFRESH_CONCURRENCY)
+        .await;
+    tracing::info!(
+        "Refreshed track flags: {} files checked, {} cover and {} lyrics flags updated",
+        report.files_checked,
+        report.cover_updated,
+        report.lyrics_updated
+    );
+    Ok(Json(report))
+}
 
-    Ok(Json(count))
+#[derive(Debug, Deserialize)]
+struct ChangesQuery {
+    #[serde(default)]
+    since: u64,
 }
 
-/// Stream a track by ID with HTTP Range support
-async fn stream_track(
+/// Get tracks added, updated or removed since a library version
+async fn get_track_changes(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<ChangesQuery>,
+) -> Json<TrackChanges> {
+    let changes = state.library.changes_since(query.since).await;
+    tracing::debug!(
+        "Changes since {}: {} added, {} updated, {} removed (version {}, resync: {})",
+        query.since,
+        changes.added.len(),
+        changes.updated.len(),
+        changes.removed.len(),
+        changes.version,
+        changes.full_resync_required
+    );
+    Json(changes)
+}
+
+/// Get every tag stored in a track's file, without normalization
+async fn get_raw_tags(
     State(state): State<AppState>,
     Path(id): Path<String>,
-    headers: HeaderMap,
-) -> Result<Response, StatusCode> {
-    tracing::debug!("Streaming track with id: {}", id);
+) -> Result<Json<Vec<RawTag>>, StatusCode> {
+    tracing::debug!("Reading raw tags for track: {}", id);
+
     let track = state
         .library
         .get_track(&id)
         .await
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    tracing::debug!("Streaming file: {}", track.path.display());
-
-    // Determine content type based on file extension
-    let content_type = match track.path.extension().and_then(|s| s.to_str()) {
-        Some("flac") => "audio/flac",
-        Some("mp3") => "audio/mpeg",
-        Some("ogg") => "audio/ogg",
-        Some("m4a") => "audio/mp4",
-        _ => "application/octet-stream",
-    };
-
-    // Get file metadata
-    let file_metadata = tokio::fs::metadata(&track.path)
+    state
+        .library
+        .read_all_tags(&track.path)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let file_size = file_metadata.len();
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Error reading raw tags for track {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
 
-    // Parse Range header
-    let range_header = headers.get(header::RANGE);
+/// A track together with its lyrics and playback stats
+#[derive(Debug, serde::Serialize)]
+struct TrackDetail {
+    track: Track,
+    lyrics: Option<Lyric>,
+    stats: TrackStats,
+}
 
-    if let Some(range_value) = range_header {
-        // Parse range: "bytes=start-end"
-        if let Ok(range_str) = range_value.to_str() {
-            if let Some(range) = parse_range(range_str, file_size) {
-                return stream_range(&track.path, range.0, range.1, file_size, content_type).await;
-            }
-        }
-    }
+/// Get a track, its lyrics and its stats in one call
+async fn get_track_detail(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<TrackDetail>, StatusCode> {
+    tracing::debug!("Fetching detail for track: {}", id);
 
-    // No range or invalid range - stream entire file
-    let file = tokio::fs::File::open(&track.path)
+    let track = state
+        .library
+        .get_track(&id)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .ok_or(StatusCode::NOT_FOUND)?;
 
-    let stream = ReaderStream::new(file);
-    let body = Body::from_stream(stream);
+    let (lyrics, stats) = tokio::try_join!(
+        async { Ok(state.lyrics_db.get_lyric(&id).await?) },
+        state.stats_db.get_stats(&id)
+    )
+    .map_err(|e| {
+        tracing::error!("Error fetching detail for track {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
-    tracing::debug!("Streaming {} bytes for track {}", file_size, id);
+    Ok(Json(TrackDetail {
+        track,
+        lyrics,
+        stats,
+    }))
+}
 
-    // Return the file as a streaming response
-    Ok((
-        StatusCode::OK,
-        [
-            (header::CONTENT_TYPE, content_type.to_string()),
-            (header::CONTENT_LENGTH, file_size.to_string()),
-            (header::ACCEPT_RANGES, "bytes".to_string()),
-            (
-                header::CONTENT_DISPOSITION,
-                format!(
-                    "inline; filename=\"{}\"",
-                    track.path.file_name().unwrap().to_string_lossy()
-                ),
-            ),
-        ],
-        body,
+#[derive(Debug, Deserialize)]
+struct PlayQuery {
+    /// Count the play even inside the debounce window
+    #[serde(default)]
+    force: bool,
+    /// Debounce per client instead of per track only
+    client_id: Option<String>,
+    /// How far into the track playback got, in seconds, kept in the history
+    position: Option<u64>,
+}
+
+/// Increment play count for a track
+async fn increment_play_count(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<PlayQuery>,
+) -> Result<Json<u64>, StatusCode> {
+    tracing::debug!("Incrementing play count for track: {}", id);
+
+    // Check if track exists
+    let track = state
+        .library
+        .get_track(&id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let (_, count) = count_play(&state, &track, query).await?;
+    Ok(Json(count))
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayedRequest {
+    /// How far playback got
+    position_ms: u64,
+    /// Length of the track as the client saw it; defaults to the library's duration
+    duration_ms: Option<u64>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct PlayedResponse {
+    /// Whether this report incremented the play count
+    counted: bool,
+    play_count: u64,
+}
+
+/// Report how far a track was played; the play counts only once the
+/// completion threshold is reached
+async fn report_track_played(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<PlayQuery>,
+    Json(request): Json<PlayedRequest>,
+) -> Result<Json<PlayedResponse>, StatusCode> {
+    let track = state
+        .library
+        .get_track(&id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let position = Duration::from_millis(request.position_ms);
+    let duration = request
+        .duration_ms
+        .map(Duration::from_millis)
+        .or(track.duration_secs.map(Duration::from_secs))
+        .filter(|duration| !duration.is_zero());
+    if !state.play_completion.is_complete(position, duration) {
+        tracing::debug!(
+            "Track {} played to {:?} of {:?}; not counted",
+            id,
+            position,
+            duration
+        );
+        return Ok(Json(PlayedResponse {
+            counted: false,
+            play_count: track.play_count,
+        }));
+    }
+
+    let query = PlayQuery {
+        position: Some(position.as_secs()),
+        ..query
+    };
+    let (counted, play_count) = count_play(&state, &track, query).await?;
+    Ok(Json(PlayedResponse {
+        counted,
+        play_count,
+    }))
+}
+
+/// Count a play of `track` unless it is debounced, and log it to the history.
+/// Returns whether the play was counted and the resulting play count.
+async fn count_play(
+    state: &AppState,
+    track: &Track,
+    query: PlayQuery,
+) -> Result<(bool, u64), StatusCode> {
+    let id = &track.id;
+    if !query.force && !state.debounce_play(id, query.client_id).await {
+        tracing::debug!(
+            "Ignoring repeated play of track {} within debounce window",
+            id
+        );
+        return Ok((false, track.play_count));
+    }
+
+    let count = state.stats_db.increment_play_count(id).await.map_err(|e| {
+        tracing::error!("Failed to increment play count: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // Update in-memory library
+    state.library.record_track_play(id, count).await;
+
+    // The play is already counted, so a history failure is only logged
+    if let Err(e) = record_history(state, id, query.position).await {
+        tracing::error!("Failed to record play history: {:#}", e);
+    }
+
+    Ok((true, count))
+}
+
+/// Append a counted play to the listening history and drop entries past the retention
+async fn record_history(
+    state: &AppState,
+    track_id: &str,
+    position: Option<u64>,
+) -> anyhow::Result<()> {
+    state.stats_db.record_history(track_id, position).await?;
+    // A retention reaching past chrono's range keeps every entry
+    let cutoff = state.history_retention.and_then(|retention| {
+        chrono::Utc::now().checked_sub_signed(chrono::Duration::from_std(retention).ok()?)
+    });
+    if let Some(cutoff) = cutoff {
+        let pruned = state.stats_db.prune_history(cutoff).await?;
+        if pruned > 0 {
+            tracing::debug!("Pruned {} play history entries", pruned);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    #[serde(default = "default_history_limit")]
+    limit: usize,
+    /// Only plays at or after this time (RFC 3339)
+    from: Option<String>,
+    /// Only plays at or before this time (RFC 3339)
+    to: Option<String>,
+}
+
+fn default_history_limit() -> usize {
+    50
+}
+
+/// A listening history entry with the track it refers to
+#[derive(Debug, serde::Serialize)]
+struct HistoryItem {
+    #[serde(flatten)]
+    entry: HistoryEntry,
+    /// `None` when the track is no longer in the library
+    track: Option<Track>,
+}
+
+/// List counted plays, newest first
+async fn get_history(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<HistoryQuery>,
+) -> Result<Json<Vec<HistoryItem>>, StatusCode> {
+    let parse_time = |value: Option<&str>| {
+        value
+            .map(|value| {
+                chrono::DateTime::parse_from_rfc3339(value)
+                    .map(|time| time.with_timezone(&chrono::Utc))
+                    .map_err(|e| {
+                        tracing::warn!("Invalid history time {:?}: {}", value, e);
+                        StatusCode::BAD_REQUEST
+                    })
+            })
+            .transpose()
+    };
+    let from = parse_time(query.from.as_deref())?;
+    let to = parse_time(query.to.as_deref())?;
+
+    let entries = state
+        .stats_db
+        .get_history(query.limit, from, to)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to get play history: {:#}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut items = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let track = state.library.get_track(&entry.track_id).await;
+        items.push(HistoryItem { entry, track });
+    }
+    tracing::debug!("Returning {} play history entries", items.len());
+    Ok(Json(items))
+}
+
+/// Stream a track by ID with HTTP Range support
+async fn stream_track(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<StreamQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let transcode = match query.transcode.as_deref() {
+        // Only transcoding can resample, so don't silently serve the original
+        None if query.sample_rate.is_some() => {
+            tracing::debug!("sample_rate given without a transcode target");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        None => None,
+        Some(codec) if codec.eq_ignore_ascii_case("opus") => {
+            let bitrate = query.bitrate.unwrap_or(DEFAULT_OPUS_BITRATE_KBPS);
+            if !OPUS_BITRATES_KBPS.contains(&bitrate) {
+                tracing::debug!("Unsupported Opus bitrate: {}", bitrate);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            if let Some(rate) = query.sample_rate
+                && !TRANSCODE_SAMPLE_RATES.contains(&rate)
+            {
+                tracing::debug!("Unsupported transcode sample rate: {}", rate);
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            Some(OpusOptions {
+                bitrate_kbps: bitrate,
+                max_sample_rate: query.sample_rate,
+            })
+        }
+        Some(codec) => {
+            tracing::debug!("Unsupported transcode target: {}", codec);
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+    serve_track(&state, &id, &headers, query.download, transcode).await
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamQuery {
+    /// Send `Content-Disposition: attachment` so browsers save the file
+    #[serde(default)]
+    download: bool,
+    /// Codec to transcode to on the fly; only `opus` is supported
+    transcode: Option<String>,
+    /// Transcode bitrate in kbit/s, one of [`OPUS_BITRATES_KBPS`]
+    bitrate: Option<u32>,
+    /// Highest sample rate of the transcoded output, one of
+    /// [`TRANSCODE_SAMPLE_RATES`]; higher-rate sources are downsampled
+    sample_rate: Option<u32>,
+}
+
+/// Download a track's file (`/stream/:id?download=true`)
+async fn download_track(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    serve_track(&state, &id, &headers, true, None).await
+}
+
+/// Stream a track for playback (`inline`) or as a file download (`attachment`),
+/// transcoded to Opus if options are passed
+async fn serve_track(
+    state: &AppState,
+    id: &str,
+    headers: &HeaderMap,
+    download: bool,
+    opus: Option<OpusOptions>,
+) -> Result<Response, StatusCode> {
+    tracing::debug!("Streaming track with id: {} (download: {})", id, download);
+    let track = state
+        .library
+        .verify_track(id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if track.missing {
+        return Err(StatusCode::GONE);
+    }
+
+    let (mut response, filename) = match opus {
+        Some(options) => {
+            let filename = std::path::Path::new(&track.filename).with_extension("opus");
+            (
+                transcode_track(state, &track, headers, options).await?,
+                filename.to_string_lossy().into_owned(),
+            )
+        }
+        None => (
+            stream_track_file(state, &track, headers).await?,
+            track.filename.clone(),
+        ),
+    };
+    if response.status().is_success() {
+        let disposition = content_disposition(download, &filename);
+        response.headers_mut().insert(
+            header::CONTENT_DISPOSITION,
+            disposition
+                .parse()
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        );
+    }
+
+    // Let players normalize loudness client-side; omitted when the file has no tags
+    let gains = [
+        ("x-replaygain-track-gain", track.replaygain_track_gain),
+        ("x-replaygain-album-gain", track.replaygain_album_gain),
+    ];
+    for (name, gain) in gains {
+        if let Some(gain) = gain
+            && let Ok(value) = format!("{:.2} dB", gain).parse()
+        {
+            response.headers_mut().insert(name, value);
+        }
+    }
+
+    Ok(response)
+}
+
+/// Stream a track encoded as Ogg Opus by the transcoder. The output only
+/// exists as it is produced, so it has no length and ranges other than the
+/// `bytes=0-` players open media with are refused.
+async fn transcode_track(
+    state: &AppState,
+    track: &Track,
+    headers: &HeaderMap,
+    options: OpusOptions,
+) -> Result<Response, StatusCode> {
+    if let Some(range) = headers.get(header::RANGE)
+        && range.to_str().ok().map(str::trim) != Some("bytes=0-")
+    {
+        tracing::debug!(
+            "Refusing range {:?} of transcoded track {}",
+            range,
+            track.id
+        );
+        return Ok((
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::ACCEPT_RANGES, "none")],
+        )
+            .into_response());
+    }
+
+    let clip = (track.start_ms.unwrap_or(0), track.end_ms);
+    let output = state
+        .transcoder
+        .opus(&track.path, clip, options)
+        .await
+        .map_err(|e| {
+            if e.downcast_ref::<UnsupportedSource>().is_some() {
+                tracing::debug!("Can't transcode track {}: {:#}", track.id, e);
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            } else {
+                tracing::error!("Failed to transcode track {}: {:#}", track.id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?
+        .ok_or_else(|| {
+            tracing::warn!("All transcode slots are busy, refusing track {}", track.id);
+            StatusCode::SERVICE_UNAVAILABLE
+        })?;
+
+    tracing::debug!("Transcoding track {} to Opus ({:?})", track.id, options);
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, "audio/ogg"),
+            (header::ACCEPT_RANGES, "none"),
+        ],
+        stream_body(output, state.stream_rate_limit),
+    )
+        .into_response())
+}
+
+/// Build the full, ranged or CUE-track response for streaming a track's file
+async fn stream_track_file(
+    state: &AppState,
+    track: &Track,
+    headers: &HeaderMap,
+) -> Result<Response, StatusCode> {
+    let id = &track.id;
+    tracing::debug!("Streaming file: {}", track.path.display());
+
+    let content_type = audio_content_type(&track.path);
+
+    // Get file metadata
+    let file_metadata = tokio::fs::metadata(&track.path)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let file_size = file_metadata.len();
+
+    if let Some(start_ms) = track.start_ms {
+        if let Some(total_ms) = state.library.cue_file_duration_ms(&track.path).await {
+            return stream_cue_track(
+                track,
+                (start_ms, total_ms),
+                file_size,
+                content_type,
+                headers,
+                state.stream_rate_limit,
+            )
+            .await;
+        }
+        tracing::warn!(
+            "Unknown duration for {}, streaming the whole file for CUE track {}",
+            track.path.display(),
+            id
+        );
+    }
+
+    // Parse Range header
+    let range_header = headers.get(header::RANGE);
+
+    if let Some(range_value) = range_header {
+        // Parse range: "bytes=start-end"
+        if let Ok(range_str) = range_value.to_str() {
+            if let Some(range) = parse_range(range_str, file_size) {
+                return stream_range(
+                    &track.path,
+                    range.0,
+                    range.1,
+                    file_size,
+                    content_type,
+                    state.stream_rate_limit,
+                )
+                .await;
+            }
+            if range_not_satisfiable(range_str, file_size) {
+                tracing::debug!("Unsatisfiable range {} for track {}", range_str, id);
+                return Ok((
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    [(header::CONTENT_RANGE, format!("bytes */{}", file_size))],
+                )
+                    .into_response());
+            }
+        }
+    }
+
+    // No range or invalid range - stream entire file
+    let file = tokio::fs::File::open(&track.path)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let body = stream_body(file, state.stream_rate_limit);
+
+    tracing::debug!("Streaming {} bytes for track {}", file_size, id);
+
+    // Return the file as a streaming response
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_LENGTH, file_size.to_string()),
+            (header::ACCEPT_RANGES, "bytes".to_string()),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Content type of an audio file, based on its extension
+fn audio_content_type(path: &std::path::Path) -> &'static str {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .and_then(crate::audio::audio_mime_type)
+        .unwrap_or("application/octet-stream")
+}
+
+/// Stream a whole album as one response: every track's file, in play order,
+/// back to back. There are no ranges; clients stop by closing the connection.
+async fn stream_album(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Response, StatusCode> {
+    tracing::debug!("Streaming album: {}", name);
+
+    let album = state
+        .library
+        .get_album(&name)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // CUE tracks of one image share a file, which is sent once
+    let mut files: Vec<&Track> = Vec::new();
+    for track in album.play_order() {
+        if !files.iter().any(|f| f.path == track.path) {
+            files.push(track);
+        }
+    }
+
+    let content_type = album_stream_type(&files).ok_or_else(|| {
+        tracing::warn!(
+            "Album {} isn't all MP3 or all Ogg and can't be streamed as one",
+            name
+        );
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let mut reader: Box<dyn tokio::io::AsyncRead + Send + Unpin> = Box::new(tokio::io::empty());
+    let mut content_length = 0u64;
+    for track in files {
+        let file = match tokio::fs::File::open(&track.path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                tracing::warn!(
+                    "Album {} track file is gone: {}",
+                    name,
+                    track.path.display()
+                );
+                return Err(StatusCode::GONE);
+            }
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+        };
+        content_length += file
+            .metadata()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .len();
+        reader = Box::new(reader.chain(file));
+    }
+
+    tracing::debug!("Streaming {} bytes for album {}", content_length, name);
+
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_LENGTH, content_length.to_string()),
+            (header::ACCEPT_RANGES, "none".to_string()),
+        ],
+        stream_body(reader, state.stream_rate_limit),
     )
         .into_response())
 }
 
+/// Formats whose files still play as one stream when sent back to back: MP3
+/// frames resync after each file's tags, and Ogg allows chained streams
+const CONCATENABLE_EXTENSIONS: &[&str] = &["mp3", "ogg", "opus"];
+
+/// Content type shared by all of an album's files, or `None` when they mix
+/// formats or their format can't be concatenated
+fn album_stream_type(tracks: &[&Track]) -> Option<&'static str> {
+    let extension = |track: &Track| {
+        track
+            .path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase)
+    };
+    let first = extension(tracks.first()?)?;
+    if !CONCATENABLE_EXTENSIONS.contains(&first.as_str()) {
+        return None;
+    }
+    tracks
+        .iter()
+        .all(|t| extension(t).as_deref() == Some(first.as_str()))
+        .then(|| audio_content_type(&tracks[0].path))
+}
+
+/// Wrap an audio reader in a response body, pacing it to `rate_limit` bytes per
+/// second when non-zero. The pacing only delays chunks, so the bytes (and any
+/// range the reader was cut to) are unchanged.
+fn stream_body<R>(reader: R, rate_limit: u64) -> Body
+where
+    R: tokio::io::AsyncRead + Send + 'static,
+{
+    use futures::StreamExt;
+
+    if rate_limit == 0 {
+        return Body::from_stream(ReaderStream::new(reader));
+    }
+
+    // Roughly four chunks per second keeps the rate smooth without tiny reads
+    let chunk_size = (rate_limit / 4).clamp(1, STREAM_CHUNK_MAX as u64) as usize;
+    let started = tokio::time::Instant::now();
+    let mut sent = 0u64;
+    let paced = ReaderStream::with_capacity(reader, chunk_size).then(move |chunk| {
+        let due = started + Duration::from_secs_f64(sent as f64 / rate_limit as f64);
+        if let Ok(bytes) = &chunk {
+            sent += bytes.len() as u64;
+        }
+        async move {
+            tokio::time::sleep_until(due).await;
+            chunk
+        }
+    });
+    Body::from_stream(paced)
+}
+
+/// `Content-Disposition` value for a track file. The quoted `filename` is an
+/// ASCII fallback; non-ASCII names are also sent as RFC 5987 `filename*`.
+fn content_disposition(download: bool, filename: &str) -> String {
+    let kind = if download { "attachment" } else { "inline" };
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '_',
+        })
+        .collect();
+
+    if filename.is_ascii() {
+        return format!("{}; filename=\"{}\"", kind, fallback);
+    }
+    let encoded: String = filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        kind, fallback, encoded
+    )
+}
+
 /// Parse Range header value
 /// Returns (start, end) tuple if valid
 fn parse_range(range_str: &str, file_size: u64) -> Option<(u64, u64)> {
@@ -291,9 +1656,10 @@ fn parse_range(range_str: &str, file_size: u64) -> Option<(u64, u64)> {
             Some((start, file_size - 1))
         }
         (true, false) => {
-            // "bytes=-end" (last N bytes)
-            let suffix_length = end_str.parse::<u64>().ok()?;
-            if suffix_length == 0 || suffix_length > file_size {
+            // "bytes=-end" (last N bytes); a suffix longer than the file
+            // selects the whole file (RFC 7233 section 2.1)
+            let suffix_length = end_str.parse::<u64>().ok()?.min(file_size);
+            if suffix_length == 0 {
                 return None;
             }
             Some((file_size - suffix_length, file_size - 1))
@@ -302,6 +1668,38 @@ fn parse_range(range_str: &str, file_size: u64) -> Option<(u64, u64)> {
     }
 }
 
+/// Whether a Range header must be answered with 416 rather than the full file:
+/// a well-formed range asking only for bytes past the end of the file (or for
+/// an empty suffix, or any suffix of an empty file), or a multi-range request. Multiple ranges would need a
+/// `multipart/byteranges` response, which audio clients don't use, so they
+/// are rejected explicitly instead of being ignored.
+fn range_not_satisfiable(range_str: &str, file_size: u64) -> bool {
+    if range_str.starts_with("bytes=") && range_str.contains(',') {
+        return true;
+    }
+
+    let Some((start_str, end_str)) = range_str
+        .strip_prefix("bytes=")
+        .and_then(|range| range.split_once('-'))
+    else {
+        return false;
+    };
+    let (start_str, end_str) = (start_str.trim(), end_str.trim());
+
+    if start_str.is_empty() {
+        // "bytes=-0", or "bytes=-N" of an empty file
+        return end_str
+            .parse::<u64>()
+            .is_ok_and(|suffix| suffix == 0 || file_size == 0);
+    }
+
+    let Ok(start) = start_str.parse::<u64>() else {
+        return false;
+    };
+    let end_valid = end_str.is_empty() || end_str.parse::<u64>().is_ok_and(|end| end >= start);
+    end_valid && start >= file_size
+}
+
 /// Stream a range of bytes from a file
 async fn stream_range(
     path: &std::path::Path,
@@ -309,6 +1707,7 @@ async fn stream_range(
     end: u64,
     total_size: u64,
     content_type: &str,
+    rate_limit: u64,
 ) -> Result<Response, StatusCode> {
     let mut file = tokio::fs::File::open(path)
         .await
@@ -330,9 +1729,7 @@ async fn stream_range(
     );
 
     // Wrap in a take adapter to limit to the requested range, then stream
-    let limited = file.take(range_length);
-    let stream = ReaderStream::new(limited);
-    let body = Body::from_stream(stream);
+    let body = stream_body(file.take(range_length), rate_limit);
 
     // Return 206 Partial Content
     Ok((
@@ -351,16 +1748,149 @@ async fn stream_range(
         .into_response())
 }
 
-/// Update track metadata
-async fn update_track(
-    State(state): State<AppState>,
-    Path(id): Path<String>,
-    Json(update): Json<TrackMetadataUpdate>,
-) -> Result<Json<Track>, StatusCode> {
-    tracing::debug!(
-        "Updating track {} with metadata: title={:?}, artist={:?}, album={:?}",
-        id,
-        update.title,
+/// Stream the part of a shared file covered by a CUE-sheet track.
+///
+/// Times map to bytes proportionally over the audio data, so cut points are
+/// approximate and decoders resynchronize on the next frame. Range requests
+/// address the resulting virtual stream.
+async fn stream_cue_track(
+    track: &Track,
+    (start_ms, total_ms): (u64, u64),
+    file_size: u64,
+    content_type: &str,
+    headers: &HeaderMap,
+    rate_limit: u64,
+) -> Result<Response, StatusCode> {
+    let path = track.path.clone();
+    let layout = tokio::task::spawn_blocking(move || crate::audio::stream_layout(&path))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| {
+            tracing::error!(
+                "Failed to read stream layout of {}: {}",
+                track.path.display(),
+                e
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let segments = cue_track_segments(layout, file_size, start_ms, track.end_ms, total_ms);
+    let total_len: u64 = segments.iter().map(|(start, end)| end - start).sum();
+
+    let range_str = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+    let mut response_headers = vec![
+        (header::CONTENT_TYPE, content_type.to_string()),
+        (header::ACCEPT_RANGES, "bytes".to_string()),
+    ];
+
+    let (status, selected) = match range_str.map(|r| (r, parse_range(r, total_len))) {
+        Some((_, Some((start, end)))) => {
+            response_headers.push((
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, total_len),
+            ));
+            (
+                StatusCode::PARTIAL_CONTENT,
+                slice_segments(&segments, start, end + 1),
+            )
+        }
+        Some((range_str, None)) if range_not_satisfiable(range_str, total_len) => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", total_len))],
+            )
+                .into_response());
+        }
+        _ => (StatusCode::OK, segments),
+    };
+
+    let content_length: u64 = selected.iter().map(|(start, end)| end - start).sum();
+    response_headers.push((header::CONTENT_LENGTH, content_length.to_string()));
+
+    tracing::debug!(
+        "Streaming {} bytes of CUE track {} from {}",
+        content_length,
+        track.id,
+        track.path.display()
+    );
+
+    let mut reader: Box<dyn tokio::io::AsyncRead + Send + Unpin> = Box::new(tokio::io::empty());
+    for (start, end) in selected {
+        let mut file = tokio::fs::File::open(&track.path)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        file.seek(std::io::SeekFrom::Start(start))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        reader = Box::new(reader.chain(file.take(end - start)));
+    }
+
+    let mut response = stream_body(reader, rate_limit).into_response();
+    *response.status_mut() = status;
+    for (name, value) in response_headers {
+        response.headers_mut().insert(
+            name,
+            value
+                .parse()
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        );
+    }
+    Ok(response)
+}
+
+/// Half-open byte segments making up a CUE track: the stream header (if the
+/// format needs one), then the audio between the track's start and end times
+fn cue_track_segments(
+    layout: crate::audio::StreamLayout,
+    file_size: u64,
+    start_ms: u64,
+    end_ms: Option<u64>,
+    total_ms: u64,
+) -> Vec<(u64, u64)> {
+    let audio_len = file_size.saturating_sub(layout.audio_start);
+    let to_byte = |ms: u64| {
+        let fraction = u128::from(audio_len) * u128::from(ms.min(total_ms));
+        layout.audio_start + (fraction / u128::from(total_ms.max(1))) as u64
+    };
+
+    let mut segments = Vec::new();
+    if layout.header_len > 0 {
+        segments.push((0, layout.header_len));
+    }
+    let (start, end) = (to_byte(start_ms), end_ms.map_or(file_size, to_byte));
+    if end > start {
+        segments.push((start, end));
+    }
+    segments
+}
+
+/// Select the virtual bytes `start..end` out of a list of half-open file segments
+fn slice_segments(segments: &[(u64, u64)], start: u64, end: u64) -> Vec<(u64, u64)> {
+    let mut selected = Vec::new();
+    let mut offset = 0;
+    for &(seg_start, seg_end) in segments {
+        let len = seg_end - seg_start;
+        let (from, to) = (start.max(offset), end.min(offset + len));
+        if from < to {
+            selected.push((seg_start + from - offset, seg_start + to - offset));
+        }
+        offset += len;
+    }
+    selected
+}
+
+/// Update track metadata
+async fn update_track(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(update): Json<TrackMetadataUpdate>,
+) -> Result<Json<Track>, StatusCode> {
+    tracing::debug!(
+        "Updating track {} with metadata: title={:?}, artist={:?}, album={:?}",
+        id,
+        update.title,
         update.artist,
         update.album
     );
@@ -370,10 +1900,7 @@ async fn update_track(
         .update_track_metadata(&id, update)
         .await
         .map(Json)
-        .map_err(|e| {
-            tracing::error!("Failed to update track metadata: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        });
+        .map_err(|e| track_edit_error_status(&id, "update metadata", &e));
 
     if result.is_ok() {
         tracing::debug!("Successfully updated track {}", id);
@@ -382,6 +1909,22 @@ async fn update_track(
     result
 }
 
+/// Log a failed edit of a track's file and map it to 404 for unknown tracks,
+/// 422 for edits the track can't take (CUE-sheet tracks) and 500 otherwise
+fn track_edit_error_status(id: &str, action: &str, e: &MusicStationError) -> StatusCode {
+    match e {
+        MusicStationError::NotFound(_) => StatusCode::NOT_FOUND,
+        MusicStationError::Unsupported(_) => {
+            tracing::warn!("Can't {} for track {}: {}", action, id, e);
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        e => {
+            tracing::error!("Failed to {} for track {}: {}", action, id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
 /// List all albums
 async fn list_albums(State(state): State<AppState>) -> Json<Vec<Album>> {
     tracing::debug!("Fetching all albums");
@@ -390,6 +1933,12 @@ async fn list_albums(State(state): State<AppState>) -> Json<Vec<Album>> {
     Json(albums)
 }
 
+/// Alphabetical jump-list over `/albums`: per letter, the count and the offset
+/// of its first album
+async fn get_album_index(State(state): State<AppState>) -> Json<Vec<NameIndexEntry>> {
+    Json(state.library.get_album_index().await)
+}
+
 /// Get a specific album by name
 async fn get_album(
     State(state): State<AppState>,
@@ -412,6 +1961,23 @@ async fn get_album(
     result
 }
 
+/// Get a specific album by its name-derived ID
+async fn get_album_by_id(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Album>, StatusCode> {
+    tracing::debug!("Fetching album by id: {}", id);
+    state
+        .library
+        .get_album_by_id(&id)
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            tracing::warn!("Album with id {} not found", id);
+            StatusCode::NOT_FOUND
+        })
+}
+
 /// List all artists
 async fn list_artists(State(state): State<AppState>) -> Json<Vec<Artist>> {
     tracing::debug!("Fetching all artists");
@@ -420,6 +1986,12 @@ async fn list_artists(State(state): State<AppState>) -> Json<Vec<Artist>> {
     Json(artists)
 }
 
+/// Alphabetical jump-list over `/artists`: per letter, the count and the
+/// offset of its first artist
+async fn get_artist_index(State(state): State<AppState>) -> Json<Vec<NameIndexEntry>> {
+    Json(state.library.get_artist_index().await)
+}
+
 /// Get a specific artist by name
 async fn get_artist(
     State(state): State<AppState>,
@@ -442,6 +2014,316 @@ async fn get_artist(
     result
 }
 
+/// List an artist's albums without their tracks
+async fn get_artist_albums(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<AlbumSummary>>, StatusCode> {
+    tracing::debug!("Fetching albums for artist: {}", name);
+    state
+        .library
+        .get_artist_albums(&name)
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            tracing::warn!("Artist {} not found", name);
+            StatusCode::NOT_FOUND
+        })
+}
+
+/// Get a specific artist by its name-derived ID
+async fn get_artist_by_id(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Artist>, StatusCode> {
+    tracing::debug!("Fetching artist by id: {}", id);
+    state
+        .library
+        .get_artist_by_id(&id)
+        .await
+        .map(Json)
+        .ok_or_else(|| {
+            tracing::warn!("Artist with id {} not found", id);
+            StatusCode::NOT_FOUND
+        })
+}
+
+/// Start a background rescan of the library
+async fn start_scan(State(state): State<AppState>) -> Result<StatusCode, StatusCode> {
+    let scan = state.library.spawn_scan().await.map_err(|_| {
+        tracing::warn!("Rescan requested while a scan is already running");
+        StatusCode::CONFLICT
+    })?;
+
+    tokio::spawn(async move {
+        match scan.await {
+            // Needed when the previous list was empty, e.g. with --no-scan-on-start
+            Ok(Ok(())) => load_track_flags(&state.library, &state.lyrics_db, &state.stats_db).await,
+            Ok(Err(e)) => tracing::warn!("Rescan did not complete: {:#}", e),
+            Err(e) => tracing::error!("Rescan task failed: {}", e),
+        }
+    });
+
+    tracing::info!("Started library rescan");
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Reload lyrics flags and play counts from the databases, e.g. after editing
+/// them by hand, and rebuild the album and artist groupings without a rescan
+async fn reindex(State(state): State<AppState>) -> Result<Json<ReindexReport>, StatusCode> {
+    let tracks_with_lyrics: HashSet<String> = state
+        .lyrics_db
+        .get_tracks_with_lyrics()
+        .await
+        .map_err(|e| {
+            tracing::error!("Error reading lyrics for reindex: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .into_iter()
+        .collect();
+    let stats = state.stats_db.get_all_stats().await.map_err(|e| {
+        tracing::error!("Error reading play counts for reindex: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let flags_updated = state
+        .library
+        .reload_db_flags(&tracks_with_lyrics, &stats)
+        .await;
+    let report = ReindexReport {
+        flags_updated,
+        ..state.library.reindex().await
+    };
+
+    tracing::info!(
+        "Reindexed {} tracks into {} albums and {} artists ({} flags updated)",
+        report.tracks,
+        report.albums,
+        report.artists,
+        report.flags_updated
+    );
+    Ok(Json(report))
+}
+
+/// Rescan the library, only parsing files that are new or changed since the
+/// last scan, and report what changed
+async fn rescan(State(state): State<AppState>) -> Result<Json<RescanReport>, StatusCode> {
+    let report = rescan_library(&state.library, &state.lyrics_db, &state.stats_db)
+        .await
+        .map_err(|e| match e {
+            MusicStationError::Conflict(_) => {
+                tracing::warn!("Rescan requested while a scan is already running");
+                StatusCode::CONFLICT
+            }
+            e => {
+                tracing::error!("Rescan failed: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    Ok(Json(report))
+}
+
+/// Run an incremental rescan and load the database flags of the tracks it added
+pub async fn rescan_library(
+    library: &MusicLibrary,
+    lyrics_db: &LyricDatabase,
+    stats_db: &StatsDatabase,
+) -> crate::error::Result<RescanReport> {
+    let report = library.rescan().await?;
+
+    // Surviving tracks kept their flags; new ones still need them from the databases
+    if report.added > 0 {
+        load_track_flags(library, lyrics_db, stats_db).await;
+    }
+
+    tracing::info!(
+        "Rescanned library: {} added, {} updated, {} removed ({} files parsed)",
+        report.added,
+        report.updated,
+        report.removed,
+        report.parsed
+    );
+    Ok(report)
+}
+
+/// Readiness probe: 503 until the startup scan has loaded the track list
+async fn ready(State(state): State<AppState>) -> (StatusCode, Json<ScanStatus>) {
+    let status = if state.library.is_loaded().await {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(state.library.scan_status().await))
+}
+
+/// Get the status of the current or most recent scan
+async fn get_scan_status(State(state): State<AppState>) -> Json<ScanStatus> {
+    Json(state.library.scan_status().await)
+}
+
+/// Cancel the running scan, keeping the previous track list
+async fn cancel_scan(State(state): State<AppState>) -> StatusCode {
+    if state.library.cancel_scan().await {
+        tracing::info!("Scan cancellation requested");
+        StatusCode::ACCEPTED
+    } else {
+        tracing::debug!("Cancel requested but no scan is running");
+        StatusCode::CONFLICT
+    }
+}
+
+/// List files that were skipped during the last scan
+async fn get_scan_errors(State(state): State<AppState>) -> Json<Vec<ScanError>> {
+    let errors = state.library.get_scan_errors().await;
+    tracing::debug!("Returning {} scan errors", errors.len());
+    Json(errors)
+}
+
+#[derive(Debug, Deserialize)]
+struct InspectQuery {
+    /// File or directory relative to the library root; the whole library when empty
+    #[serde(default)]
+    path: String,
+}
+
+/// Resolve a library-relative path, rejecting anything that leads outside
+/// the library (absolute paths, `..`, symlinks pointing elsewhere)
+async fn resolve_library_subpath(
+    library: &MusicLibrary,
+    relative: &str,
+) -> Result<std::path::PathBuf, StatusCode> {
+    let relative = std::path::Path::new(relative);
+    if !relative.components().all(|c| {
+        matches!(
+            c,
+            std::path::Component::Normal(_) | std::path::Component::CurDir
+        )
+    }) {
+        tracing::warn!("Rejected path outside the library: {}", relative.display());
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let path = library.library_path().join(relative);
+    let (Ok(root), Ok(resolved)) = (
+        tokio::fs::canonicalize(library.library_path()).await,
+        tokio::fs::canonicalize(&path).await,
+    ) else {
+        tracing::debug!("Path not found: {}", path.display());
+        return Err(StatusCode::NOT_FOUND);
+    };
+    if !resolved.starts_with(&root) {
+        tracing::warn!("Rejected path outside the library: {}", path.display());
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(path)
+}
+
+/// Parse the tags of the files under a library path without adding them to
+/// the library, e.g. to preview a folder before importing it
+async fn inspect_path(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<InspectQuery>,
+) -> Result<Json<InspectReport>, StatusCode> {
+    let path = resolve_library_subpath(&state.library, &query.path).await?;
+    let report = state.library.inspect(&path).await.map_err(|e| {
+        tracing::error!("Error inspecting {}: {:#}", path.display(), e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tracing::debug!(
+        "Inspected {}: {} tracks, {} errors",
+        path.display(),
+        report.tracks.len(),
+        report.errors.len()
+    );
+    Ok(Json(report))
+}
+
+/// Versions of the running build
+#[derive(Debug, serde::Serialize)]
+struct VersionInfo {
+    crate_version: &'static str,
+    /// Commit the server was built from, when built from a git checkout
+    git_sha: Option<&'static str>,
+    /// When the binary was built (RFC 3339)
+    build_timestamp: Option<String>,
+    music_search_version: &'static str,
+}
+
+/// Report which build is running
+async fn version() -> Json<VersionInfo> {
+    let build_timestamp = env!("BUILD_TIMESTAMP")
+        .parse::<i64>()
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|time| time.to_rfc3339());
+
+    Json(VersionInfo {
+        crate_version: env!("CARGO_PKG_VERSION"),
+        git_sha: option_env!("GIT_SHA"),
+        build_timestamp,
+        music_search_version: music_search_rs::VERSION,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct SuggestQuery {
+    #[serde(default)]
+    q: String,
+    #[serde(default = "default_suggest_limit")]
+    limit: usize,
+}
+
+fn default_suggest_limit() -> usize {
+    10
+}
+
+/// Titles, artists and albums starting with `q`, for as-you-type search boxes
+async fn suggest(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<SuggestQuery>,
+) -> Json<Vec<Suggestion>> {
+    let suggestions = state.library.suggest(&query.q, query.limit).await;
+    tracing::debug!("{} suggestions for {:?}", suggestions.len(), query.q);
+    Json(suggestions)
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    #[serde(default)]
+    q: String,
+    #[serde(default = "default_search_limit")]
+    limit: usize,
+    /// Only match `title`, `artist` or `album`
+    field: Option<SuggestionKind>,
+}
+
+fn default_search_limit() -> usize {
+    50
+}
+
+/// Tracks whose title, artist or album matches `q`, best match first
+async fn search_tracks(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<SearchQuery>,
+) -> Result<Json<Vec<Track>>, StatusCode> {
+    if query.q.chars().count() > MAX_SEARCH_QUERY_CHARS {
+        tracing::warn!(
+            "Search query too long: {} characters (max {})",
+            query.q.chars().count(),
+            MAX_SEARCH_QUERY_CHARS
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let tracks = state
+        .library
+        .search(&query.q, query.field, query.limit)
+        .await;
+    tracing::debug!("{} tracks matching {:?}", tracks.len(), query.q);
+    Ok(Json(tracks))
+}
+
 /// Get library statistics
 async fn get_stats(State(state): State<AppState>) -> Json<LibraryStats> {
     tracing::debug!("Fetching library statistics");
@@ -468,32 +2350,39 @@ async fn get_cover(
         .await
         .ok_or(StatusCode::NOT_FOUND)?;
 
+    // The cache is only usable when the file's mtime can be checked
+    let cache = match &state.cover_cache {
+        Some(cache) => tokio::fs::metadata(&track.path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(|modified| (cache, modified)),
+        None => None,
+    };
+
+    if let Some((cache, modified)) = cache
+        && let Some((image_data, mime_type)) = cache.get(&id, modified).await
+    {
+        tracing::debug!("Serving cached cover art for track: {}", id);
+        return Ok(cover_response(image_data, &mime_type));
+    }
+
     match state.library.get_cover_art(&track.path).await {
-        Ok(Some(image_data)) => {
+        Ok(Some((image_data, mime_type))) => {
             tracing::debug!(
-                "Found cover art for track: {} ({} bytes)",
+                "Found cover art for track: {} ({} bytes, stored type {:?})",
                 id,
-                image_data.len()
+                image_data.len(),
+                mime_type
             );
 
-            // Try to determine MIME type from image data
-            let mime_type = if image_data.starts_with(&[0xFF, 0xD8, 0xFF]) {
-                "image/jpeg"
-            } else if image_data.starts_with(&[0x89, 0x50, 0x4E, 0x47]) {
-                "image/png"
-            } else {
-                "image/jpeg" // Default to JPEG
-            };
+            if let Some((cache, modified)) = cache
+                && let Err(e) = cache.put(&id, modified, &image_data, &mime_type).await
+            {
+                tracing::warn!("Failed to cache cover art for track {}: {}", id, e);
+            }
 
-            Ok((
-                StatusCode::OK,
-                [
-                    (header::CONTENT_TYPE, mime_type),
-                    (header::CACHE_CONTROL, "public, max-age=3600"),
-                ],
-                image_data,
-            )
-                .into_response())
+            Ok(cover_response(image_data, &mime_type))
         }
         Ok(None) => {
             tracing::debug!("No cover art found for track: {}", id);
@@ -506,86 +2395,237 @@ async fn get_cover(
     }
 }
 
-/// Upload cover art for a track
-async fn upload_cover(
+/// Get the representative cover art for an album
+async fn get_album_cover(
     State(state): State<AppState>,
-    Path(id): Path<String>,
-    mut multipart: Multipart,
-) -> Result<Json<Track>, StatusCode> {
-    tracing::debug!("Uploading cover art for track: {}", id);
-
-    let mut image_data: Option<Vec<u8>> = None;
-    let mut mime_type = "image/jpeg".to_string();
-
-    // Process multipart form data
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
-        tracing::error!("Error reading multipart field: {}", e);
-        StatusCode::BAD_REQUEST
-    })? {
-        let name = field.name().unwrap_or("").to_string();
-
-        if name == "image" || name == "cover" {
-            if let Some(content_type) = field.content_type() {
-                mime_type = content_type.to_string();
-            }
+    Path(name): Path<String>,
+) -> Result<Response, StatusCode> {
+    tracing::debug!("Fetching cover art for album: {}", name);
 
-            let data = field.bytes().await.map_err(|e| {
-                tracing::error!("Error reading image data: {}", e);
-                StatusCode::BAD_REQUEST
-            })?;
+    let album = state
+        .library
+        .get_album(&name)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
 
-            image_data = Some(data.to_vec());
-            break;
+    match state.library.get_album_art(&album).await {
+        Ok(Some((image_data, mime_type))) => Ok(cover_response(image_data, &mime_type)),
+        Ok(None) => {
+            tracing::debug!("No cover art found for album: {}", name);
+            Err(StatusCode::NOT_FOUND)
+        }
+        Err(e) => {
+            tracing::error!("Error reading cover art for album {}: {}", name, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
         }
     }
+}
 
-    let image_data = image_data.ok_or_else(|| {
-        tracing::warn!("No image data found in upload for track: {}", id);
-        StatusCode::BAD_REQUEST
-    })?;
+/// Build an image response with the MIME type stored in the tag, or one
+/// sniffed from the data when the stored type is missing or generic
+fn cover_response(image_data: Vec<u8>, stored_mime: &str) -> Response {
+    let mime_type = cover_mime(&image_data, stored_mime);
 
-    if image_data.len() > MAX_COVER_SIZE {
-        tracing::warn!(
-            "Cover art upload too large for track {}: {} bytes (max {})",
-            id,
-            image_data.len(),
-            MAX_COVER_SIZE
-        );
-        return Err(StatusCode::PAYLOAD_TOO_LARGE);
-    }
+    (
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, mime_type.as_str()),
+            (header::CACHE_CONTROL, "public, max-age=3600"),
+        ],
+        image_data,
+    )
+        .into_response()
+}
 
-    // Set the cover art
-    state
-        .library
-        .set_cover_art(&id, image_data, &mime_type)
-        .await
-        .map_err(|e| {
-            tracing::error!("Error setting cover art for track {}: {}", id, e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+/// Content type for cover art. Tags record the exact type, so it wins over
+/// sniffing unless it is empty, a wildcard or not an image type. ID3v2.2
+/// stores a bare format name (`JPG`, `PNG`) instead of a MIME type.
+fn cover_mime(image_data: &[u8], stored_mime: &str) -> String {
+    let stored = stored_mime.trim().to_ascii_lowercase();
+    match stored.as_str() {
+        "jpg" | "jpeg" | "image/jpg" => "image/jpeg".to_string(),
+        "png" => "image/png".to_string(),
+        "image/" | "image/*" => sniff_image_mime(image_data).to_string(),
+        specific if specific.starts_with("image/") => stored,
+        _ => sniff_image_mime(image_data).to_string(),
+    }
+}
 
-    // Return updated track
-    let track = state
-        .library
-        .get_track(&id)
-        .await
-        .ok_or(StatusCode::NOT_FOUND)?;
+/// Guess an image's MIME type from its magic bytes, defaulting to JPEG
+fn sniff_image_mime(image_data: &[u8]) -> &'static str {
+    if image_data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else if image_data.starts_with(&[0x89, 0x50, 0x4E, 0x47]) {
+        "image/png"
+    } else {
+        "image/jpeg" // Default to JPEG
+    }
+}
 
-    tracing::debug!("Successfully uploaded cover art for track: {}", id);
-    Ok(Json(track))
+#[derive(Debug, Deserialize)]
+struct CoverNormalizeQuery {
+    /// Apply EXIF rotation to the pixels and strip metadata before embedding
+    #[serde(default)]
+    normalize: bool,
+    /// Convert to this format before embedding; implies `normalize`
+    format: Option<CoverFormat>,
 }
 
-/// Delete cover art for a track
-async fn delete_cover(
+impl CoverNormalizeQuery {
+    /// Normalize cover art as requested, or pass it through unchanged.
+    /// 400 if the image can't be decoded.
+    async fn apply(
+        &self,
+        image_data: Vec<u8>,
+        mime_type: String,
+    ) -> Result<(Vec<u8>, String), StatusCode> {
+        if !self.normalize && self.format.is_none() {
+            return Ok((image_data, mime_type));
+        }
+
+        let format = self.format;
+        tokio::task::spawn_blocking(move || crate::cover::normalize_cover(&image_data, format))
+            .await
+            .map_err(|e| {
+                tracing::error!("Cover normalization task failed: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .map(|(data, mime_type)| (data, mime_type.to_string()))
+            .map_err(|e| {
+                tracing::warn!("Failed to normalize cover art: {:#}", e);
+                StatusCode::BAD_REQUEST
+            })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CoverUploadQuery {
+    /// Multipart field holding the image; `image` or `cover` when omitted
+    field: Option<String>,
+}
+
+/// Read the single image field of a cover upload, with its declared MIME type.
+///
+/// Errors carry a message for the response body: no image field, more than
+/// one, an empty one, or one declared as something other than `image/*`.
+async fn read_cover_field(
+    multipart: &mut Multipart,
+    field_name: Option<&str>,
+) -> Result<(Vec<u8>, String), (StatusCode, String)> {
+    let bad_request = |message: String| (StatusCode::BAD_REQUEST, message);
+    let is_image_field = |name: &str| match field_name {
+        Some(wanted) => name == wanted,
+        None => name == "image" || name == "cover",
+    };
+    let expected = field_name.map_or("'image' or 'cover'".to_string(), |name| {
+        format!("'{}'", name)
+    });
+
+    let mut image: Option<(Vec<u8>, String)> = None;
+    while let Some(mut field) = multipart.next_field().await.map_err(|e| {
+        (
+            e.status(),
+            format!("Invalid multipart body: {}", e.body_text()),
+        )
+    })? {
+        let name = field.name().unwrap_or("").to_string();
+        if !is_image_field(&name) {
+            continue;
+        }
+        if image.is_some() {
+            return Err(bad_request(format!(
+                "Multiple image fields named {}",
+                expected
+            )));
+        }
+
+        // Checked before reading so a mislabeled upload isn't buffered
+        let mime_type = match field.content_type() {
+            Some(content_type) if content_type.starts_with("image/") => content_type.to_string(),
+            Some(content_type) => {
+                return Err(bad_request(format!(
+                    "Field '{}' has Content-Type {}, expected image/*",
+                    name, content_type
+                )));
+            }
+            None => "image/jpeg".to_string(),
+        };
+
+        let mut data = Vec::new();
+        while let Some(chunk) = field.chunk().await.map_err(|e| {
+            (
+                e.status(),
+                format!("Error reading field '{}': {}", name, e.body_text()),
+            )
+        })? {
+            if data.len() + chunk.len() > MAX_COVER_SIZE {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!("Cover art is larger than {} bytes", MAX_COVER_SIZE),
+                ));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        if data.is_empty() {
+            return Err(bad_request(format!("Field '{}' is empty", name)));
+        }
+        image = Some((data, mime_type));
+    }
+
+    image.ok_or_else(|| bad_request(format!("No image field named {}", expected)))
+}
+
+/// Upload cover art for a track
+async fn upload_cover(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    axum::extract::Query(normalize): axum::extract::Query<CoverNormalizeQuery>,
+    axum::extract::Query(upload): axum::extract::Query<CoverUploadQuery>,
+    mut multipart: Multipart,
+) -> Result<Json<Track>, Response> {
+    tracing::debug!("Uploading cover art for track: {}", id);
+
+    let (image_data, mime_type) = read_cover_field(&mut multipart, upload.field.as_deref())
+        .await
+        .map_err(|(status, message)| {
+            tracing::warn!("Rejected cover upload for track {}: {}", id, message);
+            (status, message).into_response()
+        })?;
+    let (image_data, mime_type) = normalize
+        .apply(image_data, mime_type)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    // Set the cover art
+    state
+        .library
+        .set_cover_art(&id, image_data, &mime_type)
+        .await
+        .map_err(|e| track_edit_error_status(&id, "set cover art", &e).into_response())?;
+
+    // Return updated track
+    let track = state
+        .library
+        .get_track(&id)
+        .await
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+
+    tracing::debug!("Successfully uploaded cover art for track: {}", id);
+    Ok(Json(track))
+}
+
+/// Delete cover art for a track
+async fn delete_cover(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<Track>, StatusCode> {
     tracing::debug!("Deleting cover art for track: {}", id);
 
-    state.library.remove_cover_art(&id).await.map_err(|e| {
-        tracing::error!("Error removing cover art for track {}: {}", id, e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    state
+        .library
+        .remove_cover_art(&id)
+        .await
+        .map_err(|e| track_edit_error_status(&id, "remove cover art", &e))?;
 
     // Return updated track
     let track = state
@@ -598,14 +2638,287 @@ async fn delete_cover(
     Ok(Json(track))
 }
 
+/// Read a track's embedded cover art for copying to other tracks, with a
+/// concrete MIME type. 422 when the track has no art.
+async fn source_cover(state: &AppState, source: &Track) -> Result<(Vec<u8>, String), StatusCode> {
+    match state.library.get_cover_art(&source.path).await {
+        Ok(Some((image_data, stored_mime))) => {
+            let mime_type = cover_mime(&image_data, &stored_mime);
+            Ok((image_data, mime_type))
+        }
+        Ok(None) => {
+            tracing::warn!("Source track {} has no cover art to copy", source.id);
+            Err(StatusCode::UNPROCESSABLE_ENTITY)
+        }
+        Err(e) => {
+            tracing::error!("Error reading cover art for track {}: {}", source.id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Copy the embedded cover art of one track into another
+async fn copy_track_cover(
+    State(state): State<AppState>,
+    Path((id, source_id)): Path<(String, String)>,
+    axum::extract::Query(normalize): axum::extract::Query<CoverNormalizeQuery>,
+) -> Result<Json<Track>, StatusCode> {
+    tracing::debug!("Copying cover art from track {} to {}", source_id, id);
+
+    let source = state
+        .library
+        .get_track(&source_id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+    state
+        .library
+        .get_track(&id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let (image_data, mime_type) = source_cover(&state, &source).await?;
+    let (image_data, mime_type) = normalize.apply(image_data, mime_type).await?;
+
+    state
+        .library
+        .set_cover_art(&id, image_data, &mime_type)
+        .await
+        .map_err(|e| track_edit_error_status(&id, "set cover art", &e))?;
+
+    let track = state
+        .library
+        .get_track(&id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    tracing::debug!("Copied cover art from track {} to {}", source_id, id);
+    Ok(Json(track))
+}
+
+/// A track whose cover art could not be replaced
+#[derive(Debug, serde::Serialize)]
+struct CoverCopyFailure {
+    track_id: String,
+    error: String,
+}
+
+/// Result of filling an album's cover art from one track
+#[derive(Debug, serde::Serialize)]
+struct AlbumCoverFillReport {
+    source_id: String,
+    mime_type: String,
+    /// Tracks whose art was replaced
+    updated: Vec<String>,
+    /// CUE-sheet tracks, whose file is shared with the sheet's other tracks
+    skipped: Vec<String>,
+    failed: Vec<CoverCopyFailure>,
+}
+
+/// Copy one track's embedded cover art into every other track of an album
+async fn fill_album_cover(
+    State(state): State<AppState>,
+    Path((name, source_id)): Path<(String, String)>,
+    axum::extract::Query(normalize): axum::extract::Query<CoverNormalizeQuery>,
+) -> Result<Json<AlbumCoverFillReport>, StatusCode> {
+    tracing::debug!(
+        "Filling cover art of album {} from track {}",
+        name,
+        source_id
+    );
+
+    let album = state
+        .library
+        .get_album(&name)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let source = state
+        .library
+        .get_track(&source_id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let (image_data, mime_type) = source_cover(&state, &source).await?;
+    let (image_data, mime_type) = normalize.apply(image_data, mime_type).await?;
+
+    let mut report = AlbumCoverFillReport {
+        source_id,
+        mime_type,
+        updated: Vec::new(),
+        skipped: Vec::new(),
+        failed: Vec::new(),
+    };
+
+    // Write each file once, and never the source's own file
+    let mut written = HashSet::from([source.path.clone()]);
+    for track in album.tracks.iter().filter(|t| t.id != report.source_id) {
+        if track.start_ms.is_some() {
+            report.skipped.push(track.id.clone());
+            continue;
+        }
+        if !written.insert(track.path.clone()) {
+            continue;
+        }
+        match state
+            .library
+            .set_cover_art(&track.id, image_data.clone(), &report.mime_type)
+            .await
+        {
+            Ok(()) => report.updated.push(track.id.clone()),
+            Err(e) => {
+                tracing::warn!("Failed to set cover art for track {}: {}", track.id, e);
+                report.failed.push(CoverCopyFailure {
+                    track_id: track.id.clone(),
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+
+    tracing::info!(
+        "Filled cover art of album {}: {} updated, {} skipped, {} failed",
+        name,
+        report.updated.len(),
+        report.skipped.len(),
+        report.failed.len()
+    );
+    Ok(Json(report))
+}
+
+#[derive(Debug, Deserialize)]
+struct OptimizeCoverQuery {
+    #[serde(default = "default_cover_max_dim")]
+    max_dim: u32,
+    #[serde(default = "default_cover_quality")]
+    quality: u8,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+impl OptimizeCoverQuery {
+    /// Reject a zero `max_dim` or a `quality` outside 1-100 with 400
+    fn validate(&self) -> Result<(), StatusCode> {
+        if self.max_dim == 0 || !(1..=100).contains(&self.quality) {
+            tracing::warn!(
+                "Invalid cover optimization parameters: max_dim={}, quality={}",
+                self.max_dim,
+                self.quality
+            );
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Ok(())
+    }
+}
+
+fn default_cover_max_dim() -> u32 {
+    1000
+}
+
+fn default_cover_quality() -> u8 {
+    85
+}
+
+/// Shrink a track's embedded cover if it is larger than `max_dim`
+async fn optimize_cover(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<OptimizeCoverQuery>,
+) -> Result<Json<CoverOptimization>, StatusCode> {
+    tracing::debug!(
+        "Optimizing cover for track: {} (max_dim={}, quality={}, dry_run={})",
+        id,
+        query.max_dim,
+        query.quality,
+        query.dry_run
+    );
+    query.validate()?;
+
+    state
+        .library
+        .get_track(&id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    state
+        .library
+        .optimize_cover(&id, query.max_dim, query.quality, query.dry_run)
+        .await
+        .map_err(|e| track_edit_error_status(&id, "optimize cover", &e))?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Shrink every embedded cover in the library that is larger than `max_dim`
+async fn optimize_covers(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<OptimizeCoverQuery>,
+) -> Result<Json<CoverOptimizationReport>, StatusCode> {
+    tracing::info!(
+        "Optimizing library covers (max_dim={}, quality={}, dry_run={})",
+        query.max_dim,
+        query.quality,
+        query.dry_run
+    );
+    query.validate()?;
+
+    let report = state
+        .library
+        .optimize_covers(query.max_dim, query.quality, query.dry_run)
+        .await;
+    tracing::info!(
+        "Cover optimization: {} -> {} bytes across {} tracks",
+        report.total_original_size,
+        report.total_new_size,
+        report.tracks.len()
+    );
+    Ok(Json(report))
+}
+
+#[derive(Debug, Deserialize)]
+struct CoverCheckRequest {
+    ids: Vec<String>,
+}
+
+/// Report which tracks or albums have cover art, so clients can skip requests that would 404
+async fn check_covers(
+    State(state): State<AppState>,
+    Json(request): Json<CoverCheckRequest>,
+) -> Json<HashMap<String, bool>> {
+    tracing::debug!("Checking covers for {} IDs", request.ids.len());
+    Json(state.library.check_covers(&request.ids).await)
+}
+
 // ========== LYRICS ENDPOINTS ==========
 
+#[derive(Debug, Deserialize)]
+struct LyricFormatQuery {
+    /// `plain`, `lrc` or `lrc_word`; the preferred stored format when omitted
+    format: Option<String>,
+}
+
+impl LyricFormatQuery {
+    /// The requested format, or 400 for an unknown one rather than falling
+    /// back to plain
+    fn format(&self) -> Result<Option<LyricFormat>, StatusCode> {
+        self.format
+            .as_deref()
+            .map(|format| {
+                LyricFormat::parse(format).ok_or_else(|| {
+                    tracing::warn!("Unknown lyrics format: {:?}", format);
+                    StatusCode::BAD_REQUEST
+                })
+            })
+            .transpose()
+    }
+}
+
 /// Get lyrics for a track
 async fn get_lyrics(
     State(state): State<AppState>,
     Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<LyricFormatQuery>,
 ) -> Result<Json<Lyric>, StatusCode> {
     tracing::debug!("Fetching lyrics for track: {}", id);
+    let format = query.format()?;
 
     // Check if track exists
     state
@@ -614,11 +2927,12 @@ async fn get_lyrics(
         .await
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    // Get lyrics from database
-    let lyric = state
-        .lyrics_db
-        .get_lyric(&id)
-        .await
+    // Get lyrics from database, in the preferred format unless one is asked for
+    let lyric = match &format {
+        Some(format) => state.lyrics_db.get_lyric_in_format(&id, format).await,
+        None => state.lyrics_db.get_lyric(&id).await,
+    };
+    let lyric = lyric
         .map_err(|e| {
             tracing::error!("Error fetching lyrics for track {}: {}", id, e);
             StatusCode::INTERNAL_SERVER_ERROR
@@ -632,7 +2946,27 @@ async fn get_lyrics(
     Ok(Json(lyric))
 }
 
-/// Upload or update lyrics for a track
+/// Get every stored lyric of a track, preferred format first
+async fn get_all_lyrics(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<Lyric>>, StatusCode> {
+    state
+        .library
+        .get_track(&id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let lyrics = state.lyrics_db.get_lyrics(&id).await.map_err(|e| {
+        tracing::error!("Error fetching lyrics for track {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tracing::debug!("Returning {} lyrics for track: {}", lyrics.len(), id);
+    Ok(Json(lyrics))
+}
+
+/// Upload or update lyrics for a track in one format
 async fn upload_lyrics(
     State(state): State<AppState>,
     Path(id): Path<String>,
@@ -667,162 +3001,690 @@ async fn upload_lyrics(
         } else {
             LyricFormat::Plain
         }
-    };
+    };
+
+    // Save lyrics
+    let lyric = state
+        .lyrics_db
+        .save_lyric(&id, upload.content, format, upload.language, upload.source)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error saving lyrics for track {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    // Update track's has_lyrics flag
+    state.library.update_track_lyrics_status(&id, true).await;
+
+    tracing::debug!("Successfully uploaded lyrics for track: {}", id);
+    Ok(Json(lyric))
+}
+
+/// Delete lyrics for a track, in every format unless one is given
+async fn delete_lyrics(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<LyricFormatQuery>,
+) -> Result<StatusCode, StatusCode> {
+    tracing::debug!("Deleting lyrics for track: {}", id);
+    let format = query.format()?;
+
+    // Check if track exists
+    state
+        .library
+        .get_track(&id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let db_error = |e: MusicStationError| {
+        tracing::error!("Error deleting lyrics for track {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    // Delete lyrics
+    let deleted = match &format {
+        Some(format) => state
+            .lyrics_db
+            .delete_lyric_in_format(&id, format)
+            .await
+            .map_err(db_error)?,
+        None => state.lyrics_db.delete_lyric(&id).await.map_err(db_error)?,
+    };
+
+    if !deleted {
+        tracing::debug!("No lyrics found to delete for track: {}", id);
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // Update track's has_lyrics flag; other formats may remain
+    let has_lyrics = state.lyrics_db.has_lyric(&id).await.map_err(db_error)?;
+    state
+        .library
+        .update_track_lyrics_status(&id, has_lyrics)
+        .await;
+
+    tracing::debug!("Successfully deleted lyrics for track: {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbedLyricsQuery {
+    /// Comma-separated file extensions to include, e.g. `flac,mp3`
+    format_filter: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default = "default_embed_concurrency")]
+    concurrency: usize,
+}
+
+fn default_embed_concurrency() -> usize {
+    4
+}
+
+/// Upper bound for `concurrency` on `/lyrics/embed-all`
+const MAX_EMBED_CONCURRENCY: usize = 16;
+
+/// Write the database lyrics of every track into its file where the format allows it
+async fn embed_all_lyrics(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<EmbedLyricsQuery>,
+) -> Result<Json<LyricsEmbedReport>, StatusCode> {
+    let formats: Option<Vec<String>> = query.format_filter.as_deref().map(|filter| {
+        filter
+            .split(',')
+            .map(|f| f.trim().trim_start_matches('.').to_string())
+            .filter(|f| !f.is_empty())
+            .collect()
+    });
+    tracing::info!(
+        "Embedding lyrics into files (formats={:?}, dry_run={}, concurrency={})",
+        formats,
+        query.dry_run,
+        query.concurrency
+    );
+
+    let db_error = |e: MusicStationError| {
+        tracing::error!("Failed to load lyrics for embedding: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let mut lyrics = Vec::new();
+    for id in state
+        .lyrics_db
+        .get_tracks_with_lyrics()
+        .await
+        .map_err(db_error)?
+    {
+        if let Some(lyric) = state.lyrics_db.get_lyric(&id).await.map_err(db_error)? {
+            lyrics.push((id, lyric.content));
+        }
+    }
+
+    let report = state
+        .library
+        .embed_lyrics_bulk(
+            lyrics,
+            formats.as_deref(),
+            query.dry_run,
+            query.concurrency.clamp(1, MAX_EMBED_CONCURRENCY),
+        )
+        .await;
+    tracing::info!(
+        "Lyrics embed: {} embedded, {} skipped, {} failed",
+        report.embedded,
+        report.skipped,
+        report.failed
+    );
+    Ok(Json(report))
+}
+
+// ========== LYRICS SEARCH ENDPOINTS ==========
+
+use serde::{Deserialize, Serialize};
+
+/// A configured lyrics provider and its current health
+#[derive(Debug, Serialize)]
+struct LyricsProviderInfo {
+    #[serde(flatten)]
+    info: ProviderInfo,
+    healthy: bool,
+    /// Whether this provider is used when requests omit `provider`
+    is_default: bool,
+}
+
+/// List configured lyrics providers with their capabilities and health
+async fn list_lyrics_providers(State(state): State<AppState>) -> Json<Vec<LyricsProviderInfo>> {
+    let providers = state.lyrics_providers.clone();
+
+    let checks =
+        providers.iter().map(|provider| {
+            let state = state.clone();
+            async move {
+                let name = provider.name().to_string();
+                if let Some((checked_at, healthy)) = state.provider_health.read().await.get(&name)
+                    && checked_at.elapsed() < PROVIDER_HEALTH_TTL
+                {
+                    return *healthy;
+                }
+
+                let healthy =
+                    match tokio::time::timeout(PROVIDER_HEALTH_TIMEOUT, provider.health_check())
+                        .await
+                    {
+                        Ok(Ok(healthy)) => healthy,
+                        Ok(Err(e)) => {
+                            tracing::warn!("Lyrics provider {} health check failed: {}", name, e);
+                            false
+                        }
+                        Err(_) => {
+                            tracing::warn!("Lyrics provider {} health check timed out", name);
+                            false
+                        }
+                    };
+                state
+                    .provider_health
+                    .write()
+                    .await
+                    .insert(name, (Instant::now(), healthy));
+                healthy
+            }
+        });
+    let health = futures::future::join_all(checks).await;
+
+    Json(
+        providers
+            .iter()
+            .zip(health)
+            .map(|(provider, healthy)| LyricsProviderInfo {
+                info: provider.info(),
+                healthy,
+                is_default: state.default_lyrics_provider.as_deref() == Some(provider.name()),
+            })
+            .collect(),
+    )
+}
+
+/// Describe every registered lyrics provider, without the health checks of
+/// `/lyrics/providers`
+async fn lyrics_providers_info(State(state): State<AppState>) -> Json<Vec<ProviderInfo>> {
+    Json(
+        state
+            .lyrics_providers
+            .iter()
+            .map(|provider| provider.info())
+            .collect(),
+    )
+}
+
+/// Log a failed provider call and map it to 504 if the provider timed out, 500 otherwise
+fn lyrics_provider_error_status(provider: &str, action: &str, e: &anyhow::Error) -> StatusCode {
+    if e.downcast_ref::<ProviderTimedOut>().is_some() {
+        tracing::warn!("{} {} timed out: {}", provider, action, e);
+        StatusCode::GATEWAY_TIMEOUT
+    } else {
+        tracing::error!("{} {} error: {}", provider, action, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct LyricsSearchQuery {
+    q: String,
+    /// Falls back to the configured default provider when omitted
+    provider: Option<String>,
+    artist: Option<String>,
+}
+
+/// Search for lyrics from external providers
+async fn search_lyrics(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<LyricsSearchQuery>,
+) -> Result<Json<Vec<FetcherSearchResult>>, StatusCode> {
+    tracing::debug!(
+        "Searching lyrics: query='{}', provider='{:?}', artist='{:?}'",
+        query.q,
+        query.provider,
+        query.artist
+    );
+
+    // Build lyrics query
+    let mut lyrics_query = LyricsQuery::new(&query.q);
+    if let Some(artist) = query.artist {
+        lyrics_query = lyrics_query.with_artist(artist);
+    }
+
+    let provider = state.lyrics_provider(query.provider.as_deref())?;
+
+    let results = with_provider_timeout(
+        provider.name(),
+        state.provider_timeouts.for_provider(provider.name()),
+        provider.search(&lyrics_query),
+    )
+    .await
+    .map_err(|e| lyrics_provider_error_status(provider.name(), "search", &e))?;
+
+    tracing::debug!("Found {} lyrics search results", results.len());
+    Ok(Json(results))
+}
+
+/// Results of `/lyrics/search/all` scoring below this are dropped unless the
+/// request sets `min_confidence`; it takes at least a title match
+const DEFAULT_MERGED_MIN_CONFIDENCE: f32 = 0.6;
+
+#[derive(Debug, Deserialize)]
+struct MergedLyricsSearchQuery {
+    q: String,
+    artist: Option<String>,
+    /// Track length in seconds; results a few seconds off or more score lower
+    /// and aren't merged with each other
+    duration: Option<u64>,
+    /// Drop results scoring below this (0.0 to 1.0)
+    min_confidence: Option<f32>,
+}
+
+/// Search every lyrics provider and return one ranked list without duplicates
+async fn search_lyrics_all(
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<MergedLyricsSearchQuery>,
+) -> Result<Json<Vec<MergedSearchResult>>, StatusCode> {
+    let min_confidence = query
+        .min_confidence
+        .unwrap_or(DEFAULT_MERGED_MIN_CONFIDENCE);
+    if !(0.0..=1.0).contains(&min_confidence) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut lyrics_query = LyricsQuery::new(&query.q);
+    if let Some(artist) = query.artist {
+        lyrics_query = lyrics_query.with_artist(artist);
+    }
+    if let Some(secs) = query.duration {
+        lyrics_query = lyrics_query.with_duration(Duration::from_secs(secs));
+    }
+
+    let results = state
+        .lyrics_aggregator
+        .search_all_merged(&lyrics_query, min_confidence)
+        .await;
+    tracing::debug!("Found {} merged lyrics search results", results.len());
+    Ok(Json(results))
+}
+
+#[derive(Debug, Deserialize)]
+struct LyricsCandidatesQuery {
+    /// Falls back to the configured default provider when omitted
+    provider: Option<String>,
+}
+
+/// A provider's search results for a track, with the query built from its tags
+#[derive(Debug, serde::Serialize)]
+struct LyricsCandidates {
+    provider: String,
+    title: String,
+    artist: Option<String>,
+    album: Option<String>,
+    duration_secs: Option<u64>,
+    /// Best match first
+    candidates: Vec<FetcherSearchResult>,
+}
+
+/// Lyrics search query built from a track's tags; untagged tracks are searched by file name
+fn lyrics_query_for_track(track: &Track) -> LyricsQuery {
+    let title = track.title.clone().unwrap_or_else(|| {
+        std::path::Path::new(&track.filename)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| track.filename.clone())
+    });
+    LyricsQuery {
+        title,
+        artist: track.artist.clone(),
+        album: track.album.clone(),
+        duration: track.duration_secs.map(Duration::from_secs),
+    }
+}
+
+/// Preview how a track matches a lyrics provider without fetching anything
+async fn get_lyrics_candidates(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<LyricsCandidatesQuery>,
+) -> Result<Json<LyricsCandidates>, StatusCode> {
+    let track = state
+        .library
+        .get_track(&id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let provider = state.lyrics_provider(query.provider.as_deref())?;
+
+    let lyrics_query = lyrics_query_for_track(&track);
+
+    let mut candidates = with_provider_timeout(
+        provider.name(),
+        state.provider_timeouts.for_provider(provider.name()),
+        provider.search(&lyrics_query),
+    )
+    .await
+    .map_err(|e| lyrics_provider_error_status(provider.name(), "search", &e))?;
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+    tracing::debug!(
+        "{} lyrics candidates for track {} from {}",
+        candidates.len(),
+        id,
+        provider.name()
+    );
+    Ok(Json(LyricsCandidates {
+        provider: provider.name().to_string(),
+        title: lyrics_query.title,
+        artist: track.artist,
+        album: track.album,
+        duration_secs: track.duration_secs,
+        candidates,
+    }))
+}
+
+/// Fetch lyrics from a specific provider by song ID
+async fn fetch_lyrics_from_provider(
+    State(state): State<AppState>,
+    Path((provider, song_id)): Path<(String, String)>,
+) -> Result<Json<LyricsResponse>, StatusCode> {
+    tracing::debug!(
+        "Fetching lyrics: provider='{}', song_id='{}'",
+        provider,
+        song_id
+    );
+
+    let lyrics_provider = state.lyrics_provider(Some(&provider))?;
+
+    let lyrics = with_provider_timeout(
+        lyrics_provider.name(),
+        state.provider_timeouts.for_provider(lyrics_provider.name()),
+        lyrics_provider.fetch(&song_id),
+    )
+    .await
+    .map_err(|e| lyrics_provider_error_status(lyrics_provider.name(), "fetch", &e))?;
+
+    tracing::debug!(
+        "Successfully fetched lyrics from {}",
+        lyrics_provider.name()
+    );
+    Ok(Json(lyrics))
+}
+
+// ========== FETCH MISSING LYRICS JOB ==========
+
+/// Search results at or below this confidence are not saved by the fetch-missing job
+const FETCH_MISSING_MIN_CONFIDENCE: f32 = 0.5;
+
+#[derive(Debug, Deserialize)]
+struct StartFetchMissingQuery {
+    /// Falls back to the configured default provider when omitted
+    provider: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResumeFetchMissingQuery {
+    /// Put tracks whose fetch failed back in the queue
+    #[serde(default)]
+    retry_failed: bool,
+}
+
+/// Start a worker for the persisted job in `worker`'s slot, which must be empty
+fn spawn_fetch_missing_worker(state: &AppState, worker: &mut Option<CancellationToken>) {
+    let cancel = CancellationToken::new();
+    *worker = Some(cancel.clone());
+
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = run_fetch_missing(&state, &cancel).await {
+            tracing::error!("Lyrics fetch-missing job stopped: {:#}", e);
+        }
+        *state.fetch_missing_worker.lock().await = None;
+    });
+}
+
+/// Work through the persisted queue until it is empty or the job is cancelled
+async fn run_fetch_missing(state: &AppState, cancel: &CancellationToken) -> anyhow::Result<()> {
+    let Some(status) = state.lyrics_db.fetch_job_status().await? else {
+        return Ok(());
+    };
+    let Ok(provider) = state.lyrics_provider(status.provider.as_deref()) else {
+        state
+            .lyrics_db
+            .set_fetch_job_state(FetchJobState::Cancelled)
+            .await?;
+        anyhow::bail!("Lyrics provider {:?} is not available", status.provider);
+    };
+    tracing::info!(
+        "Fetching missing lyrics from {} ({} of {} tracks pending)",
+        provider.name(),
+        status.pending,
+        status.total
+    );
+
+    while !cancel.is_cancelled() {
+        let Some(track_id) = state.lyrics_db.next_pending_fetch().await? else {
+            state
+                .lyrics_db
+                .set_fetch_job_state(FetchJobState::Completed)
+                .await?;
+            tracing::info!("Lyrics fetch-missing job completed");
+            break;
+        };
+
+        let (outcome, error) = match fetch_missing_lyrics(state, provider.as_ref(), &track_id).await
+        {
+            Ok(true) => (FetchOutcome::Done, None),
+            Ok(false) => (FetchOutcome::NotFound, None),
+            Err(e) => {
+                tracing::warn!("Failed to fetch lyrics for track {}: {:#}", track_id, e);
+                (FetchOutcome::Failed, Some(format!("{:#}", e)))
+            }
+        };
+        state
+            .lyrics_db
+            .finish_fetch(&track_id, outcome, error.as_deref())
+            .await?;
+    }
+    Ok(())
+}
+
+/// Search `provider` for a track and save the best confident match.
+/// Returns whether the track has lyrics afterwards.
+async fn fetch_missing_lyrics(
+    state: &AppState,
+    provider: &dyn LyricsProviderTrait,
+    track_id: &str,
+) -> anyhow::Result<bool> {
+    let track = state
+        .library
+        .get_track(track_id)
+        .await
+        .ok_or_else(|| anyhow::anyhow!("Track is no longer in the library"))?;
+    // Lyrics may have been added by hand since the job was queued
+    if state.lyrics_db.has_lyric(track_id).await? {
+        return Ok(true);
+    }
+
+    let timeout = state.provider_timeouts.for_provider(provider.name());
+    let query = lyrics_query_for_track(&track);
+    let results = with_provider_timeout(provider.name(), timeout, provider.search(&query)).await?;
+    let Some(best) = results
+        .into_iter()
+        .filter(|result| result.confidence > FETCH_MISSING_MIN_CONFIDENCE)
+        .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+    else {
+        return Ok(false);
+    };
+
+    let lyrics = with_provider_timeout(provider.name(), timeout, provider.fetch(&best.id)).await?;
+    state
+        .lyrics_db
+        .save_lyric(
+            track_id,
+            lyrics.content,
+            lyrics.format,
+            lyrics.language,
+            Some(lyrics.source),
+        )
+        .await?;
+    state
+        .library
+        .update_track_lyrics_status(track_id, true)
+        .await;
+    Ok(true)
+}
+
+/// Resume a job that was running when the server stopped, once the library is loaded
+async fn resume_fetch_missing(state: AppState) {
+    match state.lyrics_db.fetch_job_status().await {
+        Ok(Some(status)) if status.state == FetchJobState::Running => {}
+        Ok(_) => return,
+        Err(e) => {
+            tracing::error!("Failed to read lyrics fetch-missing job: {:#}", e);
+            return;
+        }
+    }
 
-    // Save lyrics
-    let lyric = state
+    // Queued tracks would otherwise be marked as missing from the library
+    while !state.library.is_loaded().await {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    let mut worker = state.fetch_missing_worker.lock().await;
+    if worker.is_none() {
+        tracing::info!("Resuming lyrics fetch-missing job");
+        spawn_fetch_missing_worker(&state, &mut worker);
+    }
+}
+
+/// Read the persisted job status, mapping "no job yet" to 404
+async fn fetch_missing_status(state: &AppState) -> Result<FetchJobStatus, StatusCode> {
+    state
         .lyrics_db
-        .save_lyric(&id, upload.content, format, upload.language, upload.source)
+        .fetch_job_status()
         .await
         .map_err(|e| {
-            tracing::error!("Error saving lyrics for track {}: {}", id, e);
+            tracing::error!("Error reading lyrics fetch-missing job: {:#}", e);
             StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-    // Update track's has_lyrics flag
-    state.library.update_track_lyrics_status(&id, true).await;
-
-    tracing::debug!("Successfully uploaded lyrics for track: {}", id);
-    Ok(Json(lyric))
+        })?
+        .ok_or(StatusCode::NOT_FOUND)
 }
 
-/// Delete lyrics for a track
-async fn delete_lyrics(
+/// Queue every track without lyrics and start fetching them in the background
+async fn start_fetch_missing(
     State(state): State<AppState>,
-    Path(id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
-    tracing::debug!("Deleting lyrics for track: {}", id);
-
-    // Check if track exists
-    state
-        .library
-        .get_track(&id)
-        .await
-        .ok_or(StatusCode::NOT_FOUND)?;
-
-    // Delete lyrics
-    let deleted = state.lyrics_db.delete_lyric(&id).await.map_err(|e| {
-        tracing::error!("Error deleting lyrics for track {}: {}", id, e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    axum::extract::Query(query): axum::extract::Query<StartFetchMissingQuery>,
+) -> Result<(StatusCode, Json<FetchJobStatus>), StatusCode> {
+    let provider = state.lyrics_provider(query.provider.as_deref())?;
 
-    if !deleted {
-        tracing::debug!("No lyrics found to delete for track: {}", id);
-        return Err(StatusCode::NOT_FOUND);
+    let mut worker = state.fetch_missing_worker.lock().await;
+    if worker.is_some() {
+        tracing::warn!("Fetch-missing requested while a job is already running");
+        return Err(StatusCode::CONFLICT);
     }
 
-    // Update track's has_lyrics flag
-    state.library.update_track_lyrics_status(&id, false).await;
+    let track_ids: Vec<String> = state
+        .library
+        .get_tracks()
+        .await
+        .into_iter()
+        .filter(|track| !track.has_lyrics)
+        .map(|track| track.id)
+        .collect();
+    state
+        .lyrics_db
+        .start_fetch_job(Some(provider.name()), &track_ids)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error queueing lyrics fetch-missing job: {:#}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    spawn_fetch_missing_worker(&state, &mut worker);
+    drop(worker);
 
-    tracing::debug!("Successfully deleted lyrics for track: {}", id);
-    Ok(StatusCode::NO_CONTENT)
+    tracing::info!(
+        "Queued {} tracks to fetch lyrics from {}",
+        track_ids.len(),
+        provider.name()
+    );
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(fetch_missing_status(&state).await?),
+    ))
 }
 
-// ========== LYRICS SEARCH ENDPOINTS ==========
-
-use serde::Deserialize;
-
-#[derive(Debug, Deserialize)]
-struct LyricsSearchQuery {
-    q: String,
-    provider: String,
-    artist: Option<String>,
+/// Get the persisted progress of the current or last fetch-missing job
+async fn get_fetch_missing_status(
+    State(state): State<AppState>,
+) -> Result<Json<FetchJobStatus>, StatusCode> {
+    fetch_missing_status(&state).await.map(Json)
 }
 
-/// Search for lyrics from external providers
-async fn search_lyrics(
+/// Stop the running job after the track in progress; pending tracks stay queued
+async fn cancel_fetch_missing(
     State(state): State<AppState>,
-    axum::extract::Query(query): axum::extract::Query<LyricsSearchQuery>,
-) -> Result<Json<Vec<FetcherSearchResult>>, StatusCode> {
-    tracing::debug!(
-        "Searching lyrics: query='{}', provider='{}', artist='{:?}'",
-        query.q,
-        query.provider,
-        query.artist
-    );
-
-    // Build lyrics query
-    let mut lyrics_query = LyricsQuery::new(&query.q);
-    if let Some(artist) = query.artist {
-        lyrics_query = lyrics_query.with_artist(artist);
+) -> Result<Json<FetchJobStatus>, StatusCode> {
+    let status = fetch_missing_status(&state).await?;
+    if status.state != FetchJobState::Running {
+        tracing::debug!("Cancel requested but the fetch-missing job is not running");
+        return Err(StatusCode::CONFLICT);
     }
 
-    // Select provider and search
-    let results = match query.provider.as_str() {
-        "netease" => {
-            let provider = state.netease_provider.as_ref().ok_or_else(|| {
-                tracing::error!("NetEase provider not initialized");
-                StatusCode::SERVICE_UNAVAILABLE
-            })?;
-
-            provider.search(&lyrics_query).await.map_err(|e| {
-                tracing::error!("NetEase search error: {}", e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?
-        }
-        "qqmusic" => {
-            let provider = state.qqmusic_provider.as_ref().ok_or_else(|| {
-                tracing::error!("QQ Music provider not initialized");
-                StatusCode::SERVICE_UNAVAILABLE
-            })?;
-
-            provider.search(&lyrics_query).await.map_err(|e| {
-                tracing::error!("QQ Music search error: {}", e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?
-        }
-        _ => {
-            tracing::warn!("Unknown provider: {}", query.provider);
-            return Err(StatusCode::BAD_REQUEST);
-        }
-    };
+    if let Some(cancel) = state.fetch_missing_worker.lock().await.as_ref() {
+        cancel.cancel();
+    }
+    state
+        .lyrics_db
+        .set_fetch_job_state(FetchJobState::Cancelled)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error cancelling lyrics fetch-missing job: {:#}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
-    tracing::debug!("Found {} lyrics search results", results.len());
-    Ok(Json(results))
+    tracing::info!("Lyrics fetch-missing job cancelled");
+    fetch_missing_status(&state).await.map(Json)
 }
 
-/// Fetch lyrics from a specific provider by song ID
-async fn fetch_lyrics_from_provider(
+/// Continue a cancelled or interrupted job with the tracks still queued
+async fn resume_fetch_missing_job(
     State(state): State<AppState>,
-    Path((provider, song_id)): Path<(String, String)>,
-) -> Result<Json<LyricsResponse>, StatusCode> {
-    tracing::debug!(
-        "Fetching lyrics: provider='{}', song_id='{}'",
-        provider,
-        song_id
-    );
+    axum::extract::Query(query): axum::extract::Query<ResumeFetchMissingQuery>,
+) -> Result<(StatusCode, Json<FetchJobStatus>), StatusCode> {
+    fetch_missing_status(&state).await?;
 
-    let lyrics = match provider.as_str() {
-        "netease" => {
-            let provider = state.netease_provider.as_ref().ok_or_else(|| {
-                tracing::error!("NetEase provider not initialized");
-                StatusCode::SERVICE_UNAVAILABLE
-            })?;
-
-            provider.fetch(&song_id).await.map_err(|e| {
-                tracing::error!("NetEase fetch error: {}", e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?
-        }
-        "qqmusic" => {
-            let provider = state.qqmusic_provider.as_ref().ok_or_else(|| {
-                tracing::error!("QQ Music provider not initialized");
-                StatusCode::SERVICE_UNAVAILABLE
-            })?;
+    let mut worker = state.fetch_missing_worker.lock().await;
+    if worker.is_some() {
+        tracing::warn!("Resume requested while the fetch-missing job is still running");
+        return Err(StatusCode::CONFLICT);
+    }
 
-            provider.fetch(&song_id).await.map_err(|e| {
-                tracing::error!("QQ Music fetch error: {}", e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?
-        }
-        _ => {
-            tracing::warn!("Unknown provider: {}", provider);
-            return Err(StatusCode::BAD_REQUEST);
-        }
+    let db_error = |e: MusicStationError| {
+        tracing::error!("Error resuming lyrics fetch-missing job: {:#}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
     };
+    if query.retry_failed {
+        let requeued = state
+            .lyrics_db
+            .requeue_failed_fetches()
+            .await
+            .map_err(db_error)?;
+        tracing::info!("Requeued {} failed lyrics fetches", requeued);
+    }
+    state
+        .lyrics_db
+        .set_fetch_job_state(FetchJobState::Running)
+        .await
+        .map_err(db_error)?;
+    spawn_fetch_missing_worker(&state, &mut worker);
+    drop(worker);
 
-    tracing::debug!("Successfully fetched lyrics from {}", provider);
-    Ok(Json(lyrics))
+    tracing::info!("Lyrics fetch-missing job resumed");
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(fetch_missing_status(&state).await?),
+    ))
 }
 
 // ========== PLAYLIST ENDPOINTS ==========
@@ -844,7 +3706,7 @@ async fn list_playlists(State(state): State<AppState>) -> Result<Json<Vec<Playli
 async fn get_playlist(
     State(state): State<AppState>,
     Path(id): Path<String>,
-) -> Result<Json<Playlist>, StatusCode> {
+) -> Result<Response, StatusCode> {
     tracing::debug!("Fetching playlist: {}", id);
 
     let playlist = state
@@ -865,7 +3727,34 @@ async fn get_playlist(
         id,
         playlist.tracks.len()
     );
-    Ok(Json(playlist))
+    Ok(playlist_response(playlist))
+}
+
+/// JSON response for a playlist, with its version as the `ETag`
+fn playlist_response(playlist: Playlist) -> Response {
+    let etag = format!("\"{}\"", playlist.version);
+    ([(header::ETAG, etag)], Json(playlist)).into_response()
+}
+
+/// Playlist version required by an `If-Match` header, `None` when absent or `*`.
+/// A value that isn't a playlist ETag can never match, so it fails the precondition.
+fn if_match_version(headers: &HeaderMap) -> Result<Option<i64>, StatusCode> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let value = value
+        .to_str()
+        .map_err(|_| StatusCode::PRECONDITION_FAILED)?
+        .trim();
+    if value == "*" {
+        return Ok(None);
+    }
+    value
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .parse()
+        .map(Some)
+        .map_err(|_| StatusCode::PRECONDITION_FAILED)
 }
 
 /// Create a new playlist
@@ -879,38 +3768,69 @@ async fn create_playlist(
         .playlist_db
         .create_playlist(create)
         .await
-        .map_err(|e| {
-            tracing::error!("Error creating playlist: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+        .map_err(|e| match e {
+            MusicStationError::Conflict(_) => {
+                tracing::warn!("Error creating playlist: {}", e);
+                StatusCode::CONFLICT
+            }
+            e => {
+                tracing::error!("Error creating playlist: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
         })?;
 
     tracing::debug!("Successfully created playlist: {}", playlist.id);
     Ok(Json(playlist))
 }
 
-/// Update a playlist
+/// Update a playlist, optionally only if it still matches the `If-Match` version
 async fn update_playlist(
     State(state): State<AppState>,
     Path(id): Path<String>,
+    headers: HeaderMap,
     Json(update): Json<PlaylistUpdate>,
-) -> Result<Json<Playlist>, StatusCode> {
+) -> Result<Response, StatusCode> {
     tracing::debug!("Updating playlist: {}", id);
 
-    let playlist = state
+    let expected_version = if_match_version(&headers)?;
+    let result = state
         .playlist_db
-        .update_playlist(&id, update)
+        .update_playlist(&id, update, expected_version)
         .await
-        .map_err(|e| {
-            tracing::error!("Error updating playlist {}: {}", id, e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?
-        .ok_or_else(|| {
-            tracing::debug!("Playlist {} not found", id);
-            StatusCode::NOT_FOUND
+        .map_err(|e| match e {
+            MusicStationError::Conflict(_) => {
+                tracing::warn!("Error updating playlist {}: {}", id, e);
+                StatusCode::CONFLICT
+            }
+            e => {
+                tracing::error!("Error updating playlist {}: {}", id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
         })?;
 
-    tracing::debug!("Successfully updated playlist: {}", id);
-    Ok(Json(playlist))
+    match result {
+        PlaylistUpdateResult::Updated(playlist) => {
+            tracing::debug!(
+                "Successfully updated playlist {} to version {}",
+                id,
+                playlist.version
+            );
+            Ok(playlist_response(playlist))
+        }
+        PlaylistUpdateResult::NotFound => {
+            tracing::debug!("Playlist {} not found", id);
+            Err(StatusCode::NOT_FOUND)
+        }
+        PlaylistUpdateResult::VersionMismatch { current } => {
+            tracing::debug!(
+                "Playlist {} is at version {}, expected {:?}",
+                id,
+                current,
+                expected_version
+            );
+            Err(StatusCode::PRECONDITION_FAILED)
+        }
+    }
 }
 
 /// Delete a playlist
@@ -934,10 +3854,18 @@ async fn delete_playlist(
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct AddTracksQuery {
+    /// Append tracks even if they're already in the playlist
+    #[serde(default)]
+    allow_duplicates: bool,
+}
+
 /// Add a track to a playlist
 async fn add_track_to_playlist(
     State(state): State<AppState>,
     Path((playlist_id, track_id)): Path<(String, String)>,
+    axum::extract::Query(query): axum::extract::Query<AddTracksQuery>,
 ) -> Result<Json<Playlist>, StatusCode> {
     tracing::debug!("Adding track {} to playlist {}", track_id, playlist_id);
 
@@ -949,7 +3877,7 @@ async fn add_track_to_playlist(
 
     let playlist = state
         .playlist_db
-        .add_track_to_playlist(&playlist_id, &track_id)
+        .add_track_to_playlist(&playlist_id, &track_id, query.allow_duplicates)
         .await
         .map_err(|e| {
             tracing::error!("Error adding track to playlist: {}", e);
@@ -968,6 +3896,95 @@ async fn add_track_to_playlist(
     Ok(Json(playlist))
 }
 
+#[derive(Debug, Deserialize)]
+struct AddTracksRequest {
+    track_ids: Vec<String>,
+}
+
+/// Append several tracks to a playlist atomically
+async fn add_tracks_to_playlist(
+    State(state): State<AppState>,
+    Path(playlist_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<AddTracksQuery>,
+    Json(request): Json<AddTracksRequest>,
+) -> Result<Json<Playlist>, StatusCode> {
+    tracing::debug!(
+        "Adding {} tracks to playlist {}",
+        request.track_ids.len(),
+        playlist_id
+    );
+
+    // Verify every track exists before changing anything
+    for track_id in &request.track_ids {
+        if state.library.get_track(track_id).await.is_none() {
+            tracing::warn!("Track {} not found", track_id);
+            return Err(StatusCode::NOT_FOUND);
+        }
+    }
+
+    let playlist = state
+        .playlist_db
+        .add_tracks(&playlist_id, &request.track_ids, query.allow_duplicates)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error adding tracks to playlist: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or_else(|| {
+            tracing::debug!("Playlist {} not found", playlist_id);
+            StatusCode::NOT_FOUND
+        })?;
+
+    tracing::debug!(
+        "Playlist {} now has {} tracks",
+        playlist_id,
+        playlist.tracks.len()
+    );
+    Ok(Json(playlist))
+}
+
+#[derive(Debug, Deserialize)]
+struct RemoveTracksRequest {
+    track_ids: Vec<String>,
+}
+
+/// Result of removing several tracks from a playlist
+#[derive(Debug, serde::Serialize)]
+struct RemoveTracksResponse {
+    playlist: Playlist,
+    /// Tracks actually removed; IDs not in the playlist are not counted
+    removed: u64,
+}
+
+/// Remove several tracks from a playlist atomically
+async fn remove_tracks_from_playlist(
+    State(state): State<AppState>,
+    Path(playlist_id): Path<String>,
+    Json(request): Json<RemoveTracksRequest>,
+) -> Result<Json<RemoveTracksResponse>, StatusCode> {
+    tracing::debug!(
+        "Removing {} tracks from playlist {}",
+        request.track_ids.len(),
+        playlist_id
+    );
+
+    let (playlist, removed) = state
+        .playlist_db
+        .remove_tracks(&playlist_id, &request.track_ids)
+        .await
+        .map_err(|e| {
+            tracing::error!("Error removing tracks from playlist: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or_else(|| {
+            tracing::debug!("Playlist {} not found", playlist_id);
+            StatusCode::NOT_FOUND
+        })?;
+
+    tracing::debug!("Removed {} tracks from playlist {}", removed, playlist_id);
+    Ok(Json(RemoveTracksResponse { playlist, removed }))
+}
+
 /// Remove a track from a playlist
 async fn remove_track_from_playlist(
     State(state): State<AppState>,
@@ -995,3 +4012,181 @@ async fn remove_track_from_playlist(
     );
     Ok(Json(playlist))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cover_mime_prefers_stored_type() {
+        let png = [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A];
+        assert_eq!(cover_mime(&png, "image/webp"), "image/webp");
+        assert_eq!(cover_mime(&png, "Image/PNG"), "image/png");
+        // ID3v2.2 format names
+        assert_eq!(cover_mime(&png, "JPG"), "image/jpeg");
+        // Missing or generic types fall back to sniffing
+        assert_eq!(cover_mime(&png, ""), "image/png");
+        assert_eq!(cover_mime(&png, "image/"), "image/png");
+        assert_eq!(cover_mime(&png, "application/octet-stream"), "image/png");
+        assert_eq!(cover_mime(&[0xFF, 0xD8, 0xFF], "-->"), "image/jpeg");
+    }
+
+    #[test]
+    fn test_album_stream_type_rejects_mixed_or_unconcatenable_formats() {
+        // Optional fields are left out and deserialize as `None`
+        let file = |path: &str, lossless: bool| -> Track {
+            serde_json::from_value(serde_json::json!({
+                "id": path, "path": path, "filename": path, "relative_path": path,
+                "file_size": 0, "has_cover": false, "has_lyrics": false, "play_count": 0,
+                "custom_fields": {}, "lossless": lossless
+            }))
+            .unwrap()
+        };
+        let (a, b) = (file("a.mp3", false), file("b.mp3", false));
+        assert_eq!(album_stream_type(&[&a, &b]), Some("audio/mpeg"));
+
+        let ogg = file("c.OGG", false);
+        assert_eq!(album_stream_type(&[&ogg]), Some("audio/ogg"));
+        assert_eq!(album_stream_type(&[&a, &ogg]), None);
+
+        // Concatenated FLAC, M4A, WavPack or APE files aren't a valid stream
+        let (flac, flac2) = (file("d.flac", true), file("e.flac", true));
+        assert_eq!(album_stream_type(&[&flac, &flac2]), None);
+        let m4a = file("f.m4a", false);
+        assert_eq!(album_stream_type(&[&m4a]), None);
+    }
+
+    #[test]
+    fn test_play_completion() {
+        let completion = PlayCompletion::default();
+        let secs = Duration::from_secs;
+        assert!(!completion.is_complete(secs(60), Some(secs(180))));
+        assert!(completion.is_complete(secs(90), Some(secs(180))));
+        // Long tracks count after four minutes
+        assert!(completion.is_complete(secs(240), Some(secs(3600))));
+        assert!(!completion.is_complete(secs(239), None));
+        assert!(completion.is_complete(secs(240), None));
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition(false, "01 Song.flac"),
+            "inline; filename=\"01 Song.flac\""
+        );
+        assert_eq!(
+            content_disposition(true, "say \"hi\".mp3"),
+            "attachment; filename=\"say _hi_.mp3\""
+        );
+        assert_eq!(
+            content_disposition(true, "Café.mp3"),
+            "attachment; filename=\"Caf_.mp3\"; filename*=UTF-8''Caf%C3%A9.mp3"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_body_rate_limit() {
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+
+        let started = Instant::now();
+        let body = stream_body(std::io::Cursor::new(data.clone()), 2000);
+        let received = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        // 500-byte chunks: the second one is held back for 250 ms
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(received, data);
+
+        let body = stream_body(std::io::Cursor::new(data.clone()), 0);
+        assert_eq!(axum::body::to_bytes(body, usize::MAX).await.unwrap(), data);
+    }
+
+    #[test]
+    fn test_parse_range_exact() {
+        assert_eq!(parse_range("bytes=0-0", 1000), Some((0, 0)));
+        assert_eq!(parse_range("bytes=100-199", 1000), Some((100, 199)));
+        // End past the file is clamped to the last byte
+        assert_eq!(parse_range("bytes=900-5000", 1000), Some((900, 999)));
+    }
+
+    #[test]
+    fn test_parse_range_open_ended_and_suffix() {
+        assert_eq!(parse_range("bytes=0-", 1000), Some((0, 999)));
+        assert_eq!(parse_range("bytes=999-", 1000), Some((999, 999)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=-1000", 1000), Some((0, 999)));
+        // A suffix longer than the file is clamped to the whole file
+        assert_eq!(parse_range("bytes=-99999999", 1000), Some((0, 999)));
+        assert_eq!(parse_range("bytes=-5", 1), Some((0, 0)));
+    }
+
+    #[test]
+    fn test_parse_range_rejects_invalid() {
+        assert_eq!(parse_range("bytes=100-50", 1000), None);
+        assert_eq!(parse_range("bytes=-0", 1000), None);
+        assert_eq!(parse_range("bytes=-5", 0), None);
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=1000-1100", 1000), None);
+        assert_eq!(parse_range("bytes=-", 1000), None);
+        assert_eq!(parse_range("bytes=a-b", 1000), None);
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+    }
+
+    #[test]
+    fn test_cue_track_segments() {
+        let flac = crate::audio::StreamLayout {
+            header_len: 100,
+            audio_start: 100,
+        };
+        // 10 000 bytes of audio over 100 s: 100 bytes per second
+        assert_eq!(
+            cue_track_segments(flac, 10_100, 30_000, Some(60_000), 100_000),
+            [(0, 100), (3_100, 6_100)]
+        );
+        assert_eq!(
+            cue_track_segments(flac, 10_100, 90_000, None, 100_000),
+            [(0, 100), (9_100, 10_100)]
+        );
+
+        let mp3 = crate::audio::StreamLayout {
+            header_len: 0,
+            audio_start: 0,
+        };
+        assert_eq!(
+            cue_track_segments(mp3, 1_000, 0, Some(500), 1_000),
+            [(0, 500)]
+        );
+    }
+
+    #[test]
+    fn test_slice_segments() {
+        let segments = [(0, 100), (3_100, 6_100)];
+        assert_eq!(slice_segments(&segments, 0, 3_100), segments);
+        assert_eq!(slice_segments(&segments, 0, 50), [(0, 50)]);
+        assert_eq!(
+            slice_segments(&segments, 90, 110),
+            [(90, 100), (3_100, 3_110)]
+        );
+        assert_eq!(slice_segments(&segments, 200, 3_100), [(3_200, 6_100)]);
+        assert!(slice_segments(&segments, 3_100, 4_000).is_empty());
+    }
+
+    #[test]
+    fn test_range_not_satisfiable() {
+        assert!(range_not_satisfiable("bytes=1000-", 1000));
+        assert!(range_not_satisfiable("bytes=1000-1100", 1000));
+        assert!(range_not_satisfiable("bytes=-0", 1000));
+        assert!(range_not_satisfiable("bytes=-5", 0));
+        assert!(!range_not_satisfiable("bytes=-99999999", 1000));
+
+        // Malformed ranges are ignored instead, serving the whole file
+        assert!(!range_not_satisfiable("bytes=100-50", 1000));
+        assert!(!range_not_satisfiable("bytes=1100-1000", 1000));
+        assert!(!range_not_satisfiable("bytes=a-", 1000));
+        assert!(!range_not_satisfiable("items=1000-", 1000));
+        assert!(!range_not_satisfiable("bytes=0-", 1000));
+
+        // Multiple ranges are rejected even when each one is satisfiable
+        assert!(range_not_satisfiable("bytes=0-99,200-299", 1000));
+        assert!(range_not_satisfiable("bytes=0-0, -1", 1000));
+    }
+}
@@ -1,8 +1,39 @@
 use anyhow::{Context, Result};
+use serde::Serialize;
 use sqlx::SqlitePool;
 use std::collections::HashMap;
 use std::path::Path;
 
+/// Playback statistics for a single track
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TrackStats {
+    pub play_count: u64,
+    /// When the track was last played (RFC 3339), if ever
+    pub last_played: Option<String>,
+}
+
+/// One counted play in the listening history
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    pub track_id: String,
+    /// When the play was counted (RFC 3339, UTC)
+    pub played_at: String,
+    /// How far into the track the client got, in seconds, if it said so
+    pub position_secs: Option<u64>,
+}
+
+/// History timestamps are stored in one fixed UTC format so they compare as text
+fn history_time(time: chrono::DateTime<chrono::Utc>) -> String {
+    time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// SQLite's `datetime('now')` is UTC without an offset; convert it to RFC 3339
+fn sqlite_time_to_rfc3339(value: String) -> String {
+    chrono::NaiveDateTime::parse_from_str(&value, "%Y-%m-%d %H:%M:%S")
+        .map(|time| time.and_utc().to_rfc3339())
+        .unwrap_or(value)
+}
+
 #[derive(Clone)]
 pub struct StatsDatabase {
     pool: SqlitePool,
@@ -41,6 +72,39 @@ impl StatsDatabase {
         .await
         .context("Failed to create track_stats table")?;
 
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS track_added (
+                track_id TEXT PRIMARY KEY,
+                date_added TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .context("Failed to create track_added table")?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS play_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                track_id TEXT NOT NULL,
+                played_at TEXT NOT NULL,
+                position_secs INTEGER
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .context("Failed to create play_history table")?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_play_history_played_at ON play_history (played_at)",
+        )
+        .execute(&pool)
+        .await
+        .context("Failed to create play_history index")?;
+
         tracing::info!("Stats database initialized: {}", db_path.display());
 
         Ok(Self { pool })
@@ -84,19 +148,223 @@ impl StatsDatabase {
             .unwrap_or(0))
     }
 
-    /// Get all play counts
-    pub async fn get_all_play_counts(&self) -> Result<HashMap<String, u64>> {
-        let rows = sqlx::query("SELECT track_id, play_count FROM track_stats")
-            .fetch_all(&self.pool)
+    /// Get play count and last played time for a track
+    pub async fn get_stats(&self, track_id: &str) -> Result<TrackStats> {
+        let row = sqlx::query_as::<_, (i64, Option<String>)>(
+            "SELECT play_count, last_played_at FROM track_stats WHERE track_id = ?",
+        )
+        .bind(track_id)
+        .fetch_optional(&self.pool)
+        .await
+        .context("Failed to get track stats")?;
+
+        let Some((play_count, last_played_at)) = row else {
+            return Ok(TrackStats::default());
+        };
+
+        Ok(TrackStats {
+            play_count: play_count as u64,
+            last_played: last_played_at.map(sqlite_time_to_rfc3339),
+        })
+    }
+
+    /// Get play count and last played time of every track that was played,
+    /// in one query rather than one per track
+    pub async fn get_all_stats(&self) -> Result<HashMap<String, TrackStats>> {
+        let rows = sqlx::query_as::<_, (String, i64, Option<String>)>(
+            "SELECT track_id, play_count, last_played_at FROM track_stats",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get all track stats")?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(track_id, play_count, last_played_at)| {
+                let stats = TrackStats {
+                    play_count: play_count as u64,
+                    last_played: last_played_at.map(sqlite_time_to_rfc3339),
+                };
+                (track_id, stats)
+            })
+            .collect())
+    }
+
+    /// Append a counted play to the listening history
+    pub async fn record_history(&self, track_id: &str, position_secs: Option<u64>) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO play_history (track_id, played_at, position_secs) VALUES (?, ?, ?)",
+        )
+        .bind(track_id)
+        .bind(history_time(chrono::Utc::now()))
+        .bind(position_secs.map(|secs| secs as i64))
+        .execute(&self.pool)
+        .await
+        .context("Failed to record play history")?;
+        Ok(())
+    }
+
+    /// Get up to `limit` history entries played within `from..=to`, newest first
+    pub async fn get_history(
+        &self,
+        limit: usize,
+        from: Option<chrono::DateTime<chrono::Utc>>,
+        to: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<Vec<HistoryEntry>> {
+        let rows = sqlx::query_as::<_, (String, String, Option<i64>)>(
+            r#"
+            SELECT track_id, played_at, position_secs FROM play_history
+            WHERE (?1 IS NULL OR played_at >= ?1) AND (?2 IS NULL OR played_at <= ?2)
+            ORDER BY played_at DESC, id DESC
+            LIMIT ?3
+            "#,
+        )
+        .bind(from.map(history_time))
+        .bind(to.map(history_time))
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to get play history")?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(track_id, played_at, position_secs)| HistoryEntry {
+                track_id,
+                played_at,
+                position_secs: position_secs.map(|secs| secs as u64),
+            })
+            .collect())
+    }
+
+    /// Delete history entries played before `before`; returns how many were deleted
+    pub async fn prune_history(&self, before: chrono::DateTime<chrono::Utc>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM play_history WHERE played_at < ?")
+            .bind(history_time(before))
+            .execute(&self.pool)
             .await
-            .context("Failed to get all play counts")?;
+            .context("Failed to prune play history")?;
+        Ok(result.rows_affected())
+    }
 
-        let mut counts = HashMap::new();
-        for row in rows {
-            let id: String = sqlx::Row::get(&row, 0);
-            let count: i64 = sqlx::Row::get(&row, 1);
-            counts.insert(id, count as u64);
+    /// Store when tracks entered the library, keeping dates already stored,
+    /// and return the stored date of every known track
+    pub async fn record_dates_added(
+        &self,
+        dates: &[(String, String)],
+    ) -> Result<HashMap<String, String>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Failed to start transaction")?;
+        for (track_id, date_added) in dates {
+            sqlx::query("INSERT OR IGNORE INTO track_added (track_id, date_added) VALUES (?, ?)")
+                .bind(track_id)
+                .bind(date_added)
+                .execute(&mut *tx)
+                .await
+                .context("Failed to record date added")?;
         }
-        Ok(counts)
+        tx.commit().await.context("Failed to record dates added")?;
+
+        let rows =
+            sqlx::query_as::<_, (String, String)>("SELECT track_id, date_added FROM track_added")
+                .fetch_all(&self.pool)
+                .await
+                .context("Failed to get dates added")?;
+        Ok(rows.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_get_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = StatsDatabase::new(&dir.path().join("stats.db"))
+            .await
+            .unwrap();
+
+        assert_eq!(db.get_stats("t1").await.unwrap(), TrackStats::default());
+
+        db.increment_play_count("t1").await.unwrap();
+        db.increment_play_count("t1").await.unwrap();
+
+        let stats = db.get_stats("t1").await.unwrap();
+        assert_eq!(stats.play_count, 2);
+        let last_played = stats.last_played.clone().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(&last_played).is_ok());
+
+        db.increment_play_count("t2").await.unwrap();
+        let all = db.get_all_stats().await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all["t1"], stats);
+        assert_eq!(all["t2"].play_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_play_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = StatsDatabase::new(&dir.path().join("stats.db"))
+            .await
+            .unwrap();
+
+        db.record_history("t1", Some(95)).await.unwrap();
+        db.record_history("t2", None).await.unwrap();
+
+        let history = db.get_history(10, None, None).await.unwrap();
+        let ids: Vec<&str> = history.iter().map(|e| e.track_id.as_str()).collect();
+        assert_eq!(ids, ["t2", "t1"]);
+        assert_eq!(history[1].position_secs, Some(95));
+        assert!(chrono::DateTime::parse_from_rfc3339(&history[0].played_at).is_ok());
+        assert_eq!(db.get_history(1, None, None).await.unwrap().len(), 1);
+
+        let hour = chrono::Duration::hours(1);
+        let now = chrono::Utc::now();
+        assert!(
+            db.get_history(10, Some(now + hour), None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            db.get_history(10, Some(now - hour), Some(now + hour))
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+
+        assert_eq!(db.prune_history(now - hour).await.unwrap(), 0);
+        assert_eq!(db.prune_history(now + hour).await.unwrap(), 2);
+        assert!(db.get_history(10, None, None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_record_dates_added_keeps_first_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = StatsDatabase::new(&dir.path().join("stats.db"))
+            .await
+            .unwrap();
+        let date = |id: &str, date: &str| (id.to_string(), date.to_string());
+
+        let stored = db
+            .record_dates_added(&[date("t1", "2024-01-01T00:00:00+00:00")])
+            .await
+            .unwrap();
+        assert_eq!(stored["t1"], "2024-01-01T00:00:00+00:00");
+
+        // A later scan sees a newer file time; the first date wins
+        let stored = db
+            .record_dates_added(&[
+                date("t1", "2025-06-01T00:00:00+00:00"),
+                date("t2", "2025-06-01T00:00:00+00:00"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(stored["t1"], "2024-01-01T00:00:00+00:00");
+        assert_eq!(stored["t2"], "2025-06-01T00:00:00+00:00");
     }
 }
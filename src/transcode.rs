@@ -0,0 +1,533 @@
+//! Live transcoding of tracks for low-bandwidth clients
+//!
+//! Sources are decoded with Symphonia and the PCM is piped into an `ffmpeg`
+//! subprocess that encodes Opus in an Ogg container. High sample rates can be
+//! capped on the way with `rubato`. The output is streamed as it is produced,
+//! so it has no known length and can't be seeked.
+
+use anyhow::{Context, Result};
+use rubato::{FftFixedIn, Resampler};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::Decoder;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatReader, SeekMode, SeekTo};
+use symphonia::core::units::Time;
+use tokio::io::AsyncWriteExt;
+use tokio::process::ChildStdout;
+use tokio::sync::Semaphore;
+
+/// Opus bitrates, in kbit/s, that `/stream/:id?transcode=opus` accepts
+pub const OPUS_BITRATES_KBPS: [u32; 6] = [48, 64, 96, 128, 160, 192];
+
+/// Opus bitrate used when a request doesn't pick one
+pub const DEFAULT_OPUS_BITRATE_KBPS: u32 = 96;
+
+/// Sample rates, in Hz, that transcoded output can be capped to
+pub const TRANSCODE_SAMPLE_RATES: [u32; 8] =
+    [8000, 12000, 16000, 22050, 24000, 32000, 44100, 48000];
+
+/// Input frames the resampler converts at a time
+const RESAMPLE_CHUNK_FRAMES: usize = 4096;
+
+/// PCM chunks buffered between the decoder and `ffmpeg`
+const PCM_CHANNEL_CAPACITY: usize = 8;
+
+/// Error context when a source file can't be decoded, e.g. a codec Symphonia
+/// doesn't support
+#[derive(Debug)]
+pub struct UnsupportedSource;
+
+impl std::fmt::Display for UnsupportedSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Audio format can't be transcoded")
+    }
+}
+
+impl std::error::Error for UnsupportedSource {}
+
+/// Settings of an Opus transcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpusOptions {
+    /// Bitrate in kbit/s, one of [`OPUS_BITRATES_KBPS`]
+    pub bitrate_kbps: u32,
+    /// Sources above this sample rate are downsampled to it before encoding;
+    /// one of [`TRANSCODE_SAMPLE_RATES`]
+    pub max_sample_rate: Option<u32>,
+}
+
+impl Default for OpusOptions {
+    fn default() -> Self {
+        Self {
+            bitrate_kbps: DEFAULT_OPUS_BITRATE_KBPS,
+            max_sample_rate: None,
+        }
+    }
+}
+
+/// Runs `ffmpeg` encodes, at most `max_concurrent` at a time
+#[derive(Debug, Clone)]
+pub struct Transcoder {
+    ffmpeg: PathBuf,
+    permits: Arc<Semaphore>,
+}
+
+impl Transcoder {
+    pub fn new(ffmpeg: impl Into<PathBuf>, max_concurrent: usize) -> Self {
+        Self {
+            ffmpeg: ffmpeg.into(),
+            permits: Arc::new(Semaphore::new(max_concurrent)),
+        }
+    }
+
+    /// Start encoding `path` as Ogg Opus with `options` and return the
+    /// encoder's output. `clip_ms` limits the output to a `(start, end)` span
+    /// of the file, for CUE tracks.
+    ///
+    /// Returns `None` when every transcode slot is busy. The slot is released
+    /// once `ffmpeg` exits, which it does soon after the output is dropped.
+    pub async fn opus(
+        &self,
+        path: &Path,
+        clip_ms: (u64, Option<u64>),
+        options: OpusOptions,
+    ) -> Result<Option<ChildStdout>> {
+        let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+            return Ok(None);
+        };
+
+        let source = path.to_path_buf();
+        let mut decoder = tokio::task::spawn_blocking(move || {
+            PcmDecoder::open(&source, clip_ms, options.max_sample_rate)
+        })
+        .await
+        .context("PCM decoder task failed")??;
+
+        let mut child = tokio::process::Command::new(&self.ffmpeg)
+            .args(ffmpeg_opus_args(
+                decoder.sample_rate,
+                decoder.channels,
+                options.bitrate_kbps,
+            ))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start {}", self.ffmpeg.display()))?;
+        let mut stdin = child.stdin.take().context("ffmpeg has no stdin")?;
+        let stdout = child.stdout.take().context("ffmpeg has no stdout")?;
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel::<Vec<u8>>(PCM_CHANNEL_CAPACITY);
+        let source = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = decoder.run(|pcm| sender.blocking_send(pcm).is_ok()) {
+                tracing::warn!("Failed to decode {}: {:#}", source.display(), e);
+            }
+        });
+        tokio::spawn(async move {
+            let _permit = permit;
+            while let Some(pcm) = receiver.recv().await {
+                // Fails once ffmpeg exits, e.g. because the client went away
+                if stdin.write_all(&pcm).await.is_err() {
+                    break;
+                }
+            }
+            // Closing both ends stops the decoder and lets ffmpeg finish
+            drop(receiver);
+            drop(stdin);
+            match child.wait().await {
+                Ok(status) if !status.success() => {
+                    tracing::debug!("ffmpeg exited with {}", status)
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Failed to wait for ffmpeg: {}", e),
+            }
+        });
+
+        Ok(Some(stdout))
+    }
+}
+
+/// `ffmpeg` arguments encoding interleaved `f32le` PCM from stdin as Ogg Opus on stdout
+fn ffmpeg_opus_args(sample_rate: u32, channels: usize, bitrate_kbps: u32) -> Vec<String> {
+    [
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-f",
+        "f32le",
+        "-ar",
+        &sample_rate.to_string(),
+        "-ac",
+        &channels.to_string(),
+        "-i",
+        "pipe:0",
+        "-c:a",
+        "libopus",
+        "-b:a",
+        &format!("{}k", bitrate_kbps),
+        "-f",
+        "ogg",
+        "pipe:1",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect()
+}
+
+/// Decodes the default track of a file to interleaved `f32` PCM
+struct PcmDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    /// Sample rate of the PCM handed out, after any downsampling
+    sample_rate: u32,
+    channels: usize,
+    /// Frames (at the source rate) still to drop before the clip starts
+    skip_frames: u64,
+    /// Frames left until the clip ends; `None` decodes to the end of the file
+    remaining_frames: Option<u64>,
+    downsampler: Option<Downsampler>,
+}
+
+impl PcmDecoder {
+    fn open(
+        path: &Path,
+        (start_ms, end_ms): (u64, Option<u64>),
+        max_sample_rate: Option<u32>,
+    ) -> Result<Self> {
+        use symphonia::core::io::MediaSourceStream;
+
+        let file = std::fs::File::open(path).context("Failed to open audio file")?;
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut hint = symphonia::core::probe::Hint::new();
+        if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(extension);
+        }
+        let mut format = symphonia::default::get_probe()
+            .format(&hint, mss, &Default::default(), &Default::default())
+            .context("Failed to probe audio file")
+            .context(UnsupportedSource)?
+            .format;
+
+        let track = format
+            .default_track()
+            .context("No audio track")
+            .context(UnsupportedSource)?;
+        let params = &track.codec_params;
+        let sample_rate = params
+            .sample_rate
+            .context("Unknown sample rate")
+            .context(UnsupportedSource)?;
+        let channels = params
+            .channels
+            .context("Unknown channel layout")
+            .context(UnsupportedSource)?
+            .count();
+        let mut decoder = symphonia::default::get_codecs()
+            .make(params, &Default::default())
+            .context("No decoder for this codec")
+            .context(UnsupportedSource)?;
+        let track_id = track.id;
+        let time_base = params.time_base;
+
+        let downsampler = match max_sample_rate {
+            Some(max) if max < sample_rate => Some(Downsampler::new(sample_rate, max, channels)?),
+            _ => None,
+        };
+
+        let to_frames = |ms: u64| ms * u64::from(sample_rate) / 1000;
+
+        // Seek to the start of a clip, leaving only the packet it lands in to
+        // trim; if the reader can't seek, frames are decoded and dropped instead
+        let mut skip_frames = to_frames(start_ms);
+        if start_ms > 0 {
+            let time = Time::new(start_ms / 1000, (start_ms % 1000) as f64 / 1000.0);
+            let seek_to = SeekTo::Time {
+                time,
+                track_id: Some(track_id),
+            };
+            match format.seek(SeekMode::Accurate, seek_to) {
+                Ok(seeked) => {
+                    let behind = seeked.required_ts.saturating_sub(seeked.actual_ts);
+                    skip_frames = match time_base {
+                        Some(time_base) => {
+                            let time = time_base.calc_time(behind);
+                            time.seconds * u64::from(sample_rate)
+                                + (time.frac * f64::from(sample_rate)).round() as u64
+                        }
+                        None => behind,
+                    };
+                    decoder.reset();
+                }
+                Err(e) => tracing::debug!(
+                    "Failed to seek to {} ms in {}, decoding from the start: {}",
+                    start_ms,
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
+        Ok(Self {
+            track_id,
+            format,
+            decoder,
+            sample_rate: max_sample_rate.map_or(sample_rate, |max| max.min(sample_rate)),
+            channels,
+            skip_frames,
+            remaining_frames: end_ms.map(|end| to_frames(end.saturating_sub(start_ms))),
+            downsampler,
+        })
+    }
+
+    /// Decode packets and hand the PCM bytes of the clip to `emit` until the
+    /// clip or file ends, or `emit` returns false
+    fn run(&mut self, mut emit: impl FnMut(Vec<u8>) -> bool) -> Result<()> {
+        let mut samples: Option<SampleBuffer<f32>> = None;
+
+        while self.remaining_frames != Some(0) {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(e))
+                    if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+                {
+                    break;
+                }
+                Err(SymphoniaError::ResetRequired) => break,
+                Err(e) => return Err(e).context("Failed to read packet"),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // A corrupt packet only costs a moment of audio
+                Err(SymphoniaError::DecodeError(e)) => {
+                    tracing::debug!("Skipping undecodable packet: {}", e);
+                    continue;
+                }
+                Err(e) => return Err(e).context("Failed to decode packet"),
+            };
+
+            let buffer = samples.get_or_insert_with(|| {
+                SampleBuffer::new(decoded.capacity() as u64, *decoded.spec())
+            });
+            if buffer.capacity() < decoded.capacity() * self.channels {
+                *buffer = SampleBuffer::new(decoded.capacity() as u64, *decoded.spec());
+            }
+            buffer.copy_interleaved_ref(decoded);
+
+            let mut frames = buffer.samples().chunks_exact(self.channels);
+            let skipped = frames.by_ref().take(self.skip_frames as usize).count() as u64;
+            self.skip_frames -= skipped;
+            let keep = self.remaining_frames.unwrap_or(u64::MAX) as usize;
+            let clip: Vec<f32> = frames.take(keep).flatten().copied().collect();
+            if clip.is_empty() {
+                continue;
+            }
+            if let Some(remaining) = &mut self.remaining_frames {
+                *remaining -= (clip.len() / self.channels) as u64;
+            }
+
+            let clip = match &mut self.downsampler {
+                Some(downsampler) => downsampler.push(&clip)?,
+                None => clip,
+            };
+            if !clip.is_empty() && !emit(pcm_bytes(&clip)) {
+                return Ok(());
+            }
+        }
+
+        if let Some(downsampler) = &mut self.downsampler {
+            let rest = downsampler.finish()?;
+            if !rest.is_empty() {
+                emit(pcm_bytes(&rest));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Interleaved samples as `f32le` bytes
+fn pcm_bytes(samples: &[f32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect()
+}
+
+/// Converts interleaved PCM to a lower sample rate. The FFT resampler works
+/// on fixed-size chunks, so input is buffered until a chunk is full.
+struct Downsampler {
+    resampler: FftFixedIn<f32>,
+    channels: usize,
+    rates: (u32, u32),
+    /// Per-channel input waiting for a full chunk
+    pending: Vec<Vec<f32>>,
+    /// Output frames still to drop for the resampler's delay
+    delay_frames: usize,
+    frames_in: u64,
+    frames_out: u64,
+}
+
+impl Downsampler {
+    fn new(from: u32, to: u32, channels: usize) -> Result<Self> {
+        let resampler = FftFixedIn::new(
+            from as usize,
+            to as usize,
+            RESAMPLE_CHUNK_FRAMES,
+            1,
+            channels,
+        )
+        .context("Failed to create resampler")?;
+        Ok(Self {
+            delay_frames: resampler.output_delay(),
+            resampler,
+            channels,
+            rates: (from, to),
+            pending: vec![Vec::new(); channels],
+            frames_in: 0,
+            frames_out: 0,
+        })
+    }
+
+    /// Queue interleaved input and return the interleaved output of every full chunk
+    fn push(&mut self, interleaved: &[f32]) -> Result<Vec<f32>> {
+        for frame in interleaved.chunks_exact(self.channels) {
+            for (channel, sample) in self.pending.iter_mut().zip(frame) {
+                channel.push(*sample);
+            }
+        }
+        self.frames_in += (interleaved.len() / self.channels) as u64;
+
+        let mut output = Vec::new();
+        while self.pending[0].len() >= self.resampler.input_frames_next() {
+            let needed = self.resampler.input_frames_next();
+            let chunk: Vec<Vec<f32>> = self
+                .pending
+                .iter_mut()
+                .map(|channel| channel.drain(..needed).collect())
+                .collect();
+            let resampled = self
+                .resampler
+                .process(&chunk, None)
+                .context("Failed to resample")?;
+            self.interleave_into(&resampled, &mut output);
+        }
+        Ok(output)
+    }
+
+    /// Flush the buffered input and the resampler's delay line
+    fn finish(&mut self) -> Result<Vec<f32>> {
+        let mut output = Vec::new();
+        let pending = std::mem::replace(&mut self.pending, vec![Vec::new(); self.channels]);
+        let resampled = self
+            .resampler
+            .process_partial(Some(&pending), None)
+            .context("Failed to resample")?;
+        self.interleave_into(&resampled, &mut output);
+        let resampled = self
+            .resampler
+            .process_partial(None::<&[Vec<f32>]>, None)
+            .context("Failed to resample")?;
+        self.interleave_into(&resampled, &mut output);
+        Ok(output)
+    }
+
+    /// Append planar resampler output, skipping the delay and anything past
+    /// the length the input converts to
+    fn interleave_into(&mut self, planar: &[Vec<f32>], output: &mut Vec<f32>) {
+        let (from, to) = self.rates;
+        let expected = self.frames_in * u64::from(to) / u64::from(from);
+        let frames = planar.first().map_or(0, Vec::len);
+        let skip = self.delay_frames.min(frames);
+        self.delay_frames -= skip;
+
+        for index in skip..frames {
+            if self.frames_out >= expected {
+                break;
+            }
+            output.extend(planar.iter().map(|channel| channel[index]));
+            self.frames_out += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ffmpeg_opus_args() {
+        let args = ffmpeg_opus_args(44100, 2, 96).join(" ");
+        assert!(
+            args.contains("-f f32le -ar 44100 -ac 2 -i pipe:0"),
+            "{}",
+            args
+        );
+        assert!(
+            args.ends_with("-c:a libopus -b:a 96k -f ogg pipe:1"),
+            "{}",
+            args
+        );
+    }
+
+    #[test]
+    fn test_decoder_seeks_to_clip_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.mp3");
+        crate::audio::tests::write_silent_mp3(&path);
+
+        let decoded_frames = |clip| {
+            let mut decoder = PcmDecoder::open(&path, clip, None).unwrap();
+            let mut bytes = 0;
+            decoder
+                .run(|pcm| {
+                    bytes += pcm.len();
+                    true
+                })
+                .unwrap();
+            bytes / (4 * decoder.channels)
+        };
+
+        let total = decoded_frames((0, None));
+        assert_eq!(total, 40 * 1152);
+        // Starting mid-file keeps exactly the frames after the start
+        assert_eq!(decoded_frames((500, None)), total - 22_050);
+        assert_eq!(decoded_frames((500, Some(700))), 8_820);
+    }
+
+    #[test]
+    fn test_downsampler() {
+        // One second of a 1 kHz stereo tone at 96 kHz, pushed in uneven pieces
+        let tone: Vec<f32> = (0..96_000)
+            .flat_map(|i| {
+                let sample = (i as f32 * 1000.0 * std::f32::consts::TAU / 96_000.0).sin() * 0.5;
+                [sample, -sample]
+            })
+            .collect();
+        let mut downsampler = Downsampler::new(96_000, 44_100, 2).unwrap();
+        let mut output = Vec::new();
+        for piece in tone.chunks(2 * 1000) {
+            output.extend(downsampler.push(piece).unwrap());
+        }
+        output.extend(downsampler.finish().unwrap());
+
+        assert_eq!(output.len(), 2 * 44_100);
+        // The tone keeps its level and the channels stay apart
+        let middle = &output[2 * 20_000..2 * 24_000];
+        let peak = middle
+            .iter()
+            .step_by(2)
+            .fold(0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - 0.5).abs() < 0.02, "{}", peak);
+        assert!(
+            middle
+                .chunks(2)
+                .all(|frame| (frame[0] + frame[1]).abs() < 1e-3)
+        );
+    }
+}
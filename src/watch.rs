@@ -0,0 +1,199 @@
+//! Keep the library up to date with the files on disk, for `--watch`
+//!
+//! Filesystem events are collected until the library has been quiet for the
+//! debounce period, then applied with one incremental rescan. Files the
+//! library already has up to date, such as those the server just wrote tags,
+//! covers or lyrics to, don't trigger a rescan.
+
+use crate::error::MusicStationError;
+use crate::library::MusicLibrary;
+use crate::lyrics::LyricDatabase;
+use crate::server::rescan_library;
+use crate::stats::StatsDatabase;
+use anyhow::{Context, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How long the library must be quiet before changes are applied
+pub const WATCH_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// Watch the library folder recursively and rescan it after files are
+/// created, modified or removed. The watcher stops when the returned task is
+/// aborted.
+pub fn watch_library(
+    library: MusicLibrary,
+    lyrics_db: LyricDatabase,
+    stats_db: StatsDatabase,
+    debounce: Duration,
+) -> Result<tokio::task::JoinHandle<()>> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        // Only fails once the task below is gone
+        let _ = tx.send(event);
+    })
+    .context("Failed to create filesystem watcher")?;
+    watcher
+        .watch(library.library_path(), RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", library.library_path().display()))?;
+
+    tracing::info!("Watching {} for changes", library.library_path().display());
+
+    Ok(tokio::spawn(async move {
+        // Events stop when the watcher is dropped
+        let _watcher = watcher;
+        let mut changed = BTreeSet::new();
+
+        loop {
+            // Changes left over from a rescan that couldn't start are retried
+            // after the next quiet period, without waiting for another event
+            if changed.is_empty() {
+                let Some(event) = rx.recv().await else {
+                    return;
+                };
+                collect_changes(&library, event, &mut changed).await;
+            }
+
+            // Wait for the burst of events to end
+            loop {
+                match tokio::time::timeout(debounce, rx.recv()).await {
+                    Ok(Some(event)) => collect_changes(&library, event, &mut changed).await,
+                    Ok(None) => return,
+                    Err(_) => break,
+                }
+            }
+            let mut pending = Vec::new();
+            for path in std::mem::take(&mut changed) {
+                if !library.is_up_to_date(&path).await {
+                    pending.push(path);
+                }
+            }
+            if pending.is_empty() {
+                continue;
+            }
+
+            match rescan_library(&library, &lyrics_db, &stats_db).await {
+                Ok(_) => {}
+                Err(MusicStationError::Conflict(_)) => {
+                    tracing::info!("A scan is already running; rescanning once it finishes");
+                    library.wait_for_scan().await;
+                    changed.extend(pending);
+                    continue;
+                }
+                Err(e) => tracing::warn!("Rescan after library change failed: {}", e),
+            }
+        }
+    }))
+}
+
+/// Add the paths of a create, modify or remove event that can affect the
+/// track list to `changed`
+async fn collect_changes(
+    library: &MusicLibrary,
+    event: notify::Result<notify::Event>,
+    changed: &mut BTreeSet<PathBuf>,
+) {
+    let event = match event {
+        Ok(event) => event,
+        Err(e) => {
+            tracing::warn!("Filesystem watcher error: {}", e);
+            return;
+        }
+    };
+    if !matches!(
+        event.kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    ) {
+        return;
+    }
+    for path in event.paths {
+        if library.affects_tracks(&path).await && changed.insert(path.clone()) {
+            tracing::info!("Library change: {:?} {}", event.kind, path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::tests::write_silent_mp3;
+
+    #[tokio::test]
+    async fn test_watch_applies_added_and_removed_files() {
+        let library_dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&library_dir.path().join("Album/01.mp3"));
+        let library = MusicLibrary::new(library_dir.path().to_path_buf());
+        library.scan().await.unwrap();
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let lyrics_db = LyricDatabase::new(data_dir.path().join("lyrics.db"))
+            .await
+            .unwrap();
+        let stats_db = StatsDatabase::new(&data_dir.path().join("stats.db"))
+            .await
+            .unwrap();
+        let task = watch_library(
+            library.clone(),
+            lyrics_db,
+            stats_db,
+            Duration::from_millis(200),
+        )
+        .unwrap();
+
+        let wait_for_tracks = |count: usize| {
+            let library = library.clone();
+            async move {
+                for _ in 0..100 {
+                    if library.get_tracks().await.len() == count {
+                        return;
+                    }
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+                panic!("library never reached {} tracks", count);
+            }
+        };
+
+        write_silent_mp3(&library_dir.path().join("Album/02.mp3"));
+        wait_for_tracks(2).await;
+
+        std::fs::remove_file(library_dir.path().join("Album/01.mp3")).unwrap();
+        wait_for_tracks(1).await;
+        assert!(library.get_tracks().await[0].path.ends_with("Album/02.mp3"));
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_watch_ignores_the_libraries_own_edits() {
+        let library_dir = tempfile::tempdir().unwrap();
+        write_silent_mp3(&library_dir.path().join("Album/01.mp3"));
+        let library = MusicLibrary::new(library_dir.path().to_path_buf());
+        library.scan().await.unwrap();
+        let scanned_at = library.scan_status().await.started_at;
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let lyrics_db = LyricDatabase::new(data_dir.path().join("lyrics.db"))
+            .await
+            .unwrap();
+        let stats_db = StatsDatabase::new(&data_dir.path().join("stats.db"))
+            .await
+            .unwrap();
+        let task = watch_library(
+            library.clone(),
+            lyrics_db,
+            stats_db,
+            Duration::from_millis(200),
+        )
+        .unwrap();
+
+        let id = library.get_tracks().await[0].id.clone();
+        let update = serde_json::from_str(r#"{"title": "New"}"#).unwrap();
+        library.update_track_metadata(&id, update).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        assert_eq!(library.scan_status().await.started_at, scanned_at);
+
+        task.abort();
+    }
+}
@@ -2576,10 +2576,36 @@ function updateSynchronizedLyrics(currentTime) {
 
 let currentSearchTrackId = null;
 
+let lyricsProvidersLoaded = false;
+
+// Fill the provider select from the server's registered providers
+async function loadLyricsProviders() {
+    if (lyricsProvidersLoaded) return;
+
+    try {
+        const response = await fetch(`${API_BASE}/lyrics/providers/info`);
+        if (!response.ok) return;
+
+        const providers = await response.json();
+        if (providers.length === 0) return;
+
+        const select = document.getElementById('lyricsSearchProvider');
+        select.innerHTML = providers.map(p => {
+            const notes = [p.supports_synced ? 'synced' : 'plain', ...p.regions].join(', ');
+            return `<option value="${escapeHtml(p.name)}">${escapeHtml(p.name)} (${escapeHtml(notes)})</option>`;
+        }).join('');
+        lyricsProvidersLoaded = true;
+    } catch (error) {
+        console.error('Error loading lyrics providers:', error);
+    }
+}
+
 // Open lyrics search modal
 function openLyricsSearchModal() {
     if (!currentLyricsTrackId) return;
 
+    loadLyricsProviders();
+
     // Store the track ID for search
     currentSearchTrackId = currentLyricsTrackId;
 
@@ -0,0 +1,2113 @@
+//! End-to-end tests driving the HTTP router over a temporary library
+
+use axum::Router;
+use axum::body::Body;
+use axum::http::{Method, Request, StatusCode, header};
+use axum::response::Response;
+use music_station::library::MusicLibrary;
+use music_station::lyrics::{LyricDatabase, LyricFormat};
+use music_station::playlist::PlaylistDatabase;
+use music_station::server::{
+    LyricsProviderOptions, ServerOptions, create_router, load_track_flags,
+};
+use music_station::stats::StatsDatabase;
+use music_station::transcode::Transcoder;
+use serde_json::{Value, json};
+use std::path::Path;
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+/// Write a short, valid, tagless MPEG-1 Layer III stream (128 kbps, 44.1 kHz)
+fn write_silent_mp3(path: &Path) {
+    write_silent_mp3_frames(path, 40);
+}
+
+/// Write a tagless MP3 of `frames` silent 417-byte frames
+fn write_silent_mp3_frames(path: &Path, frames: usize) {
+    let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+    frame.resize(417, 0);
+    let data: Vec<u8> = frame.iter().cycle().take(417 * frames).copied().collect();
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, data).unwrap();
+}
+
+struct TestServer {
+    router: Router,
+    // Kept alive for the duration of the test
+    library_dir: TempDir,
+    data_dir: TempDir,
+}
+
+impl TestServer {
+    async fn new() -> Self {
+        Self::with_library(|dir| {
+            write_silent_mp3(&dir.join("Artist/Album/01 First.mp3"));
+            write_silent_mp3(&dir.join("Artist/Album/02 Second.mp3"));
+        })
+        .await
+    }
+
+    /// Start a server over a library populated by `setup`
+    async fn with_library(setup: impl FnOnce(&Path)) -> Self {
+        Self::with_options(setup, ServerOptions::default()).await
+    }
+
+    /// Start a server with `options`; lyrics always come from the offline mock provider
+    async fn with_options(setup: impl FnOnce(&Path), options: ServerOptions) -> Self {
+        let library_dir = tempfile::tempdir().unwrap();
+        setup(library_dir.path());
+
+        let library = MusicLibrary::new(library_dir.path().to_path_buf());
+        library.scan().await.unwrap();
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let lyrics_db = LyricDatabase::new(data_dir.path().join("lyrics.db"))
+            .await
+            .unwrap();
+        let playlist_db = PlaylistDatabase::new(&data_dir.path().join("playlists.db"))
+            .await
+            .unwrap();
+        let stats_db = StatsDatabase::new(&data_dir.path().join("stats.db"))
+            .await
+            .unwrap();
+
+        let router = create_router(
+            library,
+            lyrics_db,
+            playlist_db,
+            stats_db,
+            ServerOptions {
+                lyrics: LyricsProviderOptions {
+                    offline: true,
+                    default_provider: Some("mock".to_string()),
+                    ..Default::default()
+                },
+                ..options
+            },
+        );
+
+        Self {
+            router,
+            library_dir,
+            data_dir,
+        }
+    }
+
+    async fn send(&self, request: Request<Body>) -> Response {
+        self.router.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn get(&self, uri: &str) -> Response {
+        self.send(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+    }
+
+    async fn send_json(&self, method: Method, uri: &str, body: Value) -> Response {
+        self.send(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+    }
+
+    async fn send_empty(&self, method: Method, uri: &str) -> Response {
+        self.send(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// IDs of all tracks, ordered by file name
+    async fn track_ids(&self) -> Vec<String> {
+        let mut tracks = json_body(self.get("/tracks").await).await;
+        let tracks = tracks.as_array_mut().unwrap();
+        tracks.sort_by_key(|t| t["filename"].as_str().unwrap().to_string());
+        tracks
+            .iter()
+            .map(|t| t["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+}
+
+async fn body_bytes(response: Response) -> Vec<u8> {
+    axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap()
+        .to_vec()
+}
+
+async fn json_body(response: Response) -> Value {
+    assert_eq!(response.status(), StatusCode::OK);
+    serde_json::from_slice(&body_bytes(response).await).unwrap()
+}
+
+#[tokio::test]
+async fn test_list_and_get_tracks() {
+    let server = TestServer::new().await;
+
+    let tracks = json_body(server.get("/tracks").await).await;
+    let tracks = tracks.as_array().unwrap();
+    assert_eq!(tracks.len(), 2);
+    let mut filenames: Vec<&str> = tracks
+        .iter()
+        .map(|t| t["filename"].as_str().unwrap())
+        .collect();
+    filenames.sort();
+    assert_eq!(filenames, ["01 First.mp3", "02 Second.mp3"]);
+
+    let id = tracks[0]["id"].as_str().unwrap();
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["id"], id);
+    assert_eq!(track["relative_path"], tracks[0]["relative_path"]);
+    assert_eq!(track["has_lyrics"], false);
+    assert_eq!(track["sample_rate"], 44100);
+    assert_eq!(track["channels"], 2);
+    assert!(track["bits_per_sample"].is_null());
+    assert_eq!(track["bitrate_kbps"], 128);
+
+    let missing = server.get("/tracks/does-not-exist").await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_list_tracks_streams_json_array() {
+    let server = TestServer::new().await;
+    let response = server.get("/tracks").await;
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    let tracks = json_body(response).await;
+    assert_eq!(tracks.as_array().unwrap().len(), 2);
+
+    let empty = TestServer::with_library(|_| {}).await;
+    let response = empty.get("/tracks").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(&body_bytes(response).await[..], b"[]");
+}
+
+#[tokio::test]
+async fn test_search_tracks() {
+    let tagged = |path: &Path, title: &str, artist: &str| {
+        use id3::TagLike;
+
+        write_silent_mp3(path);
+        let mut tag = id3::Tag::new();
+        tag.set_title(title);
+        tag.set_artist(artist);
+        tag.write_to_path(path, id3::Version::Id3v24).unwrap();
+    };
+    let server = TestServer::with_library(|dir| {
+        tagged(&dir.join("a.mp3"), "Helpless", "Neil Young");
+        tagged(&dir.join("b.mp3"), "Help!", "The Beatles");
+        tagged(&dir.join("c.mp3"), "Yesterday", "The Beatles");
+    })
+    .await;
+    let titles = |tracks: Value| -> Vec<String> {
+        tracks
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["title"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let tracks = json_body(server.get("/search?q=help!").await).await;
+    assert_eq!(titles(tracks), ["Help!"]);
+    let tracks = json_body(server.get("/search?q=beatels&limit=1").await).await;
+    assert_eq!(titles(tracks), ["Help!"]);
+    let tracks = json_body(server.get("/search?q=young&field=title").await).await;
+    assert!(titles(tracks).is_empty());
+
+    let response = server.get("/search?q=help&field=genre").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = server.get(&format!("/search?q={}", "a".repeat(257))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+/// Write a stand-in for `ffmpeg` that prints its arguments and how many PCM
+/// bytes it was fed
+#[cfg(unix)]
+fn write_fake_ffmpeg(dir: &Path) -> std::path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join("ffmpeg");
+    std::fs::write(&path, "#!/bin/sh\necho \"$@\"\nwc -c\n").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stream_transcode_opus() {
+    let bin_dir = tempfile::tempdir().unwrap();
+    let ffmpeg = write_fake_ffmpeg(bin_dir.path());
+    let server = TestServer::with_options(
+        |dir| write_silent_mp3(&dir.join("Artist/Album/01 First.mp3")),
+        ServerOptions {
+            transcoder: Transcoder::new(&ffmpeg, 4),
+            ..Default::default()
+        },
+    )
+    .await;
+    let id = &server.track_ids().await[0];
+
+    let response = server.get(&format!("/stream/{}?transcode=opus", id)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "audio/ogg");
+    assert_eq!(response.headers()[header::ACCEPT_RANGES], "none");
+    assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+    let output = String::from_utf8(body_bytes(response).await).unwrap();
+    let (args, pcm_len) = output.trim().split_once('\n').unwrap();
+    assert!(args.contains("-f f32le -ar 44100"), "{}", args);
+    assert!(args.contains("-c:a libopus -b:a 96k -f ogg"), "{}", args);
+    // 40 decoded MP3 frames of 1152 f32 samples per channel
+    let pcm_len: usize = pcm_len.trim().parse().unwrap();
+    assert!(
+        pcm_len > 0 && pcm_len.is_multiple_of(1152 * 4),
+        "{}",
+        pcm_len
+    );
+
+    // Downsampled to the requested rate; sources at or below it are left alone
+    let response = server
+        .get(&format!("/stream/{}?transcode=opus&sample_rate=22050", id))
+        .await;
+    let output = String::from_utf8(body_bytes(response).await).unwrap();
+    let (args, downsampled_len) = output.trim().split_once('\n').unwrap();
+    assert!(args.contains("-f f32le -ar 22050"), "{}", args);
+    let downsampled_len: usize = downsampled_len.trim().parse().unwrap();
+    assert_eq!(downsampled_len, pcm_len / 8 / 2 * 8);
+    let response = server
+        .get(&format!("/stream/{}?transcode=opus&sample_rate=48000", id))
+        .await;
+    let output = String::from_utf8(body_bytes(response).await).unwrap();
+    assert!(output.contains("-ar 44100"), "{}", output);
+
+    let response = server
+        .send(
+            Request::get(format!(
+                "/stream/{}?transcode=opus&bitrate=64&download=true",
+                id
+            ))
+            .header(header::RANGE, "bytes=0-")
+            .body(Body::empty())
+            .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let disposition = response.headers()[header::CONTENT_DISPOSITION]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(disposition.contains("01 First.opus"), "{}", disposition);
+    let output = String::from_utf8(body_bytes(response).await).unwrap();
+    assert!(output.contains("-b:a 64k"), "{}", output);
+
+    // Transcoded output can't be seeked
+    let response = server
+        .send(
+            Request::get(format!("/stream/{}?transcode=opus", id))
+                .header(header::RANGE, "bytes=100-")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+    for query in [
+        "transcode=opus&bitrate=100",
+        "transcode=opus&sample_rate=12345",
+        "transcode=mp3",
+        "sample_rate=22050",
+    ] {
+        let response = server.get(&format!("/stream/{}?{}", id, query)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+    }
+
+    let busy = TestServer::with_options(
+        |dir| write_silent_mp3(&dir.join("track.mp3")),
+        ServerOptions {
+            transcoder: Transcoder::new(&ffmpeg, 0),
+            ..Default::default()
+        },
+    )
+    .await;
+    let id = &busy.track_ids().await[0];
+    let response = busy.get(&format!("/stream/{}?transcode=opus", id)).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn test_list_tracks_pagination_and_fields() {
+    let server = TestServer::new().await;
+    let all = json_body(server.get("/tracks").await).await;
+    let all = all.as_array().unwrap();
+
+    let page = json_body(server.get("/tracks?skip=1&limit=5").await).await;
+    assert_eq!(page.as_array().unwrap(), &all[1..]);
+    let page = json_body(server.get("/tracks?limit=1").await).await;
+    assert_eq!(page.as_array().unwrap(), &all[..1]);
+    let page = json_body(server.get("/tracks?skip=10").await).await;
+    assert_eq!(page, serde_json::json!([]));
+
+    // Only the requested fields; unknown names are ignored
+    let projected = json_body(
+        server
+            .get("/tracks?fields=id,%20title,bogus,filename")
+            .await,
+    )
+    .await;
+    let projected = projected.as_array().unwrap();
+    assert_eq!(projected.len(), all.len());
+    for (track, full) in projected.iter().zip(all) {
+        let mut keys: Vec<&String> = track.as_object().unwrap().keys().collect();
+        keys.sort();
+        assert_eq!(keys, ["filename", "id", "title"]);
+        assert_eq!(track["id"], full["id"]);
+        assert_eq!(track["filename"], full["filename"]);
+    }
+
+    // An empty list is the same as no list
+    for query in ["fields=", "fields=,%20"] {
+        let tracks = json_body(server.get(&format!("/tracks?{}", query)).await).await;
+        assert_eq!(tracks.as_array().unwrap(), all, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn test_stream_track() {
+    let server = TestServer::new().await;
+    let id = &server.track_ids().await[0];
+
+    let response = server.get(&format!("/stream/{}", id)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "audio/mpeg");
+    assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+    let full = body_bytes(response).await;
+    assert_eq!(full.len(), 417 * 40);
+
+    let response = server
+        .send(
+            Request::get(format!("/stream/{}", id))
+                .header(header::RANGE, "bytes=0-3")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers()[header::CONTENT_RANGE],
+        format!("bytes 0-3/{}", full.len())
+    );
+    assert_eq!(body_bytes(response).await, &full[..4]);
+
+    let missing = server.get("/stream/does-not-exist").await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_download_track() {
+    let server = TestServer::new().await;
+    let id = &server.track_ids().await[0];
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    let filename = track["filename"].as_str().unwrap();
+
+    let inline = server.get(&format!("/stream/{}", id)).await;
+    assert_eq!(
+        inline.headers()[header::CONTENT_DISPOSITION],
+        format!("inline; filename=\"{}\"", filename)
+    );
+
+    let attachment = format!("attachment; filename=\"{}\"", filename);
+    for uri in [
+        format!("/stream/{}?download=true", id),
+        format!("/tracks/{}/download", id),
+    ] {
+        let response = server.get(&uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_DISPOSITION], attachment);
+        assert_eq!(body_bytes(response).await.len(), 417 * 40);
+    }
+
+    // Downloads can still be resumed with a range
+    let response = server
+        .send(
+            Request::get(format!("/tracks/{}/download", id))
+                .header(header::RANGE, "bytes=100-")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[header::CONTENT_DISPOSITION], attachment);
+
+    let missing = server.get("/tracks/does-not-exist/download").await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_lyrics_lifecycle() {
+    let server = TestServer::new().await;
+    let id = &server.track_ids().await[0];
+    let uri = format!("/lyrics/{}", id);
+
+    assert_eq!(server.get(&uri).await.status(), StatusCode::NOT_FOUND);
+
+    let uploaded = json_body(
+        server
+            .send_json(
+                Method::PUT,
+                &uri,
+                json!({ "content": "[00:01.00]Hello\n[00:02.00]World" }),
+            )
+            .await,
+    )
+    .await;
+    assert_eq!(uploaded["track_id"], id.as_str());
+    assert_eq!(uploaded["format"], "lrc");
+
+    let lyric = json_body(server.get(&uri).await).await;
+    assert_eq!(lyric["content"], "[00:01.00]Hello\n[00:02.00]World");
+
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["has_lyrics"], true);
+
+    let deleted = server.send_empty(Method::DELETE, &uri).await;
+    assert_eq!(deleted.status(), StatusCode::NO_CONTENT);
+    assert_eq!(server.get(&uri).await.status(), StatusCode::NOT_FOUND);
+
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["has_lyrics"], false);
+
+    let missing_track = server
+        .send_json(
+            Method::PUT,
+            "/lyrics/does-not-exist",
+            json!({ "content": "Hello" }),
+        )
+        .await;
+    assert_eq!(missing_track.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_lyrics_in_several_formats() {
+    let server = TestServer::new().await;
+    let id = &server.track_ids().await[0];
+    let uri = format!("/lyrics/{}", id);
+
+    for content in ["Translated line", "[00:01.00]Original line"] {
+        let response = server
+            .send_json(Method::PUT, &uri, json!({ "content": content }))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let all = json_body(server.get(&format!("{}/all", uri)).await).await;
+    let formats: Vec<&str> = all
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| l["format"].as_str().unwrap())
+        .collect();
+    assert_eq!(formats, ["lrc", "plain"]);
+    assert_eq!(json_body(server.get(&uri).await).await["format"], "lrc");
+    let plain = json_body(server.get(&format!("{}?format=plain", uri)).await).await;
+    assert_eq!(plain["content"], "Translated line");
+
+    // A misspelled format is rejected rather than read as plain
+    let typo = server.get(&format!("{}?format=lcr", uri)).await;
+    assert_eq!(typo.status(), StatusCode::BAD_REQUEST);
+    let typo = server
+        .send_empty(Method::DELETE, &format!("{}?format=lcr", uri))
+        .await;
+    assert_eq!(typo.status(), StatusCode::BAD_REQUEST);
+    let all = json_body(server.get(&format!("{}/all", uri)).await).await;
+    assert_eq!(all.as_array().unwrap().len(), 2);
+
+    let deleted = server
+        .send_empty(Method::DELETE, &format!("{}?format=lrc", uri))
+        .await;
+    assert_eq!(deleted.status(), StatusCode::NO_CONTENT);
+    assert_eq!(json_body(server.get(&uri).await).await["format"], "plain");
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["has_lyrics"], true);
+}
+
+#[tokio::test]
+async fn test_playlist_lifecycle() {
+    let server = TestServer::new().await;
+    let track_ids = server.track_ids().await;
+
+    let playlist = json_body(
+        server
+            .send_json(
+                Method::POST,
+                "/playlists",
+                json!({ "name": "Favourites", "description": "Best of" }),
+            )
+            .await,
+    )
+    .await;
+    assert_eq!(playlist["name"], "Favourites");
+    assert_eq!(playlist["tracks"], json!([]));
+    let uri = format!("/playlists/{}", playlist["id"].as_str().unwrap());
+
+    let duplicate = server
+        .send_json(Method::POST, "/playlists", json!({ "name": "Favourites" }))
+        .await;
+    assert_eq!(duplicate.status(), StatusCode::CONFLICT);
+
+    for track_id in &track_ids {
+        let response = server
+            .send_empty(Method::POST, &format!("{}/tracks/{}", uri, track_id))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let playlist = json_body(server.get(&uri).await).await;
+    assert_eq!(playlist["tracks"], json!(track_ids));
+
+    let missing_track = server
+        .send_empty(Method::POST, &format!("{}/tracks/does-not-exist", uri))
+        .await;
+    assert_eq!(missing_track.status(), StatusCode::NOT_FOUND);
+
+    let other = json_body(
+        server
+            .send_json(Method::POST, "/playlists", json!({ "name": "Later" }))
+            .await,
+    )
+    .await;
+    let renamed = server
+        .send_json(
+            Method::PUT,
+            &format!("/playlists/{}", other["id"].as_str().unwrap()),
+            json!({ "name": "Favourites" }),
+        )
+        .await;
+    assert_eq!(renamed.status(), StatusCode::CONFLICT);
+
+    let playlists = json_body(server.get("/playlists").await).await;
+    assert_eq!(playlists.as_array().unwrap().len(), 2);
+
+    let deleted = server.send_empty(Method::DELETE, &uri).await;
+    assert_eq!(deleted.status(), StatusCode::NO_CONTENT);
+    assert_eq!(server.get(&uri).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_playlist_update_if_match() {
+    let server = TestServer::new().await;
+    let track_id = &server.track_ids().await[0];
+
+    let playlist = json_body(
+        server
+            .send_json(Method::POST, "/playlists", json!({ "name": "Shared" }))
+            .await,
+    )
+    .await;
+    assert_eq!(playlist["version"], 1);
+    let uri = format!("/playlists/{}", playlist["id"].as_str().unwrap());
+
+    let response = server.get(&uri).await;
+    assert_eq!(response.headers()[header::ETAG], "\"1\"");
+
+    // Track changes bump the version too
+    server
+        .send_empty(Method::POST, &format!("{}/tracks/{}", uri, track_id))
+        .await;
+
+    let update = |etag: &'static str| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(&uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::IF_MATCH, etag)
+            .body(Body::from(json!({ "name": "Renamed" }).to_string()))
+            .unwrap()
+    };
+
+    let response = server.send(update("\"1\"")).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    let response = server.send(update("\"2\"")).await;
+    assert_eq!(response.headers()[header::ETAG], "\"3\"");
+    let playlist = json_body(response).await;
+    assert_eq!(playlist["name"], "Renamed");
+    assert_eq!(playlist["version"], 3);
+
+    // Without If-Match the update is unconditional
+    let response = server
+        .send_json(Method::PUT, &uri, json!({ "description": "Mine" }))
+        .await;
+    assert_eq!(json_body(response).await["version"], 4);
+}
+
+#[tokio::test]
+async fn test_add_tracks_to_playlist() {
+    let server = TestServer::new().await;
+    let track_ids = server.track_ids().await;
+    let (first, second) = (&track_ids[0], &track_ids[1]);
+
+    let playlist = json_body(
+        server
+            .send_json(Method::POST, "/playlists", json!({ "name": "Bulk" }))
+            .await,
+    )
+    .await;
+    let uri = format!("/playlists/{}/tracks", playlist["id"].as_str().unwrap());
+
+    server
+        .send_empty(Method::POST, &format!("{}/{}", uri, second))
+        .await;
+
+    // An unknown track rejects the whole request
+    let response = server
+        .send_json(
+            Method::POST,
+            &uri,
+            json!({ "track_ids": [first, "does-not-exist"] }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Tracks already present, or repeated, are skipped
+    let response = server
+        .send_json(
+            Method::POST,
+            &uri,
+            json!({ "track_ids": [second, first, first] }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json_body(response).await["tracks"], json!([second, first]));
+
+    let response = server
+        .send_json(
+            Method::POST,
+            "/playlists/does-not-exist/tracks",
+            json!({ "track_ids": [first] }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_add_tracks_allow_duplicates() {
+    let server = TestServer::new().await;
+    let track_ids = server.track_ids().await;
+    let (first, second) = (&track_ids[0], &track_ids[1]);
+
+    let playlist = json_body(
+        server
+            .send_json(Method::POST, "/playlists", json!({ "name": "Repeat" }))
+            .await,
+    )
+    .await;
+    let uri = format!("/playlists/{}/tracks", playlist["id"].as_str().unwrap());
+    server
+        .send_json(Method::POST, &uri, json!({ "track_ids": [first] }))
+        .await;
+
+    // Deduplicated by default
+    let response = server
+        .send_empty(Method::POST, &format!("{}/{}", uri, first))
+        .await;
+    assert_eq!(json_body(response).await["tracks"], json!([first]));
+
+    let response = server
+        .send_empty(
+            Method::POST,
+            &format!("{}/{}?allow_duplicates=true", uri, first),
+        )
+        .await;
+    assert_eq!(json_body(response).await["tracks"], json!([first, first]));
+
+    let response = server
+        .send_json(
+            Method::POST,
+            &format!("{}?allow_duplicates=true", uri),
+            json!({ "track_ids": [second, second] }),
+        )
+        .await;
+    assert_eq!(
+        json_body(response).await["tracks"],
+        json!([first, first, second, second])
+    );
+
+    // Existing copies are kept when deduplicating again
+    let response = server
+        .send_json(Method::POST, &uri, json!({ "track_ids": [first, second] }))
+        .await;
+    assert_eq!(
+        json_body(response).await["tracks"],
+        json!([first, first, second, second])
+    );
+
+    // Removing a track drops every copy
+    let result = json_body(
+        server
+            .send_json(Method::DELETE, &uri, json!({ "track_ids": [first] }))
+            .await,
+    )
+    .await;
+    assert_eq!(result["removed"], 2);
+    assert_eq!(result["playlist"]["tracks"], json!([second, second]));
+}
+
+#[tokio::test]
+async fn test_remove_tracks_from_playlist() {
+    let server = TestServer::new().await;
+    let track_ids = server.track_ids().await;
+    let (first, second) = (&track_ids[0], &track_ids[1]);
+
+    let playlist = json_body(
+        server
+            .send_json(Method::POST, "/playlists", json!({ "name": "Bulk" }))
+            .await,
+    )
+    .await;
+    let uri = format!("/playlists/{}/tracks", playlist["id"].as_str().unwrap());
+    server
+        .send_json(Method::POST, &uri, json!({ "track_ids": [first, second] }))
+        .await;
+
+    // Unknown IDs are ignored and not counted
+    let result = json_body(
+        server
+            .send_json(
+                Method::DELETE,
+                &uri,
+                json!({ "track_ids": [first, "does-not-exist"] }),
+            )
+            .await,
+    )
+    .await;
+    assert_eq!(result["removed"], 1);
+    assert_eq!(result["playlist"]["tracks"], json!([second]));
+
+    // Positions were renumbered, so appending continues the sequence
+    let response = server
+        .send_json(Method::POST, &uri, json!({ "track_ids": [first] }))
+        .await;
+    assert_eq!(json_body(response).await["tracks"], json!([second, first]));
+
+    let response = server
+        .send_json(
+            Method::DELETE,
+            "/playlists/does-not-exist/tracks",
+            json!({ "track_ids": [first] }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_lyrics_candidates() {
+    let server = TestServer::with_library(|dir| {
+        write_silent_mp3(&dir.join("Example Song.mp3"));
+    })
+    .await;
+    let id = &server.track_ids().await[0];
+
+    // The untagged track is searched by its file name
+    let preview = json_body(
+        server
+            .get(&format!("/tracks/{}/lyrics-candidates?provider=mock", id))
+            .await,
+    )
+    .await;
+    assert_eq!(preview["provider"], "mock");
+    assert_eq!(preview["title"], "Example Song");
+    assert_eq!(preview["artist"], Value::Null);
+    assert_eq!(preview["candidates"][0]["title"], "example song");
+
+    let response = server
+        .get(&format!("/tracks/{}/lyrics-candidates?provider=nope", id))
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = server.get("/tracks/does-not-exist/lyrics-candidates").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_fetch_missing_lyrics_job() {
+    let server = TestServer::with_library(|dir| {
+        write_silent_mp3(&dir.join("Example Song.mp3"));
+        write_silent_mp3(&dir.join("Unknown Song.mp3"));
+    })
+    .await;
+    let ids = server.track_ids().await;
+
+    let response = server.get("/lyrics/fetch-missing/status").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = server
+        .send_empty(Method::POST, "/lyrics/fetch-missing?provider=mock")
+        .await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let job: Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+    assert_eq!(job["total"], 2);
+
+    let mut status = Value::Null;
+    for _ in 0..100 {
+        status = json_body(server.get("/lyrics/fetch-missing/status").await).await;
+        if status["state"] == "completed" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(status["state"], "completed");
+    assert_eq!(status["provider"], "mock");
+    assert_eq!(
+        (&status["done"], &status["not_found"]),
+        (&1.into(), &1.into())
+    );
+
+    let lyrics = json_body(server.get(&format!("/lyrics/{}", ids[0])).await).await;
+    assert_eq!(lyrics["source"], "mock");
+    let response = server.get(&format!("/lyrics/{}", ids[1])).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Nothing is running to cancel; resuming a finished job completes it again
+    let response = server
+        .send_empty(Method::POST, "/lyrics/fetch-missing/cancel")
+        .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = server
+        .send_empty(Method::POST, "/lyrics/fetch-missing/resume")
+        .await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+}
+
+#[tokio::test]
+async fn test_offline_lyrics_search() {
+    let server = TestServer::new().await;
+
+    let results = json_body(
+        server
+            .get("/lyrics/search?q=Example%20Song&provider=mock")
+            .await,
+    )
+    .await;
+    let result = &results.as_array().unwrap()[0];
+    assert_eq!(result["title"], "example song");
+
+    let lyrics = json_body(
+        server
+            .get(&format!(
+                "/lyrics/fetch/mock/{}",
+                result["id"].as_str().unwrap().replace(' ', "%20")
+            ))
+            .await,
+    )
+    .await;
+    assert_eq!(lyrics["format"], "lrc");
+    assert_eq!(lyrics["source"], "mock");
+
+    // Omitting the provider or passing `default` uses the configured default
+    for uri in [
+        "/lyrics/search?q=Example%20Song",
+        "/lyrics/search?q=Example%20Song&provider=default",
+    ] {
+        let results = json_body(server.get(uri).await).await;
+        assert_eq!(results[0]["id"], result["id"]);
+    }
+
+    let providers = json_body(server.get("/lyrics/providers").await).await;
+    assert_eq!(providers[0]["name"], "mock");
+    assert_eq!(providers[0]["is_default"], true);
+
+    let info = json_body(server.get("/lyrics/providers/info").await).await;
+    assert_eq!(
+        info,
+        serde_json::json!([{
+            "name": "mock",
+            "supports_synced": true,
+            "requires_auth": false,
+            "authenticated": false,
+            "regions": []
+        }])
+    );
+
+    // NetEase exists but isn't registered offline; "nope" doesn't exist at all
+    let unavailable = server
+        .get("/lyrics/search?q=Example&provider=netease")
+        .await;
+    assert_eq!(unavailable.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let unknown = server.get("/lyrics/search?q=Example&provider=nope").await;
+    assert_eq!(unknown.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_merged_lyrics_search() {
+    let server = TestServer::new().await;
+
+    let results = json_body(
+        server
+            .get("/lyrics/search/all?q=Example%20Song&artist=Example%20Artist")
+            .await,
+    )
+    .await;
+    let results = results.as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["provider"], "mock");
+    assert_eq!(results[0]["providers"], json!(["mock"]));
+    assert_eq!(results[0]["title"], "example song");
+    assert_eq!(results[0]["confidence"], 1.0);
+    assert_eq!(results[0]["breakdown"]["artist_match"], true);
+
+    // The mock provider's loose matches score too low on the shared scale
+    let results = json_body(server.get("/lyrics/search/all?q=Another%20Tune").await).await;
+    assert_eq!(results, json!([]));
+
+    let invalid = server
+        .get("/lyrics/search/all?q=Example&min_confidence=2")
+        .await;
+    assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_stream_track_ranges() {
+    let server = TestServer::new().await;
+    let id = &server.track_ids().await[0];
+    let full = body_bytes(server.get(&format!("/stream/{}", id)).await).await;
+    let size = full.len();
+
+    let get_range = |range: &'static str| {
+        server.send(
+            Request::get(format!("/stream/{}", id))
+                .header(header::RANGE, range)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+
+    let partial = [
+        ("bytes=0-0", 0, 0),
+        ("bytes=0-", 0, size - 1),
+        ("bytes=-100", size - 100, size - 1),
+        ("bytes=-99999999", 0, size - 1),
+        ("bytes=100-199", 100, 199),
+        ("bytes=16000-99999", 16000, size - 1),
+    ];
+    for (range, start, end) in partial {
+        let response = get_range(range).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT, "{}", range);
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            format!("bytes {}-{}/{}", start, end, size),
+            "{}",
+            range
+        );
+        assert_eq!(
+            response.headers()[header::CONTENT_LENGTH],
+            (end - start + 1).to_string()
+        );
+        assert_eq!(body_bytes(response).await, &full[start..=end], "{}", range);
+    }
+
+    // Resuming after a dropped connection re-requests from the last byte received
+    for offset in [1, 4171, size - 1] {
+        let range = format!("bytes={}-", offset);
+        let response = server
+            .send(
+                Request::get(format!("/stream/{}", id))
+                    .header(header::RANGE, &range)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT, "{}", range);
+        let mut resumed = full[..offset].to_vec();
+        resumed.extend_from_slice(&body_bytes(response).await);
+        assert_eq!(resumed, full, "{}", range);
+    }
+
+    for range in [
+        "bytes=-0",
+        "bytes=16680-",
+        "bytes=20000-20100",
+        "bytes=0-99,200-299",
+    ] {
+        let response = get_range(range).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            "{}",
+            range
+        );
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            format!("bytes */{}", size)
+        );
+    }
+
+    for range in ["bytes=100-50", "bytes=abc", "items=0-10"] {
+        let response = get_range(range).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", range);
+        assert_eq!(body_bytes(response).await, full, "{}", range);
+    }
+}
+
+/// Compare a response body chunk by chunk against `expected`, without
+/// collecting it. Returns the number of chunks and the largest chunk size.
+async fn assert_streamed_body(
+    response: Response,
+    mut expected: impl std::io::Read,
+) -> (usize, usize) {
+    use futures::StreamExt;
+
+    let mut chunks = 0;
+    let mut largest = 0;
+    let mut stream = response.into_body().into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.unwrap();
+        let mut wanted = vec![0; chunk.len()];
+        expected.read_exact(&mut wanted).unwrap();
+        assert!(chunk[..] == wanted[..], "chunk {} differs", chunks);
+        chunks += 1;
+        largest = largest.max(chunk.len());
+    }
+    assert_eq!(expected.read(&mut [0]).unwrap(), 0, "body ended early");
+    (chunks, largest)
+}
+
+#[tokio::test]
+async fn test_stream_large_track_in_chunks() {
+    // ~5 MB, far larger than one read buffer
+    let server = TestServer::with_library(|dir| {
+        write_silent_mp3_frames(&dir.join("Long/long.mp3"), 12_000);
+    })
+    .await;
+    let id = &server.track_ids().await[0];
+    let path = server.library_dir.path().join("Long/long.mp3");
+    let size = std::fs::metadata(&path).unwrap().len() as usize;
+
+    let response = server.get(&format!("/stream/{}", id)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_LENGTH], size.to_string());
+    assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+    let (chunks, largest) =
+        assert_streamed_body(response, std::fs::File::open(&path).unwrap()).await;
+    assert!(chunks > 1 && largest < size / 4, "{} chunks", chunks);
+
+    let (start, end) = (1000, size - 1000);
+    let response = server
+        .send(
+            Request::get(format!("/stream/{}", id))
+                .header(header::RANGE, format!("bytes={}-{}", start, end))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let mut file = std::fs::File::open(&path).unwrap();
+    std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(start as u64)).unwrap();
+    let expected = std::io::Read::take(file, (end - start + 1) as u64);
+    let (chunks, largest) = assert_streamed_body(response, expected).await;
+    assert!(chunks > 1 && largest < size / 4, "{} chunks", chunks);
+}
+
+#[tokio::test]
+async fn test_stream_cue_track() {
+    let server = TestServer::with_library(|dir| {
+        write_silent_mp3(&dir.join("Live/live.mp3"));
+        std::fs::write(
+            dir.join("Live/live.cue"),
+            "FILE \"live.mp3\" MP3\n\
+             TRACK 01 AUDIO\n TITLE \"Intro\"\n INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n TITLE \"Encore\"\n INDEX 01 00:00:30\n",
+        )
+        .unwrap();
+    })
+    .await;
+
+    let tracks = json_body(server.get("/tracks").await).await;
+    let encore = tracks
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|t| t["title"] == "Encore")
+        .unwrap();
+    assert_eq!(encore["start_ms"], 400);
+    assert_eq!(encore["end_ms"], 1000);
+    let uri = format!("/stream/{}", encore["id"].as_str().unwrap());
+
+    // The file spans 1 s, so 400 ms..1 s maps to the last 60% of its bytes
+    let full = std::fs::read(server.library_dir.path().join("Live/live.mp3")).unwrap();
+    let expected = &full[full.len() * 2 / 5..];
+
+    let response = server.get(&uri).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_LENGTH],
+        expected.len().to_string()
+    );
+    assert_eq!(body_bytes(response).await, expected);
+
+    let response = server
+        .send(
+            Request::get(&uri)
+                .header(header::RANGE, "bytes=10-19")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers()[header::CONTENT_RANGE],
+        format!("bytes 10-19/{}", expected.len())
+    );
+    assert_eq!(body_bytes(response).await, &expected[10..20]);
+
+    // Tags live in the shared file, so the track can't be edited on its own
+    let response = server
+        .send_json(
+            Method::PUT,
+            &format!("/tracks/{}", encore["id"].as_str().unwrap()),
+            json!({ "title": "New" }),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_track_detail() {
+    let server = TestServer::new().await;
+    let id = &server.track_ids().await[0];
+    let uri = format!("/tracks/{}/detail", id);
+
+    let detail = json_body(server.get(&uri).await).await;
+    assert_eq!(detail["track"]["id"], id.as_str());
+    assert_eq!(detail["lyrics"], Value::Null);
+    assert_eq!(
+        detail["stats"],
+        json!({ "play_count": 0, "last_played": null })
+    );
+
+    server
+        .send_json(
+            Method::PUT,
+            &format!("/lyrics/{}", id),
+            json!({ "content": "Hello" }),
+        )
+        .await;
+    server
+        .send_empty(Method::POST, &format!("/tracks/{}/play", id))
+        .await;
+
+    let detail = json_body(server.get(&uri).await).await;
+    assert_eq!(detail["track"]["play_count"], 1);
+    assert_eq!(detail["lyrics"]["content"], "Hello");
+    assert_eq!(detail["stats"]["play_count"], 1);
+    assert!(detail["stats"]["last_played"].is_string());
+
+    let missing = server.get("/tracks/does-not-exist/detail").await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_check_covers() {
+    let server = TestServer::new().await;
+    let ids = server.track_ids().await;
+
+    let covers = json_body(
+        server
+            .send_json(
+                Method::POST,
+                "/covers/check",
+                json!({ "ids": [ids[0], ids[1], "does-not-exist"] }),
+            )
+            .await,
+    )
+    .await;
+    assert_eq!(
+        covers,
+        json!({ ids[0].as_str(): false, ids[1].as_str(): false, "does-not-exist": false })
+    );
+
+    let empty = json_body(
+        server
+            .send_json(Method::POST, "/covers/check", json!({ "ids": [] }))
+            .await,
+    )
+    .await;
+    assert_eq!(empty, json!({}));
+}
+
+#[tokio::test]
+async fn test_ready_and_rescan() {
+    let server = TestServer::new().await;
+    let id = server.track_ids().await[0].clone();
+
+    let status = json_body(server.get("/ready").await).await;
+    assert_eq!(status["state"], "completed");
+
+    server
+        .send_json(
+            Method::PUT,
+            &format!("/lyrics/{}", id),
+            json!({ "content": "Hello" }),
+        )
+        .await;
+    let response = server.send_empty(Method::POST, "/scan").await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    // Rescans keep serving the previous track list, so readiness is unaffected
+    assert_eq!(server.get("/ready").await.status(), StatusCode::OK);
+    loop {
+        let status = json_body(server.get("/scan/status").await).await;
+        if status["state"] != "running" {
+            assert_eq!(status["state"], "completed");
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["has_lyrics"], true);
+}
+
+#[tokio::test]
+async fn test_version() {
+    let server = TestServer::new().await;
+
+    let version = json_body(server.get("/version").await).await;
+    assert_eq!(version["crate_version"], env!("CARGO_PKG_VERSION"));
+    assert!(version["music_search_version"].is_string());
+    assert!(version["build_timestamp"].is_string());
+    assert!(version.get("git_sha").is_some());
+}
+
+#[tokio::test]
+async fn test_stream_album() {
+    let server = TestServer::new().await;
+
+    // The untagged tracks share the "Unknown Album" and play in path order
+    let response = server.get("/albums/Unknown%20Album/stream").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "audio/mpeg");
+
+    let album_dir = server.library_dir.path().join("Artist/Album");
+    let mut expected = std::fs::read(album_dir.join("01 First.mp3")).unwrap();
+    expected.extend(std::fs::read(album_dir.join("02 Second.mp3")).unwrap());
+    assert_eq!(
+        response.headers()[header::CONTENT_LENGTH],
+        expected.len().to_string()
+    );
+    assert_eq!(body_bytes(response).await, expected);
+
+    let response = server.get("/albums/Nope/stream").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_play_count_debounce() {
+    let server = TestServer::new().await;
+    let ids = server.track_ids().await;
+    let server = &server;
+    let play =
+        |uri: String| async move { json_body(server.send_empty(Method::POST, &uri).await).await };
+
+    assert_eq!(play(format!("/tracks/{}/play", ids[0])).await, 1);
+    // Repeats within the window return the unchanged count
+    assert_eq!(play(format!("/tracks/{}/play", ids[0])).await, 1);
+    assert_eq!(play(format!("/tracks/{}/play?force=true", ids[0])).await, 2);
+    // Other tracks and other clients are debounced separately
+    assert_eq!(play(format!("/tracks/{}/play", ids[1])).await, 1);
+    assert_eq!(
+        play(format!("/tracks/{}/play?client_id=phone", ids[0])).await,
+        3
+    );
+    assert_eq!(
+        play(format!("/tracks/{}/play?client_id=phone", ids[0])).await,
+        3
+    );
+
+    let track = json_body(server.get(&format!("/tracks/{}", ids[0])).await).await;
+    assert_eq!(track["play_count"], 3);
+}
+
+#[tokio::test]
+async fn test_artist_albums() {
+    let server = TestServer::new().await;
+
+    let albums = json_body(server.get("/artists/Unknown%20Artist/albums").await).await;
+    let albums = albums.as_array().unwrap();
+    assert_eq!(albums.len(), 1);
+    assert_eq!(albums[0]["name"], "Unknown Album");
+    assert_eq!(albums[0]["track_count"], 2);
+    assert_eq!(albums[0]["has_cover"], false);
+    assert!(albums[0].get("tracks").is_none());
+
+    let missing = server.get("/artists/Nobody/albums").await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_cover_uses_stored_mime_type() {
+    let server = TestServer::with_library(|dir| {
+        write_silent_mp3(&dir.join("cover.mp3"));
+        use id3::TagLike;
+
+        // A WebP cover, which sniffing would have served as JPEG
+        let mut tag = id3::Tag::new();
+        tag.add_frame(id3::frame::Picture {
+            mime_type: "image/webp".to_string(),
+            picture_type: id3::frame::PictureType::CoverFront,
+            description: String::new(),
+            data: b"RIFF\0\0\0\0WEBPVP8 ".to_vec(),
+        });
+        tag.write_to_path(dir.join("cover.mp3"), id3::Version::Id3v24)
+            .unwrap();
+    })
+    .await;
+
+    let id = server.track_ids().await[0].clone();
+    let response = server.get(&format!("/cover/{}", id)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/webp");
+}
+
+#[tokio::test]
+async fn test_copy_cover_between_tracks() {
+    let server = TestServer::with_library(|dir| {
+        write_silent_mp3(&dir.join("a.mp3"));
+        write_silent_mp3(&dir.join("b.mp3"));
+        write_silent_mp3(&dir.join("c.mp3"));
+        write_silent_mp3(&dir.join("d.mp3"));
+        std::fs::write(
+            dir.join("d.cue"),
+            "FILE \"d.mp3\" MP3\n\
+             TRACK 01 AUDIO\n INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n INDEX 01 00:00:30\n",
+        )
+        .unwrap();
+        use id3::TagLike;
+
+        let mut tag = id3::Tag::new();
+        tag.add_frame(id3::frame::Picture {
+            mime_type: "image/webp".to_string(),
+            picture_type: id3::frame::PictureType::CoverFront,
+            description: String::new(),
+            data: b"RIFF\0\0\0\0WEBPVP8 ".to_vec(),
+        });
+        tag.write_to_path(dir.join("a.mp3"), id3::Version::Id3v24)
+            .unwrap();
+    })
+    .await;
+    let ids = server.track_ids().await;
+    let (a, b, c) = (&ids[0], &ids[1], &ids[2]);
+
+    // Source without art
+    let response = server
+        .send_empty(
+            Method::POST,
+            &format!("/tracks/{}/cover/copy-from/{}", a, b),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = server
+        .send_empty(
+            Method::POST,
+            &format!("/tracks/{}/cover/copy-from/missing", b),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = server
+        .send_empty(
+            Method::POST,
+            &format!("/tracks/{}/cover/copy-from/{}", b, a),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(json_body(response).await["has_cover"], true);
+    let response = server.get(&format!("/cover/{}", b)).await;
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/webp");
+
+    // The untagged files share the "Unknown Album"
+    let response = server
+        .send_empty(
+            Method::POST,
+            &format!("/albums/Unknown%20Album/cover/from-track/{}", a),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let report = json_body(response).await;
+    assert_eq!(report["mime_type"], "image/webp");
+    assert_eq!(report["updated"].as_array().unwrap().len(), 2);
+    // The CUE-sheet tracks share d.mp3, so it is left alone
+    assert_eq!(report["skipped"].as_array().unwrap().len(), 2);
+    assert!(report["failed"].as_array().unwrap().is_empty());
+
+    let track = json_body(server.get(&format!("/tracks/{}", c)).await).await;
+    assert_eq!(track["has_cover"], true);
+}
+
+#[tokio::test]
+async fn test_optimize_cover_rejects_invalid_parameters() {
+    let server = TestServer::new().await;
+    let id = &server.track_ids().await[0];
+
+    for query in ["max_dim=0", "quality=0", "quality=101"] {
+        let response = server
+            .send_empty(
+                Method::POST,
+                &format!("/tracks/{}/optimize-cover?{}", id, query),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+        let response = server
+            .send_empty(Method::POST, &format!("/optimize-covers?{}", query))
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+    }
+
+    let response = server
+        .send_empty(Method::POST, "/optimize-covers?dry_run=true")
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_inspect_does_not_change_library() {
+    let server = TestServer::new().await;
+    let incoming = server.library_dir.path().join("Incoming");
+    write_silent_mp3(&incoming.join("new.mp3"));
+    std::fs::write(incoming.join("broken.flac"), b"not a flac file").unwrap();
+
+    let report = json_body(server.get("/inspect?path=Incoming").await).await;
+    let tracks = report["tracks"].as_array().unwrap();
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0]["relative_path"], "Incoming/new.mp3");
+    assert_eq!(report["errors"][0]["path"], "Incoming/broken.flac");
+    assert_eq!(server.track_ids().await.len(), 2);
+
+    let report = json_body(server.get("/inspect?path=Incoming/new.mp3").await).await;
+    assert_eq!(report["tracks"].as_array().unwrap().len(), 1);
+
+    let response = server.get("/inspect?path=../").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = server.get("/inspect?path=/etc").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = server.get("/inspect?path=Missing").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_upload_cover_fields() {
+    let server = TestServer::new().await;
+    let id = &server.track_ids().await[0];
+    let png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+    // Each part is (field name, content type, data)
+    let upload = |uri: String, parts: Vec<(&str, &str, Vec<u8>)>| {
+        let mut body = Vec::new();
+        for (name, content_type, data) in parts {
+            body.extend_from_slice(
+                format!(
+                    "--BOUNDARY\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"f\"\r\nContent-Type: {}\r\n\r\n",
+                    name, content_type
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(&data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--BOUNDARY--\r\n");
+        server.send(
+            Request::post(uri)
+                .header(
+                    header::CONTENT_TYPE,
+                    "multipart/form-data; boundary=BOUNDARY",
+                )
+                .body(Body::from(body))
+                .unwrap(),
+        )
+    };
+    let rejected = |response: Response| async move {
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        String::from_utf8(body_bytes(response).await).unwrap()
+    };
+
+    let uri = format!("/cover/{}", id);
+    let response = upload(uri.clone(), vec![("file", "image/png", png.clone())]).await;
+    assert_eq!(
+        rejected(response).await,
+        "No image field named 'image' or 'cover'"
+    );
+    let response = upload(uri.clone(), vec![("image", "image/png", Vec::new())]).await;
+    assert_eq!(rejected(response).await, "Field 'image' is empty");
+    let response = upload(uri.clone(), vec![("image", "text/plain", png.clone())]).await;
+    assert_eq!(
+        rejected(response).await,
+        "Field 'image' has Content-Type text/plain, expected image/*"
+    );
+    let response = upload(
+        uri.clone(),
+        vec![
+            ("image", "image/png", png.clone()),
+            ("cover", "image/png", png.clone()),
+        ],
+    )
+    .await;
+    assert_eq!(
+        rejected(response).await,
+        "Multiple image fields named 'image' or 'cover'"
+    );
+
+    // A custom field name replaces the defaults
+    let response = upload(
+        format!("{}?field=art", uri),
+        vec![
+            ("image", "image/jpeg", b"ignored".to_vec()),
+            ("art", "image/png", png.clone()),
+        ],
+    )
+    .await;
+    assert_eq!(json_body(response).await["has_cover"], true);
+    let response = server.get(&uri).await;
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+}
+
+#[tokio::test]
+async fn test_stream_replaygain_headers() {
+    let server = TestServer::with_library(|dir| {
+        write_silent_mp3(&dir.join("tagged.mp3"));
+        use id3::TagLike;
+
+        let mut tag = id3::Tag::new();
+        tag.add_frame(id3::frame::ExtendedText {
+            description: "REPLAYGAIN_TRACK_GAIN".to_string(),
+            value: "-6.48 dB".to_string(),
+        });
+        tag.write_to_path(dir.join("tagged.mp3"), id3::Version::Id3v24)
+            .unwrap();
+        write_silent_mp3(&dir.join("untagged.mp3"));
+    })
+    .await;
+
+    let tracks = json_body(server.get("/tracks").await).await;
+    let find = |name: &str| {
+        tracks
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|t| t["filename"] == name)
+            .unwrap()
+            .clone()
+    };
+    let tagged = find("tagged.mp3");
+    assert_eq!(tagged["replaygain_track_gain"], -6.48);
+    assert!(tagged["replaygain_album_gain"].is_null());
+
+    let response = server
+        .get(&format!("/stream/{}", tagged["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(response.headers()["x-replaygain-track-gain"], "-6.48 dB");
+    assert!(response.headers().get("x-replaygain-album-gain").is_none());
+
+    let untagged = find("untagged.mp3");
+    let response = server
+        .get(&format!("/stream/{}", untagged["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("x-replaygain-track-gain").is_none());
+}
+
+#[tokio::test]
+async fn test_missing_track_file() {
+    let server = TestServer::new().await;
+    assert_eq!(
+        json_body(server.get("/tracks/missing").await).await,
+        json!([])
+    );
+
+    let id = server.track_ids().await[0].clone();
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["missing"], false);
+    std::fs::remove_file(track["path"].as_str().unwrap()).unwrap();
+
+    let response = server.get(&format!("/stream/{}", id)).await;
+    assert_eq!(response.status(), StatusCode::GONE);
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["missing"], true);
+
+    let missing = json_body(server.get("/tracks/missing").await).await;
+    assert_eq!(missing.as_array().unwrap().len(), 1);
+    assert_eq!(missing[0]["id"], id.as_str());
+}
+
+#[tokio::test]
+async fn test_recently_added() {
+    let server = TestServer::new().await;
+    let tracks = json_body(server.get("/tracks/recently-added?limit=1").await).await;
+    assert_eq!(tracks.as_array().unwrap().len(), 1);
+    let date_added = tracks[0]["date_added"].as_str().unwrap().to_string();
+    assert!(chrono::DateTime::parse_from_rfc3339(&date_added).is_ok());
+
+    let tracks = json_body(server.get("/tracks/recently-added?days=1").await).await;
+    assert_eq!(tracks.as_array().unwrap().len(), 2);
+
+    // The stored date survives a rescan even though the file changed
+    let path = tracks[0]["path"].as_str().unwrap().to_string();
+    let id = tracks[0]["id"].as_str().unwrap().to_string();
+    let before = tracks[0]["date_added"].clone();
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    write_silent_mp3(Path::new(&path));
+    let response = server.send_empty(Method::POST, "/scan").await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    while json_body(server.get("/scan/status").await).await["state"] == "running" {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["date_added"], before);
+}
+
+#[tokio::test]
+async fn test_name_indexes() {
+    let server = TestServer::new().await;
+    // The untagged test files fall under "Unknown Artist" / "Unknown Album"
+    assert_eq!(
+        json_body(server.get("/artists/index").await).await,
+        json!([{ "key": "U", "count": 1, "offset": 0 }])
+    );
+    assert_eq!(
+        json_body(server.get("/albums/index").await).await,
+        json!([{ "key": "U", "count": 1, "offset": 0 }])
+    );
+}
+
+#[tokio::test]
+async fn test_refresh_track_flags() {
+    let server = TestServer::new().await;
+    let id = server.track_ids().await[0].clone();
+    // Lyrics written straight to the database, bypassing the API
+    LyricDatabase::new(server.data_dir.path().join("lyrics.db"))
+        .await
+        .unwrap()
+        .save_lyric(
+            &id,
+            "[00:01.00]Hello".to_string(),
+            LyricFormat::Lrc,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["has_lyrics"], false);
+
+    let response = server
+        .send_json(Method::POST, "/tracks/refresh-flags", json!({}))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let report = json_body(response).await;
+    assert_eq!(report["lyrics_updated"], 1);
+    assert_eq!(report["cover_updated"], 0);
+
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["has_lyrics"], true);
+}
+
+#[tokio::test]
+async fn test_reindex_reloads_database_flags() {
+    let server = TestServer::new().await;
+    let id = server.track_ids().await[0].clone();
+    LyricDatabase::new(server.data_dir.path().join("lyrics.db"))
+        .await
+        .unwrap()
+        .save_lyric(&id, "Hello".to_string(), LyricFormat::Plain, None, None)
+        .await
+        .unwrap();
+
+    let report = json_body(server.send_empty(Method::POST, "/reindex").await).await;
+    assert_eq!(
+        report,
+        json!({ "tracks": 2, "albums": 1, "artists": 1, "flags_updated": 1 })
+    );
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["has_lyrics"], true);
+
+    // Nothing changed since
+    let report = json_body(server.send_empty(Method::POST, "/reindex").await).await;
+    assert_eq!(report["flags_updated"], 0);
+}
+
+#[tokio::test]
+async fn test_rescan_reports_changes() {
+    let server = TestServer::new().await;
+    let first = server.track_ids().await[0].clone();
+    let response = server
+        .send_empty(Method::POST, &format!("/tracks/{}/play", first))
+        .await;
+    assert_eq!(json_body(response).await, 1);
+    let listed = server.get("/tracks").await;
+    let version = listed.headers()["x-library-version"]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    write_silent_mp3(&server.library_dir.path().join("Artist/Album/03 Third.mp3"));
+    let report = json_body(server.send_empty(Method::POST, "/rescan").await).await;
+    assert_eq!(
+        report,
+        json!({ "added": 1, "updated": 0, "removed": 0, "parsed": 1, "tracks": 3 })
+    );
+
+    // The version sent with the list picks up what the rescan changed
+    let changes = json_body(
+        server
+            .get(&format!("/tracks/changes?since={}", version))
+            .await,
+    )
+    .await;
+    assert_eq!(changes["full_resync_required"], false);
+    assert_eq!(changes["added"].as_array().unwrap().len(), 1);
+
+    let track = json_body(server.get(&format!("/tracks/{}", first)).await).await;
+    assert_eq!(track["play_count"], 1);
+}
+
+#[tokio::test]
+async fn test_play_counts_survive_restart() {
+    let server = TestServer::new().await;
+    let id = server.track_ids().await[0].clone();
+    let response = server
+        .send_empty(Method::POST, &format!("/tracks/{}/play", id))
+        .await;
+    assert_eq!(json_body(response).await, 1);
+    let played = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert!(played["last_played"].is_string());
+
+    // A restarted server scans into a fresh library and hydrates it from the databases
+    let library = MusicLibrary::new(server.library_dir.path().to_path_buf());
+    library.scan().await.unwrap();
+    assert_eq!(library.get_track(&id).await.unwrap().play_count, 0);
+    let lyrics_db = LyricDatabase::new(server.data_dir.path().join("lyrics.db"))
+        .await
+        .unwrap();
+    let stats_db = StatsDatabase::new(&server.data_dir.path().join("stats.db"))
+        .await
+        .unwrap();
+    load_track_flags(&library, &lyrics_db, &stats_db).await;
+
+    let track = library.get_track(&id).await.unwrap();
+    assert_eq!(track.play_count, 1);
+    assert!(track.last_played.is_some());
+}
+
+#[tokio::test]
+async fn test_embedded_lyrics_imported_on_load() {
+    use id3::TagLike;
+
+    let with_lyrics = |path: &Path, text: &str| {
+        write_silent_mp3(path);
+        let mut tag = id3::Tag::new();
+        tag.add_frame(id3::frame::Lyrics {
+            lang: "deu".to_string(),
+            description: String::new(),
+            text: text.to_string(),
+        });
+        tag.write_to_path(path, id3::Version::Id3v24).unwrap();
+    };
+    let server = TestServer::with_library(|dir| {
+        with_lyrics(&dir.join("01 Tagged.mp3"), "[00:01.00]Eins");
+        with_lyrics(&dir.join("02 Stored.mp3"), "Embedded");
+        write_silent_mp3(&dir.join("03 Bare.mp3"));
+    })
+    .await;
+    let ids = server.track_ids().await;
+
+    let lyrics_db = LyricDatabase::new(server.data_dir.path().join("lyrics.db"))
+        .await
+        .unwrap();
+    let stats_db = StatsDatabase::new(&server.data_dir.path().join("stats.db"))
+        .await
+        .unwrap();
+    lyrics_db
+        .save_lyric(&ids[1], "Uploaded".into(), LyricFormat::Plain, None, None)
+        .await
+        .unwrap();
+
+    let library = MusicLibrary::new(server.library_dir.path().to_path_buf());
+    library.scan().await.unwrap();
+    load_track_flags(&library, &lyrics_db, &stats_db).await;
+
+    let imported = lyrics_db.get_lyric(&ids[0]).await.unwrap().unwrap();
+    assert_eq!(imported.content, "[00:01.00]Eins");
+    assert_eq!(imported.format, LyricFormat::Lrc);
+    assert_eq!(imported.language.as_deref(), Some("deu"));
+    assert_eq!(imported.source.as_deref(), Some("embedded"));
+    assert!(library.get_track(&ids[0]).await.unwrap().has_lyrics);
+
+    // Lyrics already in the database win over the embedded ones
+    let stored = lyrics_db.get_lyrics(&ids[1]).await.unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].content, "Uploaded");
+
+    assert!(lyrics_db.get_lyric(&ids[2]).await.unwrap().is_none());
+    assert!(!library.get_track(&ids[2]).await.unwrap().has_lyrics);
+}
+
+#[tokio::test]
+async fn test_play_history() {
+    let server = TestServer::new().await;
+    let id = server.track_ids().await[0].clone();
+    let play = format!("/tracks/{}/play", id);
+    server.send_empty(Method::POST, &play).await;
+    // Debounced plays are not counted and not logged
+    server.send_empty(Method::POST, &play).await;
+    server
+        .send_empty(Method::POST, &format!("{}?force=true&position=42", play))
+        .await;
+
+    let history = json_body(server.get("/history").await).await;
+    let history = history.as_array().unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0]["track_id"], id.as_str());
+    assert_eq!(history[0]["position_secs"], 42);
+    assert_eq!(history[0]["track"]["id"], id.as_str());
+    assert!(history[1]["position_secs"].is_null());
+
+    assert_eq!(
+        json_body(server.get("/history?limit=1").await)
+            .await
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+    let empty = json_body(server.get("/history?to=2000-01-01T00:00:00Z").await).await;
+    assert!(empty.as_array().unwrap().is_empty());
+    assert_eq!(
+        server.get("/history?from=yesterday").await.status(),
+        StatusCode::BAD_REQUEST
+    );
+}
+
+#[tokio::test]
+async fn test_play_history_with_unbounded_retention() {
+    let server = TestServer::with_options(
+        |dir| write_silent_mp3(&dir.join("a.mp3")),
+        ServerOptions {
+            // Reaches past chrono's range, so nothing is pruned
+            history_retention: Some(std::time::Duration::MAX),
+            ..Default::default()
+        },
+    )
+    .await;
+    let id = server.track_ids().await[0].clone();
+
+    let response = server
+        .send_empty(Method::POST, &format!("/tracks/{}/play", id))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let history = json_body(server.get("/history").await).await;
+    assert_eq!(history.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_played_counts_only_completed_plays() {
+    let server = TestServer::new().await;
+    let id = server.track_ids().await[0].clone();
+    let played = format!("/tracks/{}/played", id);
+
+    let response = server
+        .send_json(
+            Method::POST,
+            &played,
+            json!({ "position_ms": 30_000, "duration_ms": 200_000 }),
+        )
+        .await;
+    assert_eq!(
+        json_body(response).await,
+        json!({ "counted": false, "play_count": 0 })
+    );
+
+    let response = server
+        .send_json(
+            Method::POST,
+            &played,
+            json!({ "position_ms": 100_000, "duration_ms": 200_000 }),
+        )
+        .await;
+    assert_eq!(
+        json_body(response).await,
+        json!({ "counted": true, "play_count": 1 })
+    );
+    let history = json_body(server.get("/history").await).await;
+    assert_eq!(history[0]["position_secs"], 100);
+
+    // Completed plays are debounced like `/play`
+    let response = server
+        .send_json(Method::POST, &played, json!({ "position_ms": 300_000 }))
+        .await;
+    assert_eq!(
+        json_body(response).await,
+        json!({ "counted": false, "play_count": 1 })
+    );
+}
+
+#[tokio::test]
+async fn test_web_dir() {
+    let web_dir = tempfile::tempdir().unwrap();
+    std::fs::write(web_dir.path().join("index.html"), "<h1>Music</h1>").unwrap();
+    let server = TestServer::with_options(
+        |_| {},
+        ServerOptions {
+            web_dir: Some(web_dir.path().to_path_buf()),
+            ..Default::default()
+        },
+    )
+    .await;
+
+    let page = server.get("/web/index.html").await;
+    assert_eq!(page.status(), StatusCode::OK);
+    assert_eq!(body_bytes(page).await, b"<h1>Music</h1>");
+
+    let missing = server.get("/web/missing.js").await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    // The server's directory isn't revealed
+    assert_eq!(body_bytes(missing).await, b"Not found");
+}
+
+#[cfg(feature = "embed-assets")]
+#[tokio::test]
+async fn test_embedded_web_client() {
+    let server = TestServer::new().await;
+
+    let page = server.get("/web/index.html").await;
+    assert_eq!(page.status(), StatusCode::OK);
+    assert_eq!(
+        page.headers()[header::CONTENT_TYPE],
+        "text/html; charset=utf-8"
+    );
+    assert_eq!(page.headers()[header::CACHE_CONTROL], "no-cache");
+    let etag = page.headers()[header::ETAG].clone();
+    let index = body_bytes(page).await;
+    assert_eq!(index, std::fs::read("static/index.html").unwrap());
+    assert_eq!(body_bytes(server.get("/web/").await).await, index);
+
+    let revalidated = server
+        .send(
+            Request::get("/web/index.html")
+                .header(header::IF_NONE_MATCH, etag)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(revalidated.status(), StatusCode::NOT_MODIFIED);
+
+    let script = server.get("/web/app.js").await;
+    assert_eq!(
+        script.headers()[header::CONTENT_TYPE],
+        "text/javascript; charset=utf-8"
+    );
+    assert_eq!(
+        server.get("/web/missing.js").await.status(),
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn test_get_track_by_path() {
+    let server = TestServer::new().await;
+    let id = server.track_ids().await[0].clone();
+    let by_path = |path: &str| {
+        format!(
+            "/tracks/by-path?path={}",
+            path.replace(' ', "%20").replace('/', "%2F")
+        )
+    };
+
+    let track = json_body(server.get(&by_path("Artist/Album/01 First.mp3")).await).await;
+    assert_eq!(track["id"], id.as_str());
+    let track = json_body(server.get(&by_path("./Artist/Album/01 First.mp3")).await).await;
+    assert_eq!(track["id"], id.as_str());
+    let absolute = server.library_dir.path().join("Artist/Album/01 First.mp3");
+    let track = json_body(server.get(&by_path(absolute.to_str().unwrap())).await).await;
+    assert_eq!(track["id"], id.as_str());
+
+    let missing = server.get(&by_path("Artist/Album/03 Missing.mp3")).await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    // `..` is rejected as in every other library path, even inside the library
+    for path in [
+        "Artist/Other/../Album/01 First.mp3",
+        "../Artist/Album/01 First.mp3",
+        "/elsewhere/Artist/Album/01 First.mp3",
+    ] {
+        assert_eq!(
+            server.get(&by_path(path)).await.status(),
+            StatusCode::BAD_REQUEST,
+            "{}",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_embed_all_lyrics() {
+    let server = TestServer::new().await;
+    let id = server.track_ids().await[0].clone();
+    server
+        .send_json(
+            Method::PUT,
+            &format!("/lyrics/{}", id),
+            json!({ "content": "[00:01.00]Hello" }),
+        )
+        .await;
+    let path = json_body(server.get(&format!("/tracks/{}", id)).await).await["path"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let embedded_lyrics = || {
+        id3::Tag::read_from_path(&path)
+            .ok()
+            .and_then(|tag| tag.lyrics().next().map(|l| l.text.clone()))
+    };
+
+    let report = json_body(
+        server
+            .send_empty(Method::POST, "/lyrics/embed-all?format_filter=flac")
+            .await,
+    )
+    .await;
+    assert_eq!(report["skipped"], 1);
+    assert_eq!(report["tracks"][0]["status"], "skipped");
+
+    let report = json_body(
+        server
+            .send_empty(Method::POST, "/lyrics/embed-all?dry_run=true")
+            .await,
+    )
+    .await;
+    assert_eq!(report["dry_run"], true);
+    assert_eq!(report["tracks"][0]["status"], "would_embed");
+    assert_eq!(embedded_lyrics(), None);
+
+    let report = json_body(
+        server
+            .send_empty(Method::POST, "/lyrics/embed-all?format_filter=flac,mp3")
+            .await,
+    )
+    .await;
+    assert_eq!(report["embedded"], 1);
+    assert_eq!(report["failed"], 0);
+    assert_eq!(report["tracks"][0]["track_id"], id.as_str());
+    assert_eq!(report["tracks"][0]["status"], "embedded");
+    assert_eq!(embedded_lyrics().as_deref(), Some("[00:01.00]Hello"));
+
+    // The track keeps its database lyrics flag after being re-read
+    let track = json_body(server.get(&format!("/tracks/{}", id)).await).await;
+    assert_eq!(track["has_lyrics"], true);
+}